@@ -0,0 +1,62 @@
+// Compare the tree-walking interpreter with the bytecode VM on loop-heavy programs
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use quantum_slut_transpiler::{Engine, InMemoryCache};
+
+const WHILE_PROGRAM: &str = r#"
+* <main> WhileBench {
+    ^ observe_execution {
+        n <> 0
+        total <> 0
+        loop <> while(n < 10000 && total >= 0) {
+            n <> calc(n, 1)
+            total <> calc(total, n)
+        }
+        speak("total = ~total~")
+    }
+}
+"#;
+
+const RANGE_PROGRAM: &str = r#"
+* <main> RangeBench {
+    ^ observe_execution {
+        total <> 0
+        loop <> range(0, 1000) as i {
+            total <> calc(total, i)
+        }
+        speak("total = ~total~")
+    }
+}
+"#;
+
+/// Built once per group so the timed closure covers `run_source` alone, not cache or file setup
+///
+/// No extra sink: `run_source` records each run's output itself and clears it on the next run.
+fn engine(vm: bool) -> Engine {
+    Engine::builder()
+        .cache(InMemoryCache::new())
+        .vm(vm)
+        .build()
+        .unwrap()
+}
+
+fn bench_loops(c: &mut Criterion) {
+    for (name, source) in [("while", WHILE_PROGRAM), ("range", RANGE_PROGRAM)] {
+        let mut group = c.benchmark_group(format!("loop_{}", name));
+        group.sample_size(10);
+
+        let mut tree_walker = engine(false);
+        group.bench_function("tree_walker", |b| {
+            b.iter(|| tree_walker.run_source(source).unwrap())
+        });
+        let mut bytecode_vm = engine(true);
+        group.bench_function("bytecode_vm", |b| {
+            b.iter(|| bytecode_vm.run_source(source).unwrap())
+        });
+
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_loops);
+criterion_main!(benches);
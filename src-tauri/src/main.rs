@@ -15,6 +15,10 @@ fn main() {
             tauri_commands::get_working_directory,
             tauri_commands::get_cache_history,
             tauri_commands::clear_memory_state,
+            tauri_commands::transpile_to_js,
+            tauri_commands::set_limits,
+            tauri_commands::get_limits,
+            tauri_commands::list_solutions,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,320 @@
+// Arbitrary-precision integers for targets and results that do not fit in f64
+// Kept in base 10^9 so decimal text round-trips cheaply; serialized as a decimal string
+
+use anyhow::Result;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+const BASE: u64 = 1_000_000_000;
+const BASE_DIGITS: usize = 9;
+
+/// 2^53: every integer up to this magnitude is exact in f64
+pub const F64_EXACT_LIMIT: f64 = 9_007_199_254_740_992.0;
+
+/// A signed integer of any size
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BigInt {
+    negative: bool,
+    /// Base 10^9 digits, least significant first, no trailing zeros; empty for 0
+    limbs: Vec<u32>,
+}
+
+impl BigInt {
+    pub fn zero() -> Self {
+        Self { negative: false, limbs: Vec::new() }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    /// `x` when it is a whole number; every finite integral f64 is one exactly
+    pub fn from_f64(x: f64) -> Option<Self> {
+        if !x.is_finite() || x.fract() != 0.0 {
+            return None;
+        }
+        format!("{:.0}", x).parse().ok()
+    }
+
+    /// Nearest f64, which is exact below `F64_EXACT_LIMIT`
+    pub fn to_f64(&self) -> f64 {
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
+
+    /// Whether f64 holds this value exactly, so a plain `Number` can carry it
+    pub fn fits_f64(&self) -> bool {
+        cmp_magnitude(&self.limbs, &Self::from(F64_EXACT_LIMIT as u64).limbs) != Ordering::Greater
+    }
+
+    /// Decimal digits in the magnitude
+    pub fn digits(&self) -> usize {
+        match self.limbs.last() {
+            Some(top) => (self.limbs.len() - 1) * BASE_DIGITS + top.to_string().len(),
+            None => 1,
+        }
+    }
+
+    pub fn abs(&self) -> Self {
+        Self { negative: false, limbs: self.limbs.clone() }
+    }
+
+    pub fn pow(&self, exponent: u32) -> Self {
+        let mut result = Self::from(1u64);
+        let mut base = self.clone();
+        let mut exponent = exponent;
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = &result * &base;
+            }
+            exponent >>= 1;
+            if exponent > 0 {
+                base = &base * &base;
+            }
+        }
+        result
+    }
+
+    pub fn factorial(n: u32) -> Self {
+        let mut limbs = vec![1u32];
+        for i in 2..=n {
+            mul_small(&mut limbs, i);
+        }
+        Self { negative: false, limbs }
+    }
+
+    /// The value as a small unsigned integer, e.g. for exponents
+    pub fn to_u32(&self) -> Option<u32> {
+        if self.negative || self.limbs.len() > 2 {
+            return None;
+        }
+        let value = self.limbs.iter().rev().fold(0u64, |acc, &limb| acc * BASE + limb as u64);
+        u32::try_from(value).ok()
+    }
+
+    fn from_parts(negative: bool, mut limbs: Vec<u32>) -> Self {
+        while limbs.last() == Some(&0) {
+            limbs.pop();
+        }
+        let negative = negative && !limbs.is_empty();
+        Self { negative, limbs }
+    }
+}
+
+impl From<u64> for BigInt {
+    fn from(mut n: u64) -> Self {
+        let mut limbs = Vec::new();
+        while n > 0 {
+            limbs.push((n % BASE) as u32);
+            n /= BASE;
+        }
+        Self { negative: false, limbs }
+    }
+}
+
+impl From<i64> for BigInt {
+    fn from(n: i64) -> Self {
+        let magnitude = Self::from(n.unsigned_abs());
+        Self::from_parts(n < 0, magnitude.limbs)
+    }
+}
+
+impl FromStr for BigInt {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, text.strip_prefix('+').unwrap_or(text)),
+        };
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(anyhow::anyhow!("'{}' is not an integer", text));
+        }
+
+        let limbs = digits.as_bytes()
+            .rchunks(BASE_DIGITS)
+            .map(|chunk| std::str::from_utf8(chunk).unwrap_or("0").parse::<u32>().unwrap_or(0))
+            .collect();
+        Ok(Self::from_parts(negative, limbs))
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some((top, rest)) = self.limbs.split_last() else {
+            return write!(f, "0");
+        };
+        if self.negative {
+            write!(f, "-")?;
+        }
+        write!(f, "{}", top)?;
+        for limb in rest.iter().rev() {
+            write!(f, "{:09}", limb)?;
+        }
+        Ok(())
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_magnitude(&self.limbs, &other.limbs),
+            (true, true) => cmp_magnitude(&other.limbs, &self.limbs),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl std::ops::Neg for &BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        BigInt::from_parts(!self.negative, self.limbs.clone())
+    }
+}
+
+impl std::ops::Add for &BigInt {
+    type Output = BigInt;
+
+    fn add(self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt::from_parts(self.negative, add_magnitude(&self.limbs, &other.limbs));
+        }
+        // Signs differ: subtract the smaller magnitude from the larger
+        match cmp_magnitude(&self.limbs, &other.limbs) {
+            Ordering::Less => BigInt::from_parts(other.negative, sub_magnitude(&other.limbs, &self.limbs)),
+            _ => BigInt::from_parts(self.negative, sub_magnitude(&self.limbs, &other.limbs)),
+        }
+    }
+}
+
+impl std::ops::Sub for &BigInt {
+    type Output = BigInt;
+
+    fn sub(self, other: &BigInt) -> BigInt {
+        self + &-other
+    }
+}
+
+impl std::ops::Mul for &BigInt {
+    type Output = BigInt;
+
+    fn mul(self, other: &BigInt) -> BigInt {
+        if self.is_zero() || other.is_zero() {
+            return BigInt::zero();
+        }
+        let mut product = vec![0u64; self.limbs.len() + other.limbs.len()];
+        for (i, &a) in self.limbs.iter().enumerate() {
+            let mut carry = 0u64;
+            for (j, &b) in other.limbs.iter().enumerate() {
+                let current = product[i + j] + a as u64 * b as u64 + carry;
+                product[i + j] = current % BASE;
+                carry = current / BASE;
+            }
+            product[i + other.limbs.len()] += carry;
+        }
+        BigInt::from_parts(self.negative != other.negative, product.into_iter().map(|limb| limb as u32).collect())
+    }
+}
+
+impl Serialize for BigInt {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for BigInt {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        text.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+fn cmp_magnitude(a: &[u32], b: &[u32]) -> Ordering {
+    a.len().cmp(&b.len()).then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut sum = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0u64;
+    for i in 0..a.len().max(b.len()) {
+        let current = *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+        sum.push((current % BASE) as u32);
+        carry = current / BASE;
+    }
+    if carry > 0 {
+        sum.push(carry as u32);
+    }
+    sum
+}
+
+/// `a - b` for `a >= b`
+fn sub_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut difference = Vec::with_capacity(a.len());
+    let mut borrow = 0i64;
+    for (i, &limb) in a.iter().enumerate() {
+        let mut current = limb as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        borrow = 0;
+        if current < 0 {
+            current += BASE as i64;
+            borrow = 1;
+        }
+        difference.push(current as u32);
+    }
+    difference
+}
+
+fn mul_small(limbs: &mut Vec<u32>, factor: u32) {
+    let mut carry = 0u64;
+    for limb in limbs.iter_mut() {
+        let current = *limb as u64 * factor as u64 + carry;
+        *limb = (current % BASE) as u32;
+        carry = current / BASE;
+    }
+    while carry > 0 {
+        limbs.push((carry % BASE) as u32);
+        carry /= BASE;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn big(text: &str) -> BigInt {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn test_arithmetic_is_exact() {
+        assert_eq!(BigInt::factorial(25).to_string(), "15511210043330985984000000");
+        assert_eq!(BigInt::from(2u64).pow(100).to_string(), "1267650600228229401496703205376");
+        assert_eq!((&big("999999999999999999") + &big("1")).to_string(), "1000000000000000000");
+        assert_eq!((&big("1000000000000000000") - &big("1000000000000000001")).to_string(), "-1");
+        assert_eq!((&big("-123456789012") * &big("1000000007")).to_string(), "-123456789876197523084");
+        assert_eq!(&big("-5") + &big("5"), BigInt::zero());
+        assert!(big("-10") < big("-9") && big("-1") < big("0") && big("1000000000") > big("999999999"));
+
+        assert_eq!(BigInt::from_f64(2f64.powi(60)), Some(big("1152921504606846976")));
+        assert_eq!(BigInt::from_f64(1.5), None);
+        assert!(big("9007199254740992").fits_f64() && !big("9007199254740993").fits_f64());
+        assert_eq!(big("4294967295").to_u32(), Some(u32::MAX));
+        assert!("12a".parse::<BigInt>().is_err());
+
+        let json = serde_json::to_string(&BigInt::factorial(30)).unwrap();
+        assert_eq!(json, "\"265252859812191058636308480000000\"");
+        assert_eq!(serde_json::from_str::<BigInt>(&json).unwrap(), BigInt::factorial(30));
+    }
+}
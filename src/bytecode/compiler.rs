@@ -0,0 +1,537 @@
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+
+use super::{Chunk, Expression, Instruction, Op, Operand, Segment};
+use crate::codegen::{split_template, TemplatePart};
+use crate::lexer;
+use crate::parser::{Literal, Program, Statement};
+use crate::VariableValue;
+
+/// Jump targets still waiting for the end of their loop
+struct LoopFrame {
+    label: Option<String>,
+    continue_target: usize,
+    breaks: Vec<usize>,
+}
+
+/// Compiles the main class of a parsed program into a `Chunk`
+///
+/// Function classes are not compiled: calls to them, solver statements,
+/// parallel blocks, `match` and `userIn` become `Fallback` instructions that the
+/// interpreter runs.
+#[derive(Default)]
+pub struct Compiler {
+    chunk: Chunk,
+    slots: HashMap<String, usize>,
+    loops: Vec<LoopFrame>,
+    /// Variables the host sets before the run; literal assignments to them are dropped
+    presets: HashSet<String>,
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep the host's values for `names` over the program's literal assignments
+    pub fn preset(mut self, names: impl IntoIterator<Item = String>) -> Self {
+        self.presets.extend(names);
+        self
+    }
+
+    pub fn compile(mut self, program: &Program) -> Result<Chunk> {
+        self.chunk.class_name = program.main_class.clone();
+        self.compile_block(&program.body)?;
+        Ok(self.chunk)
+    }
+
+    fn compile_block(&mut self, statements: &[Statement]) -> Result<()> {
+        for statement in statements {
+            self.compile_statement(statement)?;
+        }
+        Ok(())
+    }
+
+    fn compile_statement(&mut self, statement: &Statement) -> Result<()> {
+        match statement {
+            Statement::Assign { name, value: Literal::Text(text) } if contains_call(text) => {
+                // Host-registered functions and file builtins are only known to the interpreter
+                self.fallback(format!("{} <> {}", name, text));
+            }
+            Statement::Assign { name, .. } if self.presets.contains(name) => {
+                self.slot(name);
+            }
+            Statement::Assign { name, value } => {
+                let value = VariableValue::from(value.clone());
+                let slot = self.slot(name);
+                self.emit(Instruction::Assign { slot, value });
+            }
+            Statement::Calc { name, params } => {
+                if params.len() < 2 {
+                    // Let the interpreter report the error
+                    self.fallback(format!("{} <> calc({})", name, params.join(", ")));
+                    return Ok(());
+                }
+                let operands = params.iter().map(|p| self.operand(p)).collect();
+                let slot = self.slot(name);
+                self.emit(Instruction::Calc { slot, operands });
+            }
+            Statement::RandomChoice { name, choices } => {
+                if choices.is_empty() {
+                    return Ok(());
+                }
+                let choices = choices.iter().map(|c| self.operand(c)).collect();
+                let slot = self.slot(name);
+                self.emit(Instruction::RandomChoice { slot, choices });
+            }
+            Statement::Speak { template } => {
+                let mut segments = Vec::new();
+                for part in split_template(template) {
+                    match part {
+                        TemplatePart::Text(text) => segments.push(Segment::Text(text)),
+                        TemplatePart::Variable(name) => segments.push(Segment::Slot { slot: self.slot(&name), name }),
+                        TemplatePart::Expression { .. } => {
+                            // Expressions, format specs and host function calls are evaluated by the interpreter
+                            self.fallback(format!("speak(\"{}\")", lexer::escape(template)));
+                            return Ok(());
+                        }
+                    }
+                }
+                self.chunk.templates.push(segments);
+                self.emit(Instruction::Speak(self.chunk.templates.len() - 1));
+            }
+            Statement::Selection { branches } => {
+                let mut end_jumps = Vec::new();
+                for branch in branches {
+                    let condition = self.expression(&branch.condition)?;
+                    let test = self.emit(Instruction::BranchUnless { condition, target: 0 });
+                    self.compile_block(&branch.body)?;
+                    end_jumps.push(self.emit(Instruction::Jump(0)));
+                    let next = self.here();
+                    self.patch(test, next);
+                }
+                self.emit(Instruction::NoBranchMatched);
+                let end = self.here();
+                for jump in end_jumps {
+                    self.patch(jump, end);
+                }
+            }
+            Statement::CountLoop { label, count, body } => {
+                let count = self.expression(count)?;
+                let counter = self.hidden_slot();
+                let start = self.emit(Instruction::CountStart { count, counter, exit: 0 });
+                let head = self.emit(Instruction::CountNext { counter, exit: 0 });
+                self.compile_loop_body(label, head, body)?;
+                let exit = self.here();
+                self.patch(start, exit);
+                self.patch(head, exit);
+                self.patch_breaks(exit);
+            }
+            Statement::RangeLoop { label, start, end, variable, body } => {
+                let start = self.expression(start)?;
+                let end = self.expression(end)?;
+                let index = self.hidden_slot();
+                let limit = self.hidden_slot();
+                let variable = self.slot(variable);
+                self.emit(Instruction::RangeStart { start, end, index, limit });
+                let head = self.emit(Instruction::RangeNext { variable, index, limit, exit: 0 });
+                self.compile_loop_body(label, head, body)?;
+                let exit = self.here();
+                self.patch(head, exit);
+                self.patch_breaks(exit);
+            }
+            Statement::WhileLoop { label, condition, body } => {
+                let condition = self.expression(condition)?;
+                let counter = self.hidden_slot();
+                self.emit(Instruction::WhileStart { counter });
+                let head = self.emit(Instruction::WhileNext { condition, counter, exit: 0 });
+                self.compile_loop_body(label, head, body)?;
+                let exit = self.here();
+                self.patch(head, exit);
+                self.patch_breaks(exit);
+            }
+            Statement::Break { label } => {
+                // Outside a loop the interpreter ignores it, so do we
+                if let Some(index) = self.target_loop(label.as_deref())? {
+                    let jump = self.emit(Instruction::Jump(0));
+                    self.loops[index].breaks.push(jump);
+                }
+            }
+            Statement::Continue { label } => {
+                if let Some(index) = self.target_loop(label.as_deref())? {
+                    let target = self.loops[index].continue_target;
+                    self.emit(Instruction::Jump(target));
+                }
+            }
+            Statement::SolveTarget { name, target, inputs, options } => {
+                self.slot(name);
+                let options = match options.to_string() {
+                    options if options.is_empty() => options,
+                    options => format!(", {}", options),
+                };
+                self.fallback(format!("{}([{}]) <> randomChoice([{}]{})", name, target, inputs, options));
+            }
+            Statement::UserInput { name, prompt } => {
+                self.slot(name);
+                self.fallback(format!("{} <> userIn(\"{}\")", name, lexer::escape(prompt)));
+            }
+            Statement::FunctionCall { name, function } => {
+                self.slot(name);
+                self.fallback(format!("{} <> {}()", name, function));
+            }
+            Statement::Woof { name } => {
+                self.fallback(format!("woof {}", name));
+            }
+            Statement::Exit { code } => {
+                self.fallback(format!("exit({})", code));
+            }
+            Statement::Match { arms, source, .. } => {
+                // Patterns, bindings and guards are evaluated by the interpreter, which
+                // cannot jump out of a VM loop
+                if !self.loops.is_empty() && arms.iter().any(|arm| leaves_loop(&arm.body)) {
+                    return Err(anyhow::anyhow!("break or continue inside a match arm is not supported by the VM"));
+                }
+                self.fallback(source.clone());
+            }
+            Statement::Parallel { source, .. } | Statement::ParallelRangeLoop { source, .. } => {
+                // Frames and merging live in the interpreter
+                self.fallback(source.clone());
+            }
+            Statement::AllChoices { source, .. } | Statement::Unsupported { source } => {
+                self.fallback(source.clone());
+            }
+        }
+        Ok(())
+    }
+
+    fn compile_loop_body(&mut self, label: &Option<String>, head: usize, body: &[Statement]) -> Result<()> {
+        self.loops.push(LoopFrame { label: label.clone(), continue_target: head, breaks: Vec::new() });
+        self.compile_block(body)?;
+        self.emit(Instruction::Jump(head));
+        Ok(())
+    }
+
+    /// Index in `loops` of the loop a `break` / `continue` leaves; the innermost without a label
+    fn target_loop(&self, label: Option<&str>) -> Result<Option<usize>> {
+        match label {
+            None => Ok(self.loops.len().checked_sub(1)),
+            Some(label) => self.loops.iter()
+                .rposition(|frame| frame.label.as_deref() == Some(label))
+                .map(Some)
+                .ok_or_else(|| anyhow::anyhow!("Unknown loop label '{}'", label)),
+        }
+    }
+
+    fn patch_breaks(&mut self, exit: usize) {
+        if let Some(frame) = self.loops.pop() {
+            for jump in frame.breaks {
+                self.patch(jump, exit);
+            }
+        }
+    }
+
+    fn emit(&mut self, instruction: Instruction) -> usize {
+        self.chunk.code.push(instruction);
+        self.chunk.code.len() - 1
+    }
+
+    fn here(&self) -> usize {
+        self.chunk.code.len()
+    }
+
+    /// Point the jump or exit of the instruction at `index` to `target`
+    fn patch(&mut self, index: usize, target: usize) {
+        match &mut self.chunk.code[index] {
+            Instruction::Jump(to) => *to = target,
+            Instruction::BranchUnless { target: to, .. } => *to = target,
+            Instruction::CountStart { exit, .. }
+            | Instruction::CountNext { exit, .. }
+            | Instruction::RangeNext { exit, .. }
+            | Instruction::WhileNext { exit, .. } => *exit = target,
+            other => unreachable!("cannot patch {:?}", other),
+        }
+    }
+
+    fn fallback(&mut self, source: String) {
+        self.chunk.fallbacks.push(source);
+        self.emit(Instruction::Fallback(self.chunk.fallbacks.len() - 1));
+    }
+
+    fn slot(&mut self, name: &str) -> usize {
+        if let Some(&slot) = self.slots.get(name) {
+            return slot;
+        }
+        self.chunk.slot_names.push(Some(name.to_string()));
+        let slot = self.chunk.slot_names.len() - 1;
+        self.slots.insert(name.to_string(), slot);
+        slot
+    }
+
+    fn hidden_slot(&mut self) -> usize {
+        self.chunk.slot_names.push(None);
+        self.chunk.slot_names.len() - 1
+    }
+
+    fn operand(&mut self, text: &str) -> Operand {
+        if let Ok(num) = text.parse::<f64>() {
+            Operand::Value(VariableValue::Number(num))
+        } else if is_identifier(text) {
+            Operand::Slot { slot: self.slot(text), name: text.to_string() }
+        } else {
+            Operand::Value(VariableValue::String(lexer::text_literal(text)))
+        }
+    }
+
+    fn expression(&mut self, source: &str) -> Result<usize> {
+        let ops = compile_expression(source, &mut |name| self.slot(name))?;
+        self.chunk.expressions.push(Expression { source: source.to_string(), ops });
+        Ok(self.chunk.expressions.len() - 1)
+    }
+}
+
+/// Whether `statements` contain a `break` or `continue` aimed at an enclosing loop
+fn leaves_loop(statements: &[Statement]) -> bool {
+    statements.iter().any(|statement| match statement {
+        Statement::Break { .. } | Statement::Continue { .. } => true,
+        Statement::Selection { branches } => branches.iter().any(|branch| leaves_loop(&branch.body)),
+        Statement::Match { arms, .. } => arms.iter().any(|arm| leaves_loop(&arm.body)),
+        Statement::Parallel { body, .. } => leaves_loop(body),
+        _ => false,
+    })
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    matches!(chars.next(), Some(c) if c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Text(String),
+    Ident(String),
+    Symbol(&'static str),
+    Open,
+    Close,
+}
+
+const SYMBOLS: &[&str] = &[
+    "&&", "||", "==", "!=", "<=", ">=", "<", ">", "+", "-", "*", "/", "%", "^", "!",
+];
+
+fn tokenize(source: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    'outer: while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() || (c == '.' && chars.get(i + 1).is_some_and(|n| n.is_ascii_digit())) {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            let literal: String = chars[start..i].iter().collect();
+            tokens.push(Token::Number(literal.parse()?));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else if c == '"' {
+            let mut text = String::new();
+            i += 1;
+            while i < chars.len() && chars[i] != '"' {
+                if chars[i] == '\\' && i + 1 < chars.len() {
+                    i += 1;
+                }
+                text.push(chars[i]);
+                i += 1;
+            }
+            if i >= chars.len() {
+                return Err(anyhow::anyhow!("Unterminated string in '{}'", source));
+            }
+            i += 1;
+            tokens.push(Token::Text(text));
+        } else if c == '(' {
+            tokens.push(Token::Open);
+            i += 1;
+        } else if c == ')' {
+            tokens.push(Token::Close);
+            i += 1;
+        } else {
+            for symbol in SYMBOLS {
+                let len = symbol.len();
+                if i + len <= chars.len() && chars[i..i + len].iter().collect::<String>() == *symbol {
+                    tokens.push(Token::Symbol(symbol));
+                    i += len;
+                    continue 'outer;
+                }
+            }
+            return Err(anyhow::anyhow!("Unexpected character '{}' in '{}'", c, source));
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn binary_op(symbol: &str) -> Option<(u8, Op)> {
+    Some(match symbol {
+        "||" => (1, Op::Or),
+        "&&" => (2, Op::And),
+        "==" => (3, Op::Eq),
+        "!=" => (3, Op::Ne),
+        "<" => (3, Op::Lt),
+        "<=" => (3, Op::Le),
+        ">" => (3, Op::Gt),
+        ">=" => (3, Op::Ge),
+        "+" => (4, Op::Add),
+        "-" => (4, Op::Sub),
+        "*" => (5, Op::Mul),
+        "/" => (5, Op::Div),
+        "%" => (5, Op::Rem),
+        "^" => (7, Op::Pow),
+        _ => return None,
+    })
+}
+
+/// Precedence of unary `-` and `!`: tighter than `*`, looser than `^`
+const UNARY_PRECEDENCE: u8 = 6;
+
+/// Whether `text` calls something (`name(...)`), e.g. a host-registered function
+fn contains_call(text: &str) -> bool {
+    let chars: Vec<char> = text.chars().collect();
+    chars.windows(2).any(|pair| (pair[0].is_alphanumeric() || pair[0] == '_') && pair[1] == '(')
+}
+
+/// Compile an evalexpr-style expression into postfix ops
+///
+/// `slot_for` maps a variable name to its slot. Function calls are not
+/// supported and are reported as an error so the caller can fall back to the
+/// interpreter.
+pub fn compile_expression(source: &str, slot_for: &mut dyn FnMut(&str) -> usize) -> Result<Vec<Op>> {
+    let tokens = tokenize(source)?;
+    let mut ops = Vec::new();
+    let mut position = 0;
+    parse_binary(&tokens, &mut position, 0, &mut ops, slot_for, source)?;
+    if position != tokens.len() {
+        return Err(anyhow::anyhow!("Unexpected {:?} in '{}'", tokens[position], source));
+    }
+    Ok(ops)
+}
+
+fn parse_binary(
+    tokens: &[Token],
+    position: &mut usize,
+    min_precedence: u8,
+    ops: &mut Vec<Op>,
+    slot_for: &mut dyn FnMut(&str) -> usize,
+    source: &str,
+) -> Result<()> {
+    parse_unary(tokens, position, ops, slot_for, source)?;
+
+    while let Some(Token::Symbol(symbol)) = tokens.get(*position) {
+        let Some((precedence, op)) = binary_op(symbol) else { break };
+        if precedence < min_precedence {
+            break;
+        }
+        *position += 1;
+        // `^` is right-associative, everything else left-associative
+        let next = if op == Op::Pow { precedence } else { precedence + 1 };
+        parse_binary(tokens, position, next, ops, slot_for, source)?;
+        ops.push(op);
+    }
+
+    Ok(())
+}
+
+fn parse_unary(
+    tokens: &[Token],
+    position: &mut usize,
+    ops: &mut Vec<Op>,
+    slot_for: &mut dyn FnMut(&str) -> usize,
+    source: &str,
+) -> Result<()> {
+    match tokens.get(*position) {
+        Some(Token::Symbol("-")) => {
+            *position += 1;
+            parse_binary(tokens, position, UNARY_PRECEDENCE, ops, slot_for, source)?;
+            ops.push(Op::Neg);
+        }
+        Some(Token::Symbol("!")) => {
+            *position += 1;
+            parse_binary(tokens, position, UNARY_PRECEDENCE, ops, slot_for, source)?;
+            ops.push(Op::Not);
+        }
+        Some(Token::Number(n)) => {
+            ops.push(Op::Number(*n));
+            *position += 1;
+        }
+        Some(Token::Text(text)) => {
+            ops.push(Op::Text(text.clone()));
+            *position += 1;
+        }
+        Some(Token::Ident(name)) => {
+            *position += 1;
+            if tokens.get(*position) == Some(&Token::Open) {
+                return Err(anyhow::anyhow!("Function call '{}' in '{}' is not supported by the VM", name, source));
+            }
+            match name.as_str() {
+                "true" => ops.push(Op::Bool(true)),
+                "false" => ops.push(Op::Bool(false)),
+                _ => ops.push(Op::Load(slot_for(name))),
+            }
+        }
+        Some(Token::Open) => {
+            *position += 1;
+            parse_binary(tokens, position, 0, ops, slot_for, source)?;
+            if tokens.get(*position) != Some(&Token::Close) {
+                return Err(anyhow::anyhow!("Missing ')' in '{}'", source));
+            }
+            *position += 1;
+        }
+        other => {
+            return Err(anyhow::anyhow!("Unexpected {:?} in '{}'", other, source));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_compile_expression_precedence() {
+        let mut names = Vec::new();
+        let ops = compile_expression("x > 1 + 2 * 3 && !done", &mut |name| {
+            names.push(name.to_string());
+            names.len() - 1
+        }).unwrap();
+
+        assert_eq!(ops, vec![
+            Op::Load(0), Op::Number(1.0), Op::Number(2.0), Op::Number(3.0), Op::Mul, Op::Add, Op::Gt,
+            Op::Load(1), Op::Not, Op::And,
+        ]);
+        assert!(compile_expression("sqrt(x) > 1", &mut |_| 0).is_err());
+    }
+
+    #[test]
+    fn test_break_jumps_past_loop() {
+        let source = "* <main> Loops {\n ^ observe_execution {\n loop <> count(3) {\n break\n speak(\"never\")\n }\n speak(\"after\")\n }\n}";
+        let program = Parser::new().unwrap().parse_program(source).unwrap();
+        let chunk = Compiler::new().compile(&program).unwrap();
+
+        let exit = match chunk.code[1] {
+            Instruction::CountNext { exit, .. } => exit,
+            ref other => panic!("unexpected {:?}", other),
+        };
+        assert!(matches!(chunk.code[2], Instruction::Jump(target) if target == exit));
+        assert!(matches!(chunk.code[exit], Instruction::Speak(1)));
+    }
+}
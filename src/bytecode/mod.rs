@@ -0,0 +1,105 @@
+// Bytecode compiler and stack VM
+// Compiles a parsed program once so loops run without re-parsing strings
+
+pub mod compiler;
+pub mod vm;
+
+pub use compiler::{compile_expression, Compiler};
+pub use vm::{Vm, VmHost};
+
+use crate::VariableValue;
+
+/// Expression opcodes, evaluated in postfix order on the VM stack
+#[derive(Debug, Clone, PartialEq)]
+pub enum Op {
+    Number(f64),
+    Bool(bool),
+    Text(String),
+    Load(usize),
+    Neg,
+    Not,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Pow,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+}
+
+/// A compiled condition or numeric expression
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expression {
+    /// Original text, used in diagnostics
+    pub source: String,
+    pub ops: Vec<Op>,
+}
+
+/// Operand of `calc(...)` and `randomChoice([...])`, resolved at run time
+#[derive(Debug, Clone)]
+pub enum Operand {
+    Value(VariableValue),
+    /// A variable slot; `name` is used when the slot is still undefined
+    Slot { slot: usize, name: String },
+}
+
+/// Piece of a `speak` template
+#[derive(Debug, Clone, PartialEq)]
+pub enum Segment {
+    Text(String),
+    Slot { slot: usize, name: String },
+}
+
+/// Statement-level instructions
+///
+/// Jump targets are instruction indices; expressions, templates and fallback
+/// statements are stored once in the chunk and referenced by index.
+#[derive(Debug, Clone)]
+pub enum Instruction {
+    Assign { slot: usize, value: VariableValue },
+    Calc { slot: usize, operands: Vec<Operand> },
+    RandomChoice { slot: usize, choices: Vec<Operand> },
+    Speak(usize),
+    /// Evaluate a condition; jump to `target` if it is false
+    BranchUnless { condition: usize, target: usize },
+    Jump(usize),
+    /// Warn that no selection branch matched
+    NoBranchMatched,
+    /// Resolve the iteration count into `counter`, or warn and jump to `exit`
+    CountStart { count: usize, counter: usize, exit: usize },
+    CountNext { counter: usize, exit: usize },
+    RangeStart { start: usize, end: usize, index: usize, limit: usize },
+    RangeNext { variable: usize, index: usize, limit: usize, exit: usize },
+    /// Zero the pass counter of a `while` loop
+    WhileStart { counter: usize },
+    /// Evaluate the condition and jump to `exit` once it is false; otherwise
+    /// count the pass against the host's `while` limit
+    WhileNext { condition: usize, counter: usize, exit: usize },
+    /// Hand a statement the VM has no opcode for back to the interpreter
+    Fallback(usize),
+}
+
+/// A compiled program
+#[derive(Debug, Clone, Default)]
+pub struct Chunk {
+    pub class_name: String,
+    pub code: Vec<Instruction>,
+    pub expressions: Vec<Expression>,
+    pub templates: Vec<Vec<Segment>>,
+    pub fallbacks: Vec<String>,
+    /// Variable name for each slot; `None` for loop bookkeeping slots
+    pub slot_names: Vec<Option<String>>,
+}
+
+impl Chunk {
+    pub fn slot_count(&self) -> usize {
+        self.slot_names.len()
+    }
+}
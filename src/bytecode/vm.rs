@@ -0,0 +1,514 @@
+use anyhow::Result;
+use rand::Rng;
+
+use super::{Chunk, Instruction, Op, Operand, Segment};
+use crate::output_sink::SharedSink;
+use crate::VariableValue;
+
+/// What the VM needs from the engine running it
+///
+/// Variables live in VM slots while the chunk runs. They are loaded from the
+/// host at the start, written back before and re-read after every
+/// `Fallback`, and written back when the run finishes.
+pub trait VmHost {
+    fn output(&self) -> SharedSink;
+
+    fn load_variable(&self, name: &str) -> Option<VariableValue>;
+
+    fn store_variable(&mut self, name: &str, value: VariableValue) -> Result<()>;
+
+    /// Run a single statement with the tree-walking interpreter
+    fn execute_statement(&mut self, statement: &str, class_name: &str) -> Result<()>;
+
+    /// Pick an index below `len` for `randomChoice`, using the host's generator
+    fn random_index(&mut self, len: usize) -> usize {
+        rand::thread_rng().gen_range(0..len)
+    }
+
+    /// Account for one executed instruction against the host's resource limits and cancellation
+    fn step(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Check a `randomChoice` list against the host's size limit
+    fn check_list_len(&self, _len: usize) -> Result<()> {
+        Ok(())
+    }
+
+    /// Check the pass a `while` loop is about to make against the host's cap
+    fn check_while_iterations(&self, _iterations: u64) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Stack VM for a compiled `Chunk`
+pub struct Vm<'a> {
+    chunk: &'a Chunk,
+    slots: Vec<Option<VariableValue>>,
+    dirty: Vec<bool>,
+    stack: Vec<VariableValue>,
+}
+
+impl<'a> Vm<'a> {
+    pub fn new(chunk: &'a Chunk) -> Self {
+        Self {
+            chunk,
+            slots: vec![None; chunk.slot_count()],
+            dirty: vec![false; chunk.slot_count()],
+            stack: Vec::with_capacity(16),
+        }
+    }
+
+    pub fn run(&mut self, host: &mut dyn VmHost) -> Result<()> {
+        let output = host.output();
+        self.load_slots(host);
+
+        let chunk = self.chunk;
+        let code = &chunk.code;
+        let mut pc = 0;
+
+        while pc < code.len() {
+            // Fallbacks are accounted for by the interpreter itself
+            if !matches!(code[pc], Instruction::Jump(_) | Instruction::Fallback(_)) {
+                host.step()?;
+            }
+
+            match &code[pc] {
+                Instruction::Assign { slot, value } => {
+                    self.set(*slot, value.clone());
+                }
+                Instruction::Calc { slot, operands } => {
+                    if let Some(sum) = self.calc(operands, &output) {
+                        self.set(*slot, VariableValue::Number(sum));
+                    }
+                }
+                Instruction::RandomChoice { slot, choices } => {
+                    host.check_list_len(choices.len())?;
+                    let index = host.random_index(choices.len());
+                    let chosen = match &choices[index] {
+                        Operand::Value(value) => value.clone(),
+                        Operand::Slot { slot, name } => match &self.slots[*slot] {
+                            Some(value) => value.clone(),
+                            None => VariableValue::String(name.trim_matches('"').to_string()),
+                        },
+                    };
+                    self.set(*slot, chosen);
+                }
+                Instruction::Speak(template) => {
+                    let line = self.render(&chunk.templates[*template], &output);
+                    output.program(&line);
+                }
+                Instruction::BranchUnless { condition, target } => {
+                    if !self.condition(*condition, &output) {
+                        pc = *target;
+                        continue;
+                    }
+                }
+                Instruction::Jump(target) => {
+                    pc = *target;
+                    continue;
+                }
+                Instruction::NoBranchMatched => {
+                    output.warn("!! Warning: No condition matched (else should be true)");
+                }
+                Instruction::CountStart { count, counter, exit } => {
+                    let expression = &chunk.expressions[*count];
+                    match self.evaluate(*count) {
+                        Ok(VariableValue::Number(n)) if n >= 0.0 && n.fract() == 0.0 => {
+                            self.slots[*counter] = Some(VariableValue::Number(n));
+                        }
+                        Ok(VariableValue::Number(n)) => {
+                            output.warn(&format!("!! Count must be a non-negative integer, got {}", n));
+                            pc = *exit;
+                            continue;
+                        }
+                        Ok(_) => {
+                            output.warn(&format!("!! Count variable '{}' is not numeric", expression.source));
+                            pc = *exit;
+                            continue;
+                        }
+                        Err(e) => {
+                            output.warn(&format!("!! Could not resolve count expression '{}': {}", expression.source, e));
+                            pc = *exit;
+                            continue;
+                        }
+                    }
+                }
+                Instruction::CountNext { counter, exit } => {
+                    let remaining = self.number(*counter);
+                    if remaining <= 0.0 {
+                        pc = *exit;
+                        continue;
+                    }
+                    self.slots[*counter] = Some(VariableValue::Number(remaining - 1.0));
+                }
+                Instruction::RangeStart { start, end, index, limit } => {
+                    let start = self.range_bound(*start)?;
+                    let end = self.range_bound(*end)?;
+                    self.slots[*index] = Some(VariableValue::Number(start));
+                    self.slots[*limit] = Some(VariableValue::Number(end));
+                }
+                Instruction::RangeNext { variable, index, limit, exit } => {
+                    let current = self.number(*index);
+                    if current >= self.number(*limit) {
+                        pc = *exit;
+                        continue;
+                    }
+                    self.set(*variable, VariableValue::Number(current));
+                    self.slots[*index] = Some(VariableValue::Number(current + 1.0));
+                }
+                Instruction::WhileStart { counter } => {
+                    self.slots[*counter] = Some(VariableValue::Number(0.0));
+                }
+                Instruction::WhileNext { condition, counter, exit } => {
+                    if !self.condition(*condition, &output) {
+                        pc = *exit;
+                        continue;
+                    }
+                    let iterations = self.number(*counter) + 1.0;
+                    host.check_while_iterations(iterations as u64)?;
+                    self.slots[*counter] = Some(VariableValue::Number(iterations));
+                }
+                Instruction::Fallback(index) => {
+                    self.flush_slots(host)?;
+                    host.execute_statement(&chunk.fallbacks[*index], &chunk.class_name)?;
+                    self.load_slots(host);
+                }
+            }
+            pc += 1;
+        }
+
+        self.flush_slots(host)
+    }
+
+    /// Current value of a variable slot
+    pub fn variable(&self, name: &str) -> Option<&VariableValue> {
+        let slot = self.chunk.slot_names.iter().position(|n| n.as_deref() == Some(name))?;
+        self.slots[slot].as_ref()
+    }
+
+    fn set(&mut self, slot: usize, value: VariableValue) {
+        self.slots[slot] = Some(value);
+        self.dirty[slot] = true;
+    }
+
+    fn number(&self, slot: usize) -> f64 {
+        match &self.slots[slot] {
+            Some(VariableValue::Number(n)) => *n,
+            _ => 0.0,
+        }
+    }
+
+    fn load_slots(&mut self, host: &dyn VmHost) {
+        for (slot, name) in self.chunk.slot_names.iter().enumerate() {
+            if let Some(name) = name {
+                self.slots[slot] = host.load_variable(name);
+                self.dirty[slot] = false;
+            }
+        }
+    }
+
+    fn flush_slots(&mut self, host: &mut dyn VmHost) -> Result<()> {
+        for (slot, name) in self.chunk.slot_names.iter().enumerate() {
+            if let (Some(name), Some(value), true) = (name, &self.slots[slot], self.dirty[slot]) {
+                host.store_variable(name, value.clone())?;
+                self.dirty[slot] = false;
+            }
+        }
+        Ok(())
+    }
+
+    fn calc(&self, operands: &[Operand], output: &SharedSink) -> Option<f64> {
+        let mut sum = 0.0;
+        for operand in operands {
+            match operand {
+                Operand::Value(VariableValue::Number(n)) => sum += n,
+                Operand::Slot { slot, name } => match &self.slots[*slot] {
+                    Some(VariableValue::Number(n)) => sum += n,
+                    Some(_) => {
+                        output.warn(&format!("!! Variable '{}' is not numeric", name));
+                        return None;
+                    }
+                    None => {
+                        output.warn(&format!("!! Could not resolve parameter: {}", name));
+                        return None;
+                    }
+                },
+                Operand::Value(other) => {
+                    output.warn(&format!("!! Could not resolve parameter: {:?}", other));
+                    return None;
+                }
+            }
+        }
+        Some(sum)
+    }
+
+    fn render(&self, segments: &[Segment], output: &SharedSink) -> String {
+        let mut line = String::new();
+        for segment in segments {
+            match segment {
+                Segment::Text(text) => line.push_str(text),
+                Segment::Slot { slot, name } => match &self.slots[*slot] {
+                    Some(VariableValue::Number(n)) => line.push_str(&n.to_string()),
+                    Some(VariableValue::String(s)) => line.push_str(s),
+                    Some(VariableValue::Boolean(b)) => line.push_str(&b.to_string()),
+                    Some(VariableValue::FunctionResult(f)) => line.push_str(&format!("[Function: {}]", f)),
+                    Some(value) => line.push_str(&crate::display_value(value)),
+                    // The warning is the diagnostic; the line gets nothing
+                    None => output.warn(&format!("!! Undefined variable '{}' in speak", name)),
+                },
+            }
+        }
+        line
+    }
+
+    /// Evaluate a condition; errors count as false, like `ConditionEvaluator`
+    fn condition(&mut self, expression: usize, output: &SharedSink) -> bool {
+        let result = match self.evaluate(expression) {
+            Ok(VariableValue::Boolean(b)) => return b,
+            Ok(other) => format!("Expected a boolean, got {:?}", other),
+            Err(e) => e.to_string(),
+        };
+        output.warn(&format!(
+            "!! Error evaluating condition '{}': {}", self.chunk.expressions[expression].source, result
+        ));
+        output.debug("   Defaulting to false");
+        false
+    }
+
+    fn range_bound(&mut self, expression: usize) -> Result<f64> {
+        match self.evaluate(expression) {
+            Ok(VariableValue::Number(n)) => Ok((n as i32) as f64),
+            Ok(_) => Err(anyhow::anyhow!(
+                "Variable '{}' is not numeric", self.chunk.expressions[expression].source
+            )),
+            Err(e) => Err(anyhow::anyhow!(
+                "Could not evaluate '{}': {}", self.chunk.expressions[expression].source, e
+            )),
+        }
+    }
+
+    fn evaluate(&mut self, expression: usize) -> Result<VariableValue> {
+        self.stack.clear();
+
+        for op in &self.chunk.expressions[expression].ops {
+            let value = match op {
+                Op::Number(n) => VariableValue::Number(*n),
+                Op::Bool(b) => VariableValue::Boolean(*b),
+                Op::Text(s) => VariableValue::String(s.clone()),
+                Op::Load(slot) => match &self.slots[*slot] {
+                    Some(value) => value.clone(),
+                    None => {
+                        let name = self.chunk.slot_names[*slot].as_deref().unwrap_or("?");
+                        return Err(anyhow::anyhow!("Variable '{}' is not defined", name));
+                    }
+                },
+                Op::Neg => match self.stack.pop() {
+                    Some(VariableValue::Number(n)) => VariableValue::Number(-n),
+                    other => return Err(anyhow::anyhow!("Cannot negate {:?}", other)),
+                },
+                Op::Not => match self.stack.pop() {
+                    Some(VariableValue::Boolean(b)) => VariableValue::Boolean(!b),
+                    other => return Err(anyhow::anyhow!("Cannot apply '!' to {:?}", other)),
+                },
+                binary => {
+                    let right = self.stack.pop();
+                    let left = self.stack.pop();
+                    match (left, right) {
+                        (Some(left), Some(right)) => binary_op(binary, left, right)?,
+                        _ => return Err(anyhow::anyhow!("Stack underflow")),
+                    }
+                }
+            };
+            self.stack.push(value);
+        }
+
+        self.stack.pop().ok_or_else(|| anyhow::anyhow!("Empty expression"))
+    }
+}
+
+fn binary_op(op: &Op, left: VariableValue, right: VariableValue) -> Result<VariableValue> {
+    use VariableValue::{Boolean, Number, String as Text};
+
+    Ok(match (op, left, right) {
+        (Op::Add, Number(a), Number(b)) => Number(a + b),
+        (Op::Add, Text(a), Text(b)) => Text(a + &b),
+        (Op::Sub, Number(a), Number(b)) => Number(a - b),
+        (Op::Mul, Number(a), Number(b)) => Number(a * b),
+        (Op::Div, Number(a), Number(b)) => Number(a / b),
+        (Op::Rem, Number(a), Number(b)) => Number(a % b),
+        (Op::Pow, Number(a), Number(b)) => Number(a.powf(b)),
+        (Op::Lt, Number(a), Number(b)) => Boolean(a < b),
+        (Op::Le, Number(a), Number(b)) => Boolean(a <= b),
+        (Op::Gt, Number(a), Number(b)) => Boolean(a > b),
+        (Op::Ge, Number(a), Number(b)) => Boolean(a >= b),
+        (Op::Lt, Text(a), Text(b)) => Boolean(a < b),
+        (Op::Le, Text(a), Text(b)) => Boolean(a <= b),
+        (Op::Gt, Text(a), Text(b)) => Boolean(a > b),
+        (Op::Ge, Text(a), Text(b)) => Boolean(a >= b),
+        (Op::And, Boolean(a), Boolean(b)) => Boolean(a && b),
+        (Op::Or, Boolean(a), Boolean(b)) => Boolean(a || b),
+        (Op::Eq, a, b) => Boolean(values_equal(&a, &b)),
+        (Op::Ne, a, b) => Boolean(!values_equal(&a, &b)),
+        (op, a, b) => return Err(anyhow::anyhow!("Cannot apply {:?} to {:?} and {:?}", op, a, b)),
+    })
+}
+
+fn values_equal(a: &VariableValue, b: &VariableValue) -> bool {
+    match (a, b) {
+        (VariableValue::Number(a), VariableValue::Number(b)) => a == b,
+        (VariableValue::Integer(a), VariableValue::Integer(b)) => a == b,
+        (VariableValue::String(a), VariableValue::String(b)) => a == b,
+        (VariableValue::Boolean(a), VariableValue::Boolean(b)) => a == b,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytecode::Compiler;
+    use crate::limits::{Budget, LimitExceeded, Limits, DEFAULT_MAX_WHILE_ITERATIONS};
+    use crate::output_sink::BufferedSink;
+    use crate::parser::Parser;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    struct TestHost {
+        sink: Arc<BufferedSink>,
+        variables: HashMap<String, VariableValue>,
+        fallbacks: Vec<String>,
+        steps_left: u64,
+        budget: Budget,
+    }
+
+    impl VmHost for TestHost {
+        fn output(&self) -> SharedSink {
+            self.sink.clone()
+        }
+
+        fn load_variable(&self, name: &str) -> Option<VariableValue> {
+            self.variables.get(name).cloned()
+        }
+
+        fn store_variable(&mut self, name: &str, value: VariableValue) -> Result<()> {
+            self.variables.insert(name.to_string(), value);
+            Ok(())
+        }
+
+        fn execute_statement(&mut self, statement: &str, _class_name: &str) -> Result<()> {
+            self.fallbacks.push(statement.to_string());
+            Ok(())
+        }
+
+        fn step(&mut self) -> Result<()> {
+            self.steps_left = self.steps_left.checked_sub(1).ok_or_else(|| anyhow::anyhow!("out of steps"))?;
+            Ok(())
+        }
+
+        fn check_while_iterations(&self, iterations: u64) -> Result<()> {
+            self.budget.check_while_iterations(iterations)?;
+            Ok(())
+        }
+    }
+
+    fn run_with(source: &str, steps: u64, limits: Limits) -> (TestHost, Result<()>) {
+        let program = Parser::new().unwrap().parse_program(source).unwrap();
+        let chunk = Compiler::new().compile(&program).unwrap();
+        let mut host = TestHost {
+            sink: Arc::new(BufferedSink::new()),
+            variables: HashMap::new(),
+            fallbacks: Vec::new(),
+            steps_left: steps,
+            budget: Budget::new(limits),
+        };
+        let result = Vm::new(&chunk).run(&mut host);
+        (host, result)
+    }
+
+    fn run(source: &str) -> TestHost {
+        let (host, result) = run_with(source, u64::MAX, Limits::default());
+        result.unwrap();
+        host
+    }
+
+    #[test]
+    fn test_loops_and_selection() {
+        let host = run(r#"
+* <main> Loops {
+    ^ observe_execution {
+        n <> 0
+        total <> 0
+        loop <> while(n < 5) {
+            n <> calc(n, 1)
+            if <> (n == 3) <else> (true) {
+                continue
+                <>
+                total <> calc(total, n)
+            }
+        }
+        loop <> range(0, 10) as i {
+            if <> (i >= 2) <else> (true) {
+                break
+                <>
+                speak("i = ~i~")
+            }
+        }
+        speak("total = ~total~ ~missing~")
+        woof total
+    }
+}
+"#);
+
+        assert_eq!(host.sink.program_lines(), vec!["i = 0", "i = 1", "total = 12 "]);
+        assert_eq!(host.fallbacks, vec!["woof total"]);
+        assert!(matches!(host.variables.get("total"), Some(VariableValue::Number(n)) if *n == 12.0));
+    }
+
+    #[test]
+    fn test_while_loops_run_until_the_host_stops_them() {
+        let spin = r#"
+* <main> Spin {
+    ^ observe_execution {
+        n <> 0
+        loop <> while(true) {
+            n <> calc(n, 1)
+        }
+    }
+}
+"#;
+        let (host, result) = run_with(spin, 100000, Limits::unlimited());
+        assert_eq!(result.unwrap_err().to_string(), "out of steps");
+        assert!(host.sink.lines().is_empty());
+
+        let (_, result) = run_with(spin, u64::MAX, Limits::default());
+        assert_eq!(
+            result.unwrap_err().downcast_ref::<LimitExceeded>(),
+            Some(&LimitExceeded::WhileIterations { limit: DEFAULT_MAX_WHILE_ITERATIONS })
+        );
+
+        // Twice the default cap, which this host lifts
+        let (host, result) = run_with(r#"
+* <main> Caps {
+    ^ observe_execution {
+        n <> 0
+        loop <> while(n < 20000) {
+            n <> calc(n, 1)
+        }
+        loop <> while(undefinedThing > 1) {
+            n <> calc(n, 1)
+        }
+        speak("n = ~n~")
+    }
+}
+"#, u64::MAX, Limits::unlimited());
+        result.unwrap();
+        assert_eq!(host.sink.program_lines(), vec!["n = 20000"]);
+        let warnings: Vec<String> = host.sink.lines().into_iter()
+            .filter(|line| line.level == Some(crate::output_sink::DiagnosticLevel::Warn))
+            .map(|line| line.message)
+            .collect();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("undefinedThing"));
+    }
+}
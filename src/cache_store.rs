@@ -0,0 +1,104 @@
+// Where the learning cache lives between runs
+// The CLI and IDE keep it on disk; embedders can keep it in memory
+
+use anyhow::Result;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::memory::BinaryCache;
+use crate::output_sink::SharedSink;
+use crate::QuantumCache;
+
+/// Backing store for the `QuantumCache`
+pub trait CacheStore: Send {
+    /// The cache saved by a previous run, if there is one
+    fn load(&self) -> Result<Option<QuantumCache>>;
+
+    fn save(&mut self, cache: &QuantumCache, output: &SharedSink) -> Result<()>;
+
+    /// Directory the cache is written to, for stores that have one
+    fn directory(&self) -> Option<&Path> {
+        None
+    }
+}
+
+/// `quantum_consciousness_cache.json` plus the binary solution cache in a directory
+pub struct DiskCache {
+    directory: PathBuf,
+}
+
+impl DiskCache {
+    pub fn new(directory: PathBuf) -> Result<Self> {
+        // Ensure cache directory exists; the transpiler reports where it is
+        fs::create_dir_all(&directory)?;
+
+        Ok(Self { directory })
+    }
+
+    fn json_path(&self) -> PathBuf {
+        self.directory.join("quantum_consciousness_cache.json")
+    }
+}
+
+impl CacheStore for DiskCache {
+    fn load(&self) -> Result<Option<QuantumCache>> {
+        let cache_path = self.json_path();
+        if !cache_path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&cache_path)?;
+        Ok(Some(serde_json::from_str(&content)?))
+    }
+
+    fn save(&mut self, cache: &QuantumCache, output: &SharedSink) -> Result<()> {
+        // Save JSON (for backward compatibility)
+        let content = serde_json::to_string_pretty(cache)?;
+        fs::write(self.json_path(), content)?;
+
+        // Also save binary format (Phase 1 memory optimization)
+        // CRITICAL: Save to the cache directory, NOT project directory
+        let binary_cache_path = self.directory.join("quantum_cache.bin");
+        let binary_cache_path_str = binary_cache_path.to_string_lossy().to_string();
+
+        if let Ok(binary_cache) = BinaryCache::from_hashmap_with_path(
+            cache.math_solutions.clone(),
+            &binary_cache_path_str,
+            output
+        ) {
+            match binary_cache.save_to_disk(output) {
+                Ok(_) => output.debug(&format!("** Binary cache saved to: {} ({} solutions)",
+                        binary_cache_path_str, binary_cache.len())),
+                Err(e) => output.warn(&format!("!! Failed to save binary cache: {}", e)),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn directory(&self) -> Option<&Path> {
+        Some(&self.directory)
+    }
+}
+
+/// Keeps the cache for the lifetime of the engine without touching the filesystem
+#[derive(Default, Clone)]
+pub struct InMemoryCache {
+    cache: Option<QuantumCache>,
+}
+
+impl InMemoryCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl CacheStore for InMemoryCache {
+    fn load(&self) -> Result<Option<QuantumCache>> {
+        Ok(self.cache.clone())
+    }
+
+    fn save(&mut self, cache: &QuantumCache, _output: &SharedSink) -> Result<()> {
+        self.cache = Some(cache.clone());
+        Ok(())
+    }
+}
@@ -0,0 +1,233 @@
+// Cooperative cancellation of a running program
+// The host keeps a clone of the token and trips it; the interpreter, VM and solver poll it
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Shared stop flag; clones observe the same state
+///
+/// A tripped token stops every run it is attached to until `reset` is called.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask the run to stop at its next statement, loop iteration or solver candidate
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Clear a previous cancellation so the token can be used for another run
+    pub fn reset(&self) {
+        self.cancelled.store(false, Ordering::Relaxed);
+    }
+
+    pub fn check(&self) -> Result<(), Cancelled> {
+        if self.is_cancelled() {
+            Err(Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Tokens of every run in flight, so one stop request reaches all of them
+///
+/// Each run registers a fresh token before it waits for its turn. A run queued
+/// behind another adds its own token instead of replacing the running one's.
+#[derive(Debug, Default)]
+pub struct RunTokens {
+    live: Mutex<Vec<CancellationToken>>,
+}
+
+impl RunTokens {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A fresh token for one run; it stays reachable until the guard is dropped
+    pub fn register(&self) -> RunToken<'_> {
+        let token = CancellationToken::new();
+        self.live.lock().unwrap().push(token.clone());
+        RunToken { tokens: self, token }
+    }
+
+    /// Cancel every registered run, running or still queued
+    pub fn cancel_all(&self) {
+        for token in self.live.lock().unwrap().iter() {
+            token.cancel();
+        }
+    }
+
+    /// Runs currently registered
+    pub fn len(&self) -> usize {
+        self.live.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// One run's registration in `RunTokens`
+pub struct RunToken<'a> {
+    tokens: &'a RunTokens,
+    token: CancellationToken,
+}
+
+impl RunToken<'_> {
+    pub fn token(&self) -> CancellationToken {
+        self.token.clone()
+    }
+}
+
+impl Drop for RunToken<'_> {
+    fn drop(&mut self) {
+        self.tokens.live.lock().unwrap()
+            .retain(|token| !Arc::ptr_eq(&token.cancelled, &self.token.cancelled));
+    }
+}
+
+/// The error a run stops with when its token is cancelled
+///
+/// Returned inside `anyhow::Error`; use `error.downcast_ref::<Cancelled>()`
+/// to tell a stop request apart from a failure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Execution cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{both_backends, Engine};
+    use std::time::{Duration, Instant};
+
+    const SOLVING: &str = r#"* <main> Solve {
+    ^ observe_execution {
+        result([12]) <> randomChoice([3, 4, 5])
+    }
+}"#;
+
+    #[test]
+    fn test_clones_share_state_until_reset() {
+        let token = CancellationToken::new();
+        let handle = token.clone();
+        assert!(token.check().is_ok());
+
+        handle.cancel();
+        assert_eq!(token.check(), Err(Cancelled));
+
+        token.reset();
+        assert!(!handle.is_cancelled());
+    }
+
+    #[test]
+    fn test_cancelled_is_found_through_anyhow() {
+        let token = CancellationToken::new();
+        token.cancel();
+        let error: anyhow::Error = token.check().unwrap_err().into();
+        let error = error.context("while solving");
+        assert_eq!(error.downcast_ref::<Cancelled>(), Some(&Cancelled));
+        assert_eq!(Cancelled.to_string(), "Execution cancelled");
+    }
+
+    #[test]
+    fn test_cancellation_stops_a_running_program() {
+        let endless = r#"* <main> Forever {
+    ^ observe_execution {
+        loop <> count(4000000000) {
+            x <> 1
+        }
+        speak("done")
+    }
+}"#;
+        for mut engine in both_backends(|builder| builder) {
+            let token = engine.cancellation_token();
+            let canceller = std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(50));
+                token.cancel();
+            });
+
+            let started = Instant::now();
+            let error = engine.run_source(endless).unwrap_err();
+            canceller.join().unwrap();
+            assert_eq!(error.downcast_ref::<Cancelled>(), Some(&Cancelled));
+            assert!(started.elapsed() < Duration::from_secs(5));
+
+            engine.cancellation_token().reset();
+            assert!(engine.run_source(SOLVING).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_cancelled_solve_leaves_the_cache_untouched() {
+        let token = CancellationToken::new();
+        let mut engine = Engine::builder().cancellation(token.clone()).seed(7).build().unwrap();
+        token.cancel();
+        assert!(engine.run_source(SOLVING).is_err());
+
+        token.reset();
+        let outcome = engine.run_source(SOLVING).unwrap();
+        assert_eq!(outcome.solutions.len(), 1);
+    }
+
+    #[test]
+    fn test_stop_reaches_the_running_program_behind_a_queued_one() {
+        let endless = r#"* <main> Forever {
+    ^ observe_execution {
+        loop <> count(4000000000) {
+            x <> 1
+        }
+    }
+}"#;
+        // Stands in for the transpiler lock a host holds while a program runs
+        let runner = Arc::new(Mutex::new(()));
+        let tokens = Arc::new(RunTokens::new());
+        let run = |started: std::sync::mpsc::Sender<()>| {
+            let (runner, tokens) = (runner.clone(), tokens.clone());
+            std::thread::spawn(move || {
+                let registration = tokens.register();
+                started.send(()).unwrap();
+                let _turn = runner.lock().unwrap();
+                let mut engine = Engine::builder().cancellation(registration.token()).build().unwrap();
+                engine.run_source(endless).unwrap_err().downcast_ref::<Cancelled>().copied()
+            })
+        };
+
+        let (started, running) = std::sync::mpsc::channel();
+        let first = run(started);
+        running.recv().unwrap();
+        while runner.try_lock().is_ok() {
+            std::thread::yield_now();
+        }
+
+        let (queued, waiting) = std::sync::mpsc::channel();
+        let second = run(queued);
+        waiting.recv().unwrap();
+        assert_eq!(tokens.len(), 2);
+
+        let stopped = Instant::now();
+        tokens.cancel_all();
+        assert_eq!(first.join().unwrap(), Some(Cancelled));
+        assert_eq!(second.join().unwrap(), Some(Cancelled));
+        assert!(stopped.elapsed() < Duration::from_secs(5));
+        assert!(tokens.is_empty());
+    }
+}
@@ -0,0 +1,81 @@
+// Static checks over a parsed program, reported as warnings before it runs
+// Nothing here stops a run; a program that trips a check still executes as written
+
+use crate::parser::{MatchArm, Pattern, Program, Statement};
+
+/// Warnings for `program`, main class first, in source order
+pub fn check(program: &Program) -> Vec<String> {
+    let mut warnings = Vec::new();
+    check_block(&program.body, &mut warnings);
+    for function in &program.function_classes {
+        check_block(&function.body, &mut warnings);
+    }
+    warnings
+}
+
+fn check_block(statements: &[Statement], warnings: &mut Vec<String>) {
+    for statement in statements {
+        match statement {
+            Statement::Match { scrutinee, arms, .. } => {
+                check_match(scrutinee, arms, warnings);
+                for arm in arms {
+                    check_block(&arm.body, warnings);
+                }
+            }
+            Statement::Selection { branches } => {
+                for branch in branches {
+                    check_block(&branch.body, warnings);
+                }
+            }
+            Statement::CountLoop { body, .. }
+            | Statement::RangeLoop { body, .. }
+            | Statement::WhileLoop { body, .. }
+            | Statement::Parallel { body, .. }
+            | Statement::ParallelRangeLoop { body, .. } => check_block(body, warnings),
+            _ => {}
+        }
+    }
+}
+
+/// A match is exhaustive when an unguarded `_` or binding arm exists, or when
+/// unguarded `true` and `false` arms both do
+fn check_match(scrutinee: &str, arms: &[MatchArm], warnings: &mut Vec<String>) {
+    if let Some(index) = arms.iter().position(|arm| arm.guard.is_none() && arm.pattern.is_catch_all()) {
+        if index + 1 < arms.len() {
+            warnings.push(format!(
+                "!! match {}: arm {} matches every value, so the {} arm(s) after it never run",
+                scrutinee, index + 1, arms.len() - index - 1
+            ));
+        }
+        return;
+    }
+
+    let covers = |b: bool| arms.iter().any(|arm| arm.guard.is_none() && arm.pattern == Pattern::Boolean(b));
+    if !(covers(true) && covers(false)) {
+        warnings.push(format!("!! match {} is not exhaustive; add a `_ => ...` arm", scrutinee));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn warnings(body: &str) -> Vec<String> {
+        let source = format!("* <main> Checked {{\n    ^ observe_execution {{\n{}\n    }}\n}}", body);
+        check(&Parser::new().unwrap().parse_program(&source).unwrap())
+    }
+
+    #[test]
+    fn test_match_exhaustiveness() {
+        assert!(warnings("match x { 1 => speak(\"one\"), _ => speak(\"other\") }").is_empty());
+        assert!(warnings("match done { true => speak(\"y\"), false => speak(\"n\") }").is_empty());
+
+        let missing = warnings("loop <> count(2) {\nmatch x { 1 => speak(\"one\"), n if n > 3 => speak(\"big\") }\n}");
+        assert_eq!(missing, vec!["!! match x is not exhaustive; add a `_ => ...` arm".to_string()]);
+
+        let unreachable = warnings("match x { n => speak(\"any\"), 2 => speak(\"two\") }");
+        assert_eq!(unreachable.len(), 1);
+        assert!(unreachable[0].contains("never run"));
+    }
+}
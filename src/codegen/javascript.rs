@@ -0,0 +1,873 @@
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use super::{split_template, CodeWriter, GeneratedProject, SolvedEquations, TemplatePart};
+use crate::format_spec::FormatSpec;
+use crate::limits::DEFAULT_MAX_WHILE_ITERATIONS;
+use crate::parser::{FunctionClass, Literal, Pattern, Program, Statement};
+
+/// Runtime shared by every generated script
+///
+/// `output` receives program lines and diagnostics; without one, program
+/// output goes to stdout and diagnostics to stderr (Node) or the console.
+const RUNTIME: &str = r#"function formatValue(value) {
+    if (typeof value === "number") {
+        if (value === Infinity) return "inf";
+        if (value === -Infinity) return "-inf";
+        if (Object.is(value, -0)) return "-0";
+        return String(value);
+    }
+    return String(value);
+}
+
+// `~value:spec~` formatting; `spec` is a serialized `FormatSpec`
+function applySpec(value, spec) {
+    const numeric = typeof value === "number";
+    let text;
+    if (spec.kind === "plain") {
+        if (numeric && spec.precision !== null) {
+            text = value.toFixed(spec.precision);
+        } else {
+            text = formatValue(value);
+            if (spec.precision !== null) text = Array.from(text).slice(0, spec.precision).join("");
+        }
+    } else if (spec.kind === "exponent" && numeric) {
+        text = (spec.precision === null ? value.toExponential() : value.toExponential(spec.precision)).replace("e+", "e");
+    } else if (numeric && Number.isInteger(value)) {
+        const radix = { hex: 16, upperhex: 16, binary: 2, octal: 8 }[spec.kind];
+        text = (value < 0 ? "-" : "") + Math.abs(value).toString(radix);
+        if (spec.kind === "upperhex") text = text.toUpperCase();
+    } else {
+        throw new Error(spec.kind + " formatting needs a whole number, got " + formatValue(value));
+    }
+
+    const missing = spec.width - Array.from(text).length;
+    if (missing <= 0) return text;
+    if (spec.zeroPad && numeric && spec.align === null) {
+        const sign = text.startsWith("-") ? "-" : "";
+        return sign + "0".repeat(missing) + text.slice(sign.length);
+    }
+    const align = spec.align || (numeric ? "right" : "left");
+    const before = align === "left" ? 0 : align === "right" ? missing : Math.floor(missing / 2);
+    return spec.fill.repeat(before) + text + spec.fill.repeat(missing - before);
+}
+
+function readLine(promptText) {
+    if (typeof window !== "undefined" && typeof window.prompt === "function") {
+        return window.prompt(promptText + ":") || "";
+    }
+    if (typeof require === "function") {
+        const fs = require("fs");
+        process.stdout.write(promptText + ": ");
+        const byte = Buffer.alloc(1);
+        let line = "";
+        while (fs.readSync(0, byte, 0, 1, null) === 1 && byte[0] !== 10) {
+            line += String.fromCharCode(byte[0]);
+        }
+        return line;
+    }
+    return "";
+}
+
+// Thrown by `exit(code)` and caught in `run`
+class ProgramExit {
+    constructor(code) {
+        this.code = code;
+    }
+}
+
+function createRuntime(output) {
+    output = output || {
+        program: (line) => console.log(line),
+        diagnostic: (level, message) => console.error(message),
+    };
+    const vars = Object.create(null);
+    const sources = Object.create(null);
+
+    const rt = {
+        vars,
+        get(name) {
+            if (!(name in vars)) throw new Error("Variable '" + name + "' is not defined");
+            return vars[name];
+        },
+        set(name, value, source) {
+            vars[name] = value;
+            sources[name] = source;
+        },
+        // Run `test` with `name` bound to `value`, then put back whatever was there
+        withBinding(name, value, test) {
+            const had = name in vars;
+            const previous = vars[name];
+            vars[name] = value;
+            try {
+                return test();
+            } finally {
+                if (had) vars[name] = previous;
+                else delete vars[name];
+            }
+        },
+        show(name) {
+            if (name in vars) return formatValue(vars[name]);
+            rt.warn("!! Undefined variable '" + name + "' in speak");
+            return "";
+        },
+        format(expression, spec, source) {
+            try {
+                return applySpec(expression(), spec);
+            } catch (e) {
+                rt.warn("!! Cannot interpolate '" + source + "': " + e.message);
+                return "";
+            }
+        },
+        speak(line) {
+            output.program(line);
+        },
+        info(message) {
+            output.diagnostic("info", message);
+        },
+        warn(message) {
+            output.diagnostic("warn", message);
+        },
+        cond(test, source) {
+            try {
+                const result = test();
+                if (typeof result === "boolean") return result;
+                throw new Error("Expected a boolean, got " + formatValue(result));
+            } catch (e) {
+                rt.warn("!! Error evaluating condition '" + source + "': " + e.message);
+                return false;
+            }
+        },
+        count(expression, source) {
+            let n;
+            try {
+                n = expression();
+            } catch (e) {
+                rt.warn("!! Could not resolve count expression '" + source + "': " + e.message);
+                return 0;
+            }
+            if (typeof n !== "number") {
+                rt.warn("!! Count variable '" + source + "' is not numeric");
+                return 0;
+            }
+            if (n < 0 || !Number.isInteger(n)) {
+                rt.warn("!! Count must be a non-negative integer, got " + formatValue(n));
+                return 0;
+            }
+            return n;
+        },
+        bound(expression, source) {
+            let n;
+            try {
+                n = expression();
+            } catch (e) {
+                throw new Error("Could not evaluate '" + source + "': " + e.message);
+            }
+            if (typeof n !== "number") throw new Error("Variable '" + source + "' is not numeric");
+            return Math.trunc(n);
+        },
+        calc(name, params, source) {
+            let sum = 0;
+            for (const param of params) {
+                if (typeof param === "number") {
+                    sum += param;
+                } else if (!(param in vars)) {
+                    rt.warn("!! Could not resolve parameter: " + param);
+                    return;
+                } else if (typeof vars[param] !== "number") {
+                    rt.warn("!! Variable '" + param + "' is not numeric");
+                    return;
+                } else {
+                    sum += vars[param];
+                }
+            }
+            rt.set(name, sum, source);
+        },
+        choose(name, choices, source) {
+            const resolved = choices.map((choice) => {
+                if (typeof choice === "number") return choice;
+                if (choice in vars) return vars[choice];
+                return choice.replace(/^"+|"+$/g, "");
+            });
+            rt.set(name, resolved[Math.floor(Math.random() * resolved.length)], source);
+        },
+        input(name, promptText) {
+            const answer = readLine(promptText).trim();
+            const n = Number(answer);
+            rt.set(name, answer !== "" && !isNaN(n) ? n : answer, "userIn(\"" + promptText + "\")");
+        },
+        call(name, functionName, body) {
+            if (body === undefined) {
+                rt.warn("!! Function " + functionName + " not found");
+                return;
+            }
+            rt.set(name, body(rt), functionName + "()");
+        },
+        solve(className, name, targetText) {
+            let target = Number(targetText);
+            if (targetText.trim() === "" || isNaN(target)) {
+                if (!(targetText in vars)) {
+                    rt.warn("!! Could not resolve target: " + targetText);
+                    return;
+                }
+                if (typeof vars[targetText] !== "number") {
+                    rt.warn("!! Target variable '" + targetText + "' is not numeric");
+                    return;
+                }
+                target = vars[targetText];
+            }
+            const solved = (SOLVED[className + "-" + name] || {})[formatValue(target)];
+            if (!solved) {
+                rt.warn("!! No cached solution for '" + name + "' with target " + formatValue(target));
+                return;
+            }
+            rt.set(name, solved.value, solved.equation);
+            rt.info("== Solution found: " + solved.equation + " = " + formatValue(solved.value) +
+                " (accuracy: " + solved.accuracy + "%)");
+        },
+        woof(name) {
+            if (!(name in vars)) {
+                rt.warn("!! Variable '" + name + "' not found");
+                return;
+            }
+            output.program("Final result: " + formatValue(vars[name]));
+            if (sources[name]) rt.info("   Source: " + sources[name]);
+        },
+        checkWhile(iterations) {
+            if (MAX_WHILE_ITERATIONS !== null && iterations > MAX_WHILE_ITERATIONS) {
+                throw new Error("While loop ran past its limit of " + MAX_WHILE_ITERATIONS + " iterations");
+            }
+        },
+        exit(expression, source) {
+            let code;
+            try {
+                code = expression();
+            } catch (e) {
+                throw new Error("Could not evaluate '" + source + "': " + e.message);
+            }
+            if (!Number.isInteger(code) || code < -2147483648 || code > 2147483647) {
+                throw new Error("exit(" + source + ") needs a whole number in i32 range, got " + code);
+            }
+            throw new ProgramExit(code);
+        },
+    };
+    return rt;
+}
+"#;
+
+/// Lowers a parsed .slut program into plain JavaScript
+///
+/// Variables stay dynamically typed, as in the interpreter. Solver lines look
+/// their result up in a table of cached solutions embedded in the script, so
+/// the program runs without the solver. The script works in Node, in a browser
+/// and in the IDE webview (`run(output)` returns after the program finishes).
+pub struct JavaScriptBackend {
+    solved: SolvedEquations,
+    max_while_iterations: Option<u64>,
+}
+
+impl JavaScriptBackend {
+    pub fn new(solved: SolvedEquations) -> Self {
+        Self { solved, max_while_iterations: Some(DEFAULT_MAX_WHILE_ITERATIONS) }
+    }
+
+    /// Passes one `while` loop may make before the script throws; `None` for no cap
+    pub fn max_while_iterations(mut self, limit: Option<u64>) -> Self {
+        self.max_while_iterations = limit;
+        self
+    }
+
+    /// `program.js` plus a self-contained `index.html`
+    pub fn generate(&self, program: &Program) -> Result<GeneratedProject> {
+        let script = self.generate_script(program)?;
+        let html = self.generate_html(program, &script);
+
+        Ok(GeneratedProject {
+            program_name: program.main_class.clone(),
+            files: vec![
+                (PathBuf::from("program.js"), script),
+                (PathBuf::from("index.html"), html),
+            ],
+        })
+    }
+
+    /// The script alone, defining `run(output)`
+    pub fn generate_script(&self, program: &Program) -> Result<String> {
+        let mut ctx = EmitContext {
+            functions: program.function_classes.iter().map(|f| f.name.as_str()).collect(),
+            class_name: program.main_class.clone(),
+            in_function: false,
+            loop_labels: Vec::new(),
+            next_label: 0,
+        };
+
+        let mut out = CodeWriter::new();
+        out.line(&format!("// Generated by quantum_slut_transpiler from class {}", program.main_class));
+        out.line("\"use strict\";");
+        out.line("");
+        out.line(&format!("const SOLVED = {};", self.solved_table(program)?));
+        out.line(&format!(
+            "const MAX_WHILE_ITERATIONS = {};",
+            self.max_while_iterations.map_or("null".to_string(), |limit| limit.to_string())
+        ));
+        out.line("");
+        for line in RUNTIME.lines() {
+            out.line(line);
+        }
+        out.line("");
+
+        for function in &program.function_classes {
+            ctx.emit_function(&mut out, function)?;
+            out.line("");
+        }
+
+        out.open("function main(rt) {");
+        ctx.class_name = program.main_class.clone();
+        ctx.in_function = false;
+        for statement in &program.body {
+            ctx.emit_statement(&mut out, statement)?;
+        }
+        out.close("}");
+        out.line("");
+
+        out.open("function run(output) {");
+        out.line("const rt = createRuntime(output);");
+        out.open("try {");
+        out.line("main(rt);");
+        out.close("} catch (error) {");
+        out.indent += 1;
+        out.line("if (!(error instanceof ProgramExit)) throw error;");
+        out.line("rt.info(\"** Program exited with status \" + error.code);");
+        out.line("if (typeof process !== \"undefined\") process.exitCode = error.code;");
+        out.close("}");
+        out.line("return rt.vars;");
+        out.close("}");
+        out.line("");
+        out.open("if (typeof module !== \"undefined\" && module.exports) {");
+        out.line("module.exports = { run };");
+        out.line("if (typeof require === \"function\" && require.main === module) run();");
+        out.close("}");
+
+        Ok(out.finish())
+    }
+
+    fn generate_html(&self, program: &Program, script: &str) -> String {
+        // A literal `</script` inside a string would end the script element early
+        let script = script.replace("</script", "<\\/script");
+        format!(
+            r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<style>
+body {{ background: #111; color: #ddd; font-family: monospace; padding: 1em; }}
+.program {{ color: #eee; }}
+.info {{ color: #777; }}
+.warn {{ color: #e66; }}
+</style>
+</head>
+<body>
+<h3>{title}</h3>
+<pre id="output"></pre>
+<script>
+{script}
+const outputElement = document.getElementById("output");
+function append(text, className) {{
+    const line = document.createElement("div");
+    line.className = className;
+    line.textContent = text;
+    outputElement.appendChild(line);
+}}
+run({{
+    program: (line) => append(line, "program"),
+    diagnostic: (level, message) => append(message, level),
+}});
+</script>
+</body>
+</html>
+"#,
+            title = html_escape(&program.main_class),
+            script = script,
+        )
+    }
+
+    /// `{"Class-var": {"250": {value, equation, accuracy}}}` for every solver line
+    fn solved_table(&self, program: &Program) -> Result<String> {
+        let mut keys = Vec::new();
+        collect_solver_keys(&program.main_class, &program.body, &mut keys);
+        for function in &program.function_classes {
+            collect_solver_keys(&function.name, &function.body, &mut keys);
+        }
+
+        let mut table: BTreeMap<String, BTreeMap<String, serde_json::Value>> = BTreeMap::new();
+        for (class_name, var_name) in keys {
+            let entries = table.entry(format!("{}-{}", class_name, var_name)).or_default();
+            for (target, solution) in self.solved.by_target(&class_name, &var_name) {
+                entries.insert(target.to_string(), serde_json::json!({
+                    "value": solution.result,
+                    "equation": solution.equation,
+                    "accuracy": solution.accuracy,
+                }));
+            }
+        }
+
+        Ok(serde_json::to_string(&table)?)
+    }
+}
+
+struct EmitContext<'a> {
+    functions: Vec<&'a str>,
+    class_name: String,
+    in_function: bool,
+    /// (source label, generated label) of each enclosing loop, innermost last
+    loop_labels: Vec<(Option<String>, String)>,
+    next_label: usize,
+}
+
+impl<'a> EmitContext<'a> {
+    fn fresh_label(&mut self, prefix: &str) -> String {
+        self.next_label += 1;
+        format!("{}_{}", prefix, self.next_label)
+    }
+
+    fn emit_function(&mut self, out: &mut CodeWriter, function: &FunctionClass) -> Result<()> {
+        self.class_name = function.name.clone();
+        self.in_function = true;
+        out.open(&format!("function {}(rt) {{", function_name(&function.name)));
+        for statement in &function.body {
+            self.emit_statement(out, statement)?;
+        }
+        // Function bodies without a woof return 0, like the interpreter
+        out.line("return 0;");
+        out.close("}");
+        Ok(())
+    }
+
+    fn emit_statement(&mut self, out: &mut CodeWriter, statement: &Statement) -> Result<()> {
+        match statement {
+            Statement::Assign { name, value } => {
+                let value = match value {
+                    Literal::Number(n) => number_literal(*n),
+                    // Generated programs are f64 throughout, so this rounds
+                    Literal::Integer(n) => number_literal(n.to_f64()),
+                    Literal::Boolean(b) => b.to_string(),
+                    Literal::Text(s) => js_string(s),
+                };
+                out.line(&format!("rt.set({}, {});", js_string(name), value));
+            }
+            Statement::Calc { name, params } => {
+                if params.len() < 2 {
+                    out.line("rt.warn(\"!! calc() requires at least 2 parameters\");");
+                } else {
+                    let source = format!("calc({})", params.join(", "));
+                    let params: Vec<String> = params.iter().map(|p| choice_literal(p)).collect();
+                    out.line(&format!(
+                        "rt.calc({}, [{}], {});", js_string(name), params.join(", "), js_string(&source)
+                    ));
+                }
+            }
+            Statement::RandomChoice { name, choices } => {
+                if !choices.is_empty() {
+                    let source = format!("randomChoice({})", choices.join(", "));
+                    let choices: Vec<String> = choices.iter().map(|c| choice_literal(c)).collect();
+                    out.line(&format!(
+                        "rt.choose({}, [{}], {});", js_string(name), choices.join(", "), js_string(&source)
+                    ));
+                }
+            }
+            Statement::UserInput { name, prompt } => {
+                out.line(&format!("rt.input({}, {});", js_string(name), js_string(prompt)));
+            }
+            Statement::FunctionCall { name, function } => {
+                let body = if self.functions.contains(&function.as_str()) {
+                    function_name(function)
+                } else {
+                    "undefined".to_string()
+                };
+                out.line(&format!("rt.call({}, {}, {});", js_string(name), js_string(function), body));
+            }
+            Statement::SolveTarget { name, target, .. } => {
+                out.line(&format!(
+                    "rt.solve({}, {}, {});", js_string(&self.class_name), js_string(name), js_string(target)
+                ));
+            }
+            Statement::Speak { template } => {
+                let mut parts = Vec::new();
+                for part in split_template(template) {
+                    parts.push(match part {
+                        TemplatePart::Text(text) => js_string(&text),
+                        TemplatePart::Variable(name) => format!("rt.show({})", js_string(&name)),
+                        TemplatePart::Expression { expression, spec } => {
+                            let spec = spec.as_deref().map(FormatSpec::parse).transpose()?.unwrap_or_default();
+                            format!("rt.format(() => {}, {}, {})",
+                                translate_expression(&expression)?, serde_json::to_string(&spec)?, js_string(&expression))
+                        }
+                    });
+                }
+                let line = if parts.is_empty() { "\"\"".to_string() } else { parts.join(" + ") };
+                out.line(&format!("rt.speak({});", line));
+            }
+            Statement::Woof { name } => {
+                if self.in_function {
+                    out.open(&format!("if ({} in rt.vars) {{", js_string(name)));
+                    out.line(&format!("return rt.vars[{}];", js_string(name)));
+                    out.close("}");
+                    out.line(&format!(
+                        "rt.warn({});",
+                        js_string(&format!("!! Return variable '{}' not found in function {}", name, self.class_name))
+                    ));
+                } else {
+                    out.line(&format!("rt.woof({});", js_string(name)));
+                }
+            }
+            Statement::Exit { code } => {
+                out.line(&format!("rt.exit(() => {}, {});", translate_expression(code)?, js_string(code)));
+            }
+            Statement::Selection { branches } => {
+                for (index, branch) in branches.iter().enumerate() {
+                    let condition = translate_condition(&branch.condition)?;
+                    if index == 0 {
+                        out.open(&format!("if ({}) {{", condition));
+                    } else {
+                        out.close(&format!("}} else if ({}) {{", condition));
+                        out.indent += 1;
+                    }
+                    for statement in &branch.body {
+                        self.emit_statement(out, statement)?;
+                    }
+                }
+                out.close("} else {");
+                out.indent += 1;
+                out.line("rt.warn(\"!! Warning: No condition matched (else should be true)\");");
+                out.close("}");
+            }
+            Statement::Match { scrutinee, arms, .. } => {
+                let label = self.fresh_label("match");
+                out.open(&format!("{}: {{", label));
+                out.line(&format!("const matched = {};", translate_expression(scrutinee)?));
+                for arm in arms {
+                    let mut tests = Vec::new();
+                    let mut binding = None;
+                    match &arm.pattern {
+                        Pattern::Number(n) => tests.push(format!("matched === {}", number_literal(*n))),
+                        Pattern::Range { start, end, inclusive } => tests.push(format!(
+                            "typeof matched === \"number\" && matched >= {} && matched {} {}",
+                            number_literal(*start), if *inclusive { "<=" } else { "<" }, number_literal(*end)
+                        )),
+                        Pattern::Text(text) => tests.push(format!("matched === {}", js_string(text))),
+                        Pattern::Boolean(b) => tests.push(format!("matched === {}", b)),
+                        Pattern::Binding(name) => binding = Some(js_string(name)),
+                        Pattern::Wildcard => {}
+                    }
+                    if let Some(guard) = &arm.guard {
+                        let guard = translate_condition(guard)?;
+                        tests.push(match &binding {
+                            Some(name) => format!("rt.withBinding({}, matched, () => {})", name, guard),
+                            None => guard,
+                        });
+                    }
+
+                    if tests.is_empty() {
+                        out.open("{");
+                    } else {
+                        out.open(&format!("if ({}) {{", tests.join(" && ")));
+                    }
+                    if let Some(name) = &binding {
+                        out.line(&format!("rt.set({}, matched, \"match binding\");", name));
+                    }
+                    for statement in &arm.body {
+                        self.emit_statement(out, statement)?;
+                    }
+                    out.line(&format!("break {};", label));
+                    out.close("}");
+                }
+                out.line(&format!(
+                    "rt.warn({} + formatValue(matched));",
+                    js_string(&format!("!! No match arm matched {} = ", scrutinee))
+                ));
+                out.close("}");
+            }
+            Statement::CountLoop { label: source_label, count, body } => {
+                let count = format!("rt.count(() => {}, {})", translate_expression(count)?, js_string(count));
+                let label = self.fresh_label("loop");
+                out.open(&format!("{}: for (let remaining = {}; remaining > 0; remaining--) {{", label, count));
+                self.emit_loop_body(out, source_label, label, body)?;
+                out.close("}");
+            }
+            // Children run in isolated frames whose writes merge afterwards; one shared `rt` cannot do that
+            Statement::Parallel { source, .. } | Statement::ParallelRangeLoop { source, .. } => {
+                let first_line = source.lines().next().unwrap_or_default().trim();
+                return Err(anyhow::anyhow!("parallel is not supported by codegen: {}", first_line));
+            }
+            Statement::RangeLoop { label: source_label, start, end, variable, body } => {
+                let start = format!("rt.bound(() => {}, {})", translate_expression(start)?, js_string(start));
+                let end = format!("rt.bound(() => {}, {})", translate_expression(end)?, js_string(end));
+                let label = self.fresh_label("loop");
+                out.open(&format!(
+                    "{}: for (let index = {}, end = {}; index < end; index++) {{", label, start, end
+                ));
+                out.line(&format!("rt.set({}, index, \"loop iterator\");", js_string(variable)));
+                self.emit_loop_body(out, source_label, label, body)?;
+                out.close("}");
+            }
+            Statement::WhileLoop { label: source_label, condition, body } => {
+                let test = translate_condition(condition)?;
+                let label = self.fresh_label("loop");
+                out.open(&format!("{}: for (let iterations = 1; ; iterations++) {{", label));
+                out.open(&format!("if (!{}) {{", test));
+                out.line("break;");
+                out.close("}");
+                out.line("rt.checkWhile(iterations);");
+                self.emit_loop_body(out, source_label, label, body)?;
+                out.close("}");
+            }
+            Statement::Break { label } => match self.loop_target(label)? {
+                Some(target) => out.line(&format!("break {};", target)),
+                None => out.line("// break outside of loop"),
+            },
+            Statement::Continue { label } => match self.loop_target(label)? {
+                Some(target) => out.line(&format!("continue {};", target)),
+                None => out.line("// continue outside of loop"),
+            },
+            Statement::AllChoices { source, .. } => {
+                return Err(anyhow::anyhow!("allChoices is not supported by codegen: {}", source));
+            }
+            Statement::Unsupported { source } => {
+                out.line(&format!("// not supported by the JavaScript backend: {}", source.replace('\n', " ")));
+            }
+        }
+        Ok(())
+    }
+
+    fn emit_loop_body(&mut self, out: &mut CodeWriter, source_label: &Option<String>, label: String, body: &[Statement]) -> Result<()> {
+        self.loop_labels.push((source_label.clone(), label));
+        for statement in body {
+            self.emit_statement(out, statement)?;
+        }
+        self.loop_labels.pop();
+        Ok(())
+    }
+
+    /// Generated label a `break` / `continue` jumps to; the innermost loop without a label
+    fn loop_target(&self, label: &Option<String>) -> Result<Option<String>> {
+        match label {
+            None => Ok(self.loop_labels.last().map(|(_, generated)| generated.clone())),
+            Some(label) => self.loop_labels.iter().rev()
+                .find(|(source, _)| source.as_ref() == Some(label))
+                .map(|(_, generated)| Some(generated.clone()))
+                .ok_or_else(|| anyhow::anyhow!("Unknown loop label '{}'", label)),
+        }
+    }
+}
+
+fn collect_solver_keys(class_name: &str, statements: &[Statement], keys: &mut Vec<(String, String)>) {
+    for statement in statements {
+        match statement {
+            Statement::SolveTarget { name, .. } => {
+                let key = (class_name.to_string(), name.clone());
+                if !keys.contains(&key) {
+                    keys.push(key);
+                }
+            }
+            Statement::CountLoop { body, .. }
+            | Statement::RangeLoop { body, .. }
+            | Statement::WhileLoop { body, .. }
+            | Statement::Parallel { body, .. }
+            | Statement::ParallelRangeLoop { body, .. } => collect_solver_keys(class_name, body, keys),
+            Statement::Selection { branches } => {
+                for branch in branches {
+                    collect_solver_keys(class_name, &branch.body, keys);
+                }
+            }
+            Statement::Match { arms, .. } => {
+                for arm in arms {
+                    collect_solver_keys(class_name, &arm.body, keys);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn translate_condition(condition: &str) -> Result<String> {
+    Ok(format!("rt.cond(() => {}, {})", translate_expression(condition)?, js_string(condition)))
+}
+
+/// Translate an evalexpr expression into JavaScript reading from `rt`
+fn translate_expression(expression: &str) -> Result<String> {
+    let chars: Vec<char> = expression.chars().collect();
+    let mut out = String::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_ascii_digit() || (c == '.' && i + 1 < chars.len() && chars[i + 1].is_ascii_digit()) {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            let literal: String = chars[start..i].iter().collect();
+            out.push_str(&number_literal(literal.parse()?));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let ident: String = chars[start..i].iter().collect();
+            if chars[i..].iter().find(|c| !c.is_whitespace()) == Some(&'(') {
+                return Err(anyhow::anyhow!(
+                    "Function call '{}' in '{}' is not supported by the JavaScript backend", ident, expression
+                ));
+            }
+            if ident == "true" || ident == "false" {
+                out.push_str(&ident);
+            } else {
+                out.push_str(&format!("rt.get({})", js_string(&ident)));
+            }
+        } else if c == '"' {
+            let start = i;
+            i += 1;
+            while i < chars.len() && chars[i] != '"' {
+                if chars[i] == '\\' {
+                    i += 1;
+                }
+                i += 1;
+            }
+            i += 1;
+            out.extend(&chars[start..i.min(chars.len())]);
+        } else if c == '^' {
+            out.push_str("**");
+            i += 1;
+        } else if (c == '=' || c == '!') && chars.get(i + 1) == Some(&'=') {
+            out.push(c);
+            out.push_str("==");
+            i += 2;
+        } else {
+            out.push(c);
+            i += 1;
+        }
+    }
+
+    Ok(format!("({})", out.trim()))
+}
+
+/// Numbers become numbers; anything else is resolved by name at run time
+fn choice_literal(choice: &str) -> String {
+    match choice.parse::<f64>() {
+        Ok(n) => number_literal(n),
+        Err(_) => js_string(choice),
+    }
+}
+
+fn number_literal(n: f64) -> String {
+    if n.is_nan() {
+        "NaN".to_string()
+    } else if n.is_infinite() {
+        if n > 0.0 { "Infinity".to_string() } else { "-Infinity".to_string() }
+    } else if n < 0.0 {
+        format!("({})", n)
+    } else {
+        n.to_string()
+    }
+}
+
+fn js_string(text: &str) -> String {
+    serde_json::to_string(text).unwrap_or_else(|_| "\"\"".to_string())
+}
+
+fn function_name(class_name: &str) -> String {
+    format!("class_{}", class_name)
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::MathSolution;
+    use std::collections::HashMap;
+
+    const PROGRAM: &str = r#"
+* <main> LearnToSolve {
+    ^ observe_execution {
+        targetNum <> 250
+        result([targetNum]) <> randomChoice([7, 3, ?])
+        loop <> while(targetNum != 250 || result < 0) {
+            break
+        }
+        speak("RESULT: ~result~")
+        woof result
+    }
+}
+"#;
+
+    #[test]
+    fn test_generate_script() {
+        let mut solutions = HashMap::new();
+        solutions.insert("LearnToSolve-result-250-7,3,229".to_string(), MathSolution {
+            result: 250.0,
+            equation: "7 * 3 + 229".to_string(),
+            accuracy: 100.0,
+            timestamp: 1,
+            attempts: 1,
+            formula: None,
+            exact: None,
+            cost: None,
+        });
+
+        let program = Parser::new().unwrap().parse_program(PROGRAM).unwrap();
+        let project = JavaScriptBackend::new(SolvedEquations::new(solutions)).generate(&program).unwrap();
+        let script = project.file("program.js").unwrap();
+
+        assert!(script.contains(
+            r#"const SOLVED = {"LearnToSolve-result":{"250":{"accuracy":100.0,"equation":"7 * 3 + 229","value":250.0}}};"#
+        ));
+        assert!(script.contains(r#"rt.solve("LearnToSolve", "result", "targetNum");"#));
+        assert!(script.contains(r#"if (!rt.cond(() => (rt.get("targetNum") !== 250 || rt.get("result") < 0), "targetNum != 250 || result < 0")) {"#));
+        assert!(script.contains("const MAX_WHILE_ITERATIONS = 10000;"));
+        assert!(script.contains("loop_1: for (let iterations = 1; ; iterations++) {"));
+        assert!(script.contains("rt.checkWhile(iterations);"));
+        assert!(script.contains("break loop_1;"));
+        assert!(script.contains(r#"rt.speak("RESULT: " + rt.show("result"));"#));
+        assert!(project.file("index.html").unwrap().contains("<pre id=\"output\"></pre>"));
+    }
+
+    #[test]
+    fn test_guards_see_bindings_without_storing_them() {
+        let source = "* <main> Guarded {\n ^ observe_execution {\n n <> 5\n match n {\n n if n > 10 => speak(\"big\")\n _ => speak(\"~n~\")\n }\n }\n}";
+        let program = Parser::new().unwrap().parse_program(source).unwrap();
+        let script = JavaScriptBackend::new(SolvedEquations::new(HashMap::new())).generate(&program).unwrap()
+            .file("program.js").unwrap().to_string();
+
+        assert!(script.contains(r#"if (rt.withBinding("n", matched, () => rt.cond(() => (rt.get("n") > 10), "n > 10"))) {"#), "{}", script);
+        let taken = script.find(r#"rt.set("n", matched, "match binding");"#).unwrap();
+        assert!(taken > script.find("rt.withBinding(").unwrap());
+    }
+
+    #[test]
+    fn test_function_calls_in_conditions_are_rejected() {
+        assert!(translate_expression("sqrt(x) > 2").is_err());
+        assert_eq!(translate_expression("x ^ 2 == 4").unwrap(), r#"(rt.get("x") ** 2 === 4)"#);
+    }
+
+    #[test]
+    fn test_parallel_is_rejected() {
+        let source = "* <main> Fan {\n ^ observe_execution {\n parallel {\n a <> 1\n b <> 2\n }\n }\n}";
+        let program = Parser::new().unwrap().parse_program(source).unwrap();
+        let error = JavaScriptBackend::new(SolvedEquations::new(HashMap::new())).generate(&program).unwrap_err();
+        assert_eq!(error.to_string(), "parallel is not supported by codegen: parallel {");
+    }
+
+    #[test]
+    fn test_all_choices_is_rejected() {
+        let source = "* <main> Alts {\n ^ observe_execution {\n fives([5]) <> allChoices([3, 4, 1])\n }\n}";
+        let program = Parser::new().unwrap().parse_program(source).unwrap();
+        let error = JavaScriptBackend::new(SolvedEquations::new(HashMap::new())).generate(&program).unwrap_err();
+        assert!(error.to_string().contains("allChoices is not supported by codegen"), "{}", error);
+    }
+}
@@ -0,0 +1,272 @@
+// Code generation backends
+// Lower a parsed .slut program into standalone source for another language
+
+pub mod javascript;
+pub mod rust;
+
+pub use javascript::JavaScriptBackend;
+pub use rust::RustBackend;
+
+use anyhow::Result;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::MathSolution;
+
+/// Files produced by a backend, relative to the output directory
+#[derive(Debug, Clone)]
+pub struct GeneratedProject {
+    /// Name of the program's main class
+    pub program_name: String,
+    pub files: Vec<(PathBuf, String)>,
+}
+
+impl GeneratedProject {
+    pub fn write_to(&self, out_dir: &Path) -> Result<()> {
+        for (relative_path, contents) in &self.files {
+            let path = out_dir.join(relative_path);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&path, contents)?;
+        }
+        Ok(())
+    }
+
+    pub fn file(&self, relative_path: &str) -> Option<&str> {
+        self.files.iter()
+            .find(|(path, _)| path == Path::new(relative_path))
+            .map(|(_, contents)| contents.as_str())
+    }
+}
+
+/// Solved equations from the cache, looked up by the statement that produced them
+///
+/// Cache keys have the form `class-variable-target-inputs` (see
+/// `MathEngine::create_cache_key`); backends only know the class, variable and,
+/// when it is a compile-time constant, the target.
+pub struct SolvedEquations {
+    solutions: HashMap<String, MathSolution>,
+}
+
+impl SolvedEquations {
+    pub fn new(solutions: HashMap<String, MathSolution>) -> Self {
+        Self { solutions }
+    }
+
+    /// Best cached solution for `var_name` in `class_name`
+    ///
+    /// Exact solutions win over approximations; ties go to the most recent.
+    pub fn lookup(&self, class_name: &str, var_name: &str, target: Option<f64>) -> Option<&MathSolution> {
+        let prefix = match target {
+            Some(t) => format!("{}-{}-{}-", class_name, var_name, t),
+            None => format!("{}-{}-", class_name, var_name),
+        };
+
+        self.solutions.iter()
+            .filter(|(key, _)| key.starts_with(&prefix))
+            .map(|(_, solution)| solution)
+            .max_by(|a, b| {
+                a.accuracy.partial_cmp(&b.accuracy)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+                    .then(a.timestamp.cmp(&b.timestamp))
+            })
+    }
+
+    /// Best cached solution for every target `var_name` has been solved for
+    ///
+    /// Sorted by target, for backends that resolve the target at run time.
+    pub fn by_target(&self, class_name: &str, var_name: &str) -> Vec<(f64, &MathSolution)> {
+        let prefix = format!("{}-{}-", class_name, var_name);
+        let mut best: Vec<(f64, &MathSolution)> = Vec::new();
+
+        for (key, solution) in &self.solutions {
+            let Some(rest) = key.strip_prefix(&prefix) else { continue };
+            let Some(target) = leading_target(rest) else { continue };
+            match best.iter_mut().find(|(t, _)| *t == target) {
+                Some(entry) => {
+                    if (solution.accuracy, solution.timestamp) > (entry.1.accuracy, entry.1.timestamp) {
+                        entry.1 = solution;
+                    }
+                }
+                None => best.push((target, solution)),
+            }
+        }
+
+        best.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+        best
+    }
+}
+
+/// Parse the target from the `target-inputs` tail of a cache key
+fn leading_target(rest: &str) -> Option<f64> {
+    rest.char_indices()
+        .filter(|(i, c)| *c == '-' && *i > 0)
+        .find_map(|(i, _)| rest[..i].parse::<f64>().ok())
+}
+
+/// Indented source text builder shared by the backends
+pub(crate) struct CodeWriter {
+    out: String,
+    indent: usize,
+}
+
+impl CodeWriter {
+    pub(crate) fn new() -> Self {
+        Self { out: String::new(), indent: 0 }
+    }
+
+    pub(crate) fn line(&mut self, text: &str) {
+        if text.is_empty() {
+            self.out.push('\n');
+            return;
+        }
+        for _ in 0..self.indent {
+            self.out.push_str("    ");
+        }
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    pub(crate) fn open(&mut self, text: &str) {
+        self.line(text);
+        self.indent += 1;
+    }
+
+    pub(crate) fn close(&mut self, text: &str) {
+        self.indent = self.indent.saturating_sub(1);
+        self.line(text);
+    }
+
+    pub(crate) fn finish(self) -> String {
+        self.out
+    }
+}
+
+/// Split a `speak` template into literal text and `~...~` placeholders
+///
+/// A placeholder is a variable or an expression, optionally followed by
+/// `:spec`. Tildes around text that starts or ends with a space (`a ~ b ~`)
+/// stay literal.
+pub(crate) fn split_template(template: &str) -> Vec<TemplatePart> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find('~') {
+        let after = &rest[start + 1..];
+        match after.find('~') {
+            Some(end) if is_placeholder(&after[..end]) => {
+                if start > 0 {
+                    parts.push(TemplatePart::Text(rest[..start].to_string()));
+                }
+                parts.push(placeholder(&after[..end]));
+                rest = &after[end + 1..];
+            }
+            _ => {
+                parts.push(TemplatePart::Text(rest[..start + 1].to_string()));
+                rest = after;
+            }
+        }
+    }
+
+    if !rest.is_empty() {
+        parts.push(TemplatePart::Text(rest.to_string()));
+    }
+
+    // Merge adjacent text runs produced by stray tildes
+    let mut merged: Vec<TemplatePart> = Vec::new();
+    for part in parts {
+        match (merged.last_mut(), part) {
+            (Some(TemplatePart::Text(previous)), TemplatePart::Text(text)) => previous.push_str(&text),
+            (_, part) => merged.push(part),
+        }
+    }
+    merged
+}
+
+fn is_placeholder(content: &str) -> bool {
+    !content.is_empty()
+        && !content.starts_with(char::is_whitespace)
+        && !content.ends_with(char::is_whitespace)
+        && !content.contains('\n')
+}
+
+fn is_identifier(text: &str) -> bool {
+    !text.is_empty() && text.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+fn placeholder(content: &str) -> TemplatePart {
+    let (expression, spec) = match content.rsplit_once(':') {
+        Some((expression, spec)) if !spec.contains('"') => (expression.trim(), Some(spec.to_string())),
+        _ => (content, None),
+    };
+
+    if spec.is_none() && is_identifier(expression) {
+        TemplatePart::Variable(expression.to_string())
+    } else {
+        TemplatePart::Expression { expression: expression.to_string(), spec }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum TemplatePart {
+    Text(String),
+    Variable(String),
+    /// `~a + b~`, `~xs[0]~` or anything with a `:spec`, e.g. `~total:.2~`
+    Expression { expression: String, spec: Option<String> },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_template() {
+        assert_eq!(split_template("RESULT: ~result~!"), vec![
+            TemplatePart::Text("RESULT: ".to_string()),
+            TemplatePart::Variable("result".to_string()),
+            TemplatePart::Text("!".to_string()),
+        ]);
+        assert_eq!(split_template("a ~ b ~x~"), vec![
+            TemplatePart::Text("a ~ b ".to_string()),
+            TemplatePart::Variable("x".to_string()),
+        ]);
+        assert_eq!(split_template("~a + b~ is ~ratio:>8.2~ ~xs[0]~"), vec![
+            TemplatePart::Expression { expression: "a + b".to_string(), spec: None },
+            TemplatePart::Text(" is ".to_string()),
+            TemplatePart::Expression { expression: "ratio".to_string(), spec: Some(">8.2".to_string()) },
+            TemplatePart::Text(" ".to_string()),
+            TemplatePart::Expression { expression: "xs[0]".to_string(), spec: None },
+        ]);
+    }
+
+    #[test]
+    fn test_lookup_prefers_exact() {
+        let mut solutions = HashMap::new();
+        let solution = |accuracy: f64, equation: &str| MathSolution {
+            result: 250.0,
+            equation: equation.to_string(),
+            accuracy,
+            timestamp: 0,
+            attempts: 1,
+            formula: None,
+            exact: None,
+            cost: None,
+        };
+        solutions.insert("Main-result-250-3,7".to_string(), solution(90.0, "3 * 7"));
+        solutions.insert("Main-result-250-3,7,229".to_string(), solution(100.0, "3 * 7 + 229"));
+        solutions.insert("Main-other-250-3,7".to_string(), solution(100.0, "nope"));
+
+        solutions.insert("Main-result--4-2,2".to_string(), solution(100.0, "-2 - 2"));
+
+        let table = SolvedEquations::new(solutions);
+        assert_eq!(table.lookup("Main", "result", Some(250.0)).unwrap().equation, "3 * 7 + 229");
+        assert!(table.lookup("Main", "result", Some(12.0)).is_none());
+
+        let targets: Vec<(f64, &str)> = table.by_target("Main", "result").into_iter()
+            .map(|(target, solution)| (target, solution.equation.as_str()))
+            .collect();
+        assert_eq!(targets, vec![(-4.0, "-2 - 2"), (250.0, "3 * 7 + 229")]);
+    }
+}
@@ -0,0 +1,1273 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use super::{split_template, CodeWriter, GeneratedProject, SolvedEquations, TemplatePart};
+use crate::equation_tree::{BinaryOp, EquationNode};
+use crate::format_spec::{Align, FormatKind, FormatSpec};
+use crate::lexer;
+use crate::limits::DEFAULT_MAX_WHILE_ITERATIONS;
+use crate::parser::{self, FunctionClass, Literal, Pattern, Program, Statement};
+
+/// Deepest chain of inlined function-class calls before we give up
+const MAX_INLINE_DEPTH: usize = 16;
+
+/// Prefix of every local the backend makes up, reserved so no program variable can collide with one
+const GENERATED_PREFIX: &str = "__slut_";
+
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "break", "const", "continue", "crate", "else", "enum", "extern", "false", "fn", "for",
+    "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return",
+    "static", "struct", "trait", "true", "type", "unsafe", "use", "where", "while", "async",
+    "await", "dyn", "abstract", "become", "box", "do", "final", "macro", "override", "priv",
+    "typeof", "unsized", "virtual", "yield", "try",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LocalType {
+    Number,
+    Boolean,
+    Text,
+}
+
+impl LocalType {
+    fn rust_type(&self) -> &'static str {
+        match self {
+            LocalType::Number => "f64",
+            LocalType::Boolean => "bool",
+            LocalType::Text => "String",
+        }
+    }
+
+    fn default_value(&self) -> &'static str {
+        match self {
+            LocalType::Number => "0.0",
+            LocalType::Boolean => "false",
+            LocalType::Text => "String::new()",
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            LocalType::Number => "number",
+            LocalType::Boolean => "boolean",
+            LocalType::Text => "text",
+        }
+    }
+}
+
+/// Lowers a parsed .slut program into a standalone Rust crate
+///
+/// Every variable becomes a typed local in `main`, loops and selections become
+/// Rust control flow and `x([t]) <> randomChoice(...)` lines are replaced by the
+/// equation the solver cached for them. The emitted crate has no dependencies.
+pub struct RustBackend {
+    solved: SolvedEquations,
+    max_while_iterations: Option<u64>,
+}
+
+impl RustBackend {
+    pub fn new(solved: SolvedEquations) -> Self {
+        Self { solved, max_while_iterations: Some(DEFAULT_MAX_WHILE_ITERATIONS) }
+    }
+
+    /// Passes one `while` loop may make before the program exits with an error; `None` for no cap
+    pub fn max_while_iterations(mut self, limit: Option<u64>) -> Self {
+        self.max_while_iterations = limit;
+        self
+    }
+
+    pub fn generate(&self, program: &Program) -> Result<GeneratedProject> {
+        let crate_name = crate_name_for(&program.main_class);
+        let main_rs = self.generate_main(program)?;
+
+        let cargo_toml = format!(
+            "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[dependencies]\n",
+            crate_name
+        );
+
+        Ok(GeneratedProject {
+            program_name: program.main_class.clone(),
+            files: vec![
+                (PathBuf::from("Cargo.toml"), cargo_toml),
+                (PathBuf::from("src/main.rs"), main_rs),
+            ],
+        })
+    }
+
+    fn generate_main(&self, program: &Program) -> Result<String> {
+        let functions: HashMap<&str, &FunctionClass> = program.function_classes.iter()
+            .map(|f| (f.name.as_str(), f))
+            .collect();
+
+        let types = infer_types(program, &functions)?;
+
+        let mut ctx = EmitContext {
+            program,
+            functions,
+            types: &types,
+            solved: &self.solved,
+            max_while_iterations: self.max_while_iterations,
+            constants: HashMap::new(),
+            loop_labels: Vec::new(),
+            call_stack: Vec::new(),
+            next_label: 0,
+            uses_rng: false,
+            uses_input: false,
+            uses_factorial: false,
+            uses_radix: false,
+            uses_exit: false,
+        };
+
+        let mut body = CodeWriter::new();
+        body.indent = 1;
+        for statement in &program.body {
+            ctx.emit_statement(&mut body, statement, true)?;
+        }
+        let body = body.finish();
+
+        let mut out = CodeWriter::new();
+        out.line(&format!("// Generated by quantum_slut_transpiler from class {}", program.main_class));
+        out.line("#![allow(non_snake_case, unused_mut, unused_assignments, unused_variables, unused_labels, unused_parens, unreachable_code)]");
+        out.line("");
+        if ctx.uses_input {
+            out.line("use std::io::{self, Write};");
+            out.line("");
+        }
+        if ctx.uses_rng {
+            emit_rng_runtime(&mut out);
+        }
+        if ctx.uses_factorial {
+            out.open("fn factorial(n: f64) -> f64 {");
+            out.line("(2..=n as u64).fold(1.0, |acc, i| acc * i as f64)");
+            out.close("}");
+            out.line("");
+        }
+        if ctx.uses_radix {
+            out.line("/// Whole numbers in base 16, 2 or 8, with a sign rather than two's complement");
+            out.open("fn radix(value: f64, kind: char) -> String {");
+            out.line("let n = value.abs() as u64;");
+            out.open("let digits = match kind {");
+            out.line("'x' => format!(\"{:x}\", n),");
+            out.line("'X' => format!(\"{:X}\", n),");
+            out.line("'b' => format!(\"{:b}\", n),");
+            out.line("_ => format!(\"{:o}\", n),");
+            out.close("};");
+            out.line("if value < 0.0 { format!(\"-{}\", digits) } else { digits }");
+            out.close("}");
+            out.line("");
+        }
+        if ctx.uses_exit {
+            out.line("/// `exit(code)` takes whole numbers in i32 range only, like the interpreter");
+            out.open("fn exit_with(code: f64, source: &str) -> ! {");
+            out.open("if code.fract() != 0.0 || code < i32::MIN as f64 || code > i32::MAX as f64 {");
+            out.line("eprintln!(\"exit({}) needs a whole number in i32 range, got {}\", source, code);");
+            out.line("std::process::exit(1);");
+            out.close("}");
+            out.line("std::process::exit(code as i32)");
+            out.close("}");
+            out.line("");
+        }
+        if ctx.uses_input {
+            out.open("fn read_input(prompt: &str) -> String {");
+            out.line("print!(\"{}: \", prompt);");
+            out.line("io::stdout().flush().unwrap();");
+            out.line("let mut input = String::new();");
+            out.line("io::stdin().read_line(&mut input).unwrap();");
+            out.line("input.trim().to_string()");
+            out.close("}");
+            out.line("");
+        }
+
+        out.open("fn main() {");
+        let mut names: Vec<&String> = types.keys().collect();
+        names.sort();
+        for name in names {
+            let local_type = types[name];
+            out.line(&format!(
+                "let mut {}: {} = {};",
+                local_name(name), local_type.rust_type(), local_type.default_value()
+            ));
+        }
+        if ctx.uses_rng {
+            out.line("let mut rng = Rng::from_time();");
+        }
+        let mut text = out.finish();
+        text.push_str(&body);
+        text.push_str("}\n");
+        Ok(text)
+    }
+}
+
+struct EmitContext<'a> {
+    program: &'a Program,
+    functions: HashMap<&'a str, &'a FunctionClass>,
+    types: &'a HashMap<String, LocalType>,
+    solved: &'a SolvedEquations,
+    max_while_iterations: Option<u64>,
+    /// Numeric values known at compile time, used to resolve solver targets
+    constants: HashMap<String, f64>,
+    /// (source label, generated label) of each enclosing loop, innermost last
+    loop_labels: Vec<(Option<String>, String)>,
+    /// (function name, block label) for inlined calls
+    call_stack: Vec<(String, String, String)>,
+    next_label: usize,
+    uses_rng: bool,
+    uses_input: bool,
+    uses_factorial: bool,
+    uses_radix: bool,
+    uses_exit: bool,
+}
+
+impl<'a> EmitContext<'a> {
+    fn fresh_label(&mut self, prefix: &str) -> String {
+        self.next_label += 1;
+        format!("'{}_{}", prefix, self.next_label)
+    }
+
+    /// `__slut_<kind><n>` for the construct labelled `'<prefix>_<n>`
+    fn generated_local(&self, kind: &str, label: &str) -> String {
+        let number = label.rsplit('_').next().unwrap_or_default();
+        format!("{}{}{}", GENERATED_PREFIX, kind, number)
+    }
+
+    fn local_type(&self, name: &str) -> Option<LocalType> {
+        self.types.get(name).copied()
+    }
+
+    /// `straight_line` is false inside loops and branches, where assignments
+    /// no longer give compile-time constants
+    fn emit_statement(&mut self, out: &mut CodeWriter, statement: &Statement, straight_line: bool) -> Result<()> {
+        match statement {
+            Statement::Assign { name, value } => {
+                let rendered = match value {
+                    Literal::Number(n) => {
+                        if straight_line {
+                            self.constants.insert(name.clone(), *n);
+                        } else {
+                            self.constants.remove(name);
+                        }
+                        number_literal(*n)
+                    }
+                    // Generated programs are f64 throughout, so this rounds and is no constant to solve against
+                    Literal::Integer(n) => {
+                        self.constants.remove(name);
+                        number_literal(n.to_f64())
+                    }
+                    Literal::Boolean(b) => b.to_string(),
+                    Literal::Text(s) => format!("{:?}.to_string()", s),
+                };
+                out.line(&format!("{} = {};", local_name(name), rendered));
+            }
+            Statement::Calc { name, params } => {
+                self.constants.remove(name);
+                if params.len() < 2 {
+                    out.line("// calc() requires at least 2 parameters");
+                    return Ok(());
+                }
+                let mut terms = Vec::new();
+                for param in params {
+                    match self.numeric_operand(param) {
+                        Some(term) => terms.push(term),
+                        None => {
+                            out.line(&format!("// calc skipped: could not resolve parameter {}", param));
+                            return Ok(());
+                        }
+                    }
+                }
+                out.line(&format!("{} = {};", local_name(name), terms.join(" + ")));
+            }
+            Statement::RandomChoice { name, choices } => {
+                self.constants.remove(name);
+                let target_type = self.local_type(name).unwrap_or(LocalType::Text);
+                let options: Vec<String> = choices.iter()
+                    .map(|choice| self.choice_value(choice, target_type))
+                    .collect();
+                if options.is_empty() {
+                    return Ok(());
+                }
+                self.uses_rng = true;
+                out.line(&format!(
+                    "{} = [{}][rng.below({})].clone();",
+                    local_name(name), options.join(", "), options.len()
+                ));
+            }
+            Statement::UserInput { name, prompt } => {
+                self.constants.remove(name);
+                self.uses_input = true;
+                let read = format!("read_input({:?})", prompt);
+                match self.local_type(name) {
+                    Some(LocalType::Number) => out.line(&format!(
+                        "{} = {}.parse::<f64>().unwrap_or(f64::NAN);", local_name(name), read
+                    )),
+                    _ => out.line(&format!("{} = {};", local_name(name), read)),
+                }
+            }
+            Statement::FunctionCall { name, function } => {
+                self.constants.remove(name);
+                self.emit_function_call(out, name, function)?;
+            }
+            Statement::SolveTarget { name, target, inputs, .. } => {
+                let target_value = target.parse::<f64>().ok()
+                    .or_else(|| self.constants.get(target.as_str()).copied());
+                let class_name = self.current_class().to_string();
+                let solution = self.solved.lookup(&class_name, name, target_value)
+                    .ok_or_else(|| anyhow::anyhow!(
+                        "No cached solution for '{}([{}]) <> randomChoice([{}])' in class {}; \
+                         run the program once with the interpreter first",
+                        name, target, inputs, class_name
+                    ))?;
+                let tree = EquationNode::parse(&solution.equation)?;
+                let expression = self.equation_to_rust(&tree);
+                out.line(&format!("// {} (accuracy {}%)", solution.equation, solution.accuracy));
+                out.line(&format!("{} = {};", local_name(name), expression));
+                if straight_line {
+                    self.constants.insert(name.clone(), tree.evaluate()?);
+                } else {
+                    self.constants.remove(name);
+                }
+            }
+            Statement::Speak { template } => {
+                let line = self.print_statement(template)?;
+                out.line(&line);
+            }
+            Statement::Woof { name } => {
+                if let Some((_, label, result_var)) = self.call_stack.last().cloned() {
+                    // Inside an inlined function class: woof returns the value
+                    if self.local_type(name).is_some() {
+                        out.line(&format!("{} = {}.clone();", local_name(&result_var), local_name(name)));
+                    }
+                    out.line(&format!("break {};", label));
+                } else if self.local_type(name).is_some() {
+                    out.line(&format!("println!(\"Final result: {{}}\", {});", local_name(name)));
+                } else {
+                    out.line(&format!("// woof {}: variable is never assigned", name));
+                }
+            }
+            Statement::Exit { code } => {
+                self.uses_exit = true;
+                out.line(&format!("exit_with({}, {:?});", self.translate_numeric(code)?, code));
+            }
+            Statement::Selection { branches } => {
+                for (index, branch) in branches.iter().enumerate() {
+                    let condition = self.translate_condition(&branch.condition)?;
+                    let header = if index == 0 {
+                        format!("if {} {{", condition)
+                    } else if index == branches.len() - 1 && branch.condition.trim() == "true" {
+                        "} else {".to_string()
+                    } else {
+                        format!("}} else if {} {{", condition)
+                    };
+                    if index == 0 {
+                        out.open(&header);
+                    } else {
+                        out.close(&header);
+                        out.indent += 1;
+                    }
+                    for statement in &branch.body {
+                        self.emit_statement(out, statement, false)?;
+                    }
+                }
+                out.close("}");
+            }
+            Statement::Match { scrutinee, arms, .. } => {
+                let Some(value_type) = scrutinee_type(scrutinee, self.types) else {
+                    out.line(&format!("// match {}: variable is never assigned", scrutinee));
+                    return Ok(());
+                };
+                let value = match (self.local_type(scrutinee), value_type) {
+                    (Some(_), _) => format!("{}.clone()", local_name(scrutinee)),
+                    (None, LocalType::Text) => format!("{:?}.to_string()", lexer::text_literal(scrutinee)),
+                    (None, _) => self.translate_numeric(scrutinee)?,
+                };
+
+                let label = self.fresh_label("match");
+                let matched = self.generated_local("match", &label);
+                out.open(&format!("{}: {{", label));
+                out.line(&format!("let {} = {};", matched, value));
+                for arm in arms {
+                    let mut tests = Vec::new();
+                    let mut binding = None;
+                    match (&arm.pattern, value_type) {
+                        (Pattern::Number(n), LocalType::Number) => {
+                            tests.push(format!("{} == {}", matched, number_literal(*n)));
+                        }
+                        (Pattern::Range { start, end, inclusive }, LocalType::Number) => tests.push(format!(
+                            "{} >= {} && {} {} {}",
+                            matched, number_literal(*start), matched, if *inclusive { "<=" } else { "<" }, number_literal(*end)
+                        )),
+                        (Pattern::Text(text), LocalType::Text) => tests.push(format!("{} == {:?}", matched, text)),
+                        (Pattern::Boolean(b), LocalType::Boolean) => tests.push(format!("{} == {}", matched, b)),
+                        (Pattern::Binding(name), _) => {
+                            self.constants.remove(name);
+                            binding = Some(local_name(name));
+                        }
+                        (Pattern::Wildcard, _) => {}
+                        // A literal of another type never matches
+                        _ => continue,
+                    }
+                    if let Some(guard) = &arm.guard {
+                        let guard = self.translate_condition(guard)?;
+                        // The guard reads a shadowing copy, so a failed guard leaves the variable alone
+                        tests.push(match &binding {
+                            Some(local) => format!("{{ let {} = {}.clone(); {} }}", local, matched, guard),
+                            None => guard,
+                        });
+                    }
+
+                    if tests.is_empty() {
+                        out.open("{");
+                    } else {
+                        out.open(&format!("if {} {{", tests.join(" && ")));
+                    }
+                    if let Some(local) = &binding {
+                        out.line(&format!("{} = {}.clone();", local, matched));
+                    }
+                    for statement in &arm.body {
+                        self.emit_statement(out, statement, false)?;
+                    }
+                    out.line(&format!("break {};", label));
+                    out.close("}");
+                }
+                out.close("}");
+            }
+            Statement::CountLoop { label: source_label, count, body } => {
+                let count_expr = self.translate_numeric(count)?;
+                let label = self.fresh_label("loop");
+                out.open(&format!("{}: for _ in 0..{} as u32 {{", label, count_expr));
+                self.emit_loop_body(out, source_label, label, body)?;
+                out.close("}");
+            }
+            // Children run in isolated frames whose writes merge afterwards; plain Rust locals cannot do that
+            Statement::Parallel { source, .. } | Statement::ParallelRangeLoop { source, .. } => {
+                return Err(anyhow::anyhow!("parallel is not supported by codegen: {}", first_line(source)));
+            }
+            Statement::RangeLoop { label: source_label, start, end, variable, body } => {
+                let start_expr = self.translate_numeric(start)?;
+                let end_expr = self.translate_numeric(end)?;
+                let label = self.fresh_label("loop");
+                let index = self.generated_local("i", &label);
+                self.constants.remove(variable);
+                out.open(&format!(
+                    "{}: for {} in {} as i32..{} as i32 {{", label, index, start_expr, end_expr
+                ));
+                out.line(&format!("{} = {} as f64;", local_name(variable), index));
+                self.emit_loop_body(out, source_label, label, body)?;
+                out.close("}");
+            }
+            Statement::WhileLoop { label: source_label, condition, body } => {
+                let condition = self.translate_condition(condition)?;
+                let label = self.fresh_label("loop");
+                let counter = self.generated_local("passes", &label);
+                if self.max_while_iterations.is_some() {
+                    out.line(&format!("let mut {} = 0u64;", counter));
+                }
+                out.open(&format!("{}: loop {{", label));
+                out.open(&format!("if !({}) {{", condition));
+                out.line("break;");
+                out.close("}");
+                if let Some(limit) = self.max_while_iterations {
+                    out.line(&format!("{} += 1;", counter));
+                    out.open(&format!("if {} > {} {{", counter, limit));
+                    out.line(&format!("eprintln!(\"While loop ran past its limit of {} iterations\");", limit));
+                    out.line("std::process::exit(1);");
+                    out.close("}");
+                }
+                self.emit_loop_body(out, source_label, label, body)?;
+                out.close("}");
+            }
+            Statement::Break { label } => match self.loop_target(label)? {
+                Some(target) => out.line(&format!("break {};", target)),
+                None => out.line("// break outside of loop"),
+            },
+            Statement::Continue { label } => match self.loop_target(label)? {
+                Some(target) => out.line(&format!("continue {};", target)),
+                None => out.line("// continue outside of loop"),
+            },
+            Statement::AllChoices { source, .. } => {
+                return Err(anyhow::anyhow!("allChoices is not supported by codegen: {}", source));
+            }
+            Statement::Unsupported { source } => {
+                out.line(&format!("// not supported by the Rust backend: {}", source));
+            }
+        }
+        Ok(())
+    }
+
+    fn emit_loop_body(&mut self, out: &mut CodeWriter, source_label: &Option<String>, label: String, body: &[Statement]) -> Result<()> {
+        // Values assigned anywhere in the loop are no longer compile-time constants
+        for name in assigned_names(body) {
+            self.constants.remove(&name);
+        }
+        self.loop_labels.push((source_label.clone(), label));
+        for statement in body {
+            self.emit_statement(out, statement, false)?;
+        }
+        self.loop_labels.pop();
+        Ok(())
+    }
+
+    /// Generated label a `break` / `continue` jumps to; the innermost loop without a label
+    fn loop_target(&self, label: &Option<String>) -> Result<Option<String>> {
+        match label {
+            None => Ok(self.loop_labels.last().map(|(_, generated)| generated.clone())),
+            Some(label) => self.loop_labels.iter().rev()
+                .find(|(source, _)| source.as_ref() == Some(label))
+                .map(|(_, generated)| Some(generated.clone()))
+                .ok_or_else(|| anyhow::anyhow!("Unknown loop label '{}'", label)),
+        }
+    }
+
+    fn emit_function_call(&mut self, out: &mut CodeWriter, name: &str, function: &str) -> Result<()> {
+        let Some(class) = self.functions.get(function).copied() else {
+            out.line(&format!("// function {} not found", function));
+            return Ok(());
+        };
+        if self.call_stack.len() >= MAX_INLINE_DEPTH
+            || self.call_stack.iter().any(|(called, _, _)| called == function) {
+            return Err(anyhow::anyhow!(
+                "Function class {} calls itself; recursion is not supported by the Rust backend", function
+            ));
+        }
+
+        let label = self.fresh_label("call");
+        // Function bodies without a woof return 0, like the interpreter
+        if self.local_type(name) == Some(LocalType::Number) {
+            out.line(&format!("{} = 0.0;", local_name(name)));
+        }
+        out.open(&format!("{}: {{", label));
+        self.call_stack.push((function.to_string(), label, name.to_string()));
+        // Loops outside the function are not visible from its body
+        let outer_loops = std::mem::take(&mut self.loop_labels);
+        for statement in &class.body {
+            self.emit_statement(out, statement, false)?;
+        }
+        self.loop_labels = outer_loops;
+        self.call_stack.pop();
+        out.close("}");
+        Ok(())
+    }
+
+    fn current_class(&self) -> &str {
+        self.call_stack.last()
+            .map(|(function, _, _)| function.as_str())
+            .unwrap_or(&self.program.main_class)
+    }
+
+    fn print_statement(&mut self, template: &str) -> Result<String> {
+        let mut format_string = String::new();
+        let mut args = Vec::new();
+
+        for part in split_template(template) {
+            match part {
+                TemplatePart::Text(text) => {
+                    format_string.push_str(&text.replace('{', "{{").replace('}', "}}"));
+                }
+                TemplatePart::Variable(name) => {
+                    // An undefined variable prints nothing, as in the interpreter
+                    if self.local_type(&name).is_some() {
+                        format_string.push_str("{}");
+                        args.push(local_name(&name));
+                    }
+                }
+                TemplatePart::Expression { expression, spec } => {
+                    let spec = spec.as_deref().map(FormatSpec::parse).transpose()?.unwrap_or_default();
+                    // Neither does an expression the interpreter could not evaluate
+                    if has_unknown_identifier(&expression, self.types) {
+                        continue;
+                    }
+                    let value = translate_expression(&expression, self.types)?;
+                    let (placeholder, arg) = self.format_argument(&spec, value)?;
+                    format_string.push_str(&placeholder);
+                    args.push(arg);
+                }
+            }
+        }
+
+        let literal = format!("{:?}", format_string);
+        if args.is_empty() {
+            Ok(format!("println!({});", literal))
+        } else {
+            Ok(format!("println!({}, {});", literal, args.join(", ")))
+        }
+    }
+
+    /// Rust placeholder and argument for a `~value:spec~`
+    ///
+    /// Base 16, 2 and 8 go through the `radix` helper and are padded as text,
+    /// so they align right unless told otherwise.
+    fn format_argument(&mut self, spec: &FormatSpec, value: String) -> Result<(String, String)> {
+        if matches!(spec.fill, '{' | '}') {
+            return Err(anyhow::anyhow!("Fill character '{}' is not supported by the Rust backend", spec.fill));
+        }
+
+        let radix_kind = match spec.kind {
+            FormatKind::Hex => Some('x'),
+            FormatKind::UpperHex => Some('X'),
+            FormatKind::Binary => Some('b'),
+            FormatKind::Octal => Some('o'),
+            FormatKind::Plain | FormatKind::Exponent => None,
+        };
+        let (fill, align) = match (radix_kind, spec.align) {
+            (Some(_), None) => (if spec.zero_pad { '0' } else { spec.fill }, Some(Align::Right)),
+            _ => (spec.fill, spec.align),
+        };
+
+        let mut placeholder = String::from("{:");
+        if let Some(align) = align {
+            placeholder.push(fill);
+            placeholder.push(match align {
+                Align::Left => '<',
+                Align::Right => '>',
+                Align::Center => '^',
+            });
+        }
+        if spec.zero_pad && radix_kind.is_none() {
+            placeholder.push('0');
+        }
+        if spec.width > 0 {
+            placeholder.push_str(&spec.width.to_string());
+        }
+        if let (Some(precision), None) = (spec.precision, radix_kind) {
+            placeholder.push_str(&format!(".{}", precision));
+        }
+        if spec.kind == FormatKind::Exponent {
+            placeholder.push('e');
+        }
+        placeholder.push('}');
+
+        let arg = match radix_kind {
+            Some(kind) => {
+                self.uses_radix = true;
+                format!("radix({}, {:?})", value, kind)
+            }
+            None => value,
+        };
+        Ok((placeholder, arg))
+    }
+
+    fn numeric_operand(&self, operand: &str) -> Option<String> {
+        if let Ok(num) = operand.parse::<f64>() {
+            Some(number_literal(num))
+        } else if self.local_type(operand) == Some(LocalType::Number) {
+            Some(local_name(operand))
+        } else {
+            None
+        }
+    }
+
+    fn choice_value(&self, choice: &str, target_type: LocalType) -> String {
+        if let Ok(num) = choice.parse::<f64>() {
+            if target_type == LocalType::Number {
+                return number_literal(num);
+            }
+        }
+        if self.local_type(choice).is_some() {
+            return local_name(choice);
+        }
+        format!("{:?}.to_string()", choice.trim_matches('"'))
+    }
+
+    fn translate_numeric(&self, expression: &str) -> Result<String> {
+        translate_expression(expression, self.types)
+    }
+
+    fn translate_condition(&self, condition: &str) -> Result<String> {
+        translate_expression(condition, self.types)
+    }
+
+    fn equation_to_rust(&mut self, node: &EquationNode) -> String {
+        match node {
+            EquationNode::Number(n) => number_literal(*n),
+            EquationNode::Negate(inner) => format!("(-{})", self.equation_to_rust(inner)),
+            EquationNode::Factorial(inner) => {
+                self.uses_factorial = true;
+                format!("factorial({})", self.equation_to_rust(inner))
+            }
+            EquationNode::Binary { op, left, right } => {
+                let a = self.equation_to_rust(left);
+                let b = self.equation_to_rust(right);
+                match op {
+                    BinaryOp::Pow => format!("{}.powf({})", a, b),
+                    _ => format!("({} {} {})", a, op.symbol(), b),
+                }
+            }
+            EquationNode::Call { name, args } => {
+                let args: Vec<String> = args.iter().map(|a| self.equation_to_rust(a)).collect();
+                match (name.as_str(), args.as_slice()) {
+                    ("avg", [a, b]) => format!("(({} + {}) / 2.0)", a, b),
+                    ("avg", [a, b, c]) => format!("(({} + {} + {}) / 3.0)", a, b, c),
+                    ("geomean", [a, b]) => format!("({} * {}).sqrt()", a, b),
+                    ("geomean", [a, b, c]) => format!("({} * {} * {}).cbrt()", a, b, c),
+                    (method, [a]) => format!("{}.{}()", a, method),
+                    (method, [a, b]) => format!("{}.{}({})", a, method, b),
+                    (method, _) => format!("/* unsupported {} */ f64::NAN", method),
+                }
+            }
+            EquationNode::Slot(i) => format!("/* unfilled slot {} */ f64::NAN", i),
+        }
+    }
+}
+
+/// Collect a type for every variable the program assigns
+fn infer_types(program: &Program, functions: &HashMap<&str, &FunctionClass>) -> Result<HashMap<String, LocalType>> {
+    let mut assignments: Vec<(String, Source)> = Vec::new();
+    collect_assignments(&program.body, &mut assignments);
+    for function in &program.function_classes {
+        collect_assignments(&function.body, &mut assignments);
+    }
+
+    let mut types: HashMap<String, LocalType> = HashMap::new();
+
+    // Resolve until nothing changes: choices and calls depend on other variables
+    for _ in 0..=assignments.len() {
+        let mut changed = false;
+        for (name, source) in &assignments {
+            let resolved = match source {
+                Source::Fixed(local_type) => Some(*local_type),
+                Source::Choices(choices) => choice_type(choices, &types),
+                Source::Call(function) => match functions.get(function.as_str()) {
+                    Some(class) => match woof_name(&class.body) {
+                        Some(returned) => types.get(&returned).copied(),
+                        None => Some(LocalType::Number),
+                    },
+                    None => None,
+                },
+                Source::UserInput => None,
+                Source::Scrutinee(scrutinee) => scrutinee_type(scrutinee, &types),
+            };
+            if let Some(local_type) = resolved {
+                match types.get(name) {
+                    Some(existing) if *existing != local_type => {
+                        return Err(anyhow::anyhow!(
+                            "Variable '{}' is assigned both {} and {} values; \
+                             the Rust backend needs one type per variable",
+                            name, existing.name(), local_type.name()
+                        ));
+                    }
+                    Some(_) => {}
+                    None => {
+                        types.insert(name.clone(), local_type);
+                        changed = true;
+                    }
+                }
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    // userIn answers are text unless the variable is used as a number elsewhere
+    for (name, source) in &assignments {
+        if matches!(source, Source::UserInput) {
+            types.entry(name.clone()).or_insert(LocalType::Text);
+        }
+    }
+    // Anything still unresolved (choices over unknown variables) defaults to text
+    for (name, _) in &assignments {
+        types.entry(name.clone()).or_insert(LocalType::Text);
+    }
+
+    if let Some(name) = types.keys().find(|name| name.starts_with(GENERATED_PREFIX)) {
+        return Err(anyhow::anyhow!(
+            "Variable '{}' starts with '{}', which the Rust backend reserves for its own locals",
+            name, GENERATED_PREFIX
+        ));
+    }
+
+    Ok(types)
+}
+
+enum Source {
+    Fixed(LocalType),
+    Choices(Vec<String>),
+    Call(String),
+    UserInput,
+    /// A match binding takes the type of the value matched on
+    Scrutinee(String),
+}
+
+fn collect_assignments(statements: &[Statement], out: &mut Vec<(String, Source)>) {
+    for statement in statements {
+        match statement {
+            Statement::Assign { name, value } => {
+                let local_type = match value {
+                    Literal::Number(_) | Literal::Integer(_) => LocalType::Number,
+                    Literal::Boolean(_) => LocalType::Boolean,
+                    Literal::Text(_) => LocalType::Text,
+                };
+                out.push((name.clone(), Source::Fixed(local_type)));
+            }
+            Statement::Calc { name, .. } | Statement::SolveTarget { name, .. } => {
+                out.push((name.clone(), Source::Fixed(LocalType::Number)));
+            }
+            Statement::RandomChoice { name, choices } => {
+                out.push((name.clone(), Source::Choices(choices.clone())));
+            }
+            Statement::UserInput { name, .. } => out.push((name.clone(), Source::UserInput)),
+            Statement::FunctionCall { name, function } => {
+                out.push((name.clone(), Source::Call(function.clone())));
+            }
+            Statement::RangeLoop { variable, body, .. }
+            | Statement::ParallelRangeLoop { variable, body, .. } => {
+                out.push((variable.clone(), Source::Fixed(LocalType::Number)));
+                collect_assignments(body, out);
+            }
+            Statement::CountLoop { body, .. }
+            | Statement::WhileLoop { body, .. }
+            | Statement::Parallel { body, .. } => {
+                collect_assignments(body, out);
+            }
+            Statement::Selection { branches } => {
+                for branch in branches {
+                    collect_assignments(&branch.body, out);
+                }
+            }
+            Statement::Match { scrutinee, arms, .. } => {
+                for arm in arms {
+                    if let Pattern::Binding(name) = &arm.pattern {
+                        out.push((name.clone(), Source::Scrutinee(scrutinee.clone())));
+                    }
+                    collect_assignments(&arm.body, out);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn assigned_names(statements: &[Statement]) -> Vec<String> {
+    let mut assignments = Vec::new();
+    collect_assignments(statements, &mut assignments);
+    assignments.into_iter().map(|(name, _)| name).collect()
+}
+
+/// Type of a match scrutinee: the variable's, the literal's, or a number for arithmetic
+///
+/// `None` while the variable has no type yet.
+fn scrutinee_type(scrutinee: &str, types: &HashMap<String, LocalType>) -> Option<LocalType> {
+    if let Some(local_type) = types.get(scrutinee) {
+        return Some(*local_type);
+    }
+    match parser::parse_literal(scrutinee) {
+        Literal::Number(_) | Literal::Integer(_) => Some(LocalType::Number),
+        Literal::Boolean(_) => Some(LocalType::Boolean),
+        Literal::Text(_) if scrutinee.starts_with('"') => Some(LocalType::Text),
+        Literal::Text(_) if scrutinee.chars().all(|c| c.is_alphanumeric() || c == '_') => None,
+        Literal::Text(_) => Some(LocalType::Number),
+    }
+}
+
+fn choice_type(choices: &[String], types: &HashMap<String, LocalType>) -> Option<LocalType> {
+    let mut result = None;
+    for choice in choices {
+        let choice_type = if choice.parse::<f64>().is_ok() {
+            LocalType::Number
+        } else if let Some(local_type) = types.get(choice) {
+            *local_type
+        } else if choice.chars().all(|c| c.is_alphanumeric() || c == '_') && !choice.is_empty() {
+            // Might be a variable we have not typed yet
+            return None;
+        } else {
+            LocalType::Text
+        };
+        match result {
+            None => result = Some(choice_type),
+            Some(existing) if existing != choice_type => return Some(LocalType::Text),
+            _ => {}
+        }
+    }
+    result
+}
+
+fn woof_name(statements: &[Statement]) -> Option<String> {
+    statements.iter().find_map(|statement| match statement {
+        Statement::Woof { name } => Some(name.clone()),
+        _ => None,
+    })
+}
+
+/// Translate an evalexpr condition or arithmetic expression into Rust
+///
+/// Numeric literals become `f64`, identifiers become locals. An identifier
+/// that names no local is an error rather than a value the program computes with.
+fn translate_expression(expression: &str, types: &HashMap<String, LocalType>) -> Result<String> {
+    let chars: Vec<char> = expression.chars().collect();
+    let mut out = String::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_ascii_digit() || (c == '.' && i + 1 < chars.len() && chars[i + 1].is_ascii_digit()) {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            let literal: String = chars[start..i].iter().collect();
+            out.push_str(&number_literal(literal.parse()?));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let ident: String = chars[start..i].iter().collect();
+            if ident == "true" || ident == "false" {
+                out.push_str(&ident);
+            } else if types.contains_key(&ident) {
+                out.push_str(&local_name(&ident));
+            } else {
+                return Err(anyhow::anyhow!("Undefined variable '{}' in '{}'", ident, expression));
+            }
+        } else if c == '"' {
+            let start = i;
+            i += 1;
+            while i < chars.len() && chars[i] != '"' {
+                if chars[i] == '\\' {
+                    i += 1;
+                }
+                i += 1;
+            }
+            i += 1;
+            let literal: String = chars[start..i.min(chars.len())].iter().collect();
+            out.push_str(&literal);
+        } else if c == '^' {
+            return Err(anyhow::anyhow!(
+                "Exponentiation in '{}' is not supported by the Rust backend", expression
+            ));
+        } else {
+            out.push(c);
+            i += 1;
+        }
+    }
+
+    Ok(format!("({})", out.trim()))
+}
+
+/// Whether `expression` names something that is not a local
+fn has_unknown_identifier(expression: &str, types: &HashMap<String, LocalType>) -> bool {
+    let mut in_string = false;
+    let mut ident = String::new();
+
+    for c in expression.chars().chain(std::iter::once(' ')) {
+        if in_string {
+            in_string = c != '"';
+        } else if c.is_alphanumeric() || c == '_' {
+            ident.push(c);
+        } else {
+            let starts_with_letter = ident.starts_with(|c: char| c.is_alphabetic() || c == '_');
+            if starts_with_letter && ident != "true" && ident != "false" && !types.contains_key(&ident) {
+                return true;
+            }
+            ident.clear();
+            in_string = c == '"';
+        }
+    }
+    false
+}
+
+fn first_line(source: &str) -> &str {
+    source.lines().next().unwrap_or_default().trim()
+}
+
+fn number_literal(n: f64) -> String {
+    if n.is_nan() {
+        "f64::NAN".to_string()
+    } else if n.is_infinite() {
+        if n > 0.0 { "f64::INFINITY".to_string() } else { "f64::NEG_INFINITY".to_string() }
+    } else if n < 0.0 {
+        format!("({:?}_f64)", n)
+    } else {
+        format!("{:?}_f64", n)
+    }
+}
+
+fn local_name(name: &str) -> String {
+    if RUST_KEYWORDS.contains(&name) {
+        format!("r#{}", name)
+    } else {
+        name.to_string()
+    }
+}
+
+fn crate_name_for(class_name: &str) -> String {
+    let mut name = String::new();
+    for (index, c) in class_name.chars().enumerate() {
+        if c.is_uppercase() && index > 0 {
+            name.push('_');
+        }
+        name.push(c.to_ascii_lowercase());
+    }
+    if name.is_empty() {
+        "slut_program".to_string()
+    } else {
+        name
+    }
+}
+
+fn emit_rng_runtime(out: &mut CodeWriter) {
+    out.line("/// Small xorshift generator so the crate needs no dependencies");
+    out.line("struct Rng(u64);");
+    out.line("");
+    out.open("impl Rng {");
+    out.open("fn from_time() -> Self {");
+    out.line("let nanos = std::time::SystemTime::now()");
+    out.line("    .duration_since(std::time::UNIX_EPOCH)");
+    out.line("    .map(|d| d.as_nanos() as u64)");
+    out.line("    .unwrap_or(0x2545F4914F6CDD1D);");
+    out.line("Rng(nanos | 1)");
+    out.close("}");
+    out.line("");
+    out.open("fn below(&mut self, n: usize) -> usize {");
+    out.line("self.0 ^= self.0 << 13;");
+    out.line("self.0 ^= self.0 >> 7;");
+    out.line("self.0 ^= self.0 << 17;");
+    out.line("(self.0 % n as u64) as usize");
+    out.close("}");
+    out.close("}");
+    out.line("");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::MathSolution;
+
+    const PROGRAM: &str = r#"
+* <main> LearnToSolve {
+    ^ observe_execution {
+        targetNum <> 250
+        firstInput <> 3
+        secondInput <> 7
+        speak("Searching for: ~targetNum~ using [~firstInput~, ~secondInput~, ?]")
+        result([targetNum]) <> randomChoice([secondInput, firstInput, ?])
+        speak("RESULT: ~result~")
+        loop <> range(0, 2) as i {
+            if <> (i > 0) <else> (true) {
+                speak("late ~i~")
+                <>
+                speak("early")
+            }
+        }
+        woof result
+    }
+}
+"#;
+
+    fn solved() -> SolvedEquations {
+        let mut solutions = HashMap::new();
+        solutions.insert("LearnToSolve-result-250-7,3,229".to_string(), MathSolution {
+            result: 250.0,
+            equation: "7 * 3 + 229".to_string(),
+            accuracy: 100.0,
+            timestamp: 1,
+            attempts: 1,
+            formula: None,
+            exact: None,
+            cost: None,
+        });
+        SolvedEquations::new(solutions)
+    }
+
+    #[test]
+    fn test_generate_main() {
+        let program = Parser::new().unwrap().parse_program(PROGRAM).unwrap();
+        let project = RustBackend::new(solved()).generate(&program).unwrap();
+        let main_rs = project.file("src/main.rs").unwrap();
+
+        assert!(main_rs.contains("let mut targetNum: f64 = 0.0;"));
+        assert!(main_rs.contains("result = ((7.0_f64 * 3.0_f64) + 229.0_f64);"));
+        assert!(main_rs.contains("println!(\"RESULT: {}\", result);"));
+        assert!(main_rs.contains("for __slut_i1 in (0.0_f64) as i32..(2.0_f64) as i32 {"));
+        assert!(main_rs.contains("i = __slut_i1 as f64;"));
+        assert!(!main_rs.contains("else if (true)"));
+        assert!(main_rs.contains("if (i > 0.0_f64) {"));
+        assert!(main_rs.contains("} else {"));
+        assert!(main_rs.contains("println!(\"Final result: {}\", result);"));
+        assert!(project.file("Cargo.toml").unwrap().contains("name = \"learn_to_solve\""));
+    }
+
+    #[test]
+    fn test_missing_solution_is_an_error() {
+        let program = Parser::new().unwrap().parse_program(PROGRAM).unwrap();
+        let backend = RustBackend::new(SolvedEquations::new(HashMap::new()));
+        assert!(backend.generate(&program).is_err());
+    }
+
+    #[test]
+    fn test_conflicting_types_are_rejected() {
+        let source = "* <main> Mixed {\n ^ observe_execution {\n x <> 5\n x <> hello\n }\n}";
+        let program = Parser::new().unwrap().parse_program(source).unwrap();
+        let backend = RustBackend::new(SolvedEquations::new(HashMap::new()));
+        assert!(backend.generate(&program).is_err());
+    }
+
+    #[test]
+    fn test_parallel_is_rejected_rather_than_run_sequentially() {
+        let source = r#"* <main> Fan {
+    ^ observe_execution {
+        a <> 0
+        b <> 0
+        parallel {
+            a <> calc(a, 1)
+            b <> calc(a, 1)
+        }
+        speak("~a~ ~b~")
+    }
+}"#;
+        // Siblings start from the same frame, so `b` never sees the child's `a`
+        let mut engine = crate::Engine::builder().build().unwrap();
+        assert_eq!(engine.run_source(source).unwrap().program_lines(), ["1 1"]);
+
+        let ranged = source.replace("parallel {", "loop <> parallel range(0, 2) as i {");
+        for source in [source.to_string(), ranged] {
+            let program = Parser::new().unwrap().parse_program(&source).unwrap();
+            let error = RustBackend::new(SolvedEquations::new(HashMap::new())).generate(&program).unwrap_err();
+            assert!(error.to_string().starts_with("parallel is not supported by codegen:"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn test_exit_codes_must_be_whole_i32s() {
+        let source = |code: &str| format!("* <main> Leave {{\n ^ observe_execution {{\n speak(\"bye\")\n exit({})\n }}\n}}", code);
+        let program = Parser::new().unwrap().parse_program(&source("2.5")).unwrap();
+        let project = RustBackend::new(SolvedEquations::new(HashMap::new())).generate(&program).unwrap();
+        let main_rs = project.file("src/main.rs").unwrap();
+        assert!(main_rs.contains("exit_with((2.5_f64), \"2.5\");"));
+        assert!(main_rs.contains("if code.fract() != 0.0 || code < i32::MIN as f64 || code > i32::MAX as f64 {"));
+        assert!(!main_rs.contains("as i32);"));
+
+        // The interpreter refuses 2.5 too; the crate exits 1 instead of truncating to 2
+        let mut engine = crate::Engine::builder().build().unwrap();
+        assert!(engine.run_source(&source("2.5")).unwrap_err().to_string().contains("needs a whole number in i32 range"));
+        assert_eq!(run_generated(&project, "exit-fraction"), (vec!["bye".to_string()], Some(1)));
+
+        let program = Parser::new().unwrap().parse_program(&source("4")).unwrap();
+        let project = RustBackend::new(SolvedEquations::new(HashMap::new())).generate(&program).unwrap();
+        assert_eq!(run_generated(&project, "exit-whole"), (vec!["bye".to_string()], Some(4)));
+    }
+
+    #[test]
+    fn test_all_choices_is_rejected() {
+        let source = "* <main> Alts {\n ^ observe_execution {\n fives([5]) <> allChoices([3, 4, 1])\n }\n}";
+        let program = Parser::new().unwrap().parse_program(source).unwrap();
+        let error = RustBackend::new(SolvedEquations::new(HashMap::new())).generate(&program).unwrap_err();
+        assert!(error.to_string().contains("allChoices is not supported by codegen"), "{}", error);
+    }
+
+    #[test]
+    fn test_while_loops_are_capped() {
+        let source = "* <main> Spin {\n ^ observe_execution {\n loop <> while(true) {\n }\n }\n}";
+        let program = Parser::new().unwrap().parse_program(source).unwrap();
+
+        let project = RustBackend::new(SolvedEquations::new(HashMap::new())).generate(&program).unwrap();
+        let main_rs = project.file("src/main.rs").unwrap();
+        assert!(main_rs.contains("let mut __slut_passes1 = 0u64;"));
+        assert!(main_rs.contains("if __slut_passes1 > 10000 {"));
+        assert!(main_rs.contains("std::process::exit(1);"));
+
+        let backend = RustBackend::new(SolvedEquations::new(HashMap::new())).max_while_iterations(None);
+        assert!(!backend.generate(&program).unwrap().file("src/main.rs").unwrap().contains("__slut_passes1"));
+    }
+
+    #[test]
+    fn test_generated_locals_never_collide_with_program_variables() {
+        let source = "* <main> Indexed {\n ^ observe_execution {\n index <> 10\n loop <> range(0, 3) as i {\n index <> calc(index, i)\n }\n speak(\"~index~\")\n }\n}";
+        let program = Parser::new().unwrap().parse_program(source).unwrap();
+        let main_rs = RustBackend::new(SolvedEquations::new(HashMap::new())).generate(&program).unwrap()
+            .file("src/main.rs").unwrap().to_string();
+        assert!(main_rs.contains("let mut index: f64 = 0.0;"));
+        assert!(main_rs.contains("'loop_1: for __slut_i1 in (0.0_f64) as i32..(3.0_f64) as i32 {"));
+        assert!(main_rs.contains("index = index + i;"));
+
+        let reserved = source.replace("index", "__slut_i1");
+        let program = Parser::new().unwrap().parse_program(&reserved).unwrap();
+        let error = RustBackend::new(SolvedEquations::new(HashMap::new())).generate(&program).unwrap_err();
+        assert!(error.to_string().contains("reserves for its own locals"), "{}", error);
+    }
+
+    #[test]
+    fn test_undefined_identifiers_are_errors() {
+        let types = HashMap::from([("n".to_string(), LocalType::Number)]);
+        assert_eq!(translate_expression("n > 2", &types).unwrap(), "(n > 2.0_f64)");
+        let error = translate_expression("n + missing", &types).unwrap_err();
+        assert_eq!(error.to_string(), "Undefined variable 'missing' in 'n + missing'");
+
+        for line in ["loop <> count(missing) {\n }", "loop <> range(0, missing) as i {\n }", "exit(missing)"] {
+            let source = format!("* <main> Missing {{\n ^ observe_execution {{\n {}\n }}\n}}", line);
+            let program = Parser::new().unwrap().parse_program(&source).unwrap();
+            let error = RustBackend::new(SolvedEquations::new(HashMap::new())).generate(&program).unwrap_err();
+            assert!(error.to_string().contains("Undefined variable 'missing'"), "{}: {}", line, error);
+        }
+    }
+
+    /// Build the crate with cargo and run it; its stdout lines and exit code
+    fn run_generated(project: &GeneratedProject, label: &str) -> (Vec<String>, Option<i32>) {
+        let dir = tempfile::Builder::new().prefix(&format!("slut-codegen-{}-", label)).tempdir().unwrap();
+        project.write_to(dir.path()).unwrap();
+
+        let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+        let output = std::process::Command::new(cargo)
+            .args(["run", "--quiet", "--offline"])
+            .current_dir(dir.path())
+            .env_remove("CARGO_TARGET_DIR")
+            .output()
+            .unwrap();
+        assert!(output.status.code().is_some(), "{}", String::from_utf8_lossy(&output.stderr));
+
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        (stdout.lines().map(str::to_string).collect(), output.status.code())
+    }
+
+    #[test]
+    fn test_generated_crate_prints_what_the_interpreter_prints() {
+        let source = r#"* Double {
+    ^ observe_execution {
+        doubled <> calc(21, 21)
+        woof doubled
+    }
+}
+
+* <main> Parity {
+    ^ observe_execution {
+        index <> 0
+        label <> "steps"
+        answer <> Double()
+        result([12]) <> randomChoice([3, 4, ?])
+        speak("~label~ toward ~result~, answer ~answer~")
+        loop <> count(3) {
+            index <> calc(index, 2)
+        }
+        loop <> range(1, 4) as i {
+            if <> (i == 2) <else> (true) {
+                speak("two")
+                <>
+                speak("i = ~i~, index = ~index~")
+            }
+        }
+        loop <> while(index < 10) {
+            index <> calc(index, 1)
+        }
+        n <> 5
+        match index {
+            n if n > 100 => { speak("huge ~n~") }
+            10 => { speak("ten") }
+            _ => { speak("other") }
+        }
+        speak("n is still ~n~")
+        speak("total ~index + result:>6.1~")
+        woof result
+        exit(3)
+    }
+}"#;
+        let mut engine = crate::Engine::builder().seed(7).build().unwrap();
+        let outcome = engine.run_source(source).unwrap();
+        let project = engine.generate_rust(source).unwrap();
+
+        let (lines, status) = run_generated(&project, "parity");
+        assert_eq!(lines, outcome.program_lines());
+        assert_eq!(status, Some(outcome.exit_code));
+        assert_eq!(outcome.exit_code, 3);
+    }
+}
@@ -2,12 +2,29 @@ use anyhow::Result;
 use evalexpr::*;
 use std::collections::HashMap;
 use crate::{StoredVariable, VariableValue};
+use crate::native_functions::{self, NativeFunctions};
+use crate::output_sink::{SharedSink, StdoutSink};
 
-pub struct ConditionEvaluator;
+pub struct ConditionEvaluator {
+    output: SharedSink,
+    natives: NativeFunctions,
+}
 
 impl ConditionEvaluator {
     pub fn new() -> Self {
-        Self
+        Self {
+            output: StdoutSink::shared(),
+            natives: NativeFunctions::default(),
+        }
+    }
+
+    pub fn set_output_sink(&mut self, sink: SharedSink) {
+        self.output = sink;
+    }
+
+    /// Make host-registered functions callable from conditions
+    pub fn set_native_functions(&mut self, natives: NativeFunctions) {
+        self.natives = natives;
     }
 
     /// Evaluates a boolean condition expression with variable substitution
@@ -24,38 +41,63 @@ impl ConditionEvaluator {
         condition: &str,
         variables: &HashMap<String, StoredVariable>
     ) -> Result<bool> {
-        // Create evalexpr context
+        let context = self.context(variables)?;
+
+        // Variables are stored as floats, so compare against float literals too
+        // (evalexpr treats `3.0 == 3` as false)
+        let condition = float_literals(&subscripts(condition));
+
+        // Evaluate the boolean expression
+        match eval_boolean_with_context(&condition, &context) {
+            Ok(result) => {
+                Ok(result)
+            }
+            Err(e) => {
+                self.output.warn(&format!("!! Error evaluating condition '{}': {}", condition, e));
+                self.output.debug("   Defaulting to false");
+                Ok(false) // Default to false on error
+            }
+        }
+    }
+
+    /// Evaluates an expression to a value, e.g. the right-hand side of an assignment
+    ///
+    /// Unlike `evaluate`, errors (including ones raised by native functions) are returned.
+    pub fn evaluate_value(
+        &self,
+        expression: &str,
+        variables: &HashMap<String, StoredVariable>
+    ) -> Result<VariableValue> {
+        let context = self.context(variables)?;
+        let value = eval_with_context(&float_literals(&subscripts(expression)), &context)
+            .map_err(|e| anyhow::anyhow!("Error evaluating '{}': {}", expression, e))?;
+        Ok(native_functions::from_evalexpr(&value))
+    }
+
+    /// evalexpr context holding every variable and native function
+    fn context(&self, variables: &HashMap<String, StoredVariable>) -> Result<HashMapContext> {
         let mut context = HashMapContext::new();
 
-        // Add all variables to the evaluation context
         for (name, var) in variables {
             match &var.value {
-                VariableValue::Number(n) => {
-                    context.set_value(name.clone(), Value::from(*n))?;
-                }
-                VariableValue::Boolean(b) => {
-                    context.set_value(name.clone(), Value::from(*b))?;
-                }
-                VariableValue::String(s) => {
-                    context.set_value(name.clone(), Value::from(s.as_str()))?;
-                }
                 VariableValue::FunctionResult(_) => {
                     // Skip function results for now
                 }
+                value => {
+                    context.set_value(name.clone(), native_functions::to_evalexpr(value))?;
+                }
             }
         }
 
-        // Evaluate the boolean expression
-        match eval_boolean_with_context(condition, &context) {
-            Ok(result) => {
-                Ok(result)
-            }
-            Err(e) => {
-                println!("!! Error evaluating condition '{}': {}", condition, e);
-                println!("   Defaulting to false");
-                Ok(false) // Default to false on error
-            }
+        for (name, function) in float_builtins() {
+            context.set_function(name.to_string(), function)?;
+        }
+        for native in self.natives.iter() {
+            context.set_function(native.name.clone(), native.to_evalexpr())?;
         }
+        context.set_function(INDEX_FUNCTION.to_string(), Function::new(index_list))?;
+
+        Ok(context)
     }
 
     /// Validates that a condition is syntactically correct
@@ -68,6 +110,219 @@ impl ConditionEvaluator {
     }
 }
 
+/// What `xs[i]` is rewritten to call; not a name programs can use
+const INDEX_FUNCTION: &str = "__index";
+
+/// `xs[i]`: element `i` of a list, counting from 0
+fn index_list(argument: &Value) -> EvalexprResult<Value> {
+    let arguments = argument.as_fixed_len_tuple(2)?;
+    let index = arguments[1].as_number()?;
+    let items = match &arguments[0] {
+        Value::Tuple(items) => items,
+        other => return Err(EvalexprError::CustomMessage(format!("Cannot index into {}", other))),
+    };
+
+    if index < 0.0 || index.fract() != 0.0 || index as usize >= items.len() {
+        return Err(EvalexprError::CustomMessage(
+            format!("Index {} is out of range for a list of {}", index, items.len())
+        ));
+    }
+    Ok(items[index as usize].clone())
+}
+
+/// Rewrite `xs[i]` as a call evalexpr understands; chains like `xs[0][1]` nest
+fn subscripts(expression: &str) -> String {
+    if !expression.contains('[') {
+        return expression.to_string();
+    }
+
+    let chars: Vec<char> = expression.chars().collect();
+    let mut out = String::with_capacity(expression.len() + 16);
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '"' {
+            out.push(c);
+            i += 1;
+            while i < chars.len() && chars[i] != '"' {
+                if chars[i] == '\\' && i + 1 < chars.len() {
+                    out.push(chars[i]);
+                    i += 1;
+                }
+                out.push(chars[i]);
+                i += 1;
+            }
+            if i < chars.len() {
+                out.push(chars[i]);
+                i += 1;
+            }
+            continue;
+        }
+
+        if c == '[' {
+            if let (Some(base_start), Some(close)) = (subscript_base(&out), closing_bracket(&chars, i)) {
+                let base = out.split_off(base_start);
+                let index: String = chars[i + 1..close].iter().collect();
+                out.push_str(&format!("{}({}, {})", INDEX_FUNCTION, base, subscripts(&index)));
+                i = close + 1;
+                continue;
+            }
+        }
+
+        out.push(c);
+        i += 1;
+    }
+
+    out
+}
+
+/// Byte offset where the value being indexed starts: a name, a call or a parenthesised expression
+fn subscript_base(out: &str) -> Option<usize> {
+    let mut start = out.len();
+    let mut reversed = out.char_indices().rev().peekable();
+
+    if out.ends_with(')') {
+        let mut depth = 0;
+        for (position, c) in reversed.by_ref() {
+            match c {
+                ')' => depth += 1,
+                '(' => depth -= 1,
+                _ => {}
+            }
+            if depth == 0 {
+                start = position;
+                break;
+            }
+        }
+    }
+
+    while let Some((position, c)) = reversed.peek().copied() {
+        if !(c.is_alphanumeric() || c == '_') {
+            break;
+        }
+        start = position;
+        reversed.next();
+    }
+
+    (start < out.len()).then_some(start)
+}
+
+fn closing_bracket(chars: &[char], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (position, c) in chars.iter().enumerate().skip(open) {
+        match c {
+            '[' => depth += 1,
+            ']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(position);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// evalexpr builtins that count or take integers, redefined over floats since
+/// `float_literals` turns every literal into one
+fn float_builtins() -> [(&'static str, Function); 8] {
+    [
+        ("len", Function::new(float_len)),
+        ("str::substring", Function::new(substring)),
+        ("bitand", bitwise(|a, b| a & b)),
+        ("bitor", bitwise(|a, b| a | b)),
+        ("bitxor", bitwise(|a, b| a ^ b)),
+        ("shl", bitwise(|a, b| a.wrapping_shl(b as u32))),
+        ("shr", bitwise(|a, b| a.wrapping_shr(b as u32))),
+        ("bitnot", Function::new(|argument| Ok(Value::Float(!whole(argument)? as f64)))),
+    ]
+}
+
+/// A number with no fractional part, as an integer
+fn whole(value: &Value) -> EvalexprResult<i64> {
+    let n = value.as_number()?;
+    if n.fract() != 0.0 {
+        return Err(EvalexprError::CustomMessage(format!("Expected a whole number, but got {}", n)));
+    }
+    Ok(n as i64)
+}
+
+fn float_len(argument: &Value) -> EvalexprResult<Value> {
+    match argument {
+        Value::String(s) => Ok(Value::Float(s.len() as f64)),
+        Value::Tuple(items) => Ok(Value::Float(items.len() as f64)),
+        other => Err(EvalexprError::CustomMessage(format!("Cannot take the length of {}", other))),
+    }
+}
+
+fn substring(argument: &Value) -> EvalexprResult<Value> {
+    let arguments = argument.as_ranged_len_tuple(2..=3)?;
+    let subject = arguments[0].as_string()?;
+    let index = |value: &Value| usize::try_from(whole(value)?).map_err(|_| EvalexprError::OutOfBoundsAccess);
+    let start = index(&arguments[1])?;
+    let end = match arguments.get(2) {
+        Some(end) => index(end)?,
+        None => subject.len(),
+    };
+    subject.get(start..end).map(Value::from).ok_or(EvalexprError::OutOfBoundsAccess)
+}
+
+fn bitwise(op: fn(i64, i64) -> i64) -> Function {
+    Function::new(move |argument| {
+        let arguments = argument.as_fixed_len_tuple(2)?;
+        Ok(Value::Float(op(whole(&arguments[0])?, whole(&arguments[1])?) as f64))
+    })
+}
+
+/// Rewrite integer literals as float literals, leaving strings and identifiers alone
+fn float_literals(condition: &str) -> String {
+    let chars: Vec<char> = condition.chars().collect();
+    let mut out = String::with_capacity(condition.len() + 8);
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '"' {
+            out.push(c);
+            i += 1;
+            while i < chars.len() && chars[i] != '"' {
+                if chars[i] == '\\' && i + 1 < chars.len() {
+                    out.push(chars[i]);
+                    i += 1;
+                }
+                out.push(chars[i]);
+                i += 1;
+            }
+            if i < chars.len() {
+                out.push(chars[i]);
+                i += 1;
+            }
+        } else if c.is_alphabetic() || c == '_' {
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                out.push(chars[i]);
+                i += 1;
+            }
+        } else if c.is_ascii_digit() || (c == '.' && chars.get(i + 1).is_some_and(|d| d.is_ascii_digit())) {
+            let mut is_float = false;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                is_float |= chars[i] == '.';
+                out.push(chars[i]);
+                i += 1;
+            }
+            if !is_float {
+                out.push_str(".0");
+            }
+        } else {
+            out.push(c);
+            i += 1;
+        }
+    }
+
+    out
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -117,4 +372,77 @@ mod tests {
         assert!(evaluator.evaluate("true", &vars).unwrap());
         assert!(!evaluator.evaluate("false", &vars).unwrap());
     }
+
+    #[test]
+    fn test_whole_number_equality() {
+        let evaluator = ConditionEvaluator::new();
+        let mut vars = HashMap::new();
+        vars.insert(create_test_variable("n", VariableValue::Number(3.0)).0,
+                   create_test_variable("n", VariableValue::Number(3.0)).1);
+
+        assert!(evaluator.evaluate("n == 3", &vars).unwrap());
+        assert!(!evaluator.evaluate("n == 3.5", &vars).unwrap());
+        assert!(evaluator.evaluate("\"a1\" == \"a1\"", &vars).unwrap());
+    }
+
+    #[test]
+    fn test_list_indexing() {
+        let evaluator = ConditionEvaluator::new();
+        let list = |items: Vec<VariableValue>| VariableValue::List(items);
+        let mut vars = HashMap::new();
+        let (name, var) = create_test_variable("xs", list(vec![
+            VariableValue::Number(4.0),
+            list(vec![VariableValue::String("a".to_string()), VariableValue::String("b".to_string())]),
+        ]));
+        vars.insert(name, var);
+
+        assert_eq!(subscripts("xs[0] + xs[1][i - 1]"), "__index(xs, 0) + __index(__index(xs, 1), i - 1)");
+        assert!(evaluator.evaluate("xs[0] == 4", &vars).unwrap());
+        assert_eq!(evaluator.evaluate_value("xs[1][1]", &vars).unwrap(), VariableValue::String("b".to_string()));
+        assert!(evaluator.evaluate_value("xs[2]", &vars).is_err());
+        assert!(evaluator.evaluate_value("\"[0]\"", &vars).is_ok());
+    }
+
+    #[test]
+    fn test_native_function_in_condition() {
+        let mut natives = NativeFunctions::default();
+        natives.register("square", 1, std::sync::Arc::new(|args: &[VariableValue]| match &args[0] {
+            VariableValue::Number(n) => Ok(VariableValue::Number(n * n)),
+            _ => Err(anyhow::anyhow!("square() needs a number")),
+        })).unwrap();
+
+        let mut evaluator = ConditionEvaluator::new();
+        evaluator.set_native_functions(natives);
+        let mut vars = HashMap::new();
+        vars.insert(create_test_variable("n", VariableValue::Number(4.0)).0,
+                   create_test_variable("n", VariableValue::Number(4.0)).1);
+
+        assert!(evaluator.evaluate("square(n) == 16", &vars).unwrap());
+        assert!(matches!(evaluator.evaluate_value("square(n) / 32", &vars).unwrap(), VariableValue::Number(v) if v == 0.5));
+    }
+
+    #[test]
+    fn test_integer_literals_in_conditions() {
+        let evaluator = ConditionEvaluator::new();
+        let mut vars = HashMap::new();
+        let (name, var) = create_test_variable("xs", VariableValue::List(vec![
+            VariableValue::Number(1.0), VariableValue::Number(2.0), VariableValue::Number(3.0),
+        ]));
+        vars.insert(name, var);
+        vars.insert(create_test_variable("x1", VariableValue::Number(4.0)).0,
+                   create_test_variable("x1", VariableValue::Number(4.0)).1);
+
+        // Division is on floats, like the variables it mixes with
+        assert!(evaluator.evaluate("7 / 2 == 3.5", &vars).unwrap());
+        assert_eq!(evaluator.evaluate_value("7 / 2", &vars).unwrap(), VariableValue::Number(3.5));
+        assert!(evaluator.evaluate("7 % 2 == 1 && 2 ^ 3 == 8 && 1e3 == 1000", &vars).unwrap());
+        assert!(evaluator.evaluate("x1 == 4 && .5 < 1 && -3 < 0", &vars).unwrap());
+
+        // Builtins that count or take integers
+        assert!(evaluator.evaluate("len(xs) == 3 && len(\"abc\") == 3", &vars).unwrap());
+        assert!(evaluator.evaluate("str::substring(\"hello\", 0, (2)) == \"he\"", &vars).unwrap());
+        assert!(evaluator.evaluate("shl(1, 2) == 4 && bitand(6, 3) + 1 == 3", &vars).unwrap());
+        assert!(evaluator.evaluate_value("str::substring(\"hello\", 0.5)", &vars).is_err());
+        assert_eq!(float_literals(".5 + 2 * x1"), ".5 + 2.0 * x1");
+    }
 }
@@ -0,0 +1,143 @@
+// How simple an equation is, so the solver can prefer `3 * 7 + 229` over `hypot(...)`
+// when both hit the target exactly
+
+use std::sync::Arc;
+
+use crate::equation_tree::EquationNode;
+use crate::operator_set::Operator;
+
+/// Scores equations; among exact matches the solver returns the lowest cost,
+/// taking the earliest candidate on a tie
+pub trait CostModel: Send + Sync {
+    /// `formula` is `equation` with cached intermediates written out, when known
+    fn cost(&self, equation: &EquationNode, formula: Option<&str>) -> u32;
+}
+
+pub type SharedCostModel = Arc<dyn CostModel>;
+
+/// Operator count weighted by how exotic each operator is, plus the steps
+/// hidden in cached intermediates
+///
+/// `+ - *` cost 1, `/` 2, powers and roots 3, rounding, `%`, `!` and the
+/// two-argument helpers 4, `hypot`/`geomean` 5 and `atan2` 6. Each operator
+/// the formula needs beyond the equation adds `intermediate_step`.
+#[derive(Debug, Clone, Copy)]
+pub struct DefaultCostModel {
+    pub intermediate_step: u32,
+}
+
+impl DefaultCostModel {
+    pub fn shared() -> SharedCostModel {
+        Arc::new(Self::default())
+    }
+
+    pub fn weight(op: Operator) -> u32 {
+        match op {
+            Operator::Add | Operator::Sub | Operator::Mul => 1,
+            Operator::Div => 2,
+            Operator::Pow | Operator::Sqrt | Operator::Abs => 3,
+            Operator::Rem | Operator::Factorial | Operator::Ceil | Operator::Floor
+                | Operator::Max | Operator::Min | Operator::Avg => 4,
+            Operator::Hypot | Operator::Geomean => 5,
+            Operator::Atan2 => 6,
+        }
+    }
+
+    fn operators_cost(equation: &EquationNode) -> u32 {
+        equation.operator_uses().into_iter().map(Self::weight).sum()
+    }
+}
+
+impl Default for DefaultCostModel {
+    fn default() -> Self {
+        Self { intermediate_step: 1 }
+    }
+}
+
+impl CostModel for DefaultCostModel {
+    fn cost(&self, equation: &EquationNode, formula: Option<&str>) -> u32 {
+        let own = equation.operator_uses().len();
+        let hidden = match formula.filter(|formula| *formula != equation.to_string()) {
+            Some(formula) => match EquationNode::parse(formula) {
+                Ok(expanded) => expanded.operator_uses().len().saturating_sub(own).max(1),
+                Err(_) => 1,
+            },
+            None => 0,
+        };
+        Self::operators_cost(equation) + self.intermediate_step * hidden as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math_engine::MathEngine;
+    use crate::output_sink::BufferedSink;
+    use crate::MathSolution;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_default_costs() {
+        let model = DefaultCostModel::default();
+        let cost = |text: &str, formula: Option<&str>| model.cost(&EquationNode::parse(text).unwrap(), formula);
+
+        assert_eq!(cost("250", None), 0);
+        assert_eq!(cost("3 * 7 + 229", None), 2);
+        assert!(cost("3 * 7 + 229", None) < cost("hypot(150, 200)", None));
+        assert!(cost("10 / 2", None) > cost("10 - 5", None));
+        assert_eq!(cost("3 * 7 + 229", Some("3 * 7 + 229")), 2);
+
+        // 21 stands for `3 * 7` from an earlier solve: one hidden step
+        assert_eq!(cost("21 + 229", Some("3 * 7 + 229")), 2);
+        assert_eq!(cost("21 + 229", Some("(3 * 7) + 229 - 0")), 3);
+    }
+
+    struct LovesHypot;
+
+    impl CostModel for LovesHypot {
+        fn cost(&self, equation: &EquationNode, _: Option<&str>) -> u32 {
+            if equation.operators().contains(Operator::Hypot) { 0 } else { 10 }
+        }
+    }
+
+    fn priced_engine(cost_model: SharedCostModel) -> MathEngine {
+        let mut engine = MathEngine::new(HashMap::new(), HashMap::new());
+        engine.set_output_sink(Arc::new(BufferedSink::new()));
+        engine.set_cost_model(cost_model);
+        engine
+    }
+
+    #[test]
+    fn test_cheapest_exact_equation_wins() {
+        // hypot(3, 4) also makes 5; the default model prefers plain arithmetic every time
+        for _ in 0..5 {
+            let solution = priced_engine(DefaultCostModel::shared()).solve_target(5.0, &[3.0, 4.0, 1.0], "five", "Cost").unwrap();
+            assert_eq!((solution.equation.as_str(), solution.cost), ("4 + 1", Some(1)));
+        }
+
+        let solution = priced_engine(Arc::new(LovesHypot)).solve_target(5.0, &[3.0, 4.0, 1.0], "five", "Cost").unwrap();
+        assert_eq!((solution.equation.as_str(), solution.cost), ("hypot(3, 4)", Some(0)));
+
+        // Equal accuracy upgrades only to a cheaper equation; costless entries predate costs
+        let mut cheaper = solution.clone();
+        cheaper.cost = Some(1);
+        let legacy = MathSolution { cost: None, ..solution.clone() };
+        assert!(cheaper.improves_on(&MathSolution { cost: Some(5), ..solution.clone() }));
+        assert!(cheaper.improves_on(&legacy) && !legacy.improves_on(&cheaper));
+        assert!(!cheaper.improves_on(&MathSolution { accuracy: 100.0, cost: Some(0), ..solution.clone() }));
+    }
+
+    #[test]
+    fn test_cached_exact_solution_gives_way_to_a_cheaper_one() {
+        let mut engine = priced_engine(Arc::new(LovesHypot));
+        let cached = engine.solve_target(5.0, &[3.0, 4.0, 1.0], "five", "Cost").unwrap();
+        assert_eq!(cached.equation, "hypot(3, 4)");
+
+        // Repriced under the default model, the cached hypot loses to plain addition
+        engine.set_cost_model(DefaultCostModel::shared());
+        let upgraded = engine.solve_target(5.0, &[3.0, 4.0, 1.0], "five", "Cost").unwrap();
+        assert_eq!((upgraded.equation.as_str(), upgraded.cost), ("4 + 1", Some(1)));
+        assert_eq!(engine.solve_target(5.0, &[3.0, 4.0, 1.0], "five", "Cost").unwrap().equation, "4 + 1");
+        assert_eq!(engine.get_solutions()["Cost-five-5-3,4,1"].equation, "4 + 1");
+    }
+}
@@ -0,0 +1,474 @@
+// Embedding API
+// Run .slut source from a host program and get output, variables and solutions back as data
+
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::cache_store::{CacheStore, InMemoryCache};
+use crate::cancellation::CancellationToken;
+use crate::codegen::GeneratedProject;
+use crate::cost_model::{CostModel, DefaultCostModel, SharedCostModel};
+use crate::expression_search::SearchConfig;
+use crate::file_io::FsPermissions;
+use crate::function_builder::FunctionBuilder;
+use crate::input_source::{InputSource, ScriptedInput};
+use crate::limits::Limits;
+use crate::operator_set::OperatorSet;
+use crate::output_sink::{BufferedSink, OutputLine, OutputStream, SharedSink, TeeSink};
+use crate::parser;
+use crate::program_result::ProgramResult;
+use crate::{MathSolution, QuantumTranspiler, VariableValue};
+
+/// Everything a run produced
+#[derive(Debug, Clone, Serialize)]
+pub struct RunOutcome {
+    /// Program output and diagnostics, in emission order; empty when recording is off
+    pub output: Vec<OutputLine>,
+    pub variables: BTreeMap<String, VariableValue>,
+    /// Solved equations, keyed by `class-variable-target-inputs`
+    pub solutions: BTreeMap<String, MathSolution>,
+    /// The main class's last `woof`
+    pub result: Option<ProgramResult>,
+    /// Set by `exit(code)`; 0 otherwise
+    pub exit_code: i32,
+}
+
+impl RunOutcome {
+    /// Only the program output (`speak` / `woof`)
+    pub fn program_lines(&self) -> Vec<&str> {
+        self.output.iter()
+            .filter(|line| line.stream == OutputStream::Program)
+            .map(|line| line.message.as_str())
+            .collect()
+    }
+
+    pub fn variable(&self, name: &str) -> Option<&VariableValue> {
+        self.variables.get(name)
+    }
+}
+
+/// Configures an `Engine`
+///
+/// Defaults suit embedding: an in-memory cache, no `functions/` crate on
+/// disk, and `userIn(...)` answered from an empty script rather than stdin.
+pub struct EngineBuilder {
+    cache: Box<dyn CacheStore>,
+    output: Option<SharedSink>,
+    input: Option<Box<dyn InputSource>>,
+    seed: Option<u64>,
+    limits: Limits,
+    search: SearchConfig,
+    operators: OperatorSet,
+    cost_model: SharedCostModel,
+    cancellation: Option<CancellationToken>,
+    fs: FsPermissions,
+    presets: BTreeMap<String, VariableValue>,
+    vm: bool,
+    function_library: bool,
+    record_output: bool,
+}
+
+impl EngineBuilder {
+    fn new() -> Self {
+        Self {
+            cache: Box::new(InMemoryCache::new()),
+            output: None,
+            input: None,
+            seed: None,
+            limits: Limits::default(),
+            search: SearchConfig::default(),
+            operators: OperatorSet::ALL,
+            cost_model: DefaultCostModel::shared(),
+            cancellation: None,
+            fs: FsPermissions::default(),
+            presets: BTreeMap::new(),
+            vm: false,
+            function_library: false,
+            record_output: true,
+        }
+    }
+
+    /// Where learning is kept between runs (`InMemoryCache`, `DiskCache`)
+    pub fn cache(mut self, cache: impl CacheStore + 'static) -> Self {
+        self.cache = Box::new(cache);
+        self
+    }
+
+    /// Also send output to `sink` as it is produced
+    pub fn output(mut self, sink: SharedSink) -> Self {
+        self.output = Some(sink);
+        self
+    }
+
+    /// Answer `userIn(...)` prompts from `input`
+    pub fn input(mut self, input: impl InputSource + 'static) -> Self {
+        self.input = Some(Box::new(input));
+        self
+    }
+
+    /// Make `randomChoice` and the equation search reproducible
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Resource limits for every run; exceeding one fails the run with `LimitExceeded`
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// How far the expression tree search goes when no fixed-shape equation hits a target
+    pub fn search(mut self, search: SearchConfig) -> Self {
+        self.search = search;
+        self
+    }
+
+    /// Operators the solver may use; a statement's `ops: ...` overrides it
+    pub fn operators(mut self, operators: OperatorSet) -> Self {
+        self.operators = operators;
+        self
+    }
+
+    /// How the solver ranks equations that all hit a target exactly; the cheapest wins
+    pub fn cost_model(mut self, cost_model: impl CostModel + 'static) -> Self {
+        self.cost_model = Arc::new(cost_model);
+        self
+    }
+
+    /// Stop runs from another thread by cancelling `token`; they fail with `Cancelled`
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Directories the file builtins may read and write; none by default
+    pub fn fs_permissions(mut self, permissions: FsPermissions) -> Self {
+        self.fs = permissions;
+        self
+    }
+
+    /// Set `name` before every run, overriding its cached value
+    ///
+    /// A `name <> literal` line in the program is skipped, so it acts as a default.
+    pub fn var(mut self, name: &str, value: VariableValue) -> Self {
+        self.presets.insert(name.to_string(), value);
+        self
+    }
+
+    /// Expose `args` to the program as a list, numbers parsed as in `x <> 5`
+    pub fn args<I, S>(self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let list = args.into_iter()
+            .map(|arg| VariableValue::from(parser::parse_literal(arg.as_ref())))
+            .collect();
+        self.var("args", VariableValue::List(list))
+    }
+
+    /// Run on the bytecode VM instead of the tree-walking interpreter
+    pub fn vm(mut self, vm: bool) -> Self {
+        self.vm = vm;
+        self
+    }
+
+    /// Write synthesized functions into the `functions/` crate in the working directory
+    pub fn function_library(mut self, enabled: bool) -> Self {
+        self.function_library = enabled;
+        self
+    }
+
+    /// Keep each run's output for `RunOutcome::output`; on by default
+    ///
+    /// Turn it off when `output` already shows everything and a long run
+    /// shouldn't hold all of its lines in memory.
+    pub fn record_output(mut self, enabled: bool) -> Self {
+        self.record_output = enabled;
+        self
+    }
+
+    pub fn build(self) -> Result<Engine> {
+        let recorder = self.record_output.then(|| Arc::new(BufferedSink::new()));
+        let mut sinks: Vec<SharedSink> = Vec::new();
+        if let Some(recorder) = &recorder {
+            sinks.push(recorder.clone());
+        }
+        sinks.extend(self.output);
+        let sink: SharedSink = Arc::new(TeeSink::new(sinks));
+
+        let function_builder = if self.function_library {
+            FunctionBuilder::with_output_sink(sink.clone())?
+        } else {
+            FunctionBuilder::detached(sink.clone())
+        };
+
+        let mut transpiler = QuantumTranspiler::with_store(self.cache, function_builder, sink)?;
+        transpiler.set_input_source(
+            self.input.unwrap_or_else(|| Box::new(ScriptedInput::new(Vec::<String>::new())))
+        );
+        if let Some(seed) = self.seed {
+            transpiler.set_seed(seed);
+        }
+        transpiler.set_limits(self.limits);
+        transpiler.set_search_config(self.search);
+        transpiler.set_operators(self.operators);
+        transpiler.set_cost_model(self.cost_model);
+        if let Some(token) = self.cancellation {
+            transpiler.set_cancellation_token(token);
+        }
+        transpiler.set_fs_permissions(self.fs);
+        transpiler.set_preset_variables(self.presets);
+
+        Ok(Engine { transpiler, recorder, vm: self.vm })
+    }
+}
+
+/// A configured interpreter that runs .slut source and reports what happened
+pub struct Engine {
+    transpiler: QuantumTranspiler,
+    recorder: Option<Arc<BufferedSink>>,
+    vm: bool,
+}
+
+impl Engine {
+    pub fn builder() -> EngineBuilder {
+        EngineBuilder::new()
+    }
+
+    /// Make a host function callable from .slut assignments, conditions and interpolation
+    ///
+    /// Calls with the wrong number of arguments, duplicate registrations and
+    /// function classes with the same name are errors.
+    pub fn register_fn<F>(&mut self, name: &str, arity: usize, function: F) -> Result<()>
+    where
+        F: Fn(&[VariableValue]) -> Result<VariableValue> + Send + Sync + 'static,
+    {
+        self.transpiler.register_native_fn(name, arity, Arc::new(function))
+    }
+
+    /// Token that stops the current run when cancelled from another thread
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.transpiler.cancellation_token()
+    }
+
+    /// Run a program; learning carries over to later runs through the cache
+    pub fn run_source(&mut self, source: &str) -> Result<RunOutcome> {
+        if let Some(recorder) = &self.recorder {
+            recorder.clear();
+        }
+
+        if self.vm {
+            self.transpiler.execute_source_vm(source)?;
+        } else {
+            self.transpiler.execute_source(source)?;
+        }
+
+        Ok(RunOutcome {
+            output: self.recorder.as_ref().map(|recorder| recorder.lines()).unwrap_or_default(),
+            variables: self.transpiler.variables().into_iter().collect(),
+            solutions: self.transpiler.solutions().into_iter().collect(),
+            result: self.transpiler.program_result().cloned(),
+            exit_code: self.transpiler.exit_code(),
+        })
+    }
+
+    pub fn run_file(&mut self, path: &Path) -> Result<RunOutcome> {
+        let source = fs::read_to_string(path)?;
+        self.run_source(&source)
+    }
+
+    /// Compile a program into a standalone Rust crate using the cached solutions
+    pub fn generate_rust(&mut self, source: &str) -> Result<GeneratedProject> {
+        self.transpiler.generate_rust_project(source)
+    }
+
+    /// Compile a program into `program.js` and `index.html` using the cached solutions
+    pub fn generate_js(&mut self, source: &str) -> Result<GeneratedProject> {
+        self.transpiler.generate_js_project(source)
+    }
+}
+
+/// An interpreter engine and a VM engine with the same settings, for tests
+/// that check both backends behave alike
+#[cfg(test)]
+pub(crate) fn both_backends(configure: impl Fn(EngineBuilder) -> EngineBuilder) -> [Engine; 2] {
+    [false, true].map(|vm| configure(Engine::builder().vm(vm)).build().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROGRAM: &str = r#"* <main> Embedded {
+    ^ observe_execution {
+        count <> 3
+        result([12]) <> randomChoice([count, 4, ?])
+        speak("count is ~count~")
+        woof result
+    }
+}"#;
+
+    #[test]
+    fn test_run_source_returns_data() {
+        let mut engine = Engine::builder().seed(7).build().unwrap();
+        let outcome = engine.run_source(PROGRAM).unwrap();
+
+        assert_eq!(outcome.program_lines()[0], "count is 3");
+        assert!(matches!(outcome.variable("count"), Some(VariableValue::Number(n)) if *n == 3.0));
+        assert!(outcome.solutions.keys().any(|key| key.starts_with("Embedded-result-12-")));
+    }
+
+    #[test]
+    fn test_output_goes_only_to_the_sink_when_recording_is_off() {
+        let sink = Arc::new(BufferedSink::new());
+        let mut engine = Engine::builder()
+            .seed(7)
+            .output(sink.clone())
+            .record_output(false)
+            .build()
+            .unwrap();
+        let outcome = engine.run_source(PROGRAM).unwrap();
+
+        assert!(outcome.output.is_empty());
+        assert_eq!(sink.program_lines()[0], "count is 3");
+        assert!(matches!(outcome.variable("count"), Some(VariableValue::Number(n)) if *n == 3.0));
+    }
+
+    #[test]
+    fn test_transpiler_diagnostics_reach_the_sink() {
+        let sink = Arc::new(BufferedSink::new());
+        let mut engine = Engine::builder().output(sink.clone()).build().unwrap();
+        let outcome = engine.run_source("speak(\"no class\")").unwrap();
+
+        let warnings: Vec<&str> = outcome.output.iter()
+            .filter(|line| line.level == Some(crate::output_sink::DiagnosticLevel::Warn))
+            .map(|line| line.message.as_str())
+            .collect();
+        assert_eq!(warnings, ["!! No main class found in source"]);
+        // Printed while the engine is built, before the first run
+        assert_eq!(sink.lines()[0].message, "** Starting with fresh quantum consciousness cache");
+    }
+
+    #[test]
+    fn test_woof_result_and_exit_code() {
+        let program = r#"* Double {
+    ^ observe_execution {
+        inner <> 21
+        woof inner
+    }
+}
+
+* <main> Finishing {
+    ^ observe_execution {
+        count <> 3
+        result([12]) <> randomChoice([count, 4, ?])
+        doubled <> Double()
+        woof result
+        status <> 4
+        exit(status)
+        speak("never")
+    }
+}"#;
+        for mut engine in both_backends(|builder| builder.seed(7)) {
+            let outcome = engine.run_source(program).unwrap();
+
+            assert_eq!(outcome.exit_code, 4);
+            assert_eq!(outcome.program_lines(), ["Final result: 12"]);
+            let result = outcome.result.unwrap();
+            assert_eq!(result.variable, "result");
+            assert_eq!(result.value, VariableValue::Number(12.0));
+            assert!(result.source_equation.is_some());
+            assert_eq!(result.accuracy, Some(100.0));
+
+            let outcome = engine.run_source(PROGRAM.replace("woof result", "speak(\"done\")").as_str()).unwrap();
+            assert_eq!((outcome.result, outcome.exit_code), (None, 0));
+        }
+    }
+
+    #[test]
+    fn test_exit_code_must_be_a_whole_i32() {
+        let mut engine = Engine::builder().build().unwrap();
+        let run = |engine: &mut Engine, code: &str| engine.run_source(&format!(
+            "* <main> Exit {{\n    ^ observe_execution {{\n        big <> 4294967297\n        exit({})\n    }}\n}}", code));
+
+        assert_eq!(run(&mut engine, "-3").unwrap().exit_code, -3);
+        assert_eq!(run(&mut engine, "2 * 3").unwrap().exit_code, 6);
+        for code in ["2.5", "big", "big - 4294967296 + 0.5", "-2147483649"] {
+            let error = run(&mut engine, code).unwrap_err().to_string();
+            assert!(error.contains("needs a whole number in i32 range"), "{}: {}", code, error);
+        }
+    }
+
+    #[test]
+    fn test_preset_variables_and_args() {
+        let program = r#"* <main> Targeted {
+    ^ observe_execution {
+        targetNum <> 250
+        label <> "default"
+        speak("~label~ ~targetNum~ from ~args[0]~ and ~args[1]~")
+        targetNum <> calc(targetNum, 1)
+        woof targetNum
+    }
+}"#;
+        for mut engine in both_backends(|builder| builder.var("targetNum", VariableValue::Number(300.0)).args(["7", "x"])) {
+            let outcome = engine.run_source(program).unwrap();
+
+            assert_eq!(outcome.program_lines(), ["default 300 from 7 and x", "Final result: 301"]);
+            assert_eq!(outcome.variable("args"), Some(&VariableValue::List(vec![
+                VariableValue::Number(7.0),
+                VariableValue::String("x".to_string()),
+            ])));
+
+            // Each run starts from the preset again, not from the cached 301
+            let outcome = engine.run_source(program).unwrap();
+            assert_eq!(outcome.program_lines()[1], "Final result: 301");
+        }
+    }
+
+    #[test]
+    fn test_presets_are_not_saved_to_the_cache() {
+        let program = r#"* <main> Counter {
+    ^ observe_execution {
+        speak("start ~total~")
+        total <> 250
+        total <> calc(total, 1)
+    }
+}"#;
+        for mut engine in both_backends(|builder| builder) {
+            engine.run_source(program).unwrap();
+
+            engine.transpiler.set_preset_variables(BTreeMap::from([("total".to_string(), VariableValue::Number(300.0))]));
+            let outcome = engine.run_source(program).unwrap();
+            assert_eq!(outcome.program_lines(), ["start 300"]);
+            assert_eq!(outcome.variable("total"), Some(&VariableValue::Number(301.0)));
+
+            // The next run without the preset starts from the program's own last value
+            engine.transpiler.set_preset_variables(BTreeMap::new());
+            let outcome = engine.run_source(program).unwrap();
+            assert_eq!(outcome.program_lines(), ["start 251"]);
+            assert_eq!(outcome.variable("total"), Some(&VariableValue::Number(251.0)));
+        }
+    }
+
+    #[test]
+    fn test_engine_is_send() {
+        fn assert_send<T: Send>() {}
+        assert_send::<Engine>();
+    }
+
+    #[test]
+    fn test_in_memory_cache_carries_learning_between_runs() {
+        let mut engine = Engine::builder().cache(InMemoryCache::new()).seed(7).build().unwrap();
+        let first = engine.run_source(PROGRAM).unwrap();
+        let second = engine.run_source(PROGRAM).unwrap();
+
+        assert!(first.solutions.keys().all(|key| second.solutions.contains_key(key)));
+        assert_eq!(second.program_lines(), first.program_lines());
+    }
+}
@@ -1,6 +1,12 @@
 use std::f64;
 use rayon::prelude::*;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+use anyhow::Result;
+use crate::bigint::BigInt;
+use crate::expression_search::{self, SearchConfig, SearchGuard, SearchOutcome, SolveRules};
+use crate::equation_tree::EquationNode;
+use crate::operator_set::OperatorSet;
 
 #[derive(Debug, Clone)]
 pub struct Operation {
@@ -18,6 +24,80 @@ impl EquationSolver {
         Self {}
     }
 
+    /// Most operations `generate_all_operations` can produce for `input_count` inputs
+    pub fn candidate_upper_bound(input_count: usize) -> usize {
+        let n = input_count;
+        let pairs = n * n.saturating_sub(1) / 2;
+        let triples = pairs * n.saturating_sub(2) / 3;
+        8 * n + 16 * pairs + 39 * triples
+    }
+
+    /// Search full binary expression trees over any subset of `inputs` for `target`
+    ///
+    /// Reaches shapes the fixed templates miss, such as `(3 + 7) * 25 - 0`;
+    /// see `expression_search::search`.
+    pub fn search_trees(
+        &self,
+        inputs: &[f64],
+        target: f64,
+        config: &SearchConfig,
+        rules: SolveRules,
+        operators: OperatorSet,
+        guard: &SearchGuard
+    ) -> Result<SearchOutcome> {
+        expression_search::search(inputs, target, config, rules, operators, guard)
+    }
+
+    /// Every tree hit `search_trees` can find under `SolveRules::Free`, not just the fewest-operator ones;
+    /// see `expression_search::enumerate` for what it still leaves out
+    pub fn enumerate_trees(
+        &self,
+        inputs: &[f64],
+        target: f64,
+        config: &SearchConfig,
+        operators: OperatorSet,
+        guard: &SearchGuard
+    ) -> Result<SearchOutcome> {
+        expression_search::enumerate(inputs, target, config, operators, guard)
+    }
+
+    /// `search_trees` in big integers, for targets past what f64 holds exactly
+    pub fn search_integer_trees(
+        &self,
+        inputs: &[BigInt],
+        target: &BigInt,
+        config: &SearchConfig,
+        rules: SolveRules,
+        operators: OperatorSet,
+        guard: &SearchGuard
+    ) -> Result<SearchOutcome> {
+        expression_search::search_integers(inputs, target, config, rules, operators, guard)
+    }
+
+    /// Only the operations whose equations stay within `operators`
+    pub fn restrict(ops: Vec<Operation>, operators: OperatorSet) -> Vec<Operation> {
+        if operators.is_all() {
+            return ops;
+        }
+        ops.into_iter()
+            .filter(|op| EquationNode::parse(&op.equation).is_ok_and(|node| operators.covers(node.operators())))
+            .collect()
+    }
+
+    /// Keep the first operation for each canonical form, so `3 + 7 * 25` and
+    /// `7 * 25 + 3` from different sources are listed once; see `EquationNode::canonical_key`
+    pub fn dedup_canonical(ops: Vec<Operation>) -> Vec<Operation> {
+        let keys: Vec<Option<String>> = ops.par_iter()
+            .map(|op| EquationNode::parse(&op.equation).ok().map(|node| node.canonical_key()))
+            .collect();
+        let mut seen = HashSet::with_capacity(ops.len());
+        ops.into_iter()
+            .zip(keys)
+            .filter(|(_, key)| key.as_ref().is_none_or(|key| seen.insert(key.clone())))
+            .map(|(op, _)| op)
+            .collect()
+    }
+
     /// Generate all operations with optional formula substitution
     /// formula_map: maps result values to their cumulative formulas
     pub fn generate_all_operations(&self, inputs: &[f64]) -> Vec<Operation> {
@@ -43,543 +123,322 @@ impl EquationSolver {
     }
 
     pub fn generate_all_operations_with_formulas(&self, inputs: &[f64], formula_map: &HashMap<String, String>) -> Vec<Operation> {
-        let mut operations = Vec::new();
-
         let nums: Vec<f64> = inputs.iter()
             .filter(|&&x| x.is_finite() && !x.is_nan())
             .copied()
             .collect();
 
-        if nums.is_empty() {
-            return operations;
-        }
-
-        for &num in &nums {
-            let formula = self.get_formula(num, formula_map);
-            operations.push(Operation {
-                result: num,
-                equation: num.to_string(),
-                formula: formula.clone(),
-            });
-
-            // Square root for positive numbers
-            if num >= 0.0 {
-                operations.push(Operation {
-                    result: num.sqrt(),
-                    equation: format!("sqrt({})", num),
-                    formula: format!("sqrt({})", formula),
-                });
-            }
-
-            // Absolute value
-            operations.push(Operation {
-                result: num.abs(),
-                equation: format!("abs({})", num),
-                formula: format!("abs({})", formula),
-            });
-
-            // Square
-            operations.push(Operation {
-                result: num * num,
-                equation: format!("{} ^ 2", num),
-                formula: format!("{} ^ 2", formula),
-            });
-
-            // Cube
-            operations.push(Operation {
-                result: num * num * num,
-                equation: format!("{} ^ 3", num),
-                formula: format!("{} ^ 3", formula),
-            });
-
-            // Factorial for small positive integers
-            if num >= 0.0 && num <= 12.0 && num.fract() == 0.0 {
-                let factorial = self.factorial(num as u32);
-                operations.push(Operation {
-                    result: factorial,
-                    equation: format!("{}!", num),
-                    formula: format!("{}!", formula),
-                });
-            }
-
-            // Ceiling and floor
-            operations.push(Operation {
-                result: num.ceil(),
-                equation: format!("ceil({})", num),
-                formula: format!("ceil({})", formula),
-            });
-
-            operations.push(Operation {
-                result: num.floor(),
-                equation: format!("floor({})", num),
-                formula: format!("floor({})", formula),
-            });
-        }
-        
-        operations.extend(self.generate_two_number_operations(&nums, formula_map));
+        let n = nums.len();
+        let singles: Vec<Vec<f64>> = nums.iter().map(|&x| vec![x]).collect();
+        let pairs: Vec<Vec<f64>> = (0..n)
+            .flat_map(|i| ((i + 1)..n).map(move |j| (i, j)))
+            .map(|(i, j)| vec![nums[i], nums[j]])
+            .collect();
+        let triples: Vec<Vec<f64>> = (0..n)
+            .flat_map(|i| ((i + 1)..n).flat_map(move |j| ((j + 1)..n).map(move |k| (i, j, k))))
+            .map(|(i, j, k)| vec![nums[i], nums[j], nums[k]])
+            .collect();
 
-        if nums.len() >= 3 {
-            operations.extend(self.generate_three_number_operations(&nums, formula_map));
-        }
-        
-        operations.into_iter()
-            .filter(|op| op.result.is_finite() && !op.result.is_nan())
+        // Within a pair or triple duplicates are skipped as the templates are applied; across them, here
+        let mut seen = HashSet::new();
+        templates().iter()
+            .zip([singles, pairs, triples])
+            .flat_map(|((templates, shapes), tuples)| self.apply_templates(templates, shapes, tuples, formula_map))
+            .filter_map(|(key, op)| seen.insert(key).then_some(op))
             .collect()
     }
-    
-    fn generate_two_number_operations(&self, nums: &[f64], formula_map: &HashMap<String, String>) -> Vec<Operation> {
-        // Generate pairs of indices in parallel
-        let pairs: Vec<(usize, usize)> = (0..nums.len())
-            .flat_map(|i| ((i + 1)..nums.len()).map(move |j| (i, j)))
+
+    /// Every template applied to every tuple, each operation with its canonical key
+    ///
+    /// Keys come from the template's parsed shape with the tuple's numbers put
+    /// in, so no equation is parsed; a template whose key the tuple already
+    /// produced is neither evaluated nor formatted. Tuples repeating an earlier
+    /// one's numbers are skipped outright.
+    fn apply_templates(
+        &self,
+        templates: &[Template],
+        shapes: &[EquationNode],
+        tuples: Vec<Vec<f64>>,
+        formula_map: &HashMap<String, String>
+    ) -> Vec<(String, Operation)> {
+        let mut distinct = HashSet::new();
+        let tuples: Vec<Vec<f64>> = tuples.into_iter()
+            .filter(|tuple| distinct.insert(tuple.iter().map(|x| x.to_bits()).collect::<Vec<_>>()))
             .collect();
 
-        pairs.par_iter()
-            .flat_map(|&(i, j)| {
-                let a = nums[i];
-                let b = nums[j];
-                let a_formula = self.get_formula(a, formula_map);
-                let b_formula = self.get_formula(b, formula_map);
+        tuples.par_iter()
+            .flat_map_iter(|tuple| {
+                let values: Vec<String> = tuple.iter().map(|x| x.to_string()).collect();
+                let formulas: Vec<String> = tuple.iter().map(|&x| self.get_formula(x, formula_map)).collect();
+                let mut keys = HashSet::new();
                 let mut ops = Vec::new();
-
-                ops.push(Operation {
-                    result: a + b,
-                    equation: format!("{} + {}", a, b),
-                    formula: format!("{} + {}", a_formula, b_formula),
-                });
-
-                ops.push(Operation {
-                    result: a - b,
-                    equation: format!("{} - {}", a, b),
-                    formula: format!("{} - {}", a_formula, b_formula),
-                });
-
-                ops.push(Operation {
-                    result: b - a,
-                    equation: format!("{} - {}", b, a),
-                    formula: format!("{} - {}", b_formula, a_formula),
-                });
-
-                ops.push(Operation {
-                    result: a * b,
-                    equation: format!("{} * {}", a, b),
-                    formula: format!("{} * {}", a_formula, b_formula),
-                });
-
-                if b.abs() > f64::EPSILON {
-                    ops.push(Operation {
-                        result: a / b,
-                        equation: format!("{} / {}", a, b),
-                        formula: format!("{} / {}", a_formula, b_formula),
-                    });
-                }
-
-                if a.abs() > f64::EPSILON {
-                    ops.push(Operation {
-                        result: b / a,
-                        equation: format!("{} / {}", b, a),
-                        formula: format!("{} / {}", b_formula, a_formula),
-                    });
-                }
-
-                if a.abs() <= 100.0 && b.abs() <= 10.0 && b >= 0.0 {
-                    let pow_result = a.powf(b);
-                    if pow_result.is_finite() && !pow_result.is_nan() {
-                        ops.push(Operation {
-                            result: pow_result,
-                            equation: format!("{} ^ {}", a, b),
-                            formula: format!("{} ^ {}", a_formula, b_formula),
-                        });
-                    }
-                }
-
-                if b.abs() <= 100.0 && a.abs() <= 10.0 && a >= 0.0 {
-                    let pow_result = b.powf(a);
-                    if pow_result.is_finite() && !pow_result.is_nan() {
-                        ops.push(Operation {
-                            result: pow_result,
-                            equation: format!("{} ^ {}", b, a),
-                            formula: format!("{} ^ {}", b_formula, a_formula),
-                        });
+                for (template, shape) in templates.iter().zip(shapes) {
+                    let key = shape.fill_slots(tuple).canonical_key();
+                    if keys.contains(&key) {
+                        continue;
                     }
+                    let Some(result) = (template.eval)(tuple).filter(|r| r.is_finite()) else { continue };
+                    keys.insert(key.clone());
+                    ops.push((key, Operation {
+                        result,
+                        equation: fill(template.pattern, &values),
+                        formula: fill(template.pattern, &formulas),
+                    }));
                 }
-
-                if b.abs() > f64::EPSILON {
-                    ops.push(Operation {
-                        result: a % b,
-                        equation: format!("{} % {}", a, b),
-                        formula: format!("{} % {}", a_formula, b_formula),
-                    });
-                }
-
-                if a.abs() > f64::EPSILON {
-                    ops.push(Operation {
-                        result: b % a,
-                        equation: format!("{} % {}", b, a),
-                        formula: format!("{} % {}", b_formula, a_formula),
-                    });
-                }
-
-                ops.push(Operation {
-                    result: a.max(b),
-                    equation: format!("max({}, {})", a, b),
-                    formula: format!("max({}, {})", a_formula, b_formula),
-                });
-
-                ops.push(Operation {
-                    result: a.min(b),
-                    equation: format!("min({}, {})", a, b),
-                    formula: format!("min({}, {})", a_formula, b_formula),
-                });
-
-                ops.push(Operation {
-                    result: a.hypot(b),
-                    equation: format!("hypot({}, {})", a, b),
-                    formula: format!("hypot({}, {})", a_formula, b_formula),
-                });
-
-                ops.push(Operation {
-                    result: a.atan2(b),
-                    equation: format!("atan2({}, {})", a, b),
-                    formula: format!("atan2({}, {})", a_formula, b_formula),
-                });
-
-                // Average
-                ops.push(Operation {
-                    result: (a + b) / 2.0,
-                    equation: format!("avg({}, {})", a, b),
-                    formula: format!("avg({}, {})", a_formula, b_formula),
-                });
-
-                // Geometric mean for positive numbers
-                if a > 0.0 && b > 0.0 {
-                    ops.push(Operation {
-                        result: (a * b).sqrt(),
-                        equation: format!("geomean({}, {})", a, b),
-                        formula: format!("geomean({}, {})", a_formula, b_formula),
-                    });
-                }
-
                 ops
             })
             .collect()
     }
+}
 
-    /// Calculate factorial for small numbers (up to 12! = 479,001,600)
-    fn factorial(&self, n: u32) -> f64 {
-        match n {
-            0 | 1 => 1.0,
-            2 => 2.0,
-            3 => 6.0,
-            4 => 24.0,
-            5 => 120.0,
-            6 => 720.0,
-            7 => 5040.0,
-            8 => 40320.0,
-            9 => 362880.0,
-            10 => 3628800.0,
-            11 => 39916800.0,
-            12 => 479001600.0,
-            _ => {
-                // For larger numbers, compute iteratively
-                let mut result = 1.0;
-                for i in 2..=n {
-                    result *= i as f64;
-                }
-                result
+/// One fixed shape over a single input, a pair or a triple; `{i}` stands for the tuple's i-th number
+struct Template {
+    pattern: &'static str,
+    /// `None` where the shape does not apply, such as division by zero
+    eval: fn(&[f64]) -> Option<f64>,
+}
+
+/// Stand-ins for `{0}`, `{1}` and `{2}` while a pattern is parsed into its shape
+const SINGLE_TEMPLATES: &[Template] = &[
+    Template { pattern: "{0}", eval: |v| Some(v[0]) },
+    Template { pattern: "sqrt({0})", eval: |v| (v[0] >= 0.0).then(|| v[0].sqrt()) },
+    Template { pattern: "abs({0})", eval: |v| Some(v[0].abs()) },
+    Template { pattern: "{0} ^ 2", eval: |v| Some(v[0] * v[0]) },
+    Template { pattern: "{0} ^ 3", eval: |v| Some(v[0] * v[0] * v[0]) },
+    // Factorial for small positive integers
+    Template { pattern: "{0}!", eval: |v| (v[0] >= 0.0 && v[0] <= 12.0 && v[0].fract() == 0.0).then(|| factorial(v[0] as u32)) },
+    Template { pattern: "ceil({0})", eval: |v| Some(v[0].ceil()) },
+    Template { pattern: "floor({0})", eval: |v| Some(v[0].floor()) },
+];
+
+const PAIR_TEMPLATES: &[Template] = &[
+    Template { pattern: "{0} + {1}", eval: |v| Some(v[0] + v[1]) },
+    Template { pattern: "{0} - {1}", eval: |v| Some(v[0] - v[1]) },
+    Template { pattern: "{1} - {0}", eval: |v| Some(v[1] - v[0]) },
+    Template { pattern: "{0} * {1}", eval: |v| Some(v[0] * v[1]) },
+    Template { pattern: "{0} / {1}", eval: |v| (v[1].abs() > f64::EPSILON).then(|| v[0] / v[1]) },
+    Template { pattern: "{1} / {0}", eval: |v| (v[0].abs() > f64::EPSILON).then(|| v[1] / v[0]) },
+    Template { pattern: "{0} ^ {1}", eval: |v| (v[0].abs() <= 100.0 && v[1].abs() <= 10.0 && v[1] >= 0.0).then(|| v[0].powf(v[1])) },
+    Template { pattern: "{1} ^ {0}", eval: |v| (v[1].abs() <= 100.0 && v[0].abs() <= 10.0 && v[0] >= 0.0).then(|| v[1].powf(v[0])) },
+    Template { pattern: "{0} % {1}", eval: |v| (v[1].abs() > f64::EPSILON).then(|| v[0] % v[1]) },
+    Template { pattern: "{1} % {0}", eval: |v| (v[0].abs() > f64::EPSILON).then(|| v[1] % v[0]) },
+    Template { pattern: "max({0}, {1})", eval: |v| Some(v[0].max(v[1])) },
+    Template { pattern: "min({0}, {1})", eval: |v| Some(v[0].min(v[1])) },
+    Template { pattern: "hypot({0}, {1})", eval: |v| Some(v[0].hypot(v[1])) },
+    Template { pattern: "atan2({0}, {1})", eval: |v| Some(v[0].atan2(v[1])) },
+    Template { pattern: "avg({0}, {1})", eval: |v| Some((v[0] + v[1]) / 2.0) },
+    // Geometric mean for positive numbers
+    Template { pattern: "geomean({0}, {1})", eval: |v| (v[0] > 0.0 && v[1] > 0.0).then(|| (v[0] * v[1]).sqrt()) },
+];
+
+const TRIPLE_TEMPLATES: &[Template] = &[
+    Template { pattern: "{0} + {1} + {2}", eval: |v| Some(v[0] + v[1] + v[2]) },
+    Template { pattern: "{0} + {1} - {2}", eval: |v| Some(v[0] + v[1] - v[2]) },
+    Template { pattern: "{0} - {1} + {2}", eval: |v| Some(v[0] - v[1] + v[2]) },
+    Template { pattern: "{0} - {1} - {2}", eval: |v| Some(v[0] - v[1] - v[2]) },
+    Template { pattern: "{0} * {1} + {2}", eval: |v| Some(v[0] * v[1] + v[2]) },
+    Template { pattern: "{0} * {1} - {2}", eval: |v| Some(v[0] * v[1] - v[2]) },
+    Template { pattern: "{0} + {1} * {2}", eval: |v| Some(v[0] + v[1] * v[2]) },
+    Template { pattern: "{0} - {1} * {2}", eval: |v| Some(v[0] - v[1] * v[2]) },
+    Template { pattern: "{0} * {2} + {1}", eval: |v| Some(v[0] * v[2] + v[1]) },
+    Template { pattern: "{0} * {2} - {1}", eval: |v| Some(v[0] * v[2] - v[1]) },
+    Template { pattern: "{1} * {2} + {0}", eval: |v| Some(v[1] * v[2] + v[0]) },
+    Template { pattern: "{1} * {2} - {0}", eval: |v| Some(v[1] * v[2] - v[0]) },
+    Template { pattern: "({0} + {1}) * {2}", eval: |v| Some((v[0] + v[1]) * v[2]) },
+    Template { pattern: "({0} - {1}) * {2}", eval: |v| Some((v[0] - v[1]) * v[2]) },
+    Template { pattern: "{0} * ({1} + {2})", eval: |v| Some(v[0] * (v[1] + v[2])) },
+    Template { pattern: "{0} * ({1} - {2})", eval: |v| Some(v[0] * (v[1] - v[2])) },
+    Template { pattern: "({0} + {2}) * {1}", eval: |v| Some((v[0] + v[2]) * v[1]) },
+    Template { pattern: "({0} - {2}) * {1}", eval: |v| Some((v[0] - v[2]) * v[1]) },
+    Template { pattern: "{1} * ({0} + {2})", eval: |v| Some(v[1] * (v[0] + v[2])) },
+    Template { pattern: "{1} * ({0} - {2})", eval: |v| Some(v[1] * (v[0] - v[2])) },
+    Template { pattern: "({0} + {1}) / {2}", eval: |v| (v[2].abs() > f64::EPSILON).then(|| (v[0] + v[1]) / v[2]) },
+    Template { pattern: "({0} - {1}) / {2}", eval: |v| (v[2].abs() > f64::EPSILON).then(|| (v[0] - v[1]) / v[2]) },
+    Template { pattern: "({0} * {1}) / {2}", eval: |v| (v[2].abs() > f64::EPSILON).then(|| (v[0] * v[1]) / v[2]) },
+    Template { pattern: "({0} + {2}) / {1}", eval: |v| (v[1].abs() > f64::EPSILON).then(|| (v[0] + v[2]) / v[1]) },
+    Template { pattern: "({0} - {2}) / {1}", eval: |v| (v[1].abs() > f64::EPSILON).then(|| (v[0] - v[2]) / v[1]) },
+    Template { pattern: "({0} * {2}) / {1}", eval: |v| (v[1].abs() > f64::EPSILON).then(|| (v[0] * v[2]) / v[1]) },
+    Template { pattern: "({1} + {2}) / {0}", eval: |v| (v[0].abs() > f64::EPSILON).then(|| (v[1] + v[2]) / v[0]) },
+    Template { pattern: "({1} - {2}) / {0}", eval: |v| (v[0].abs() > f64::EPSILON).then(|| (v[1] - v[2]) / v[0]) },
+    Template { pattern: "({1} * {2}) / {0}", eval: |v| (v[0].abs() > f64::EPSILON).then(|| (v[1] * v[2]) / v[0]) },
+    Template { pattern: "{0} / {1} / {2}", eval: |v| (v[1].abs() > f64::EPSILON && v[2].abs() > f64::EPSILON).then(|| v[0] / v[1] / v[2]) },
+    Template { pattern: "{1} / {0} / {2}", eval: |v| (v[0].abs() > f64::EPSILON && v[2].abs() > f64::EPSILON).then(|| v[1] / v[0] / v[2]) },
+    Template { pattern: "{2} / {0} / {1}", eval: |v| (v[0].abs() > f64::EPSILON && v[1].abs() > f64::EPSILON).then(|| v[2] / v[0] / v[1]) },
+    Template { pattern: "{0} * {1} * {2}", eval: |v| Some(v[0] * v[1] * v[2]) },
+    Template { pattern: "{0} ^ {1} + {2}", eval: |v| (v[0].abs() <= 10.0 && v[1].abs() <= 5.0 && v[1] >= 0.0).then(|| v[0].powf(v[1]) + v[2]) },
+    Template { pattern: "{0} ^ {1} - {2}", eval: |v| (v[0].abs() <= 10.0 && v[1].abs() <= 5.0 && v[1] >= 0.0).then(|| v[0].powf(v[1]) - v[2]) },
+    Template { pattern: "({0} + {1}) ^ {2}", eval: |v| ((v[0] + v[1]).abs() <= 10.0 && v[2].abs() <= 5.0 && v[2] >= 0.0).then(|| (v[0] + v[1]).powf(v[2])) },
+    Template { pattern: "({0} - {1}) ^ {2}", eval: |v| ((v[0] - v[1]).abs() <= 10.0 && v[2].abs() <= 5.0 && v[2] >= 0.0).then(|| (v[0] - v[1]).powf(v[2])) },
+    Template { pattern: "avg({0}, {1}, {2})", eval: |v| Some((v[0] + v[1] + v[2]) / 3.0) },
+    // Geometric mean for three positive numbers
+    Template { pattern: "geomean({0}, {1}, {2})", eval: |v| (v[0] > 0.0 && v[1] > 0.0 && v[2] > 0.0).then(|| (v[0] * v[1] * v[2]).cbrt()) },
+];
+
+/// The single, pair and triple templates, each with its pattern parsed once
+/// into a shape whose `EquationNode::Slot`s stand in for the inputs
+fn templates() -> &'static [(&'static [Template], Vec<EquationNode>); 3] {
+    static TEMPLATES: OnceLock<[(&'static [Template], Vec<EquationNode>); 3]> = OnceLock::new();
+    TEMPLATES.get_or_init(|| {
+        [SINGLE_TEMPLATES, PAIR_TEMPLATES, TRIPLE_TEMPLATES].map(|templates| {
+            let shapes = templates.iter()
+                .map(|template| EquationNode::parse_template(template.pattern).expect("solver templates parse"))
+                .collect();
+            (templates, shapes)
+        })
+    })
+}
+
+/// `pattern` with each `{i}` replaced by `args[i]`
+fn fill(pattern: &str, args: &[String]) -> String {
+    let mut text = String::with_capacity(pattern.len() + args.iter().map(String::len).sum::<usize>());
+    let mut rest = pattern;
+    while let Some(open) = rest.find('{') {
+        text.push_str(&rest[..open]);
+        let index = rest.as_bytes()[open + 1] - b'0';
+        text.push_str(&args[index as usize]);
+        rest = &rest[open + 3..];
+    }
+    text.push_str(rest);
+    text
+}
+
+/// Calculate factorial for small numbers (up to 12! = 479,001,600)
+fn factorial(n: u32) -> f64 {
+    match n {
+        0 | 1 => 1.0,
+        2 => 2.0,
+        3 => 6.0,
+        4 => 24.0,
+        5 => 120.0,
+        6 => 720.0,
+        7 => 5040.0,
+        8 => 40320.0,
+        9 => 362880.0,
+        10 => 3628800.0,
+        11 => 39916800.0,
+        12 => 479001600.0,
+        _ => {
+            // For larger numbers, compute iteratively
+            let mut result = 1.0;
+            for i in 2..=n {
+                result *= i as f64;
             }
+            result
         }
     }
-    
-    fn generate_three_number_operations(&self, nums: &[f64], formula_map: &HashMap<String, String>) -> Vec<Operation> {
-        // Generate triplets of indices in parallel
-        let triplets: Vec<(usize, usize, usize)> = (0..nums.len())
-            .flat_map(|i| {
-                ((i + 1)..nums.len()).flat_map(move |j| {
-                    ((j + 1)..nums.len()).map(move |k| (i, j, k))
-                })
-            })
-            .collect();
+}
 
-        triplets.par_iter()
-            .flat_map(|&(i, j, k)| {
-                let a = nums[i];
-                let b = nums[j];
-                let c = nums[k];
-                let a_formula = self.get_formula(a, formula_map);
-                let b_formula = self.get_formula(b, formula_map);
-                let c_formula = self.get_formula(c, formula_map);
-                let mut ops = Vec::new();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-                ops.push(Operation {
-                    result: a + b + c,
-                    equation: format!("{} + {} + {}", a, b, c),
-                    formula: format!("{} + {} + {}", a_formula, b_formula, c_formula),
-                });
-
-                ops.push(Operation {
-                        result: a + b - c,
-                        equation: format!("{} + {} - {}", a, b, c),
-                        formula: format!("{} + {} - {}", a_formula, b_formula, c_formula),
-                    });
-
-                ops.push(Operation {
-                        result: a - b + c,
-                        equation: format!("{} - {} + {}", a, b, c),
-                        formula: format!("{} - {} + {}", a_formula, b_formula, c_formula),
-                    });
-
-                ops.push(Operation {
-                        result: a - b - c,
-                        equation: format!("{} - {} - {}", a, b, c),
-                        formula: format!("{} - {} - {}", a_formula, b_formula, c_formula),
-                    });
-
-                ops.push(Operation {
-                        result: a * b + c,
-                        equation: format!("{} * {} + {}", a, b, c),
-                        formula: format!("{} * {} + {}", a_formula, b_formula, c_formula),
-                    });
-
-                ops.push(Operation {
-                        result: a * b - c,
-                        equation: format!("{} * {} - {}", a, b, c),
-                        formula: format!("{} * {} - {}", a_formula, b_formula, c_formula),
-                    });
-
-                ops.push(Operation {
-                        result: a + b * c,
-                        equation: format!("{} + {} * {}", a, b, c),
-                        formula: format!("{} + {} * {}", a_formula, b_formula, c_formula),
-                    });
-
-                ops.push(Operation {
-                        result: a - b * c,
-                        equation: format!("{} - {} * {}", a, b, c),
-                        formula: format!("{} - {} * {}", a_formula, b_formula, c_formula),
-                    });
-
-                ops.push(Operation {
-                        result: a * c + b,
-                        equation: format!("{} * {} + {}", a, c, b),
-                        formula: format!("{} * {} + {}", a_formula, c_formula, b_formula),
-                    });
-
-                ops.push(Operation {
-                        result: a * c - b,
-                        equation: format!("{} * {} - {}", a, c, b),
-                        formula: format!("{} * {} - {}", a_formula, c_formula, b_formula),
-                    });
-
-                ops.push(Operation {
-                        result: b * c + a,
-                        equation: format!("{} * {} + {}", b, c, a),
-                        formula: format!("{} * {} + {}", b_formula, c_formula, a_formula),
-                    });
-
-                ops.push(Operation {
-                        result: b * c - a,
-                        equation: format!("{} * {} - {}", b, c, a),
-                        formula: format!("{} * {} - {}", b_formula, c_formula, a_formula),
-                    });
-                    
-                ops.push(Operation {
-                        result: (a + b) * c,
-                        equation: format!("({} + {}) * {}", a, b, c),
-                        formula: format!("({} + {}) * {}", a_formula, b_formula, c_formula),
-                    });
-
-                ops.push(Operation {
-                        result: (a - b) * c,
-                        equation: format!("({} - {}) * {}", a, b, c),
-                        formula: format!("({} - {}) * {}", a_formula, b_formula, c_formula),
-                    });
-
-                ops.push(Operation {
-                        result: a * (b + c),
-                        equation: format!("{} * ({} + {})", a, b, c),
-                        formula: format!("{} * ({} + {})", a_formula, b_formula, c_formula),
-                    });
-
-                ops.push(Operation {
-                        result: a * (b - c),
-                        equation: format!("{} * ({} - {})", a, b, c),
-                        formula: format!("{} * ({} - {})", a_formula, b_formula, c_formula),
-                    });
-
-                ops.push(Operation {
-                        result: (a + c) * b,
-                        equation: format!("({} + {}) * {}", a, c, b),
-                        formula: format!("({} + {}) * {}", a_formula, c_formula, b_formula),
-                    });
-
-                ops.push(Operation {
-                        result: (a - c) * b,
-                        equation: format!("({} - {}) * {}", a, c, b),
-                        formula: format!("({} - {}) * {}", a_formula, c_formula, b_formula),
-                    });
-
-                ops.push(Operation {
-                        result: b * (a + c),
-                        equation: format!("{} * ({} + {})", b, a, c),
-                        formula: format!("{} * ({} + {})", b_formula, a_formula, c_formula),
-                    });
-
-                ops.push(Operation {
-                        result: b * (a - c),
-                        equation: format!("{} * ({} - {})", b, a, c),
-                        formula: format!("{} * ({} - {})", b_formula, a_formula, c_formula),
-                    });
-                    
-                    if c.abs() > f64::EPSILON {
-                    ops.push(Operation {
-                            result: (a + b) / c,
-                            equation: format!("({} + {}) / {}", a, b, c),
-                            formula: format!("({} + {}) / {}", a_formula, b_formula, c_formula),
-                        });
-
-                    ops.push(Operation {
-                            result: (a - b) / c,
-                            equation: format!("({} - {}) / {}", a, b, c),
-                            formula: format!("({} - {}) / {}", a_formula, b_formula, c_formula),
-                        });
-
-                    ops.push(Operation {
-                            result: (a * b) / c,
-                            equation: format!("({} * {}) / {}", a, b, c),
-                            formula: format!("({} * {}) / {}", a_formula, b_formula, c_formula),
-                        });
-                    }
+    #[test]
+    fn test_canonical_dedup_shrinks_candidates() {
+        let solver = EquationSolver::new();
+        let count = |inputs: &[f64]| solver.generate_all_operations(inputs).len();
 
-                    if b.abs() > f64::EPSILON {
-                    ops.push(Operation {
-                            result: (a + c) / b,
-                            equation: format!("({} + {}) / {}", a, c, b),
-                            formula: format!("({} + {}) / {}", a_formula, c_formula, b_formula),
-                        });
-
-                    ops.push(Operation {
-                            result: (a - c) / b,
-                            equation: format!("({} - {}) / {}", a, c, b),
-                            formula: format!("({} - {}) / {}", a_formula, c_formula, b_formula),
-                        });
-
-                    ops.push(Operation {
-                            result: (a * c) / b,
-                            equation: format!("({} * {}) / {}", a, c, b),
-                            formula: format!("({} * {}) / {}", a_formula, c_formula, b_formula),
-                        });
-                    }
+        // `a + b * c` and `b * c + a` templates overlap even for distinct inputs
+        let ops = solver.generate_all_operations(&[3.0, 7.0, 25.0]);
+        assert!(ops.iter().any(|op| op.equation == "3 + 7 * 25"));
+        assert!(!ops.iter().any(|op| op.equation == "7 * 25 + 3"));
+        assert!(count(&[3.0, 7.0, 25.0, 100.0]) < EquationSolver::candidate_upper_bound(4));
 
-                    if a.abs() > f64::EPSILON {
-                    ops.push(Operation {
-                            result: (b + c) / a,
-                            equation: format!("({} + {}) / {}", b, c, a),
-                            formula: format!("({} + {}) / {}", b_formula, c_formula, a_formula),
-                        });
-
-                    ops.push(Operation {
-                            result: (b - c) / a,
-                            equation: format!("({} - {}) / {}", b, c, a),
-                            formula: format!("({} - {}) / {}", b_formula, c_formula, a_formula),
-                        });
-
-                    ops.push(Operation {
-                            result: (b * c) / a,
-                            equation: format!("({} * {}) / {}", b, c, a),
-                            formula: format!("({} * {}) / {}", b_formula, c_formula, a_formula),
-                        });
-                    }
+        // Repeated inputs repeat whole pairs and triples
+        assert!(count(&[2.0, 2.0, 5.0, 5.0, 9.0]) * 2 < EquationSolver::candidate_upper_bound(5));
 
-                    if b.abs() > f64::EPSILON && c.abs() > f64::EPSILON {
-                    ops.push(Operation {
-                            result: a / b / c,
-                            equation: format!("{} / {} / {}", a, b, c),
-                            formula: format!("{} / {} / {}", a_formula, b_formula, c_formula),
-                        });
-                    }
+        let ops = solver.generate_all_operations(&[2.0, 2.0, 5.0, 5.0, 9.0]);
+        let keys: HashSet<String> = ops.iter()
+            .map(|op| EquationNode::parse(&op.equation).unwrap().canonical_key())
+            .collect();
+        assert_eq!(keys.len(), ops.len());
+        assert_eq!(EquationSolver::dedup_canonical(ops.clone()).len(), ops.len());
+    }
 
-                    if a.abs() > f64::EPSILON && c.abs() > f64::EPSILON {
-                    ops.push(Operation {
-                            result: b / a / c,
-                            equation: format!("{} / {} / {}", b, a, c),
-                            formula: format!("{} / {} / {}", b_formula, a_formula, c_formula),
-                        });
+    #[test]
+    fn test_dedup_keeps_one_member_per_class_and_loses_none() {
+        let solver = EquationSolver::new();
+        // The last set holds the numbers the template shapes once used as stand-ins
+        for inputs in [
+            vec![3.0, 7.0, 25.0],
+            vec![2.0, 2.0, 5.0, 5.0, 9.0],
+            vec![-4.0, 0.5, 6.0, 1_000_000_001.0],
+            vec![1_000_000_001.0, 1_000_000_002.0, 1_000_000_003.0],
+        ] {
+            // Every template on every tuple, each equation parsed back for its key
+            let n = inputs.len();
+            let tuples = (0..n).map(|i| vec![i])
+                .chain((0..n).flat_map(|i| (i + 1..n).map(move |j| vec![i, j])))
+                .chain((0..n).flat_map(|i| (i + 1..n).flat_map(move |j| (j + 1..n).map(move |k| vec![i, j, k]))));
+            let mut naive: HashMap<String, HashSet<u64>> = HashMap::new();
+            for indices in tuples {
+                let tuple: Vec<f64> = indices.iter().map(|&i| inputs[i]).collect();
+                let values: Vec<String> = tuple.iter().map(f64::to_string).collect();
+                for template in [SINGLE_TEMPLATES, PAIR_TEMPLATES, TRIPLE_TEMPLATES][tuple.len() - 1] {
+                    if let Some(result) = (template.eval)(&tuple).filter(|r| r.is_finite()) {
+                        let key = EquationNode::parse(&fill(template.pattern, &values)).unwrap().canonical_key();
+                        naive.entry(key).or_default().insert(result.to_bits());
                     }
+                }
+            }
 
-                    if a.abs() > f64::EPSILON && b.abs() > f64::EPSILON {
-                    ops.push(Operation {
-                            result: c / a / b,
-                            equation: format!("{} / {} / {}", c, a, b),
-                            formula: format!("{} / {} / {}", c_formula, a_formula, b_formula),
-                        });
-                    }
+            let ops = solver.generate_all_operations(&inputs);
+            let mut kept = HashSet::new();
+            for op in &ops {
+                let key = EquationNode::parse(&op.equation).unwrap().canonical_key();
+                assert!(naive[&key].contains(&op.result.to_bits()), "{} = {}", op.equation, op.result);
+                assert!(kept.insert(key), "{} repeats a class", op.equation);
+            }
+            assert_eq!(kept.len(), naive.len(), "{:?}", inputs);
+        }
+    }
 
-                ops.push(Operation {
-                        result: a * b * c,
-                        equation: format!("{} * {} * {}", a, b, c),
-                        formula: format!("{} * {} * {}", a_formula, b_formula, c_formula),
-                    });
-                    
-                    if a.abs() <= 10.0 && b.abs() <= 5.0 && b >= 0.0 {
-                        let pow_result = a.powf(b) + c;
-                        if pow_result.is_finite() && !pow_result.is_nan() {
-                        ops.push(Operation {
-                                result: pow_result,
-                                equation: format!("{} ^ {} + {}", a, b, c),
-                                formula: format!("{} ^ {} + {}", a_formula, b_formula, c_formula),
-                            });
-                        }
-
-                        let pow_result = a.powf(b) - c;
-                        if pow_result.is_finite() && !pow_result.is_nan() {
-                        ops.push(Operation {
-                                result: pow_result,
-                                equation: format!("{} ^ {} - {}", a, b, c),
-                                formula: format!("{} ^ {} - {}", a_formula, b_formula, c_formula),
-                            });
-                        }
-                    }
+    #[test]
+    fn test_candidate_bound_and_operator_restriction() {
+        let solver = EquationSolver::new();
+        assert_eq!(EquationSolver::candidate_upper_bound(0), 0);
+        assert_eq!(EquationSolver::candidate_upper_bound(1), 8);
+        assert_eq!(EquationSolver::candidate_upper_bound(3), 111);
+        for inputs in [&[4.0][..], &[3.0, 4.0], &[3.0, 7.0, 25.0]] {
+            assert!(solver.generate_all_operations(inputs).len() <= EquationSolver::candidate_upper_bound(inputs.len()));
+        }
 
-                    if (a + b).abs() <= 10.0 && c.abs() <= 5.0 && c >= 0.0 {
-                        let pow_result = (a + b).powf(c);
-                        if pow_result.is_finite() && !pow_result.is_nan() {
-                        ops.push(Operation {
-                                result: pow_result,
-                                equation: format!("({} + {}) ^ {}", a, b, c),
-                                formula: format!("({} + {}) ^ {}", a_formula, b_formula, c_formula),
-                            });
-                        }
-                    }
+        let ops = solver.generate_all_operations(&[3.0, 4.0]);
+        assert_eq!(EquationSolver::restrict(ops.clone(), OperatorSet::ALL).len(), ops.len());
+        let basic = EquationSolver::restrict(ops.clone(), OperatorSet::basic());
+        assert!(!basic.is_empty() && basic.len() < ops.len());
+        assert!(basic.iter().all(|op| OperatorSet::basic().covers(EquationNode::parse(&op.equation).unwrap().operators())));
+        assert!(!basic.iter().any(|op| op.equation == "hypot(3, 4)"));
+    }
 
-                    if (a - b).abs() <= 10.0 && c.abs() <= 5.0 && c >= 0.0 {
-                        let pow_result = (a - b).powf(c);
-                        if pow_result.is_finite() && !pow_result.is_nan() {
-                        ops.push(Operation {
-                                result: pow_result,
-                                equation: format!("({} - {}) ^ {}", a, b, c),
-                                formula: format!("({} - {}) ^ {}", a_formula, b_formula, c_formula),
-                            });
-                        }
-                    }
+    #[test]
+    fn test_formulas_stand_in_for_cached_values() {
+        let solver = EquationSolver::new();
+        let formulas = HashMap::from([(format!("{:.10}", 21.0), "3 * 7".to_string())]);
+        let ops = solver.generate_all_operations_with_formulas(&[21.0, 229.0], &formulas);
 
-                // Average of three numbers
-                ops.push(Operation {
-                    result: (a + b + c) / 3.0,
-                    equation: format!("avg({}, {}, {})", a, b, c),
-                    formula: format!("avg({}, {}, {})", a_formula, b_formula, c_formula),
-                });
-
-                // Geometric mean for three positive numbers
-                if a > 0.0 && b > 0.0 && c > 0.0 {
-                    ops.push(Operation {
-                        result: (a * b * c).cbrt(),
-                        equation: format!("geomean({}, {}, {})", a, b, c),
-                        formula: format!("geomean({}, {}, {})", a_formula, b_formula, c_formula),
-                    });
-                }
+        let sum = ops.iter().find(|op| op.equation == "21 + 229").unwrap();
+        assert_eq!(sum.result, 250.0);
+        assert!(sum.formula.contains("3 * 7") && sum.formula.contains("229"), "{}", sum.formula);
+    }
 
-                ops
-            })
-            .collect()
+    #[test]
+    fn test_tree_search_wrappers_honour_rules_and_the_guard() {
+        let solver = EquationSolver::new();
+        let config = SearchConfig::default();
+        let guard = SearchGuard::default();
+
+        let all = solver.search_trees(&[2.0, 3.0, 5.0, 7.0], 60.0, &config, SolveRules::All, OperatorSet::ALL, &guard).unwrap();
+        let exact = all.exact.unwrap();
+        assert_eq!(exact.result, 60.0);
+        assert!(["2", "3", "5", "7"].iter().all(|n| exact.equation.contains(n)), "{}", exact.equation);
+
+        let integers = [BigInt::from_f64(6.0).unwrap(), BigInt::from_f64(7.0).unwrap()];
+        let product = solver.search_integer_trees(&integers, &BigInt::from_f64(42.0).unwrap(), &config, SolveRules::Free, OperatorSet::basic(), &guard).unwrap();
+        assert_eq!(product.exact.unwrap().equation, "6 * 7");
+
+        let stopped = SearchGuard::new(crate::CancellationToken::new());
+        stopped.cancel.cancel();
+        let error = solver.enumerate_trees(&[2.0, 3.0, 5.0], 30.0, &config, OperatorSet::ALL, &stopped).unwrap_err();
+        assert_eq!(error.downcast_ref::<crate::Cancelled>(), Some(&crate::Cancelled));
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,655 @@
+use anyhow::Result;
+use std::fmt;
+
+use crate::bigint::{BigInt, F64_EXACT_LIMIT};
+use crate::operator_set::{Operator, OperatorSet};
+use crate::rational::Rational;
+
+/// Binary operators that appear in solver equations
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Pow,
+}
+
+impl BinaryOp {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Rem => "%",
+            BinaryOp::Pow => "^",
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            BinaryOp::Add | BinaryOp::Sub => 1,
+            BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => 2,
+            BinaryOp::Pow => 3,
+        }
+    }
+}
+
+/// Expression tree for equations produced by `EquationSolver`
+///
+/// Equations are stored in the cache as text (`"(3 + 7) * 25"`, `"sqrt(9)"`,
+/// `"5!"`); this parses them back so backends can lower or re-evaluate them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EquationNode {
+    Number(f64),
+    Negate(Box<EquationNode>),
+    Factorial(Box<EquationNode>),
+    Binary {
+        op: BinaryOp,
+        left: Box<EquationNode>,
+        right: Box<EquationNode>,
+    },
+    Call {
+        name: String,
+        args: Vec<EquationNode>,
+    },
+    /// `{i}` in a solver template, standing for the i-th input; see `parse_template`
+    Slot(usize),
+}
+
+/// Functions the solver emits, with their accepted argument counts
+pub const SOLVER_FUNCTIONS: &[(&str, &[usize])] = &[
+    ("sqrt", &[1]),
+    ("abs", &[1]),
+    ("ceil", &[1]),
+    ("floor", &[1]),
+    ("max", &[2]),
+    ("min", &[2]),
+    ("hypot", &[2]),
+    ("atan2", &[2]),
+    ("avg", &[2, 3]),
+    ("geomean", &[2, 3]),
+];
+
+impl EquationNode {
+    pub fn parse(text: &str) -> Result<Self> {
+        Self::parse_tokens(text, false)
+    }
+
+    /// Parse a solver template such as `{0} * ({1} + {2})`, where `{i}` becomes `Slot(i)`
+    ///
+    /// Only templates may hold slots; `parse` rejects `{`, so equations read
+    /// back from the cache never do.
+    pub(crate) fn parse_template(pattern: &str) -> Result<Self> {
+        Self::parse_tokens(pattern, true)
+    }
+
+    /// The template with each `Slot(i)` replaced by `values[i]`
+    pub(crate) fn fill_slots(&self, values: &[f64]) -> EquationNode {
+        match self {
+            EquationNode::Slot(i) => EquationNode::Number(values[*i]),
+            EquationNode::Number(n) => EquationNode::Number(*n),
+            EquationNode::Negate(inner) => EquationNode::Negate(Box::new(inner.fill_slots(values))),
+            EquationNode::Factorial(inner) => EquationNode::Factorial(Box::new(inner.fill_slots(values))),
+            EquationNode::Binary { op, left, right } => EquationNode::Binary {
+                op: *op,
+                left: Box::new(left.fill_slots(values)),
+                right: Box::new(right.fill_slots(values)),
+            },
+            EquationNode::Call { name, args } => EquationNode::Call {
+                name: name.clone(),
+                args: args.iter().map(|arg| arg.fill_slots(values)).collect(),
+            },
+        }
+    }
+
+    fn parse_tokens(text: &str, slots: bool) -> Result<Self> {
+        let tokens = tokenize(text, slots)?;
+        let mut parser = TreeParser { tokens, position: 0 };
+        let node = parser.parse_expression(0)?;
+        if parser.position != parser.tokens.len() {
+            return Err(anyhow::anyhow!("Unexpected trailing input in equation: {}", text));
+        }
+        Ok(node)
+    }
+
+    /// Evaluate with the same f64 semantics the solver used to produce the equation
+    pub fn evaluate(&self) -> Result<f64> {
+        Ok(match self {
+            EquationNode::Number(n) => *n,
+            EquationNode::Slot(i) => return Err(anyhow::anyhow!("Unfilled template slot {{{}}}", i)),
+            EquationNode::Negate(inner) => -inner.evaluate()?,
+            EquationNode::Factorial(inner) => {
+                let n = inner.evaluate()?;
+                if n < 0.0 || n.fract() != 0.0 {
+                    return Err(anyhow::anyhow!("Factorial of non-integer {}", n));
+                }
+                (2..=n as u64).fold(1.0, |acc, i| acc * i as f64)
+            }
+            EquationNode::Binary { op, left, right } => {
+                let a = left.evaluate()?;
+                let b = right.evaluate()?;
+                match op {
+                    BinaryOp::Add => a + b,
+                    BinaryOp::Sub => a - b,
+                    BinaryOp::Mul => a * b,
+                    BinaryOp::Div => a / b,
+                    BinaryOp::Rem => a % b,
+                    BinaryOp::Pow => a.powf(b),
+                }
+            }
+            EquationNode::Call { name, args } => {
+                let values = args.iter()
+                    .map(|arg| arg.evaluate())
+                    .collect::<Result<Vec<f64>>>()?;
+                match (name.as_str(), values.as_slice()) {
+                    ("sqrt", [a]) => a.sqrt(),
+                    ("abs", [a]) => a.abs(),
+                    ("ceil", [a]) => a.ceil(),
+                    ("floor", [a]) => a.floor(),
+                    ("max", [a, b]) => a.max(*b),
+                    ("min", [a, b]) => a.min(*b),
+                    ("hypot", [a, b]) => a.hypot(*b),
+                    ("atan2", [a, b]) => a.atan2(*b),
+                    ("avg", [a, b]) => (a + b) / 2.0,
+                    ("avg", [a, b, c]) => (a + b + c) / 3.0,
+                    ("geomean", [a, b]) => (a * b).sqrt(),
+                    ("geomean", [a, b, c]) => (a * b * c).cbrt(),
+                    _ => return Err(anyhow::anyhow!("Unknown function {}/{}", name, values.len())),
+                }
+            }
+        })
+    }
+
+    /// Evaluate without rounding when every step stays rational
+    ///
+    /// Numbers are read as the decimals they are written as. `None` means some
+    /// step is irrational (`atan2`, a non-square root) or overflows, and the
+    /// f64 result from `evaluate` is all there is.
+    pub fn evaluate_exact(&self) -> Option<Rational> {
+        match self {
+            EquationNode::Number(n) => Rational::from_f64(*n),
+            EquationNode::Slot(_) => None,
+            EquationNode::Negate(inner) => inner.evaluate_exact()?.checked_neg(),
+            EquationNode::Factorial(inner) => {
+                let n = inner.evaluate_exact()?;
+                if !n.is_integer() || n.numer() < 0 {
+                    return None;
+                }
+                (2..=n.numer()).try_fold(Rational::ONE, |acc, i| acc.checked_mul(Rational::integer(i)))
+            }
+            EquationNode::Binary { op, left, right } => {
+                let a = left.evaluate_exact()?;
+                let b = right.evaluate_exact()?;
+                match op {
+                    BinaryOp::Add => a.checked_add(b),
+                    BinaryOp::Sub => a.checked_sub(b),
+                    BinaryOp::Mul => a.checked_mul(b),
+                    BinaryOp::Div => a.checked_div(b),
+                    BinaryOp::Rem => a.checked_rem(b),
+                    BinaryOp::Pow => a.checked_pow(b),
+                }
+            }
+            EquationNode::Call { name, args } => {
+                let values = args.iter()
+                    .map(|arg| arg.evaluate_exact())
+                    .collect::<Option<Vec<Rational>>>()?;
+                match (name.as_str(), values.as_slice()) {
+                    ("sqrt", [a]) => a.sqrt(),
+                    ("abs", [a]) => a.abs(),
+                    ("ceil", [a]) => Some(a.ceil()),
+                    ("floor", [a]) => Some(a.floor()),
+                    ("max", [a, b]) => Some(*a.max(b)),
+                    ("min", [a, b]) => Some(*a.min(b)),
+                    ("hypot", [a, b]) => a.checked_mul(*a)?.checked_add(b.checked_mul(*b)?)?.sqrt(),
+                    ("avg", [a, b]) => a.checked_add(*b)?.checked_div(Rational::integer(2)),
+                    ("avg", [a, b, c]) => a.checked_add(*b)?.checked_add(*c)?.checked_div(Rational::integer(3)),
+                    ("geomean", [a, b]) => a.checked_mul(*b)?.sqrt(),
+                    _ => None,
+                }
+            }
+        }
+    }
+
+    /// Every operator and function the equation uses
+    pub fn operators(&self) -> OperatorSet {
+        OperatorSet::of(&self.operator_uses())
+    }
+
+    /// Each use of an operator or function, repeats included; `-x` counts as `-`
+    pub fn operator_uses(&self) -> Vec<Operator> {
+        let mut uses = Vec::new();
+        self.collect_operators(&mut uses);
+        uses
+    }
+
+    fn collect_operators(&self, uses: &mut Vec<Operator>) {
+        match self {
+            EquationNode::Number(_) | EquationNode::Slot(_) => {}
+            EquationNode::Negate(inner) => {
+                uses.push(Operator::Sub);
+                inner.collect_operators(uses);
+            }
+            EquationNode::Factorial(inner) => {
+                uses.push(Operator::Factorial);
+                inner.collect_operators(uses);
+            }
+            EquationNode::Binary { op, left, right } => {
+                uses.push(Operator::from(*op));
+                left.collect_operators(uses);
+                right.collect_operators(uses);
+            }
+            EquationNode::Call { name, args } => {
+                uses.extend(Operator::from_name(name));
+                for arg in args {
+                    arg.collect_operators(uses);
+                }
+            }
+        }
+    }
+
+    /// A key shared by equations that differ only in operand order or grouping
+    ///
+    /// `+`/`-` chains flatten to sorted signed terms and `*`/`/` chains to a
+    /// sorted numerator and denominator, so `a - b + c`, `c + a - b` and
+    /// `a + (c - b)` agree; arguments of `max`, `min`, `hypot`, `avg` and
+    /// `geomean` are sorted, and nested `max`/`min` flatten. `a - b` and
+    /// `b - a` stay distinct.
+    pub fn canonical_key(&self) -> String {
+        match self {
+            EquationNode::Number(n) if *n >= 0.0 => n.to_string(),
+            EquationNode::Number(_) | EquationNode::Negate(_) | EquationNode::Binary { op: BinaryOp::Add | BinaryOp::Sub, .. } => {
+                let (mut plus, mut minus) = (Vec::new(), Vec::new());
+                self.collect_chain(true, BinaryOp::Add, &mut plus, &mut minus);
+                chain_key('+', plus, minus)
+            }
+            EquationNode::Binary { op: BinaryOp::Mul | BinaryOp::Div, .. } => {
+                let (mut numerator, mut denominator) = (Vec::new(), Vec::new());
+                self.collect_chain(true, BinaryOp::Mul, &mut numerator, &mut denominator);
+                chain_key('*', numerator, denominator)
+            }
+            EquationNode::Binary { op, left, right } => {
+                format!("{}({},{})", op.symbol(), left.canonical_key(), right.canonical_key())
+            }
+            EquationNode::Factorial(inner) => format!("!({})", inner.canonical_key()),
+            EquationNode::Slot(i) => format!("{{{}}}", i),
+            EquationNode::Call { name, args } => {
+                let mut keys = Vec::with_capacity(args.len());
+                for arg in args {
+                    match arg {
+                        EquationNode::Call { name: inner, args: nested }
+                            if inner == name && matches!(name.as_str(), "max" | "min") =>
+                        {
+                            keys.extend(nested.iter().map(EquationNode::canonical_key));
+                        }
+                        _ => keys.push(arg.canonical_key()),
+                    }
+                }
+                if matches!(name.as_str(), "max" | "min" | "hypot" | "avg" | "geomean") {
+                    keys.sort();
+                }
+                format!("{}({})", name, keys.join(","))
+            }
+        }
+    }
+
+    /// Split an additive (`Add`) or multiplicative (`Mul`) chain into terms
+    /// that count positively and terms that are subtracted or divided
+    fn collect_chain(&self, positive: bool, chain: BinaryOp, plus: &mut Vec<String>, minus: &mut Vec<String>) {
+        let inverse = if chain == BinaryOp::Add { BinaryOp::Sub } else { BinaryOp::Div };
+        match self {
+            // The tokenizer folds `-5` into the literal; count it as `- 5`
+            EquationNode::Number(n) if chain == BinaryOp::Add && *n < 0.0 => {
+                if positive { minus } else { plus }.push((-n).to_string())
+            }
+            EquationNode::Negate(inner) if chain == BinaryOp::Add => inner.collect_chain(!positive, chain, plus, minus),
+            EquationNode::Binary { op, left, right } if *op == chain || *op == inverse => {
+                left.collect_chain(positive, chain, plus, minus);
+                right.collect_chain(positive == (*op == chain), chain, plus, minus);
+            }
+            _ if positive => plus.push(self.canonical_key()),
+            _ => minus.push(self.canonical_key()),
+        }
+    }
+
+    /// Evaluate with integers of any size, e.g. `30! * 2 ^ 80`
+    ///
+    /// Numbers must be whole and below 2^53 so they are read exactly. `None`
+    /// for division, functions, and exponents or factorials too large to be practical.
+    pub fn evaluate_integer(&self) -> Option<BigInt> {
+        match self {
+            EquationNode::Number(n) if n.abs() <= F64_EXACT_LIMIT => BigInt::from_f64(*n),
+            EquationNode::Number(_) => None,
+            EquationNode::Negate(inner) => Some(-&inner.evaluate_integer()?),
+            EquationNode::Factorial(inner) => {
+                let n = inner.evaluate_integer()?.to_u32().filter(|&n| n <= 1000)?;
+                Some(BigInt::factorial(n))
+            }
+            EquationNode::Binary { op, left, right } => {
+                let a = left.evaluate_integer()?;
+                let b = right.evaluate_integer()?;
+                match op {
+                    BinaryOp::Add => Some(&a + &b),
+                    BinaryOp::Sub => Some(&a - &b),
+                    BinaryOp::Mul => Some(&a * &b),
+                    BinaryOp::Pow => Some(a.pow(b.to_u32().filter(|&e| e <= 10_000)?)),
+                    BinaryOp::Div | BinaryOp::Rem => None,
+                }
+            }
+            EquationNode::Call { .. } | EquationNode::Slot(_) => None,
+        }
+    }
+}
+
+fn chain_key(op: char, mut plus: Vec<String>, mut minus: Vec<String>) -> String {
+    plus.sort();
+    minus.sort();
+    format!("{}({};{})", op, plus.join(","), minus.join(","))
+}
+
+impl fmt::Display for EquationNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EquationNode::Number(n) => write!(f, "{}", n),
+            EquationNode::Slot(i) => write!(f, "{{{}}}", i),
+            EquationNode::Negate(inner) => match inner.as_ref() {
+                EquationNode::Binary { .. } => write!(f, "-({})", inner),
+                _ => write!(f, "-{}", inner),
+            },
+            EquationNode::Factorial(inner) => match inner.as_ref() {
+                EquationNode::Number(_) | EquationNode::Slot(_) | EquationNode::Call { .. } => write!(f, "{}!", inner),
+                _ => write!(f, "({})!", inner),
+            },
+            EquationNode::Binary { op, left, right } => {
+                let wrap_left = matches!(left.as_ref(), EquationNode::Binary { op: inner, .. }
+                    if inner.precedence() < op.precedence()
+                        || (*op == BinaryOp::Pow && inner.precedence() == op.precedence()));
+                let wrap_right = matches!(right.as_ref(), EquationNode::Binary { op: inner, .. }
+                    if inner.precedence() < op.precedence()
+                        || (inner.precedence() == op.precedence() && *op != BinaryOp::Pow));
+                if wrap_left {
+                    write!(f, "({})", left)?;
+                } else {
+                    write!(f, "{}", left)?;
+                }
+                write!(f, " {} ", op.symbol())?;
+                if wrap_right {
+                    write!(f, "({})", right)
+                } else {
+                    write!(f, "{}", right)
+                }
+            }
+            EquationNode::Call { name, args } => {
+                let rendered: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+                write!(f, "{}({})", name, rendered.join(", "))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Slot(usize),
+    Ident(String),
+    Op(char),
+    LeftParen,
+    RightParen,
+    Comma,
+    Bang,
+}
+
+fn tokenize(text: &str, slots: bool) -> Result<Vec<Token>> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            // Exponent form produced by f64 Display for very large/small values
+            if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+                i += 1;
+                if i < chars.len() && (chars[i] == '-' || chars[i] == '+') {
+                    i += 1;
+                }
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+            }
+            let literal: String = chars[start..i].iter().collect();
+            tokens.push(Token::Number(literal.parse()?));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let ident: String = chars[start..i].iter().collect();
+            match ident.as_str() {
+                "inf" => tokens.push(Token::Number(f64::INFINITY)),
+                "NaN" => tokens.push(Token::Number(f64::NAN)),
+                _ => tokens.push(Token::Ident(ident)),
+            }
+        } else if c == '{' && slots {
+            let close = chars[i..].iter().position(|&c| c == '}')
+                .ok_or_else(|| anyhow::anyhow!("Unclosed slot in template: {}", text))?;
+            let index: String = chars[i + 1..i + close].iter().collect();
+            tokens.push(Token::Slot(index.parse()?));
+            i += close + 1;
+        } else {
+            tokens.push(match c {
+                '+' | '-' | '*' | '/' | '%' | '^' => Token::Op(c),
+                '(' => Token::LeftParen,
+                ')' => Token::RightParen,
+                ',' => Token::Comma,
+                '!' => Token::Bang,
+                _ => return Err(anyhow::anyhow!("Unexpected character '{}' in equation: {}", c, text)),
+            });
+            i += 1;
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct TreeParser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl TreeParser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<()> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            other => Err(anyhow::anyhow!("Expected {:?}, found {:?}", expected, other)),
+        }
+    }
+
+    /// Precedence climbing; `^` is right associative
+    fn parse_expression(&mut self, min_precedence: u8) -> Result<EquationNode> {
+        let mut left = self.parse_unary()?;
+
+        loop {
+            let op = match self.peek() {
+                Some(Token::Op('+')) => BinaryOp::Add,
+                Some(Token::Op('-')) => BinaryOp::Sub,
+                Some(Token::Op('*')) => BinaryOp::Mul,
+                Some(Token::Op('/')) => BinaryOp::Div,
+                Some(Token::Op('%')) => BinaryOp::Rem,
+                Some(Token::Op('^')) => BinaryOp::Pow,
+                _ => break,
+            };
+            if op.precedence() < min_precedence {
+                break;
+            }
+            self.next();
+            let next_min = if op == BinaryOp::Pow { op.precedence() } else { op.precedence() + 1 };
+            let right = self.parse_expression(next_min)?;
+            left = EquationNode::Binary { op, left: Box::new(left), right: Box::new(right) };
+        }
+
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<EquationNode> {
+        if let Some(Token::Op('-')) = self.peek() {
+            self.next();
+            let inner = self.parse_unary()?;
+            // Fold negative literals so `-3` round-trips as a number
+            return Ok(match inner {
+                EquationNode::Number(n) => EquationNode::Number(-n),
+                other => EquationNode::Negate(Box::new(other)),
+            });
+        }
+        self.parse_postfix()
+    }
+
+    fn parse_postfix(&mut self) -> Result<EquationNode> {
+        let mut node = self.parse_primary()?;
+        while let Some(Token::Bang) = self.peek() {
+            self.next();
+            node = EquationNode::Factorial(Box::new(node));
+        }
+        Ok(node)
+    }
+
+    fn parse_primary(&mut self) -> Result<EquationNode> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(EquationNode::Number(n)),
+            Some(Token::Slot(i)) => Ok(EquationNode::Slot(i)),
+            Some(Token::LeftParen) => {
+                let inner = self.parse_expression(0)?;
+                self.expect(Token::RightParen)?;
+                Ok(inner)
+            }
+            Some(Token::Ident(name)) => {
+                self.expect(Token::LeftParen)?;
+                let mut args = Vec::new();
+                if self.peek() != Some(&Token::RightParen) {
+                    loop {
+                        args.push(self.parse_expression(0)?);
+                        if self.peek() == Some(&Token::Comma) {
+                            self.next();
+                        } else {
+                            break;
+                        }
+                    }
+                }
+                self.expect(Token::RightParen)?;
+                let known = SOLVER_FUNCTIONS.iter()
+                    .any(|(f, arities)| *f == name && arities.contains(&args.len()));
+                if !known {
+                    return Err(anyhow::anyhow!("Unknown function {}/{}", name, args.len()));
+                }
+                Ok(EquationNode::Call { name, args })
+            }
+            other => Err(anyhow::anyhow!("Unexpected token in equation: {:?}", other)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_evaluate() {
+        let cases = [
+            ("3 * 7 + 229", 250.0),
+            ("(3 + 7) * 25", 250.0),
+            ("2 ^ 3 ^ 2", 512.0),
+            ("5!", 120.0),
+            ("sqrt(9) - -3", 6.0),
+            ("avg(2, 4, 6)", 4.0),
+            ("max(3, 7) % 4", 3.0),
+            ("100 / 4 / 5", 5.0),
+        ];
+        for (text, expected) in cases {
+            let node = EquationNode::parse(text).unwrap();
+            assert_eq!(node.evaluate().unwrap(), expected, "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_evaluate_exact() {
+        let exact = |text: &str| EquationNode::parse(text).unwrap().evaluate_exact();
+        assert_eq!(exact("1.1 * 3"), Rational::from_f64(3.3));
+        assert_eq!(exact("(0.1 + 0.2) / 3"), Rational::new(1, 10));
+        assert_eq!(exact("sqrt(2.25) + 2 ^ -1"), Some(Rational::integer(2)));
+        assert_eq!(exact("hypot(3, 4) * 5!"), Some(Rational::integer(600)));
+        assert_eq!(exact("sqrt(2)"), None);
+        assert_eq!(exact("atan2(1, 1)"), None);
+        assert_eq!(exact("4 ^ 0.5"), None);
+
+        let integer = |text: &str| EquationNode::parse(text).unwrap().evaluate_integer().map(|n| n.to_string());
+        assert_eq!(integer("25! + 1").as_deref(), Some("15511210043330985984000001"));
+        assert_eq!(integer("2 ^ 64 - 3 * 4").as_deref(), Some("18446744073709551604"));
+        assert_eq!(integer("6 / 3"), None);
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        for text in ["(3 + 7) * 25", "3 - (4 - 5)", "geomean(2, 8)", "(2 ^ 3) ^ 2", "12 / (2 * 3)"] {
+            let node = EquationNode::parse(text).unwrap();
+            assert_eq!(node.to_string(), text);
+            assert_eq!(EquationNode::parse(&node.to_string()).unwrap(), node);
+        }
+    }
+
+    #[test]
+    fn test_canonical_key() {
+        let key = |text: &str| EquationNode::parse(text).unwrap().canonical_key();
+        for (a, b) in [
+            ("3 + 7 * 25", "25 * 7 + 3"),
+            ("2 - 5 + 9", "9 + (2 - 5)"),
+            ("2 - 5 + 9", "-5 + 9 + 2"),
+            ("100 / 4 / 5", "100 / (5 * 4)"),
+            ("(3 + 4) * 6", "6 * (4 + 3)"),
+            ("max(max(1, 2), 3)", "max(3, max(2, 1))"),
+            ("avg(4, 2, 8)", "avg(8, 4, 2)"),
+        ] {
+            assert_eq!(key(a), key(b), "{} vs {}", a, b);
+        }
+        for (a, b) in [("3 - 7", "7 - 3"), ("8 / 2", "2 / 8"), ("2 ^ 3", "3 ^ 2"), ("atan2(1, 2)", "atan2(2, 1)")] {
+            assert_ne!(key(a), key(b), "{} vs {}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_rejects_unknown_function() {
+        assert!(EquationNode::parse("foo(1)").is_err());
+        assert!(EquationNode::parse("3 +").is_err());
+    }
+
+    #[test]
+    fn test_template_slots() {
+        let shape = EquationNode::parse_template("{1} * ({0} + {2})").unwrap();
+        assert_eq!(shape.to_string(), "{1} * ({0} + {2})");
+        assert!(shape.evaluate().is_err());
+        let filled = shape.fill_slots(&[1_000_000_001.0, 2.0, -3.0]);
+        assert_eq!(filled, EquationNode::parse("2 * (1000000001 + -3)").unwrap());
+        assert_eq!(filled.evaluate().unwrap(), 1_999_999_996.0);
+
+        // Only templates hold slots
+        assert!(EquationNode::parse("{0} + 1").is_err());
+        assert!(EquationNode::parse_template("{0 + 1").is_err());
+    }
+}
@@ -0,0 +1,736 @@
+// Iterative-deepening search over binary expression trees built from solver inputs
+// Each input is a leaf at most once; trees over the same inputs that reach the same value are kept once
+// Values are f64 with an exact rational alongside, or big integers for integer-only problems
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::hash::Hash;
+use std::str::FromStr;
+
+use crate::bigint::BigInt;
+use crate::cancellation::CancellationToken;
+use crate::equation_solver::Operation;
+use crate::equation_tree::{BinaryOp, EquationNode};
+use crate::limits::SolveLimits;
+use crate::operator_set::{Operator, OperatorSet};
+use crate::rational::Rational;
+
+/// How far `EquationSolver::search_trees` looks for a target
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SearchConfig {
+    /// Binary operators in one tree; a tree with k operators uses k + 1 inputs
+    pub max_operators: usize,
+    /// Nesting depth of a tree, a lone input being depth 0; `None` only caps operators
+    pub max_depth: Option<usize>,
+    /// Trees evaluated before the search settles for its best approximation
+    pub max_candidates: usize,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            max_operators: 4,
+            max_depth: None,
+            max_candidates: 200_000,
+        }
+    }
+}
+
+/// Which inputs a solved equation may use, set per statement with `rules: ...`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SolveRules {
+    /// Any operation the solver knows; inputs may repeat and cached intermediates stand in for them
+    #[default]
+    Free,
+    /// Each input at most once, as in a numbers puzzle (`rules: once`)
+    Once,
+    /// Every input exactly once (`rules: all`)
+    All,
+}
+
+impl SolveRules {
+    /// Suffix that keeps solutions found under different rules apart in the cache
+    pub fn cache_suffix(&self) -> &'static str {
+        match self {
+            SolveRules::Free => "",
+            SolveRules::Once => "-once",
+            SolveRules::All => "-all",
+        }
+    }
+
+    pub fn describe(&self) -> &'static str {
+        match self {
+            SolveRules::Free => "with any operation",
+            SolveRules::Once => "using each input at most once",
+            SolveRules::All => "using every input exactly once",
+        }
+    }
+}
+
+impl FromStr for SolveRules {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name {
+            "free" => Ok(SolveRules::Free),
+            "once" => Ok(SolveRules::Once),
+            "all" => Ok(SolveRules::All),
+            _ => Err(anyhow::anyhow!("Unknown solving rules '{}'; expected free, once or all", name)),
+        }
+    }
+}
+
+impl fmt::Display for SolveRules {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SolveRules::Free => "free",
+            SolveRules::Once => "once",
+            SolveRules::All => "all",
+        })
+    }
+}
+
+/// How one `randomChoice` target is solved: `randomChoice([...], rules: once, ops: basic)`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SolveOptions {
+    pub rules: SolveRules,
+    /// `None` leaves it to the engine's operator set
+    pub operators: Option<OperatorSet>,
+}
+
+/// `rules: once, ops: [+, -]`, each option optional and separated by commas
+impl FromStr for SolveOptions {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        let mut options = SolveOptions::default();
+        let mut rest = text;
+        loop {
+            rest = rest.trim_start_matches(|c: char| c == ',' || c.is_whitespace());
+            if rest.is_empty() {
+                return Ok(options);
+            }
+            let (key, after) = rest.split_once(':')
+                .ok_or_else(|| anyhow::anyhow!("Expected 'name: value' in solve options, got '{}'", rest))?;
+            let after = after.trim_start();
+            let end = if after.starts_with('[') {
+                after.find(']').map(|i| i + 1).ok_or_else(|| anyhow::anyhow!("Unclosed '[' in solve options"))?
+            } else {
+                after.find(',').unwrap_or(after.len())
+            };
+            let value = after[..end].trim();
+            match key.trim() {
+                "rules" => options.rules = value.parse()?,
+                "ops" => options.operators = Some(value.parse()?),
+                other => return Err(anyhow::anyhow!("Unknown solve option '{}'; expected rules or ops", other)),
+            }
+            rest = &after[end..];
+        }
+    }
+}
+
+/// The options as written after the input list, empty when all are defaults
+impl fmt::Display for SolveOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if self.rules != SolveRules::Free {
+            parts.push(format!("rules: {}", self.rules));
+        }
+        if let Some(operators) = self.operators {
+            parts.push(format!("ops: {}", operators));
+        }
+        f.write_str(&parts.join(", "))
+    }
+}
+
+/// What stops a search before its config does: Stop requests and the run's `SolveLimits`
+#[derive(Debug, Clone, Default)]
+pub struct SearchGuard {
+    pub cancel: CancellationToken,
+    pub limits: SolveLimits,
+}
+
+impl SearchGuard {
+    /// Stop requests only, with no deadline or candidate limit
+    pub fn new(cancel: CancellationToken) -> Self {
+        Self { cancel, limits: SolveLimits::default() }
+    }
+}
+
+/// Candidates evaluated between deadline checks
+const DEADLINE_POLL: usize = 256;
+
+/// What a tree search found
+#[derive(Debug, Clone, Default)]
+pub struct SearchOutcome {
+    /// The first tree that hits the target, fewest operators first
+    pub exact: Option<Operation>,
+    /// Every tree that hits the target, in search order; `exact` is the first
+    pub hits: Vec<Operation>,
+    /// The closest tree seen before the search stopped
+    pub best: Option<Operation>,
+    /// Trees evaluated, duplicates included
+    pub candidates: usize,
+    /// Every tree within the config's limits was tried
+    pub exhausted: bool,
+}
+
+/// A number the tree search combines: f64 with its exact rational alongside, or a big integer
+trait SearchValue: Sized {
+    type Key: Eq + Hash;
+
+    /// Identity for pruning: two trees over the same inputs with equal keys are interchangeable
+    fn key(&self) -> Self::Key;
+    /// `x op y`, or `None` when it is undefined or the solver would not emit it
+    fn apply(op: BinaryOp, x: &Self, y: &Self) -> Option<Self>;
+    fn hits(&self, target: &Self) -> bool;
+    /// How far from `target`, for ranking approximations
+    fn distance(&self, target: &Self) -> f64;
+    fn to_f64(&self) -> f64;
+}
+
+/// An f64 value and, while every step has stayed rational, the same value without rounding
+struct Float {
+    value: f64,
+    exact: Option<Rational>,
+}
+
+/// Values are told apart exactly when rational, by their bits otherwise
+#[derive(PartialEq, Eq, Hash)]
+enum FloatKey {
+    Exact(Rational),
+    Bits(u64),
+}
+
+impl Float {
+    fn new(value: f64) -> Self {
+        Self { value, exact: Rational::from_f64(value) }
+    }
+}
+
+impl SearchValue for Float {
+    type Key = FloatKey;
+
+    fn key(&self) -> FloatKey {
+        match self.exact {
+            Some(exact) => FloatKey::Exact(exact),
+            // -0 and 0 are the same value
+            None => FloatKey::Bits(if self.value == 0.0 { 0 } else { self.value.to_bits() }),
+        }
+    }
+
+    fn apply(op: BinaryOp, x: &Self, y: &Self) -> Option<Self> {
+        let (a, b) = (x.value, y.value);
+        let value = match op {
+            BinaryOp::Add => a + b,
+            BinaryOp::Sub => a - b,
+            BinaryOp::Mul => a * b,
+            BinaryOp::Div if b.abs() > f64::EPSILON => a / b,
+            // Small whole exponents only, as in the fixed-shape search
+            BinaryOp::Pow if a.abs() <= 100.0 && (0.0..=10.0).contains(&b) && b.fract() == 0.0 => a.powf(b),
+            _ => return None,
+        };
+        if !value.is_finite() {
+            return None;
+        }
+
+        let exact = x.exact.zip(y.exact).and_then(|(a, b)| match op {
+            BinaryOp::Add => a.checked_add(b),
+            BinaryOp::Sub => a.checked_sub(b),
+            BinaryOp::Mul => a.checked_mul(b),
+            BinaryOp::Div => a.checked_div(b),
+            BinaryOp::Rem => a.checked_rem(b),
+            BinaryOp::Pow => a.checked_pow(b),
+        });
+        Some(Self { value, exact })
+    }
+
+    /// Exact equality when both sides are rational, within `f64::EPSILON` otherwise
+    fn hits(&self, target: &Self) -> bool {
+        match (self.exact, target.exact) {
+            (Some(a), Some(b)) => a == b,
+            _ => self.distance(target) < f64::EPSILON,
+        }
+    }
+
+    fn distance(&self, target: &Self) -> f64 {
+        (self.value - target.value).abs()
+    }
+
+    fn to_f64(&self) -> f64 {
+        self.value
+    }
+}
+
+/// Largest input the integer search takes the factorial of
+const MAX_INTEGER_FACTORIAL: u32 = 100;
+/// Decimal digits an intermediate integer may grow to before the search drops it
+const MAX_INTEGER_DIGITS: usize = 1000;
+
+impl SearchValue for BigInt {
+    type Key = BigInt;
+
+    fn key(&self) -> BigInt {
+        self.clone()
+    }
+
+    /// Integer-only: no division, and powers whose result would pass `MAX_INTEGER_DIGITS` are skipped
+    fn apply(op: BinaryOp, x: &Self, y: &Self) -> Option<Self> {
+        let value = match op {
+            BinaryOp::Add => x + y,
+            BinaryOp::Sub => x - y,
+            BinaryOp::Mul => x * y,
+            BinaryOp::Pow => {
+                let exponent = y.to_u32()?;
+                // x ^ e has about e * log10|x| digits; bail out before computing a huge power
+                if exponent as f64 * x.abs().to_f64().log10() > MAX_INTEGER_DIGITS as f64 + 1.0 {
+                    return None;
+                }
+                x.pow(exponent)
+            }
+            BinaryOp::Div | BinaryOp::Rem => return None,
+        };
+        (value.digits() <= MAX_INTEGER_DIGITS).then_some(value)
+    }
+
+    fn hits(&self, target: &Self) -> bool {
+        self == target
+    }
+
+    fn distance(&self, target: &Self) -> f64 {
+        (self - target).to_f64().abs()
+    }
+
+    fn to_f64(&self) -> f64 {
+        BigInt::to_f64(self)
+    }
+}
+
+/// A distinct value reachable from one subset of the inputs
+struct Reached<V> {
+    value: V,
+    node: EquationNode,
+    depth: usize,
+}
+
+/// Values reachable from one subset, in the order they were found
+struct Bucket<V: SearchValue> {
+    reached: Vec<Reached<V>>,
+    index: HashMap<V::Key, usize>,
+}
+
+impl<V: SearchValue> Default for Bucket<V> {
+    fn default() -> Self {
+        Self { reached: Vec::new(), index: HashMap::new() }
+    }
+}
+
+impl<V: SearchValue> Bucket<V> {
+    /// Keep `reached` unless the value is already known from a tree at most as deep
+    fn insert(&mut self, reached: Reached<V>) {
+        let key = reached.value.key();
+        match self.index.get(&key) {
+            Some(&i) if self.reached[i].depth > reached.depth => self.reached[i] = reached,
+            Some(_) => {}
+            None => {
+                self.index.insert(key, self.reached.len());
+                self.reached.push(reached);
+            }
+        }
+    }
+}
+
+struct Search<'a, V> {
+    target: V,
+    config: &'a SearchConfig,
+    guard: &'a SearchGuard,
+    /// Under `SolveRules::All`, the mask every answer must cover
+    required: Option<u64>,
+    operators: OperatorSet,
+    /// Keep going past the level of the first hit, until the limits run out
+    enumerate: bool,
+    outcome: SearchOutcome,
+    best_distance: f64,
+}
+
+impl<V: SearchValue> Search<'_, V> {
+    /// Record one evaluated tree over the inputs in `mask`; true once the candidate cap is reached
+    ///
+    /// Fails when the run's candidate limit or deadline is passed.
+    fn consider(&mut self, mask: u64, value: &V, node: &EquationNode) -> Result<bool> {
+        self.outcome.candidates += 1;
+        self.guard.limits.check_candidates(self.outcome.candidates)?;
+        if self.outcome.candidates.is_multiple_of(DEADLINE_POLL) {
+            self.guard.limits.check_deadline()?;
+        }
+        if self.required.is_some_and(|required| mask != required) {
+            return Ok(self.outcome.candidates >= self.config.max_candidates);
+        }
+
+        let distance = value.distance(&self.target);
+        if distance < self.best_distance {
+            self.best_distance = distance;
+            self.outcome.best = Some(to_operation(value.to_f64(), node));
+        }
+        if value.hits(&self.target) {
+            let hit = to_operation(value.to_f64(), node);
+            self.outcome.exact.get_or_insert_with(|| hit.clone());
+            self.outcome.hits.push(hit);
+        }
+        Ok(self.outcome.candidates >= self.config.max_candidates)
+    }
+
+    /// True once a level has ended with a hit and the search is not enumerating
+    fn settled(&self) -> bool {
+        !self.enumerate && self.outcome.exact.is_some()
+    }
+}
+
+fn to_operation(value: f64, node: &EquationNode) -> Operation {
+    let equation = node.to_string();
+    Operation { result: value, formula: equation.clone(), equation }
+}
+
+/// Search trees with 0, 1, 2, ... operators until one hits `target`
+///
+/// Level k combines every pair of disjoint subsets whose sizes add up to
+/// k + 1, so no input is used twice in one tree. Order is fixed by input
+/// position, which makes the first exact hit the same on every run.
+/// Because of that an exhausted search with no exact hit shows that no
+/// tree within the limits and `operators` reaches the target. The level
+/// holding the first hit is finished, so `hits` has every fewest-operator
+/// answer for the caller to rank.
+pub fn search(
+    inputs: &[f64],
+    target: f64,
+    config: &SearchConfig,
+    rules: SolveRules,
+    operators: OperatorSet,
+    guard: &SearchGuard
+) -> Result<SearchOutcome> {
+    search_values(float_leaves(inputs), Float::new(target), config, rules, operators, false, guard)
+}
+
+/// `search` under `SolveRules::Free` that keeps collecting hits at every level
+/// until the limits or `max_candidates` run out
+///
+/// Still not every equation: a subtree whose value an earlier, no deeper
+/// subtree over the same inputs already reached is not built on, so
+/// `(2 + 2) * 3` stands in for `(2 * 2) * 3`.
+pub fn enumerate(
+    inputs: &[f64],
+    target: f64,
+    config: &SearchConfig,
+    operators: OperatorSet,
+    guard: &SearchGuard
+) -> Result<SearchOutcome> {
+    search_values(float_leaves(inputs), Float::new(target), config, SolveRules::Free, operators, true, guard)
+}
+
+fn float_leaves(inputs: &[f64]) -> Vec<Vec<(Float, EquationNode)>> {
+    inputs.iter()
+        .filter(|x| x.is_finite())
+        .map(|&x| vec![(Float::new(x), EquationNode::Number(x))])
+        .collect()
+}
+
+/// `search` for integer-only problems, exact at any size
+///
+/// Each input may also stand in as its factorial (`25!`) when `operators` allows it, and powers are
+/// not limited to small bases; division is left out. The outcome's `result`
+/// fields are rounded, `EquationNode::evaluate_integer` recovers the exact value.
+pub fn search_integers(
+    inputs: &[BigInt],
+    target: &BigInt,
+    config: &SearchConfig,
+    rules: SolveRules,
+    operators: OperatorSet,
+    guard: &SearchGuard
+) -> Result<SearchOutcome> {
+    let leaves = inputs.iter()
+        .map(|n| {
+            let node = EquationNode::Number(n.to_f64());
+            let mut variants = vec![(n.clone(), node.clone())];
+            let factorial = n.to_u32()
+                .filter(|small| operators.contains(Operator::Factorial) && (3..=MAX_INTEGER_FACTORIAL).contains(small));
+            if let Some(small) = factorial {
+                variants.push((BigInt::factorial(small), EquationNode::Factorial(Box::new(node))));
+            }
+            variants
+        })
+        .collect();
+    search_values(leaves, target.clone(), config, rules, operators, false, guard)
+}
+
+/// The search itself; `leaves[i]` holds the values input `i` can stand for on its own
+fn search_values<V: SearchValue>(
+    leaves: Vec<Vec<(V, EquationNode)>>,
+    target: V,
+    config: &SearchConfig,
+    rules: SolveRules,
+    operators: OperatorSet,
+    enumerate: bool,
+    guard: &SearchGuard
+) -> Result<SearchOutcome> {
+    let leaves: Vec<_> = leaves.into_iter().take(u64::BITS as usize).collect();
+
+    let mut search = Search {
+        target,
+        config,
+        guard,
+        required: (rules == SolveRules::All).then(|| u64::MAX >> (u64::BITS as usize - leaves.len().max(1))),
+        operators,
+        enumerate,
+        outcome: SearchOutcome::default(),
+        best_distance: f64::INFINITY,
+    };
+
+    // levels[k] maps a subset of inputs (as a bit mask) to the values its k-operator trees reach
+    let mut levels: Vec<BTreeMap<u64, Bucket<V>>> = Vec::new();
+
+    let input_count = leaves.len();
+    let mut level = BTreeMap::new();
+    for (index, variants) in leaves.into_iter().enumerate() {
+        let mut bucket = Bucket::default();
+        for (value, node) in variants {
+            if search.consider(1 << index, &value, &node)? {
+                return Ok(search.outcome);
+            }
+            bucket.insert(Reached { value, node, depth: 0 });
+        }
+        level.insert(1u64 << index, bucket);
+    }
+    levels.push(level);
+    if search.settled() {
+        return Ok(search.outcome);
+    }
+
+    let max_operators = config.max_operators.min(input_count.saturating_sub(1));
+    for operators in 1..=max_operators {
+        let mut level: BTreeMap<u64, Bucket<V>> = BTreeMap::new();
+
+        // Left subtree takes `left_ops` operators, right the rest; one split is the other mirrored
+        for left_ops in 0..operators {
+            let right_ops = operators - 1 - left_ops;
+            if left_ops > right_ops {
+                break;
+            }
+            for (&left_mask, left) in &levels[left_ops] {
+                for (&right_mask, right) in &levels[right_ops] {
+                    if left_mask & right_mask != 0 || (left_ops == right_ops && left_mask > right_mask) {
+                        continue;
+                    }
+                    search.guard.cancel.check()?;
+                    let mask = left_mask | right_mask;
+                    let bucket = level.entry(mask).or_default();
+                    if combine(&mut search, mask, left, right, bucket)? {
+                        return Ok(search.outcome);
+                    }
+                }
+            }
+        }
+
+        levels.push(level);
+        if search.settled() {
+            return Ok(search.outcome);
+        }
+    }
+
+    search.outcome.exhausted = true;
+    Ok(search.outcome)
+}
+
+/// Every operator over every value pair of two disjoint subsets; true once the candidate cap is reached
+fn combine<V: SearchValue>(
+    search: &mut Search<V>,
+    mask: u64,
+    left: &Bucket<V>,
+    right: &Bucket<V>,
+    into: &mut Bucket<V>
+) -> Result<bool> {
+    for a in &left.reached {
+        for b in &right.reached {
+            let depth = a.depth.max(b.depth) + 1;
+            if search.config.max_depth.is_some_and(|max| depth > max) {
+                continue;
+            }
+            for (op, x, y) in candidates(a, b) {
+                if !search.operators.contains(Operator::from(op)) {
+                    continue;
+                }
+                let Some(value) = V::apply(op, &x.value, &y.value) else { continue };
+                let node = EquationNode::Binary {
+                    op,
+                    left: Box::new(x.node.clone()),
+                    right: Box::new(y.node.clone()),
+                };
+                if search.consider(mask, &value, &node)? {
+                    return Ok(true);
+                }
+                into.insert(Reached { value, node, depth });
+            }
+        }
+    }
+    Ok(false)
+}
+
+/// Operator and operand order for each tree over `a` and `b`; commutative ones appear once
+fn candidates<'a, V>(a: &'a Reached<V>, b: &'a Reached<V>) -> [(BinaryOp, &'a Reached<V>, &'a Reached<V>); 8] {
+    [
+        (BinaryOp::Add, a, b),
+        (BinaryOp::Mul, a, b),
+        (BinaryOp::Sub, a, b),
+        (BinaryOp::Sub, b, a),
+        (BinaryOp::Div, a, b),
+        (BinaryOp::Div, b, a),
+        (BinaryOp::Pow, a, b),
+        (BinaryOp::Pow, b, a),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deepens_until_a_tree_hits() {
+        let guard = SearchGuard::default();
+        let config = SearchConfig::default();
+
+        // Needs a cached intermediate in the fixed shapes; two operators here
+        let outcome = search(&[3.0, 7.0, 25.0, 0.0], 250.0, &config, SolveRules::Free, OperatorSet::ALL, &guard).unwrap();
+        assert_eq!(outcome.exact.unwrap().equation, "25 * (3 + 7)");
+
+        let outcome = search(&[1.0, 2.0, 3.0, 4.0], 24.0, &config, SolveRules::Free, OperatorSet::ALL, &guard).unwrap();
+        let exact = outcome.exact.unwrap();
+        assert_eq!(EquationNode::parse(&exact.equation).unwrap().evaluate().unwrap(), 24.0);
+
+        // Each input is used once, so 24 is out of reach with one operator or one level of nesting
+        for capped in [SearchConfig { max_operators: 1, ..config }, SearchConfig { max_depth: Some(1), ..config }] {
+            let outcome = search(&[1.0, 2.0, 3.0, 4.0], 24.0, &capped, SolveRules::Free, OperatorSet::ALL, &guard).unwrap();
+            assert!(outcome.exact.is_none() && outcome.exhausted);
+        }
+
+        // 3.3000000000000003 in f64, exactly 3.3 as decimals
+        let outcome = search(&[1.1, 3.0], 3.3, &config, SolveRules::Free, OperatorSet::ALL, &guard).unwrap();
+        assert_eq!(outcome.exact.unwrap().equation, "1.1 * 3");
+
+        let outcome = search(&[2.0, 3.0], 100.0, &config, SolveRules::Free, OperatorSet::ALL, &guard).unwrap();
+        assert!(outcome.exact.is_none() && outcome.exhausted);
+        assert_eq!(outcome.best.unwrap().equation, "3 ^ 2");
+
+        let outcome = search(&[1.0, 2.0, 3.0, 4.0, 5.0], 1e9, &SearchConfig { max_candidates: 50, ..config }, SolveRules::Free, OperatorSet::ALL, &guard).unwrap();
+        assert!(!outcome.exhausted && outcome.candidates == 50 && outcome.best.is_some());
+
+        guard.cancel.cancel();
+        assert!(search(&[1.0, 2.0, 3.0], 1e9, &config, SolveRules::Free, OperatorSet::ALL, &guard).is_err());
+    }
+
+    #[test]
+    fn test_hits_cover_the_first_level_or_every_level() {
+        let guard = SearchGuard::default();
+        let config = SearchConfig::default();
+        let evaluates_to = |hits: &[Operation], target: f64| hits.iter()
+            .all(|hit| EquationNode::parse(&hit.equation).unwrap().evaluate().unwrap() == target);
+
+        // 2 + 3 and 3 + 2 are one tree; 10 / 2 and 7 - 2 also reach 5 with one operator
+        let first = search(&[2.0, 3.0, 10.0, 7.0], 5.0, &config, SolveRules::Free, OperatorSet::ALL, &guard).unwrap();
+        assert_eq!(first.exact.as_ref().unwrap().equation, first.hits[0].equation);
+        assert_eq!(first.hits.len(), 3);
+        assert!(evaluates_to(&first.hits, 5.0) && !first.exhausted);
+
+        let every = enumerate(&[2.0, 3.0, 10.0, 7.0], 5.0, &config, OperatorSet::ALL, &guard).unwrap();
+        assert!(every.exhausted && every.hits.len() > first.hits.len());
+        assert!(evaluates_to(&every.hits, 5.0));
+        assert!(every.hits.iter().any(|hit| EquationNode::parse(&hit.equation).unwrap().operator_uses().len() == 3));
+    }
+
+    #[test]
+    fn test_rules_limit_which_inputs_a_tree_covers() {
+        let guard = SearchGuard::default();
+        let config = SearchConfig { max_operators: 3, ..Default::default() };
+
+        // A lone input is the fewest-operator answer unless every input must be used
+        let once = search(&[2.0, 3.0, 5.0, 7.0], 7.0, &config, SolveRules::Once, OperatorSet::ALL, &guard).unwrap();
+        assert_eq!(once.exact.unwrap().equation, "7");
+        let all = search(&[2.0, 3.0, 5.0, 7.0], 7.0, &config, SolveRules::All, OperatorSet::ALL, &guard).unwrap();
+        let equation = all.exact.unwrap().equation;
+        assert_eq!(EquationNode::parse(&equation).unwrap().evaluate().unwrap(), 7.0);
+        assert!(["2", "3", "5", "7"].iter().all(|n| equation.contains(n)), "{}", equation);
+
+        // Exhausting every tree proves there is no answer
+        let none = search(&[1.0, 1.0, 1.0], 100.0, &config, SolveRules::Once, OperatorSet::ALL, &guard).unwrap();
+        assert!(none.exact.is_none() && none.exhausted);
+        assert_eq!(none.best.unwrap().result, 3.0);
+        let partial = search(&[2.0, 3.0, 50.0], 5.0, &config, SolveRules::All, OperatorSet::ALL, &guard).unwrap();
+        let closest = partial.exact.or(partial.best).unwrap();
+        assert!(closest.equation.contains("50"), "{}", closest.equation);
+    }
+
+    #[test]
+    fn test_integer_search_stays_exact_past_f64() {
+        let guard = SearchGuard::default();
+        let config = SearchConfig::default();
+        let big = |text: &str| text.parse::<BigInt>().unwrap();
+
+        // 25! * 3 + 1 rounds to 25! * 3 in f64
+        let target = big("46533630129992957952000001");
+        let outcome = search_integers(&[big("25"), big("3"), big("1")], &target, &config, SolveRules::Free, OperatorSet::ALL, &guard).unwrap();
+        let equation = outcome.exact.unwrap().equation;
+        assert_eq!(EquationNode::parse(&equation).unwrap().evaluate_integer(), Some(target));
+
+        let outcome = search_integers(&[big("2"), big("100")], &big("1267650600228229401496703205376"), &config, SolveRules::Free, OperatorSet::ALL, &guard).unwrap();
+        assert_eq!(outcome.exact.unwrap().equation, "2 ^ 100");
+
+        // One off is a miss, however close in f64
+        let target = big("1267650600228229401496703205377");
+        let outcome = search_integers(&[big("2"), big("100")], &target, &config, SolveRules::Free, OperatorSet::ALL, &guard).unwrap();
+        assert!(outcome.exact.is_none() && outcome.exhausted);
+    }
+
+    #[test]
+    fn test_integer_powers_past_the_digit_cap_are_not_computed() {
+        let guard = SearchGuard::default();
+        let big = |text: &str| text.parse::<BigInt>().unwrap();
+        let pow = |x: &str, e: &str| <BigInt as SearchValue>::apply(BinaryOp::Pow, &big(x), &big(e));
+
+        assert_eq!(pow("2", "3000").unwrap().digits(), 904);
+        assert!(pow("9", "4000000000").is_none());
+        assert!(pow("10", "1001").is_none());
+        assert_eq!(pow("1", "4000000000"), Some(big("1")));
+
+        // 9 ^ 200000 used to be computed in full before the digit check threw it away
+        let outcome = search_integers(&[big("9"), big("200000")], &big("7"), &SearchConfig::default(), SolveRules::Free, OperatorSet::ALL, &guard).unwrap();
+        assert!(outcome.exact.is_none() && outcome.exhausted);
+    }
+
+    #[test]
+    fn test_run_limits_stop_a_search_midway() {
+        use crate::limits::{Budget, LimitExceeded, Limits};
+
+        let inputs = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        let config = SearchConfig { max_operators: 5, ..Default::default() };
+        let guard_for = |limits: Limits| {
+            let mut budget = Budget::new(limits);
+            budget.start();
+            SearchGuard { limits: budget.solve_limits(), ..Default::default() }
+        };
+
+        let guard = guard_for(Limits { max_solver_candidates: Some(1000), ..Default::default() });
+        let error = search(&inputs, 1e9, &config, SolveRules::Free, OperatorSet::ALL, &guard).unwrap_err();
+        assert_eq!(error.downcast_ref::<LimitExceeded>(), Some(&LimitExceeded::SolverCandidates { limit: 1000, candidates: 1001 }));
+
+        let guard = guard_for(Limits { timeout_ms: Some(0), ..Default::default() });
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        let error = search(&inputs, 1e9, &config, SolveRules::Free, OperatorSet::ALL, &guard).unwrap_err();
+        assert_eq!(error.downcast_ref::<LimitExceeded>().map(|e| e.kind()), Some("timeout"));
+
+        // Within the limits the search runs as before
+        let guard = guard_for(Limits { max_solver_candidates: Some(1000), timeout_ms: Some(60_000), ..Default::default() });
+        assert!(search(&[3.0, 7.0, 25.0], 250.0, &config, SolveRules::Free, OperatorSet::ALL, &guard).unwrap().exact.is_some());
+    }
+}
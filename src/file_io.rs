@@ -0,0 +1,254 @@
+// File access for .slut programs: readFile, readLines, readCsv and writeFile
+// Off by default; the host grants read and write roots and every path is checked against them
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::VariableValue;
+
+/// Directories a program may read from and write to; no roots means no access
+///
+/// Relative paths, in programs and in the roots, are taken from the working
+/// directory. Paths are resolved through `..` and symlinks before they are
+/// checked, so a program cannot escape its roots.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct FsPermissions {
+    pub read: Vec<PathBuf>,
+    pub write: Vec<PathBuf>,
+}
+
+impl FsPermissions {
+    pub fn allow_read(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.read.push(dir.into());
+        self
+    }
+
+    pub fn allow_write(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.write.push(dir.into());
+        self
+    }
+
+    /// `readFile(path)`: the whole file as a string
+    pub fn read_file(&self, path: &str) -> Result<VariableValue> {
+        let resolved = self.check_read(path)?;
+        Ok(VariableValue::String(fs::read_to_string(resolved)?))
+    }
+
+    /// `readLines(path)`: a list with one string per line
+    pub fn read_lines(&self, path: &str) -> Result<VariableValue> {
+        let resolved = self.check_read(path)?;
+        let lines = fs::read_to_string(resolved)?
+            .lines()
+            .map(|line| VariableValue::String(line.to_string()))
+            .collect();
+        Ok(VariableValue::List(lines))
+    }
+
+    /// `readCsv(path)`: a list of records keyed by the header row
+    ///
+    /// Fields that parse as numbers become numbers; everything else stays a string.
+    pub fn read_csv(&self, path: &str) -> Result<VariableValue> {
+        let resolved = self.check_read(path)?;
+        let mut rows = parse_csv(&fs::read_to_string(resolved)?).into_iter();
+        let header = rows.next().unwrap_or_default();
+
+        let records = rows
+            .map(|row| {
+                let fields = header.iter()
+                    .zip(row.into_iter().chain(std::iter::repeat(String::new())))
+                    .map(|(name, field)| (name.clone(), csv_value(field)))
+                    .collect();
+                VariableValue::Record(fields)
+            })
+            .collect();
+        Ok(VariableValue::List(records))
+    }
+
+    /// `writeFile(path, contents)`: create or replace a file
+    pub fn write_file(&self, path: &str, contents: &str) -> Result<()> {
+        let resolved = self.check_write(path)?;
+        fs::write(resolved, contents)?;
+        Ok(())
+    }
+
+    /// Resolve `path` for reading, failing unless it lies inside a read root
+    pub fn check_read(&self, path: &str) -> Result<PathBuf> {
+        if self.read.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Reading \"{}\" is not allowed; grant access with --allow-read=<dir>", path
+            ));
+        }
+
+        let resolved = absolute(Path::new(path))?.canonicalize()
+            .map_err(|e| anyhow::anyhow!("Cannot read \"{}\": {}", path, e))?;
+        if !within_any(&resolved, &self.read) {
+            return Err(anyhow::anyhow!("\"{}\" is outside the directories allowed for reading", path));
+        }
+        Ok(resolved)
+    }
+
+    /// Resolve `path` for writing, failing unless it lies inside a write root
+    ///
+    /// The parent directory must already exist.
+    pub fn check_write(&self, path: &str) -> Result<PathBuf> {
+        if self.write.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Writing \"{}\" is not allowed; grant access with --allow-write=<dir>", path
+            ));
+        }
+
+        let absolute = absolute(Path::new(path))?;
+        let (parent, file_name) = match (absolute.parent(), absolute.file_name()) {
+            (Some(parent), Some(file_name)) => (parent, file_name),
+            _ => return Err(anyhow::anyhow!("\"{}\" does not name a file", path)),
+        };
+        let parent = parent.canonicalize()
+            .map_err(|e| anyhow::anyhow!("Cannot write \"{}\": {}", path, e))?;
+
+        let mut resolved = parent.join(file_name);
+        // An existing symlink is followed to where the write would really land
+        if fs::symlink_metadata(&resolved).is_ok_and(|meta| meta.file_type().is_symlink()) {
+            resolved = resolved.canonicalize()
+                .map_err(|e| anyhow::anyhow!("Cannot write \"{}\": {}", path, e))?;
+        }
+
+        if !within_any(&resolved, &self.write) {
+            return Err(anyhow::anyhow!("\"{}\" is outside the directories allowed for writing", path));
+        }
+        Ok(resolved)
+    }
+}
+
+fn absolute(path: &Path) -> Result<PathBuf> {
+    if path.is_absolute() {
+        Ok(path.to_path_buf())
+    } else {
+        Ok(std::env::current_dir()?.join(path))
+    }
+}
+
+fn within_any(path: &Path, roots: &[PathBuf]) -> bool {
+    roots.iter().any(|root| {
+        absolute(root)
+            .and_then(|root| Ok(root.canonicalize()?))
+            .is_ok_and(|root| path.starts_with(root))
+    })
+}
+
+fn csv_value(field: String) -> VariableValue {
+    match field.trim().parse::<f64>() {
+        Ok(n) => VariableValue::Number(n),
+        Err(_) => VariableValue::String(field),
+    }
+}
+
+/// Split CSV text into rows of fields
+///
+/// Handles quoted fields containing commas, newlines and doubled quotes.
+/// Blank lines are skipped.
+pub fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => row.push(std::mem::take(&mut field)),
+            '\r' if !in_quotes => {}
+            '\n' if !in_quotes => {
+                row.push(std::mem::take(&mut field));
+                if row.iter().any(|f| !f.is_empty()) {
+                    rows.push(std::mem::take(&mut row));
+                } else {
+                    row.clear();
+                }
+            }
+            other => field.push(other),
+        }
+    }
+
+    row.push(field);
+    if row.iter().any(|f| !f.is_empty()) {
+        rows.push(row);
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::both_backends;
+
+    #[test]
+    fn test_parse_csv_quoting() {
+        let rows = parse_csv("name,note\r\nAnn,\"likes \"\"tea\"\", cake\"\n\nBob,\"two\nlines\"\n");
+        assert_eq!(rows, vec![
+            vec!["name".to_string(), "note".to_string()],
+            vec!["Ann".to_string(), "likes \"tea\", cake".to_string()],
+            vec!["Bob".to_string(), "two\nlines".to_string()],
+        ]);
+    }
+
+    #[test]
+    fn test_access_is_denied_by_default_and_confined_to_roots() {
+        let scratch = tempfile::tempdir().unwrap();
+        let dir = scratch.path();
+        fs::create_dir(dir.join("data")).unwrap();
+        fs::write(dir.join("data/in.txt"), "3\n7\n").unwrap();
+        fs::write(dir.join("secret.txt"), "no").unwrap();
+        let inside = dir.join("data/in.txt").display().to_string();
+        let escape = dir.join("data/../secret.txt").display().to_string();
+
+        assert!(FsPermissions::default().read_lines(&inside).is_err());
+
+        let permissions = FsPermissions::default()
+            .allow_read(dir.join("data"))
+            .allow_write(dir.join("data"));
+        assert!(matches!(permissions.read_lines(&inside).unwrap(), VariableValue::List(lines) if lines.len() == 2));
+        assert!(permissions.read_file(&escape).is_err());
+        assert!(permissions.write_file(&escape, "overwritten").is_err());
+
+        permissions.write_file(&dir.join("data/out.txt").display().to_string(), "ok").unwrap();
+        assert_eq!(fs::read_to_string(dir.join("data/out.txt")).unwrap(), "ok");
+        assert_eq!(fs::read_to_string(dir.join("secret.txt")).unwrap(), "no");
+    }
+
+    #[test]
+    fn test_solver_inputs_from_a_data_file() {
+        let scratch = tempfile::tempdir().unwrap();
+        let dir = scratch.path();
+        fs::write(dir.join("parts.csv"), "part,size\nbolt,3\nnut,4\n").unwrap();
+        let program = format!(r#"* <main> Data {{
+    ^ observe_execution {{
+        rows <> readCsv("{}")
+        total([12]) <> randomChoice([rows])
+        writeFile("{}", "total is ~total~")
+        speak("rows: ~rows~")
+    }}
+}}"#, dir.join("parts.csv").display(), dir.join("out.txt").display());
+
+        for mut denied in both_backends(|builder| builder) {
+            assert!(denied.run_source(&program).is_err());
+        }
+
+        let permissions = FsPermissions::default().allow_read(dir).allow_write(dir);
+        for mut engine in both_backends(|builder| builder.seed(7).fs_permissions(permissions.clone())) {
+            let outcome = engine.run_source(&program).unwrap();
+
+            assert_eq!(outcome.program_lines(), ["rows: [{part: bolt, size: 3}, {part: nut, size: 4}]"]);
+            assert!(matches!(outcome.variable("total"), Some(VariableValue::Number(n)) if *n == 12.0));
+            assert_eq!(fs::read_to_string(dir.join("out.txt")).unwrap(), "total is 12");
+            fs::remove_file(dir.join("out.txt")).unwrap();
+        }
+    }
+}
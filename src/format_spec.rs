@@ -0,0 +1,235 @@
+// Format specifiers for `~value:spec~` placeholders in speak templates
+// `[[fill]align][width][.precision][kind]`, e.g. `.2`, `>8`, `*^10.1`, `hex`, `08bin`
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::{display_value, VariableValue};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Align {
+    Left,
+    Right,
+    Center,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FormatKind {
+    /// Numbers as `speak` always showed them; `.N` fixes the decimals
+    Plain,
+    /// `hex` / `HEX`: whole numbers in base 16
+    Hex,
+    UpperHex,
+    /// `bin`: whole numbers in base 2
+    Binary,
+    /// `oct`: whole numbers in base 8
+    Octal,
+    /// `e`: scientific notation, e.g. `1.5e3`
+    Exponent,
+}
+
+/// A parsed `:spec`, applied the way Rust's `format!` would
+///
+/// Numbers align right and everything else left unless an alignment is given.
+/// A leading `0` on the width pads numbers with zeros after the sign.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FormatSpec {
+    pub fill: char,
+    pub align: Option<Align>,
+    pub zero_pad: bool,
+    pub width: usize,
+    pub precision: Option<usize>,
+    pub kind: FormatKind,
+}
+
+impl Default for FormatSpec {
+    fn default() -> Self {
+        Self { fill: ' ', align: None, zero_pad: false, width: 0, precision: None, kind: FormatKind::Plain }
+    }
+}
+
+impl FormatSpec {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let invalid = || anyhow::anyhow!("Invalid format spec ':{}'", spec);
+        let mut parsed = FormatSpec::default();
+        let chars: Vec<char> = spec.chars().collect();
+        let mut i = 0;
+
+        let align_of = |c: char| match c {
+            '<' => Some(Align::Left),
+            '>' => Some(Align::Right),
+            '^' => Some(Align::Center),
+            _ => None,
+        };
+        if let Some(align) = chars.get(1).and_then(|c| align_of(*c)) {
+            parsed.fill = chars[0];
+            parsed.align = Some(align);
+            i = 2;
+        } else if let Some(align) = chars.first().and_then(|c| align_of(*c)) {
+            parsed.align = Some(align);
+            i = 1;
+        }
+
+        if chars.get(i) == Some(&'0') {
+            parsed.zero_pad = true;
+            i += 1;
+        }
+        let width_start = i;
+        while chars.get(i).is_some_and(|c| c.is_ascii_digit()) {
+            i += 1;
+        }
+        if i > width_start {
+            parsed.width = chars[width_start..i].iter().collect::<String>().parse().map_err(|_| invalid())?;
+        }
+
+        if chars.get(i) == Some(&'.') {
+            let precision_start = i + 1;
+            i = precision_start;
+            while chars.get(i).is_some_and(|c| c.is_ascii_digit()) {
+                i += 1;
+            }
+            if i == precision_start {
+                return Err(invalid());
+            }
+            parsed.precision = Some(chars[precision_start..i].iter().collect::<String>().parse().map_err(|_| invalid())?);
+        }
+
+        parsed.kind = match chars[i..].iter().collect::<String>().as_str() {
+            "" => FormatKind::Plain,
+            "hex" => FormatKind::Hex,
+            "HEX" => FormatKind::UpperHex,
+            "bin" => FormatKind::Binary,
+            "oct" => FormatKind::Octal,
+            "e" => FormatKind::Exponent,
+            _ => return Err(invalid()),
+        };
+
+        Ok(parsed)
+    }
+
+    pub fn apply(&self, value: &VariableValue) -> Result<String> {
+        let (text, numeric) = match (self.kind, value) {
+            (FormatKind::Plain, VariableValue::Number(n)) => match self.precision {
+                Some(precision) => (format!("{:.*}", precision, n), true),
+                None => (n.to_string(), true),
+            },
+            (FormatKind::Plain, VariableValue::Integer(n)) => match self.precision {
+                Some(precision) if precision > 0 => (format!("{}.{}", n, "0".repeat(precision)), true),
+                _ => (n.to_string(), true),
+            },
+            (FormatKind::Plain, other) => {
+                let text = display_value(other);
+                match self.precision {
+                    Some(precision) => (text.chars().take(precision).collect(), false),
+                    None => (text, false),
+                }
+            }
+            (FormatKind::Exponent, VariableValue::Number(n)) => match self.precision {
+                Some(precision) => (format!("{:.*e}", precision, n), true),
+                None => (format!("{:e}", n), true),
+            },
+            (kind, VariableValue::Number(n)) if n.fract() == 0.0 && n.abs() < 2f64.powi(63) => {
+                let magnitude = n.abs() as u64;
+                let digits = match kind {
+                    FormatKind::Hex => format!("{:x}", magnitude),
+                    FormatKind::UpperHex => format!("{:X}", magnitude),
+                    FormatKind::Binary => format!("{:b}", magnitude),
+                    _ => format!("{:o}", magnitude),
+                };
+                let sign = if *n < 0.0 { "-" } else { "" };
+                (format!("{}{}", sign, digits), true)
+            }
+            (_, other) => {
+                return Err(anyhow::anyhow!("{:?} formatting needs a whole number, got {}", self.kind, display_value(other)));
+            }
+        };
+
+        Ok(self.pad(text, numeric))
+    }
+
+    fn pad(&self, text: String, numeric: bool) -> String {
+        let len = text.chars().count();
+        if len >= self.width {
+            return text;
+        }
+        let missing = self.width - len;
+
+        if self.zero_pad && numeric && self.align.is_none() {
+            let (sign, digits) = match text.strip_prefix('-') {
+                Some(digits) => ("-", digits),
+                None => ("", text.as_str()),
+            };
+            return format!("{}{}{}", sign, "0".repeat(missing), digits);
+        }
+
+        let align = self.align.unwrap_or(if numeric { Align::Right } else { Align::Left });
+        let (before, after) = match align {
+            Align::Left => (0, missing),
+            Align::Right => (missing, 0),
+            Align::Center => (missing / 2, missing - missing / 2),
+        };
+        let fill = |count: usize| self.fill.to_string().repeat(count);
+        format!("{}{}{}", fill(before), text, fill(after))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::both_backends;
+    use crate::output_sink::DiagnosticLevel;
+
+    fn format(spec: &str, value: VariableValue) -> String {
+        FormatSpec::parse(spec).unwrap().apply(&value).unwrap()
+    }
+
+    #[test]
+    fn test_format_specs() {
+        let n = |n: f64| VariableValue::Number(n);
+        assert_eq!(format(".2", n(0.1 + 0.2)), "0.30");
+        assert_eq!(format("8.1", n(-2.25)), "    -2.2");
+        assert_eq!(format("<6", n(42.0)), "42    ");
+        assert_eq!(format("*^7", VariableValue::String("hi".to_string())), "**hi***");
+        assert_eq!(format("06", n(-42.0)), "-00042");
+        assert_eq!(format("hex", n(255.0)), "ff");
+        assert_eq!(format(">6HEX", n(-255.0)), "   -FF");
+        assert_eq!(format("08bin", n(5.0)), "00000101");
+        assert_eq!(format(".1e", n(1500.0)), "1.5e3");
+        assert_eq!(format(".3", VariableValue::String("abcdef".to_string())), "abc");
+
+        assert!(FormatSpec::parse("x").is_err());
+        assert!(FormatSpec::parse(".").is_err());
+        assert!(FormatSpec::parse("hex").unwrap().apply(&n(1.5)).is_err());
+    }
+
+    #[test]
+    fn test_interpolation_formats_and_expressions() {
+        let program = r#"* <main> Report {
+    ^ observe_execution {
+        a <> 0.1
+        b <> 0.2
+        n <> 255
+        speak("sum ~a + b~ is ~a + b:.2~, [~n:>6~] ~n:hex~ ~n > 100~")
+        speak("[~missing~] is empty, [~n +~] is too, a ~ b ~ stays literal")
+    }
+}"#;
+        for mut engine in both_backends(|builder| builder) {
+            let outcome = engine.run_source(program).unwrap();
+
+            assert_eq!(outcome.program_lines(), [
+                "sum 0.30000000000000004 is 0.30, [   255] ff true",
+                "[] is empty, [] is too, a ~ b ~ stays literal",
+            ]);
+            let diagnostics: Vec<&str> = outcome.output.iter()
+                .filter(|line| line.level == Some(DiagnosticLevel::Warn))
+                .map(|line| line.message.as_str())
+                .collect();
+            assert_eq!(diagnostics.len(), 2);
+            assert!(diagnostics[0].contains("'missing'"));
+            assert!(diagnostics[1].starts_with("!! Cannot interpolate 'n +'"), "{}", diagnostics[1]);
+        }
+    }
+}
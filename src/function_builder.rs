@@ -3,13 +3,16 @@ use std::fs;
 use std::path::Path;
 
 use crate::{BuiltFunction, FunctionVariant};
+use crate::output_sink::SharedSink;
 
 pub struct FunctionBuilder {
-    functions_dir: String,
+    /// `None` when generated code should not be written anywhere
+    functions_dir: Option<String>,
+    output: SharedSink,
 }
 
 impl FunctionBuilder {
-    pub fn new() -> Result<Self> {
+    pub fn with_output_sink(output: SharedSink) -> Result<Self> {
         let functions_dir = "functions".to_string();
         
         fs::create_dir_all(&functions_dir)?;
@@ -29,7 +32,7 @@ crate-type = ["cdylib", "rlib"]
 [dependencies]
 "#;
             fs::write(&cargo_toml_path, cargo_toml_content)?;
-            println!("** Created functions library Cargo.toml");
+            output.info("** Created functions library Cargo.toml");
         }
         
         let lib_rs_path = format!("{}/src/lib.rs", &functions_dir);
@@ -39,13 +42,26 @@ crate-type = ["cdylib", "rlib"]
 pub use smart_loop::*;
 "#;
             fs::write(&lib_rs_path, lib_rs_content)?;
-            println!("** Created functions library lib.rs");
+            output.info("** Created functions library lib.rs");
         }
         
         Ok(Self {
-            functions_dir,
+            functions_dir: Some(functions_dir),
+            output,
         })
     }
+
+    /// A builder that generates function variants without writing the `functions/` crate
+    pub fn detached(output: SharedSink) -> Self {
+        Self {
+            functions_dir: None,
+            output,
+        }
+    }
+
+    pub fn set_output_sink(&mut self, sink: SharedSink) {
+        self.output = sink;
+    }
     
     pub fn build_function(&self, name: &str, func_type: &str, _param_count: usize) -> Result<BuiltFunction> {
         match func_type {
@@ -56,17 +72,17 @@ pub use smart_loop::*;
     }
     
     fn build_loop_function(&self, name: &str) -> Result<BuiltFunction> {
-        let file_name = format!("{}.rs", name.to_lowercase());
-        let file_path = format!("{}/src/{}", self.functions_dir, file_name);
-        
-        println!(">> Building loop function variants for: {}", name);
+        self.output.info(&format!(">> Building loop function variants for: {}", name));
         
         let rust_code = self.generate_loop_code(name)?;
         
-        fs::write(&file_path, rust_code)?;
-        println!("** Generated Rust code: {}", file_path);
-        
-        self.update_lib_rs(name)?;
+        if let Some(functions_dir) = &self.functions_dir {
+            let file_path = format!("{}/src/{}.rs", functions_dir, name.to_lowercase());
+            fs::write(&file_path, rust_code)?;
+            self.output.info(&format!("** Generated Rust code: {}", file_path));
+            
+            self.update_lib_rs(functions_dir, name)?;
+        }
         
         let variants = vec![
             FunctionVariant {
@@ -139,8 +155,8 @@ pub fn {}_condition(condition: &str, body: &str) {{
         todo!("Conditional function generation not yet implemented")
     }
     
-    fn update_lib_rs(&self, function_name: &str) -> Result<()> {
-        let lib_rs_path = format!("{}/src/lib.rs", self.functions_dir);
+    fn update_lib_rs(&self, functions_dir: &str, function_name: &str) -> Result<()> {
+        let lib_rs_path = format!("{}/src/lib.rs", functions_dir);
         let mut content = fs::read_to_string(&lib_rs_path)?;
         
         let module_line = format!("pub mod {};", function_name.to_lowercase());
@@ -172,7 +188,7 @@ pub fn {}_condition(condition: &str, body: &str) {{
         }
         
         fs::write(&lib_rs_path, content)?;
-        println!("** Updated lib.rs to include {}", function_name);
+        self.output.info(&format!("** Updated lib.rs to include {}", function_name));
         Ok(())
     }
 }
\ No newline at end of file
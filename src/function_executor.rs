@@ -1,22 +1,36 @@
 use anyhow::Result;
 use crate::BuiltFunction;
+use crate::cancellation::CancellationToken;
+use crate::output_sink::{SharedSink, StdoutSink};
 
 pub struct FunctionExecutor {
-    
+    output: SharedSink,
+    cancel: CancellationToken,
 }
 
 impl FunctionExecutor {
     pub fn new() -> Result<Self> {
-        Ok(Self {})
+        Ok(Self {
+            output: StdoutSink::shared(),
+            cancel: CancellationToken::new(),
+        })
+    }
+
+    pub fn set_output_sink(&mut self, sink: SharedSink) {
+        self.output = sink;
+    }
+
+    pub fn set_cancellation_token(&mut self, token: CancellationToken) {
+        self.cancel = token;
     }
     
     pub fn execute_function(&self, built_function: &BuiltFunction, params: &[&str], body: &str) -> Result<()> {
-        println!(">> Loading built function: {}", built_function.name);
+        self.output.info(&format!(">> Loading built function: {}", built_function.name));
         
         let param_count = params.len();
         
         if let Some(variant) = built_function.variants.iter().find(|v| v.parameter_count == param_count) {
-            println!("== Using variant: {} ({})", variant.rust_function_name, variant.parameter_pattern);
+            self.output.info(&format!("== Using variant: {} ({})", variant.rust_function_name, variant.parameter_pattern));
             
             match variant.parameter_pattern.as_str() {
                 "count" => {
@@ -40,21 +54,21 @@ impl FunctionExecutor {
                     }
                 }
                 _ => {
-                    println!("!! Unknown pattern: {}", variant.parameter_pattern);
+                    self.output.warn(&format!("!! Unknown pattern: {}", variant.parameter_pattern));
                 }
             }
             
-            println!("== Function execution complete: {}", built_function.name);
+            self.output.info(&format!("== Function execution complete: {}", built_function.name));
         } else {
-            println!("!! No variant found for {} parameters in function {}", 
-                    param_count, built_function.name);
+            self.output.warn(&format!("!! No variant found for {} parameters in function {}", 
+                    param_count, built_function.name));
         }
         
         Ok(())
     }
     
     fn execute_count_loop(&self, count: u32, body: &str) -> Result<()> {
-        println!("-- Executing REAL count-based loop: {} iterations", count);
+        self.output.debug(&format!("-- Executing REAL count-based loop: {} iterations", count));
         for i in 0..count {
             self.execute_statement(body, i)?;
         }
@@ -62,7 +76,7 @@ impl FunctionExecutor {
     }
     
     fn execute_range_loop(&self, start: u32, end: u32, body: &str) -> Result<()> {
-        println!("-- Executing REAL range-based loop: {} to {}", start, end);
+        self.output.debug(&format!("-- Executing REAL range-based loop: {} to {}", start, end));
         for i in start..end {
             self.execute_statement(body, i)?;
         }
@@ -70,7 +84,7 @@ impl FunctionExecutor {
     }
     
     fn execute_step_loop(&self, start: u32, end: u32, step: u32, body: &str) -> Result<()> {
-        println!("-- Executing REAL step-based loop: {} to {} by {}", start, end, step);
+        self.output.debug(&format!("-- Executing REAL step-based loop: {} to {} by {}", start, end, step));
         let mut i = start;
         while i < end {
             self.execute_statement(body, i)?;
@@ -80,6 +94,7 @@ impl FunctionExecutor {
     }
     
     fn execute_statement(&self, statement: &str, iteration: u32) -> Result<()> {
+        self.cancel.check()?;
         let statement = statement.trim();
         
         if statement.starts_with("println!") {
@@ -87,7 +102,7 @@ impl FunctionExecutor {
         }
         
         else {
-            println!("!! Unknown statement type: {}", statement);
+            self.output.warn(&format!("!! Unknown statement type: {}", statement));
         }
         
         Ok(())
@@ -101,9 +116,9 @@ impl FunctionExecutor {
                 .replace("{}", &iteration.to_string())
                 .replace("{i}", &iteration.to_string());
             
-            println!("{}", output);
+            self.output.program(&output);
         } else {
-            println!("!! Could not parse println statement: {}", statement);
+            self.output.warn(&format!("!! Could not parse println statement: {}", statement));
         }
         
         Ok(())
@@ -0,0 +1,322 @@
+// Golden-output tests for .slut programs
+// `name.slut` is run in isolation and its speak/woof output is compared with `name.expected`
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use crate::cache_store::InMemoryCache;
+use crate::engine::Engine;
+use crate::input_source::ScriptedInput;
+
+pub const DEFAULT_SEED: u64 = 42;
+
+#[derive(Debug, Clone)]
+pub struct GoldenOptions {
+    /// Seed for `randomChoice` and the equation search
+    pub seed: u64,
+    /// Overwrite (or create) `.expected` files with the actual output
+    pub bless: bool,
+}
+
+impl Default for GoldenOptions {
+    fn default() -> Self {
+        Self { seed: DEFAULT_SEED, bless: false }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum CaseStatus {
+    Passed,
+    Failed { diff: String },
+    Blessed,
+    Error { message: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CaseResult {
+    pub name: String,
+    pub path: PathBuf,
+    #[serde(flatten)]
+    pub status: CaseStatus,
+    pub duration_secs: f64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TestReport {
+    pub cases: Vec<CaseResult>,
+    pub duration_secs: f64,
+}
+
+impl TestReport {
+    pub fn passed(&self) -> usize {
+        self.count(|status| matches!(status, CaseStatus::Passed | CaseStatus::Blessed))
+    }
+
+    pub fn failed(&self) -> usize {
+        self.count(|status| matches!(status, CaseStatus::Failed { .. }))
+    }
+
+    pub fn errors(&self) -> usize {
+        self.count(|status| matches!(status, CaseStatus::Error { .. }))
+    }
+
+    pub fn success(&self) -> bool {
+        self.failed() == 0 && self.errors() == 0
+    }
+
+    fn count(&self, predicate: impl Fn(&CaseStatus) -> bool) -> usize {
+        self.cases.iter().filter(|case| predicate(&case.status)).count()
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn to_junit_xml(&self) -> String {
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml.push_str(&format!(
+            "<testsuites tests=\"{}\" failures=\"{}\" errors=\"{}\" time=\"{:.3}\">\n",
+            self.cases.len(), self.failed(), self.errors(), self.duration_secs
+        ));
+        xml.push_str(&format!(
+            "  <testsuite name=\"quantum\" tests=\"{}\" failures=\"{}\" errors=\"{}\" time=\"{:.3}\">\n",
+            self.cases.len(), self.failed(), self.errors(), self.duration_secs
+        ));
+
+        for case in &self.cases {
+            let open = format!(
+                "    <testcase name=\"{}\" classname=\"{}\" time=\"{:.3}\"",
+                escape_xml(&case.name), escape_xml(&case.path.display().to_string()), case.duration_secs
+            );
+            match &case.status {
+                CaseStatus::Passed | CaseStatus::Blessed => xml.push_str(&format!("{}/>\n", open)),
+                CaseStatus::Failed { diff } => {
+                    xml.push_str(&format!("{}>\n", open));
+                    xml.push_str(&format!(
+                        "      <failure message=\"output differs from expected\">{}</failure>\n",
+                        escape_xml(diff)
+                    ));
+                    xml.push_str("    </testcase>\n");
+                }
+                CaseStatus::Error { message } => {
+                    xml.push_str(&format!("{}>\n", open));
+                    xml.push_str(&format!("      <error message=\"{}\"/>\n", escape_xml(message)));
+                    xml.push_str("    </testcase>\n");
+                }
+            }
+        }
+
+        xml.push_str("  </testsuite>\n</testsuites>\n");
+        xml
+    }
+
+    /// Human-readable report for the terminal
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for case in &self.cases {
+            match &case.status {
+                CaseStatus::Passed => text.push_str(&format!("PASS  {}\n", case.name)),
+                CaseStatus::Blessed => text.push_str(&format!("BLESS {}\n", case.name)),
+                CaseStatus::Failed { diff } => {
+                    text.push_str(&format!("FAIL  {}\n", case.name));
+                    for line in diff.lines() {
+                        text.push_str(&format!("      {}\n", line));
+                    }
+                }
+                CaseStatus::Error { message } => {
+                    text.push_str(&format!("ERROR {}: {}\n", case.name, message));
+                }
+            }
+        }
+        text.push_str(&format!(
+            "\n{} passed, {} failed, {} errors ({:.2}s)\n",
+            self.passed(), self.failed(), self.errors(), self.duration_secs
+        ));
+        text
+    }
+}
+
+/// `.slut` files under `dir` (recursively) that have a sibling `.expected` file
+///
+/// With `include_unblessed`, programs without one are returned too so that
+/// `--bless` can create it.
+pub fn discover(dir: &Path, include_unblessed: bool) -> Result<Vec<PathBuf>> {
+    let mut found = Vec::new();
+    collect_programs(dir, include_unblessed, &mut found)
+        .with_context(|| format!("Failed to read test directory {}", dir.display()))?;
+    found.sort();
+    Ok(found)
+}
+
+fn collect_programs(dir: &Path, include_unblessed: bool, found: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_programs(&path, include_unblessed, found)?;
+        } else if path.extension().is_some_and(|ext| ext == "slut")
+            && (include_unblessed || path.with_extension("expected").exists())
+        {
+            found.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Run every golden test under `dir`
+pub fn run_dir(dir: &Path, options: &GoldenOptions) -> Result<TestReport> {
+    let start = Instant::now();
+    let cases = discover(dir, options.bless)?
+        .iter()
+        .map(|path| run_case(dir, path, options))
+        .collect();
+
+    Ok(TestReport { cases, duration_secs: start.elapsed().as_secs_f64() })
+}
+
+/// Run one program and compare (or bless) its output
+pub fn run_case(root: &Path, path: &Path, options: &GoldenOptions) -> CaseResult {
+    let start = Instant::now();
+    let name = path.strip_prefix(root).unwrap_or(path).with_extension("").display().to_string();
+    let expected_path = path.with_extension("expected");
+
+    let status = match run_program(path, options.seed) {
+        Err(e) => CaseStatus::Error { message: format!("{:#}", e) },
+        Ok(actual) if options.bless => {
+            let mut contents = actual.join("\n");
+            contents.push('\n');
+            match fs::write(&expected_path, contents) {
+                Ok(()) => CaseStatus::Blessed,
+                Err(e) => CaseStatus::Error {
+                    message: format!("Failed to write {}: {}", expected_path.display(), e),
+                },
+            }
+        }
+        Ok(actual) => match fs::read_to_string(&expected_path) {
+            Err(e) => CaseStatus::Error {
+                message: format!("Failed to read {}: {}", expected_path.display(), e),
+            },
+            Ok(expected) => {
+                let expected: Vec<&str> = expected.lines().map(|line| line.trim_end_matches('\r')).collect();
+                let actual: Vec<&str> = actual.iter().map(String::as_str).collect();
+                if expected == actual {
+                    CaseStatus::Passed
+                } else {
+                    CaseStatus::Failed { diff: line_diff(&expected, &actual) }
+                }
+            }
+        },
+    };
+
+    CaseResult {
+        name,
+        path: path.to_path_buf(),
+        status,
+        duration_secs: start.elapsed().as_secs_f64(),
+    }
+}
+
+/// Run a program with a fresh in-memory cache and return its speak/woof lines
+///
+/// Answers for `userIn(...)` are read from a sibling `.input` file, one per line.
+pub fn run_program(path: &Path, seed: u64) -> Result<Vec<String>> {
+    let input_path = path.with_extension("input");
+    let answers = if input_path.exists() { fs::read_to_string(&input_path)? } else { String::new() };
+
+    let outcome = Engine::builder()
+        .cache(InMemoryCache::new())
+        .seed(seed)
+        .input(ScriptedInput::from_text(&answers))
+        .build()?
+        .run_file(path)?;
+
+    Ok(outcome.program_lines().into_iter().map(str::to_string).collect())
+}
+
+/// Line diff of two outputs: unchanged lines are prefixed with two spaces,
+/// missing ones with `-` and unexpected ones with `+`
+pub fn line_diff(expected: &[&str], actual: &[&str]) -> String {
+    // Longest common subsequence table, filled from the end
+    let (n, m) = (expected.len(), actual.len());
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if expected[i] == actual[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && expected[i] == actual[j] {
+            diff.push_str(&format!("  {}\n", expected[i]));
+            i += 1;
+            j += 1;
+        } else if i < n && (j == m || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            diff.push_str(&format!("- {}\n", expected[i]));
+            i += 1;
+        } else {
+            diff.push_str(&format!("+ {}\n", actual[j]));
+            j += 1;
+        }
+    }
+    diff
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROGRAM: &str = r#"* <main> Golden {
+    ^ observe_execution {
+        name <> userIn("Name")
+        speak("Hello ~name~")
+        woof name
+    }
+}"#;
+
+    #[test]
+    fn test_line_diff_marks_changes() {
+        let diff = line_diff(&["a", "b", "c"], &["a", "x", "c"]);
+        assert_eq!(diff, "  a\n- b\n+ x\n  c\n");
+    }
+
+    #[test]
+    fn test_bless_then_pass_then_fail() {
+        let scratch = tempfile::tempdir().unwrap();
+        let dir = scratch.path();
+        fs::write(dir.join("hello.slut"), PROGRAM).unwrap();
+        fs::write(dir.join("hello.input"), "World\n").unwrap();
+
+        // Without an expectation the program is not a test yet
+        assert!(run_dir(&dir, &GoldenOptions::default()).unwrap().cases.is_empty());
+
+        let blessed = run_dir(&dir, &GoldenOptions { bless: true, ..Default::default() }).unwrap();
+        assert_eq!(blessed.cases[0].status, CaseStatus::Blessed);
+        let expected = fs::read_to_string(dir.join("hello.expected")).unwrap();
+        assert!(expected.starts_with("Hello World\n"));
+
+        let report = run_dir(&dir, &GoldenOptions::default()).unwrap();
+        assert!(report.success());
+
+        fs::write(dir.join("hello.input"), "Moon\n").unwrap();
+        let report = run_dir(&dir, &GoldenOptions::default()).unwrap();
+        assert_eq!(report.failed(), 1);
+        assert!(report.to_junit_xml().contains("+ Hello Moon"));
+        assert!(report.to_json().unwrap().contains("\"status\": \"failed\""));
+    }
+}
@@ -0,0 +1,67 @@
+use anyhow::Result;
+use std::collections::VecDeque;
+use std::io::{self, Write};
+
+/// Where `userIn("prompt")` answers come from
+pub trait InputSource: Send {
+    fn read_line(&mut self, prompt: &str) -> Result<String>;
+}
+
+/// Default source for the CLI: prompts on stdout and reads a line from stdin
+pub struct StdinInput;
+
+impl InputSource for StdinInput {
+    fn read_line(&mut self, prompt: &str) -> Result<String> {
+        print!("{}: ", prompt);
+        io::stdout().flush()?;
+
+        let mut input = String::new();
+        io::stdin().read_line(&mut input)?;
+        Ok(input.trim().to_string())
+    }
+}
+
+/// Answers fixed in advance, handed out in order (golden tests, embedding)
+pub struct ScriptedInput {
+    answers: VecDeque<String>,
+}
+
+impl ScriptedInput {
+    pub fn new<I, S>(answers: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self { answers: answers.into_iter().map(Into::into).collect() }
+    }
+
+    /// One answer per line
+    pub fn from_text(text: &str) -> Self {
+        Self::new(text.lines().map(|line| line.trim_end_matches('\r')))
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.answers.len()
+    }
+}
+
+impl InputSource for ScriptedInput {
+    fn read_line(&mut self, prompt: &str) -> Result<String> {
+        self.answers.pop_front()
+            .map(|answer| answer.trim().to_string())
+            .ok_or_else(|| anyhow::anyhow!("No scripted answer left for userIn(\"{}\")", prompt))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scripted_input_in_order() {
+        let mut input = ScriptedInput::from_text("42\r\n  hello \n");
+        assert_eq!(input.read_line("a").unwrap(), "42");
+        assert_eq!(input.read_line("b").unwrap(), "hello");
+        assert!(input.read_line("c").is_err());
+    }
+}
@@ -9,8 +9,12 @@ use indicatif::{ProgressBar, ProgressStyle};
 use colored::Colorize;
 
 use crate::{VariableValue, MathSolution, VariableAttempt};
+use crate::equation_solver::EquationSolver;
+use crate::expression_search::{SearchConfig, SolveOptions, SolveRules};
+use crate::limits::{Budget, LimitExceeded, Limits};
+use crate::operator_set::OperatorSet;
 use crate::math_engine::MathEngine;
-use crate::variable_manager::VariableManager;
+use crate::output_sink::{SharedSink, StdoutSink};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct InteractiveSession {
@@ -45,51 +49,98 @@ pub enum UserInput {
     Problem { target: f64, inputs: Vec<f64> },
     Help,
     Stats,
+    Rules(SolveRules),
+    /// Toggle listing every exact equation after each problem
+    ListAll,
     Quit,
 }
 
 pub struct InteractiveEngine {
     session: InteractiveSession,
     math_engine: MathEngine,
-    variable_manager: VariableManager,
     session_file: String,
+    output: SharedSink,
+    /// Which inputs solutions may use; strict rules also skip cached numbers as extra inputs
+    rules: SolveRules,
+    operators: OperatorSet,
+    /// After each problem, list every exact equation, or the closest few when none is exact
+    list_all: bool,
+    /// Applied to each problem as if it were a run of its own
+    limits: Limits,
 }
 
+/// How many approximations the `all` listing shows when no equation is exact
+const CLOSEST_SHOWN: usize = 5;
+
 impl InteractiveEngine {
     pub fn new() -> Result<Self> {
+        Self::with_output_sink(StdoutSink::shared())
+    }
+
+    pub fn with_output_sink(output: SharedSink) -> Result<Self> {
         let session_file = "test_interactive/user_session_cache.json".to_string();
         
         fs::create_dir_all("test_interactive")?;
         
-        let session = Self::load_or_create_session(&session_file)?;
+        let session = Self::load_or_create_session(&session_file, &output)?;
         
         let math_solutions = Self::convert_cached_to_math_solutions(&session.learned_solutions);
         let variable_attempts: HashMap<String, Vec<VariableAttempt>> = HashMap::new();
         
-        let math_engine = MathEngine::new(math_solutions, variable_attempts);
-        let variables: HashMap<String, crate::StoredVariable> = HashMap::new();
-        let variable_manager = VariableManager::new(variables);
+        let mut math_engine = MathEngine::new(math_solutions, variable_attempts);
+        math_engine.set_output_sink(output.clone());
         
-        println!("** Interactive Mathematical Reasoning Engine Initialized **");
-        println!("** Loaded {} previous solutions from cache **", session.learned_solutions.len());
+        output.info("** Interactive Mathematical Reasoning Engine Initialized **");
+        output.info(&format!("** Loaded {} previous solutions from cache **", session.learned_solutions.len()));
         
         Ok(Self {
             session,
             math_engine,
-            variable_manager,
             session_file,
+            output,
+            rules: SolveRules::Free,
+            operators: OperatorSet::ALL,
+            list_all: false,
+            limits: Limits::default(),
         })
     }
     
-    fn load_or_create_session(file_path: &str) -> Result<InteractiveSession> {
+    pub fn set_rules(&mut self, rules: SolveRules) {
+        self.rules = rules;
+    }
+
+    pub fn rules(&self) -> SolveRules {
+        self.rules
+    }
+
+    pub fn set_list_all(&mut self, list_all: bool) {
+        self.list_all = list_all;
+    }
+
+    pub fn set_operators(&mut self, operators: OperatorSet) {
+        self.operators = operators;
+        self.math_engine.set_operators(operators);
+    }
+
+    /// Input list, candidate and time limits for each problem
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// How far the tree search goes when no fixed-shape equation hits a target
+    pub fn set_search_config(&mut self, search: SearchConfig) {
+        self.math_engine.set_search_config(search);
+    }
+
+    fn load_or_create_session(file_path: &str, output: &SharedSink) -> Result<InteractiveSession> {
         match fs::read_to_string(file_path) {
             Ok(content) => {
                 let session: InteractiveSession = serde_json::from_str(&content)?;
-                println!("** Loaded previous session with {} learned solutions", session.learned_solutions.len());
+                output.info(&format!("** Loaded previous session with {} learned solutions", session.learned_solutions.len()));
                 Ok(session)
             },
             Err(_) => {
-                println!("** Creating new interactive session");
+                output.info("** Creating new interactive session");
                 Ok(InteractiveSession {
                     session_id: format!("session_{}", SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs()),
                     learned_solutions: HashMap::new(),
@@ -112,6 +163,8 @@ impl InteractiveEngine {
                 timestamp: cached_solution.timestamp,
                 attempts: cached_solution.success_count,
                 formula: Some(cached_solution.equation.clone()),
+                exact: None,
+                cost: None,
             };
             math_solutions.insert(key.clone(), math_solution);
         }
@@ -120,14 +173,18 @@ impl InteractiveEngine {
     }
     
     pub fn run_interactive_session(&mut self) -> Result<()> {
-        println!("\n{}", "=== QUANTUM INTERACTIVE MATHEMATICAL REASONING ===".bright_cyan().bold());
-        println!("{}", "This system learns from each problem and uses previous solutions as building blocks.".bright_white());
-        println!("{}\n", "Type 'quit' or 'exit' to stop, 'help' for instructions.".bright_yellow());
+        self.output.info(&format!("\n{}", "=== QUANTUM INTERACTIVE MATHEMATICAL REASONING ===".bright_cyan().bold()));
+        self.output.info(&format!("{}", "This system learns from each problem and uses previous solutions as building blocks.".bright_white()));
+        self.output.info(&format!("{}\n", "Type 'quit' or 'exit' to stop, 'help' for instructions.".bright_yellow()));
         
         loop {
             match self.get_user_problem()? {
                 UserInput::Problem { target, inputs } => {
-                    self.solve_interactive_problem(target, inputs)?;
+                    // A problem that hits a limit ends, the session does not
+                    if let Err(e) = self.solve_interactive_problem(target, inputs) {
+                        let Some(limit) = e.downcast_ref::<LimitExceeded>() else { return Err(e) };
+                        self.output.warn(&format!("!! Problem stopped ({} limit): {}", limit.kind(), limit));
+                    }
                 },
                 UserInput::Help => {
                     self.show_help();
@@ -135,22 +192,31 @@ impl InteractiveEngine {
                 UserInput::Stats => {
                     self.show_statistics();
                 },
+                UserInput::Rules(rules) => {
+                    self.rules = rules;
+                    self.output.info(&format!("== Solving {} from now on", rules.describe()));
+                },
+                UserInput::ListAll => {
+                    self.list_all = !self.list_all;
+                    let state = if self.list_all { "on" } else { "off" };
+                    self.output.info(&format!("== Listing every exact equation: {}", state));
+                },
                 UserInput::Quit => {
                     break;
                 },
             }
             
-            println!();
+            self.output.info("");
         }
         
         self.save_session()?;
-        println!("** Session saved. Thank you for using Quantum Consciousness! **");
+        self.output.info("** Session saved. Thank you for using Quantum Consciousness! **");
         Ok(())
     }
     
     fn get_user_problem(&self) -> Result<UserInput> {
         let input: String = Input::with_theme(&ColorfulTheme::default())
-            .with_prompt("Enter target number (or 'help', 'stats', 'quit')")
+            .with_prompt("Enter target number (or 'help', 'stats', 'rules free|once|all', 'all', 'quit')")
             .interact_text()?;
 
         let input = input.trim().to_lowercase();
@@ -159,13 +225,24 @@ impl InteractiveEngine {
             "quit" | "exit" => return Ok(UserInput::Quit),
             "help" => return Ok(UserInput::Help),
             "stats" => return Ok(UserInput::Stats),
+            "all" => return Ok(UserInput::ListAll),
             _ => {}
         }
 
+        if let Some(name) = input.strip_prefix("rules") {
+            return match name.trim().parse() {
+                Ok(rules) => Ok(UserInput::Rules(rules)),
+                Err(e) => {
+                    self.output.warn(&format!("!! {}", e));
+                    self.get_user_problem()
+                }
+            };
+        }
+
         let target: f64 = match input.parse() {
             Ok(num) => num,
             Err(_) => {
-                println!("{}", "!! Please enter a valid number".red());
+                self.output.warn(&format!("{}", "!! Please enter a valid number".red()));
                 return self.get_user_problem();
             }
         };
@@ -190,7 +267,7 @@ impl InteractiveEngine {
             } else if let Ok(num) = part.parse::<f64>() {
                 numbers.push(num);
             } else {
-                println!("!! Skipping invalid input: {}", part);
+                self.output.warn(&format!("!! Skipping invalid input: {}", part));
             }
         }
         
@@ -198,7 +275,13 @@ impl InteractiveEngine {
     }
     
     fn solve_interactive_problem(&mut self, target: f64, mut inputs: Vec<f64>) -> Result<()> {
-        println!("\n{} Find {} using {:?}", ">> Analyzing problem:".bright_blue(), target, inputs);
+        self.output.info(&format!("\n{} Find {} using {:?}", ">> Analyzing problem:".bright_blue(), target, inputs));
+
+        let mut budget = Budget::new(self.limits);
+        budget.start();
+        budget.check_list(inputs.len())?;
+        budget.check_solver_candidates(EquationSolver::candidate_upper_bound(inputs.len()))?;
+        self.math_engine.set_solve_limits(budget.solve_limits());
 
         // Create a spinner to show progress
         let spinner = ProgressBar::new_spinner();
@@ -213,41 +296,32 @@ impl InteractiveEngine {
         let mut thinking_steps = Vec::new();
         let start_time = std::time::Instant::now();
 
-        thinking_steps.push(format!("Trying with provided inputs: {:?}", inputs));
-        let mut solution = self.math_engine.solve_target(target, &inputs, "interactive", "interactive")?;
-        
-        if solution.accuracy < 100.0 {
-            spinner.set_message("Checking cached solutions...");
-            thinking_steps.push("No exact solution with provided inputs. Checking cached solutions...".to_string());
-
-            let enhanced_inputs = self.enhance_inputs_with_cache(&inputs, target);
-            if enhanced_inputs.len() > inputs.len() {
-                thinking_steps.push(format!("Enhanced inputs with cached solutions: {:?}", enhanced_inputs));
-                solution = self.math_engine.solve_target(target, &enhanced_inputs, "interactive", "interactive")?;
-                inputs = enhanced_inputs;
-            }
-        }
-
+        let solved = self.search_problem(target, &mut inputs, &budget, &mut thinking_steps, &spinner);
         spinner.finish_and_clear();
+        let solution = solved?;
         let solve_time = start_time.elapsed();
 
-        println!("\n{}", "== THINKING PROCESS:".bright_yellow());
+        self.output.info(&format!("\n{}", "== THINKING PROCESS:".bright_yellow()));
         for (i, step) in thinking_steps.iter().enumerate() {
-            println!("   {}. {}", i + 1, step);
+            self.output.info(&format!("   {}. {}", i + 1, step));
         }
 
         if solution.accuracy >= 100.0 {
-            println!("\n{}", "== SOLUTION FOUND!".green().bold());
-            println!("   {}: {}", "Equation".cyan(), solution.equation.bright_white());
-            println!("   {}: {}", "Result".cyan(), solution.result.to_string().green());
-            println!("   {}: {:?}", "Solve time".cyan(), solve_time);
+            self.output.program(&format!("\n{}", "== SOLUTION FOUND!".green().bold()));
+            self.output.program(&format!("   {}: {}", "Equation".cyan(), solution.equation.bright_white()));
+            self.output.program(&format!("   {}: {}", "Result".cyan(), solution.result.to_string().green()));
+            self.output.program(&format!("   {}: {:?}", "Solve time".cyan(), solve_time));
 
             self.cache_solution(target, &inputs, &solution)?;
 
         } else {
-            println!("\n{}", "!! NO EXACT SOLUTION FOUND".red().bold());
-            println!("   Best approximation: {} = {}", solution.equation, solution.result);
-            println!("   Accuracy: {:.1}%", solution.accuracy);
+            self.output.program(&format!("\n{}", "!! NO EXACT SOLUTION FOUND".red().bold()));
+            self.output.program(&format!("   Best approximation: {} = {}", solution.equation, solution.result));
+            self.output.program(&format!("   Accuracy: {:.1}%", solution.accuracy));
+        }
+
+        if self.list_all {
+            self.show_alternatives(target, &inputs)?;
         }
         
         let interaction = UserInteraction {
@@ -265,6 +339,34 @@ impl InteractiveEngine {
         Ok(())
     }
     
+    /// Solve with the given inputs, then with cached results added when that misses
+    fn search_problem(
+        &mut self,
+        target: f64,
+        inputs: &mut Vec<f64>,
+        budget: &Budget,
+        thinking_steps: &mut Vec<String>,
+        spinner: &ProgressBar,
+    ) -> Result<MathSolution> {
+        thinking_steps.push(format!("Trying with provided inputs: {:?}", inputs));
+        let mut solution = self.math_engine.solve_target_with(target, inputs, "interactive", "interactive", SolveOptions { rules: self.rules, operators: None })?;
+
+        if solution.accuracy < 100.0 && self.rules == SolveRules::Free {
+            spinner.set_message("Checking cached solutions...");
+            thinking_steps.push("No exact solution with provided inputs. Checking cached solutions...".to_string());
+
+            let enhanced_inputs = self.enhance_inputs_with_cache(inputs, target);
+            if enhanced_inputs.len() > inputs.len() {
+                budget.check_list(enhanced_inputs.len())?;
+                budget.check_solver_candidates(EquationSolver::candidate_upper_bound(enhanced_inputs.len()))?;
+                thinking_steps.push(format!("Enhanced inputs with cached solutions: {:?}", enhanced_inputs));
+                solution = self.math_engine.solve_target(target, &enhanced_inputs, "interactive", "interactive")?;
+                *inputs = enhanced_inputs;
+            }
+        }
+        Ok(solution)
+    }
+
     fn enhance_inputs_with_cache(&self, inputs: &[f64], target: f64) -> Vec<f64> {
         let mut enhanced = inputs.to_vec();
         
@@ -273,8 +375,8 @@ impl InteractiveEngine {
             if cached_solution.result <= target * 2.0 && cached_solution.result >= 1.0 {
                 if !enhanced.contains(&cached_solution.result) {
                     enhanced.push(cached_solution.result);
-                    println!("   -- Adding cached solution {} (from: {})", 
-                            cached_solution.result, cached_solution.equation);
+                    self.output.debug(&format!("   -- Adding cached solution {} (from: {})", 
+                            cached_solution.result, cached_solution.equation));
                 }
             }
             
@@ -289,7 +391,7 @@ impl InteractiveEngine {
     }
     
     fn cache_solution(&mut self, target: f64, inputs: &[f64], solution: &MathSolution) -> Result<()> {
-        let cache_key = format!("{}_{:?}", target, inputs);
+        let cache_key = format!("{}_{:?}{}{}", target, inputs, self.rules.cache_suffix(), self.operators.cache_suffix());
         
         let cached_solution = CachedSolution {
             target,
@@ -304,46 +406,76 @@ impl InteractiveEngine {
             existing.success_count += 1;
         } else {
             self.session.learned_solutions.insert(cache_key, cached_solution);
-            println!("   ++ Solution cached for future use! (Total cached: {})", 
-                    self.session.learned_solutions.len());
+            self.output.debug(&format!("   ++ Solution cached for future use! (Total cached: {})", 
+                    self.session.learned_solutions.len()));
         }
         
         Ok(())
     }
     
+    /// Every exact equation for the problem, cheapest first, or the closest few
+    fn show_alternatives(&self, target: f64, inputs: &[f64]) -> Result<()> {
+        if self.rules != SolveRules::Free {
+            self.output.warn(&format!("!! The equation list ignores rules; skipped while solving {}", self.rules.describe()));
+            return Ok(());
+        }
+
+        let exact = self.math_engine.enumerate_exact(target, inputs)?;
+        let (title, solutions) = if exact.is_empty() {
+            ("== CLOSEST EQUATIONS:".to_string(), self.math_engine.solve_target_topk(target, inputs, CLOSEST_SHOWN)?)
+        } else {
+            (format!("== ALL EXACT EQUATIONS ({}):", exact.len()), exact)
+        };
+
+        self.output.program(&format!("\n{}", title.bright_yellow()));
+        for (i, solution) in solutions.iter().enumerate() {
+            let cost = solution.cost.map(|cost| cost.to_string()).unwrap_or_else(|| "?".to_string());
+            self.output.program(&format!("   {}. {} = {} (accuracy: {:.1}%, cost: {})",
+                    (i + 1).to_string().bright_white(),
+                    solution.equation.green(),
+                    solution.result,
+                    solution.accuracy,
+                    cost));
+        }
+        Ok(())
+    }
+
     fn show_help(&self) {
-        println!("\n=== HELP ===");
-        println!("How to use:");
-        println!("   - Enter a target number you want to reach");
-        println!("   - Enter available numbers separated by commas");
-        println!("   - Use '?' for blank spots (system will try to fill with cached solutions)");
-        println!("");
-        println!("Examples:");
-        println!("   Target: 24, Numbers: 3,4,2  ->  System finds: 3 * 4 * 2 = 24");
-        println!("   Target: 48, Numbers: 2,?    ->  System uses cached 24: 2 * 24 = 48");
-        println!("   Target: 100, Numbers: ?     ->  System finds combinations from cache");
+        self.output.info("\n=== HELP ===");
+        self.output.info("How to use:");
+        self.output.info("   - Enter a target number you want to reach");
+        self.output.info("   - Enter available numbers separated by commas");
+        self.output.info("   - Use '?' for blank spots (system will try to fill with cached solutions)");
+        self.output.info("   - Type 'rules once' to use each number at most once, 'rules all' to use every");
+        self.output.info("     number exactly once, 'rules free' to go back to any operation");
+        self.output.info("   - Type 'all' to also list every exact equation (or the closest few) per problem");
+        self.output.info("");
+        self.output.info("Examples:");
+        self.output.info("   Target: 24, Numbers: 3,4,2  ->  System finds: 3 * 4 * 2 = 24");
+        self.output.info("   Target: 48, Numbers: 2,?    ->  System uses cached 24: 2 * 24 = 48");
+        self.output.info("   Target: 100, Numbers: ?     ->  System finds combinations from cache");
     }
     
     fn show_statistics(&self) {
-        println!("\n{}", "=== STATISTICS ===".bright_cyan().bold());
-        println!("{}: {}", "Session ID".cyan(), self.session.session_id.bright_white());
-        println!("{}: {}", "Problems solved this session".cyan(), self.session.total_problems_solved.to_string().green());
-        println!("{}: {}", "Total cached solutions".cyan(), self.session.learned_solutions.len().to_string().green());
-        println!("{}: {} minutes", "Session duration".cyan(),
+        self.output.info(&format!("\n{}", "=== STATISTICS ===".bright_cyan().bold()));
+        self.output.info(&format!("{}: {}", "Session ID".cyan(), self.session.session_id.bright_white()));
+        self.output.info(&format!("{}: {}", "Problems solved this session".cyan(), self.session.total_problems_solved.to_string().green()));
+        self.output.info(&format!("{}: {}", "Total cached solutions".cyan(), self.session.learned_solutions.len().to_string().green()));
+        self.output.info(&format!("{}: {} minutes", "Session duration".cyan(),
                 ((SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64
-                 - self.session.session_start_time) / 60000).to_string().bright_white());
+                 - self.session.session_start_time) / 60000).to_string().bright_white()));
 
         if !self.session.learned_solutions.is_empty() {
-            println!("\n{}", "Recent cached solutions:".bright_yellow());
+            self.output.info(&format!("\n{}", "Recent cached solutions:".bright_yellow()));
             let mut solutions: Vec<_> = self.session.learned_solutions.values().collect();
             solutions.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
 
             for (i, solution) in solutions.iter().take(5).enumerate() {
-                println!("   {}. {} = {} (from: {:?})",
+                self.output.info(&format!("   {}. {} = {} (from: {:?})",
                         (i + 1).to_string().bright_white(),
                         solution.equation.green(),
                         solution.result.to_string().bright_cyan(),
-                        solution.inputs);
+                        solution.inputs));
             }
         }
     }
@@ -351,7 +483,7 @@ impl InteractiveEngine {
     fn save_session(&self) -> Result<()> {
         let content = serde_json::to_string_pretty(&self.session)?;
         fs::write(&self.session_file, content)?;
-        println!("** Session data saved to: {}", self.session_file);
+        self.output.info(&format!("** Session data saved to: {}", self.session_file));
         Ok(())
     }
     
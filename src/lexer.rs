@@ -0,0 +1,253 @@
+// Tokeniser for .slut source: string literals, comments and the code between them
+// Comments are dropped before parsing; escapes in string literals are decoded where a literal is used
+
+use anyhow::Result;
+
+/// Pattern for a double-quoted literal that may contain escapes; the contents are group 1
+pub const STRING_LITERAL: &str = r#""((?:[^"\\]|\\.)*)""#;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Token<'a> {
+    /// Anything outside strings and comments, newlines included
+    Code(&'a str),
+    /// A string literal with its quotes, escapes still encoded
+    Str(&'a str),
+    /// `# ...` up to the end of the line, or `#[ ... ]#`
+    Comment(&'a str),
+}
+
+/// Split `source` into code, string and comment tokens
+///
+/// Strings may not span lines, block comments may. Escapes are checked here
+/// so a bad one is reported with its line number.
+pub fn tokenize(source: &str) -> Result<Vec<Token<'_>>> {
+    let mut tokens = Vec::new();
+    let mut code_start = 0;
+    let mut line = 1;
+    let mut chars = source.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        match c {
+            '\n' => line += 1,
+            '"' => {
+                let literal_line = line;
+                let mut end = None;
+                while let Some((position, c)) = chars.next() {
+                    match c {
+                        '\\' => {
+                            chars.next_if(|(_, c)| *c != '\n');
+                        }
+                        '"' => {
+                            end = Some(position + 1);
+                            break;
+                        }
+                        '\n' => break,
+                        _ => {}
+                    }
+                }
+                let end = end.ok_or_else(|| anyhow::anyhow!("Unterminated string literal on line {}", literal_line))?;
+                unescape(&source[start + 1..end - 1])
+                    .map_err(|e| anyhow::anyhow!("{} on line {}", e, literal_line))?;
+
+                push_code(&mut tokens, &source[code_start..start]);
+                tokens.push(Token::Str(&source[start..end]));
+                code_start = end;
+            }
+            '#' if chars.peek().map(|(_, c)| *c) == Some('[') => {
+                let comment_line = line;
+                let end = source[start..].find("]#")
+                    .map(|offset| start + offset + 2)
+                    .ok_or_else(|| anyhow::anyhow!("Unterminated block comment starting on line {}", comment_line))?;
+                line += source[start..end].matches('\n').count();
+                while chars.next_if(|(position, _)| *position < end).is_some() {}
+
+                push_code(&mut tokens, &source[code_start..start]);
+                tokens.push(Token::Comment(&source[start..end]));
+                code_start = end;
+            }
+            '#' => {
+                let end = source[start..].find('\n').map_or(source.len(), |offset| start + offset);
+                while chars.next_if(|(position, _)| *position < end).is_some() {}
+
+                push_code(&mut tokens, &source[code_start..start]);
+                tokens.push(Token::Comment(&source[start..end]));
+                code_start = end;
+            }
+            _ => {}
+        }
+    }
+
+    push_code(&mut tokens, &source[code_start..]);
+    Ok(tokens)
+}
+
+fn push_code<'a>(tokens: &mut Vec<Token<'a>>, code: &'a str) {
+    if !code.is_empty() {
+        tokens.push(Token::Code(code));
+    }
+}
+
+/// `source` without comments; a block comment leaves its line breaks behind
+/// so statements after it stay on their own lines
+pub fn strip_comments(source: &str) -> Result<String> {
+    let mut out = String::with_capacity(source.len());
+    for token in tokenize(source)? {
+        match token {
+            Token::Code(text) | Token::Str(text) => out.push_str(text),
+            Token::Comment(text) => out.extend(text.chars().filter(|&c| c == '\n')),
+        }
+    }
+    Ok(out)
+}
+
+/// Decode `\"`, `\\`, `\n`, `\t`, `\r`, `\0` and `\u{...}` in a literal's contents
+pub fn unescape(text: &str) -> Result<String> {
+    if !text.contains('\\') {
+        return Ok(text.to_string());
+    }
+
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('"') => out.push('"'),
+            Some('\\') => out.push('\\'),
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some('0') => out.push('\0'),
+            Some('u') => {
+                let rest = chars.as_str();
+                let hex = rest.strip_prefix('{')
+                    .and_then(|rest| rest.split_once('}'))
+                    .map(|(hex, _)| hex)
+                    .ok_or_else(|| anyhow::anyhow!("Expected \\u{{...}} in \"{}\"", text))?;
+                let decoded = u32::from_str_radix(hex, 16).ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| anyhow::anyhow!("Invalid unicode escape \\u{{{}}}", hex))?;
+                out.push(decoded);
+                chars = rest[hex.len() + 2..].chars();
+            }
+            Some(other) => return Err(anyhow::anyhow!("Unknown escape sequence \\{}", other)),
+            None => return Err(anyhow::anyhow!("Trailing backslash in \"{}\"", text)),
+        }
+    }
+    Ok(out)
+}
+
+/// The inverse of `unescape`, for putting decoded text back into source
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            '\0' => out.push_str("\\0"),
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+/// Value of a plain assignment's right-hand side: a decoded literal when quoted
+pub fn text_literal(expression: &str) -> String {
+    let quoted = expression.len() >= 2 && expression.starts_with('"') && expression.ends_with('"');
+    match quoted.then(|| unescape(&expression[1..expression.len() - 1])) {
+        Some(Ok(text)) => text,
+        _ => expression.trim_matches('"').to_string(),
+    }
+}
+
+/// Net `{` minus `}` on a line, ignoring braces inside string literals
+pub fn brace_delta(line: &str) -> i32 {
+    let mut delta = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for c in line.chars() {
+        match (in_string, c) {
+            (true, _) if escaped => escaped = false,
+            (true, '\\') => escaped = true,
+            (true, '"') => in_string = false,
+            (true, _) => {}
+            (false, '"') => in_string = true,
+            (false, '{') => delta += 1,
+            (false, '}') => delta -= 1,
+            (false, _) => {}
+        }
+    }
+    delta
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{both_backends, Engine};
+    use crate::VariableValue;
+
+    #[test]
+    fn test_strip_comments_keeps_strings_and_lines() {
+        let source = "x <> 5 # note\nspeak(\"# not a comment \\\" still\") #[ block\nspans ]# y <> 2\n";
+        assert_eq!(
+            strip_comments(source).unwrap(),
+            "x <> 5 \nspeak(\"# not a comment \\\" still\") \n y <> 2\n"
+        );
+
+        assert!(strip_comments("speak(\"open)\n").unwrap_err().to_string().contains("line 1"));
+        assert!(strip_comments("a\n#[ never closed").unwrap_err().to_string().contains("line 2"));
+        assert!(strip_comments("a\nspeak(\"\\q\")").unwrap_err().to_string().contains("line 2"));
+    }
+
+    #[test]
+    fn test_escapes_round_trip() {
+        let decoded = unescape(r#"say \"hi\"\n\tto \u{1F600} \\ you"#).unwrap();
+        assert_eq!(decoded, "say \"hi\"\n\tto \u{1F600} \\ you");
+        assert_eq!(unescape(&escape(&decoded)).unwrap(), decoded);
+        assert!(unescape(r"\u{110000}").is_err());
+        assert_eq!(text_literal(r#""a\"b""#), "a\"b");
+        assert_eq!(brace_delta(r#"loop <> count(2) { speak("}") "#), 1);
+    }
+
+    #[test]
+    fn test_comments_and_escapes_in_programs() {
+        let program = r#"* Greeting {
+    ^ observe_execution {
+        word <> "say \"hi\"" # trailing comment
+        woof word
+    }
+}
+
+* <main> Lexed {
+    ^ observe_execution {
+        #[ a block comment
+           spanning lines ]#
+        x <> 5 # not part of the value
+        greeting <> Greeting()
+        loop <> count(1) {
+            speak("x is ~x~\t{braces} and # kept") # loop body comment
+        }
+        speak("~greeting~\nsmile \u{263A}")
+    }
+}"#;
+        for mut engine in both_backends(|builder| builder) {
+            let outcome = engine.run_source(program).unwrap();
+
+            assert!(matches!(outcome.variable("x"), Some(VariableValue::Number(n)) if *n == 5.0));
+            assert_eq!(outcome.program_lines(), [
+                "x is 5\t{braces} and # kept",
+                "say \"hi\"\nsmile \u{263A}",
+            ]);
+        }
+
+        let mut engine = Engine::builder().build().unwrap();
+        let error = engine.run_source("* <main> Bad {\n    ^ observe_execution {\n        speak(\"\\q\")\n    }\n}").unwrap_err();
+        assert_eq!(error.to_string(), "Unknown escape sequence \\q on line 3");
+    }
+}
@@ -7,16 +7,15 @@ pub mod tauri_commands;
 // Instead, re-export what's needed
 
 use anyhow::Result;
-use clap::Parser;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::path::PathBuf;
 use std::time::{SystemTime, UNIX_EPOCH};
-use std::io::{self, Write};
 use std::sync::Arc;
-use tracing::{info, debug, warn, error};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use tracing_subscriber;
 
 // Type alias for console output callback
@@ -26,36 +25,69 @@ mod function_builder;
 mod function_executor;
 mod math_engine;
 mod equation_solver;
+pub mod expression_search;
+pub mod rational;
+pub mod bigint;
+pub mod operator_set;
+pub mod cost_model;
 mod variable_manager;
 mod interactive_engine;
 mod condition_evaluator;
 mod loop_executor;
 mod memory;
+pub mod output_sink;
+pub mod parser;
+pub mod equation_tree;
+pub mod codegen;
+pub mod bytecode;
+pub mod input_source;
+pub mod golden;
+pub mod cache_store;
+pub mod engine;
+pub mod native_functions;
+pub mod limits;
+pub mod cancellation;
+pub mod parallel;
+pub mod file_io;
+pub mod format_spec;
+pub mod lexer;
+pub mod checker;
+pub mod program_result;
+
+pub use output_sink::{
+    BufferedSink, CallbackSink, DiagnosticLevel, JsonLinesSink, OutputLine, OutputSink,
+    OutputStream, SharedSink, StdoutSink, TeeSink,
+};
+pub use input_source::{InputSource, ScriptedInput, StdinInput};
+pub use cache_store::{CacheStore, DiskCache, InMemoryCache};
+pub use engine::{Engine, EngineBuilder, RunOutcome};
+pub use native_functions::{NativeFn, NativeFunctions};
+pub use limits::{LimitExceeded, Limits};
+pub use cancellation::{CancellationToken, Cancelled, RunToken, RunTokens};
+pub use parallel::WriteConflict;
+pub use file_io::FsPermissions;
+pub use program_result::{ProgramExit, ProgramResult};
+pub use expression_search::{SearchConfig, SolveOptions, SolveRules};
+pub use rational::Rational;
+pub use bigint::BigInt;
+pub use operator_set::{Operator, OperatorSet};
+pub use cost_model::{CostModel, DefaultCostModel, SharedCostModel};
+pub use interactive_engine::InteractiveEngine;
 
 use function_builder::FunctionBuilder;
 use function_executor::FunctionExecutor;
 use math_engine::MathEngine;
 use variable_manager::VariableManager;
-use interactive_engine::InteractiveEngine;
 use condition_evaluator::ConditionEvaluator;
-use loop_executor::LoopExecutor;
-
-#[derive(Parser)]
-#[command(name = "quantum")]
-#[command(about = "Quantum Consciousness Programming Language Transpiler")]
-struct Args {
-    
-    file: Option<PathBuf>,
-    
-    #[arg(short, long, default_value = "1")]
-    observations: u32,
-    
-    #[arg(short, long)]
-    interactive: bool,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct QuantumCache {
+use loop_executor::{LoopControl, LoopExecutor};
+use equation_solver::EquationSolver;
+use limits::Budget;
+use codegen::TemplatePart;
+use format_spec::FormatSpec;
+
+/// Everything learned across runs: variables, solved equations, built functions
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct QuantumCache {
     templates: HashMap<String, CachedTemplate>,
     variables: HashMap<String, StoredVariable>,
     quantum_states: HashMap<String, CollapsedState>,
@@ -73,12 +105,18 @@ pub struct StoredVariable {
     pub source_equation: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub enum VariableValue {
     Number(f64),
     String(String),
     Boolean(bool),
     FunctionResult(String), 
+    /// Lines from `readLines`, rows from `readCsv`
+    List(Vec<VariableValue>),
+    /// One `readCsv` row: fields in column order
+    Record(Vec<(String, VariableValue)>),
+    /// A whole number beyond 2^53, which `Number` cannot hold exactly
+    Integer(BigInt),
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -89,7 +127,7 @@ pub struct FunctionResult {
     pub timestamp: u64,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CachedTemplate {
     name: String,
     func_type: String,
@@ -99,21 +137,21 @@ pub struct CachedTemplate {
     file_path: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BuiltFunction {
     pub name: String,
     pub variants: Vec<FunctionVariant>,
     pub timestamp: u64,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FunctionVariant {
     pub parameter_count: usize,
     pub parameter_pattern: String,
     pub rust_function_name: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct CollapsedState {
     result: f64,
     equation: String,
@@ -141,6 +179,38 @@ pub struct MathSolution {
     pub attempts: u32,
     #[serde(default)]
     pub formula: Option<String>,
+    /// The result in full when it is an integer too large for `result` to hold exactly
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exact: Option<BigInt>,
+    /// What the cost model charged for `equation`; a cheaper exact equation replaces this one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost: Option<u32>,
+}
+
+impl MathSolution {
+    /// The result as a variable holds it: `exact` when set, `result` otherwise
+    pub fn value(&self) -> VariableValue {
+        match &self.exact {
+            Some(exact) => VariableValue::Integer(exact.clone()),
+            None => VariableValue::Number(self.result),
+        }
+    }
+
+    pub fn display_result(&self) -> String {
+        display_value(&self.value())
+    }
+
+    /// More accurate, or as accurate and cheaper; a known cost beats none
+    pub fn improves_on(&self, other: &MathSolution) -> bool {
+        if self.accuracy != other.accuracy {
+            return self.accuracy > other.accuracy;
+        }
+        match (self.cost, other.cost) {
+            (Some(cost), Some(other)) => cost < other,
+            (Some(_), None) => true,
+            _ => false,
+        }
+    }
 }
 
 // main() function is only in src/main.rs (the binary)
@@ -156,8 +226,27 @@ pub struct QuantumTranspiler {
     condition_evaluator: ConditionEvaluator,
     loop_executor: LoopExecutor,
     current_class_name: String,
-    cache_directory: PathBuf,
-    console_callback: Option<ConsoleCallback>,
+    store: Box<dyn CacheStore>,
+    output: SharedSink,
+    input: Box<dyn InputSource>,
+    rng: StdRng,
+    seed: Option<u64>,
+    natives: NativeFunctions,
+    budget: Budget,
+    cancel: CancellationToken,
+    fs: FsPermissions,
+    /// The main class's last `woof` in the current run
+    result: Option<ProgramResult>,
+    /// Status from `exit(code)` in the current run
+    exit_code: Option<i32>,
+    /// Variables set by the host before each run; literal assignments to them are skipped
+    presets: BTreeMap<String, VariableValue>,
+    /// How far the expression tree search goes when the fixed shapes miss a target
+    search: SearchConfig,
+    /// Operators the solver may use unless a statement sets its own
+    operators: OperatorSet,
+    /// Picks among exact equations; the cheapest wins
+    cost_model: SharedCostModel,
 }
 
 impl QuantumTranspiler {
@@ -168,41 +257,43 @@ impl QuantumTranspiler {
     }
 
     pub fn new_with_cache_dir(cache_dir: PathBuf) -> Result<Self> {
-        // Ensure cache directory exists
-        if !cache_dir.exists() {
-            fs::create_dir_all(&cache_dir)?;
-            info!("** Created cache directory: {}", cache_dir.display());
-        } else {
-            info!("** Using existing cache directory: {}", cache_dir.display());
-        }
-
-        let cache = Self::load_cache(&cache_dir).unwrap_or_else(|_| {
-            info!("** Starting with fresh quantum consciousness cache");
-            info!("** Cache location: {}", cache_dir.join("quantum_consciousness_cache.json").display());
-            QuantumCache {
-                templates: HashMap::new(),
-                variables: HashMap::new(),
-                quantum_states: HashMap::new(),
-                variable_attempts: HashMap::new(),
-                built_functions: HashMap::new(),
-                math_solutions: HashMap::new(),
-                function_results: HashMap::new(),
+        Self::with_output_sink(cache_dir, StdoutSink::shared())
+    }
+
+    /// Like `new_with_cache_dir`, but routes output through `output` from the start
+    pub fn with_output_sink(cache_dir: PathBuf, output: SharedSink) -> Result<Self> {
+        let store = DiskCache::new(cache_dir)?;
+        let function_builder = FunctionBuilder::with_output_sink(output.clone())?;
+        Self::with_store(Box::new(store), function_builder, output)
+    }
+
+    /// Build a transpiler around any cache store (see `Engine::builder`)
+    fn with_store(store: Box<dyn CacheStore>, function_builder: FunctionBuilder, output: SharedSink) -> Result<Self> {
+        let cache_location = store.directory()
+            .map(|dir| dir.join("quantum_consciousness_cache.json").display().to_string())
+            .unwrap_or_else(|| "in memory".to_string());
+
+        let cache = match store.load() {
+            Ok(Some(cache)) => cache,
+            _ => {
+                output.info("** Starting with fresh quantum consciousness cache");
+                output.info(&format!("** Cache location: {}", cache_location));
+                QuantumCache::default()
             }
-        });
+        };
 
         if !cache.templates.is_empty() || !cache.built_functions.is_empty() || !cache.math_solutions.is_empty() {
-            info!("** Loaded previous quantum states, built functions, variables, and math solutions from cache");
-            info!("** Cache location: {}", cache_dir.join("quantum_consciousness_cache.json").display());
+            output.info("** Loaded previous quantum states, built functions, variables, and math solutions from cache");
+            output.info(&format!("** Cache location: {}", cache_location));
         }
 
-        let function_builder = FunctionBuilder::new()?;
         let function_executor = FunctionExecutor::new()?;
         let math_engine = MathEngine::new(cache.math_solutions.clone(), cache.variable_attempts.clone());
-        let variable_manager = VariableManager::new(cache.variables.clone());
+        let variable_manager = VariableManager::with_output_sink(cache.variables.clone(), output.clone());
         let condition_evaluator = ConditionEvaluator::new();
         let loop_executor = LoopExecutor::new();
 
-        Ok(Self {
+        let mut transpiler = Self {
             cache,
             execution_count: 0,
             function_builder,
@@ -212,82 +303,367 @@ impl QuantumTranspiler {
             condition_evaluator,
             loop_executor,
             current_class_name: String::new(),
-            cache_directory: cache_dir,
-            console_callback: None,
-        })
-    }
-
-    fn load_cache(cache_dir: &PathBuf) -> Result<QuantumCache> {
-        let cache_path = cache_dir.join("quantum_consciousness_cache.json");
-        let content = fs::read_to_string(&cache_path)?;
-        let cache: QuantumCache = serde_json::from_str(&content)?;
-        Ok(cache)
+            store,
+            output,
+            input: Box::new(StdinInput),
+            rng: StdRng::from_entropy(),
+            seed: None,
+            natives: NativeFunctions::default(),
+            budget: Budget::new(Limits::default()),
+            cancel: CancellationToken::new(),
+            fs: FsPermissions::default(),
+            result: None,
+            exit_code: None,
+            presets: BTreeMap::new(),
+            search: SearchConfig::default(),
+            operators: OperatorSet::ALL,
+            cost_model: DefaultCostModel::shared(),
+        };
+        transpiler.propagate_output_sink();
+        transpiler.propagate_cancellation();
+        Ok(transpiler)
     }
 
     fn save_cache(&mut self) -> Result<()> {
 
         self.cache.math_solutions = self.math_engine.get_solutions();
         self.cache.variable_attempts = self.math_engine.get_variable_attempts();
-        self.cache.variables = self.variable_manager.get_all_variables();
-
-        // Save JSON (for backward compatibility)
-        let content = serde_json::to_string_pretty(&self.cache)?;
-        let cache_path = self.cache_directory.join("quantum_consciousness_cache.json");
-        fs::write(&cache_path, content)?;
-
-        // Also save binary format (Phase 1 memory optimization)
-        // CRITICAL: Save to cache_directory, NOT project directory
-        use memory::BinaryCache;
-        let binary_cache_path = self.cache_directory.join("quantum_cache.bin");
-        let binary_cache_path_str = binary_cache_path.to_string_lossy().to_string();
-
-        if let Ok(binary_cache) = BinaryCache::from_hashmap_with_path(
-            self.cache.math_solutions.clone(),
-            &binary_cache_path_str
-        ) {
-            if let Err(e) = binary_cache.save_to_disk() {
-                warn!("!! Failed to save binary cache: {}", e);
-            } else {
-                info!("** Binary cache saved to: {}", binary_cache_path_str);
-            }
+
+        // Presets only hold for the run; keep whatever the cache had under those names
+        let mut variables = self.variable_manager.get_all_variables();
+        for name in self.presets.keys() {
+            match self.cache.variables.get(name) {
+                Some(cached) => variables.insert(name.clone(), cached.clone()),
+                None => variables.remove(name),
+            };
         }
+        self.cache.variables = variables;
 
-        Ok(())
+        self.store.save(&self.cache, &self.output)
     }
 
     /// Set a console callback for emitting output to Tauri IDE
+    ///
+    /// Output still goes to stdout as well, for the terminal running the app.
     pub fn set_console_callback(&mut self, callback: ConsoleCallback) {
-        self.console_callback = Some(callback.clone());
+        self.set_output_sink(Arc::new(TeeSink::new(vec![
+            StdoutSink::shared(),
+            Arc::new(CallbackSink::new(callback)),
+        ])));
+    }
+
+    /// Route all program output and diagnostics through `sink`
+    pub fn set_output_sink(&mut self, sink: SharedSink) {
+        self.output = sink;
         // Propagate to child components
-        self.math_engine.set_console_callback(callback.clone());
-        self.variable_manager.set_console_callback(callback);
+        self.propagate_output_sink();
+    }
+
+    /// Answer `userIn(...)` prompts from `input` instead of stdin
+    pub fn set_input_source(&mut self, input: Box<dyn InputSource>) {
+        self.input = input;
     }
 
-    /// Helper method to print to both console and Tauri IDE
-    fn emit(&self, message: String, level: &str) {
-        // Always print to stdout for cmd.exe
-        println!("{}", message);
+    /// Make runs reproducible by seeding `randomChoice`
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = Some(seed);
+        self.rng = StdRng::seed_from_u64(seed);
+    }
 
-        // Also emit to Tauri IDE if callback is set
-        if let Some(callback) = &self.console_callback {
-            callback(message, level);
-        }
+    /// Resource limits applied to every following run
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.budget = Budget::new(limits);
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.budget.limits()
+    }
+
+    /// Stop runs when `token` is cancelled; they fail with `Cancelled`
+    pub fn set_cancellation_token(&mut self, token: CancellationToken) {
+        self.cancel = token;
+        self.propagate_cancellation();
+    }
+
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancel.clone()
+    }
+
+    /// Directories `readFile`, `readLines`, `readCsv` and `writeFile` may touch
+    pub fn set_fs_permissions(&mut self, permissions: FsPermissions) {
+        self.fs = permissions;
+    }
+
+    pub fn fs_permissions(&self) -> &FsPermissions {
+        &self.fs
+    }
+
+    /// Set these variables at the start of every run, over their cached values
+    ///
+    /// The program's own `name <> literal` lines become defaults and are skipped
+    /// for preset names; computed assignments still update them. Neither the
+    /// presets nor those updates are saved to the cache.
+    pub fn set_preset_variables(&mut self, presets: BTreeMap<String, VariableValue>) {
+        self.presets = presets;
+    }
+
+    /// Operator and depth caps for the expression tree search used when solving targets
+    pub fn set_search_config(&mut self, search: SearchConfig) {
+        self.search = search;
+        self.math_engine.set_search_config(search);
+    }
+
+    pub fn search_config(&self) -> SearchConfig {
+        self.search
+    }
+
+    /// Operators the solver may use where a statement does not set `ops: ...`
+    pub fn set_operators(&mut self, operators: OperatorSet) {
+        self.operators = operators;
+        self.math_engine.set_operators(operators);
+    }
+
+    pub fn operators(&self) -> OperatorSet {
+        self.operators
+    }
+
+    /// How exact equations are ranked; `DefaultCostModel` unless set
+    pub fn set_cost_model(&mut self, cost_model: SharedCostModel) {
+        self.cost_model = cost_model.clone();
+        self.math_engine.set_cost_model(cost_model);
+    }
+
+    pub fn cost_model(&self) -> SharedCostModel {
+        self.cost_model.clone()
+    }
+
+    /// The main class's last `woof` in the latest run, if it had one
+    pub fn program_result(&self) -> Option<&ProgramResult> {
+        self.result.as_ref()
+    }
+
+    /// Status the latest run set with `exit(code)`; 0 when it never called it
+    pub fn exit_code(&self) -> i32 {
+        self.exit_code.unwrap_or(0)
+    }
+
+    fn propagate_cancellation(&mut self) {
+        self.math_engine.set_cancellation_token(self.cancel.clone());
+        self.loop_executor.set_cancellation_token(self.cancel.clone());
+        self.function_executor.set_cancellation_token(self.cancel.clone());
+    }
+
+    /// Poll for cancellation and account for one statement or loop iteration
+    fn checkpoint(&mut self) -> Result<()> {
+        self.cancel.check()?;
+        self.budget.step()?;
+        Ok(())
+    }
+
+    /// Expose a host function to .slut assignments, conditions and interpolation
+    pub fn register_native_fn(&mut self, name: &str, arity: usize, function: NativeFn) -> Result<()> {
+        self.natives.register(name, arity, function)?;
+        self.condition_evaluator.set_native_functions(self.natives.clone());
+        Ok(())
+    }
+
+    fn propagate_output_sink(&mut self) {
+        self.math_engine.set_output_sink(self.output.clone());
+        self.variable_manager.set_output_sink(self.output.clone());
+        self.condition_evaluator.set_output_sink(self.output.clone());
+        self.loop_executor.set_output_sink(self.output.clone());
+        self.function_builder.set_output_sink(self.output.clone());
+        self.function_executor.set_output_sink(self.output.clone());
     }
 
     pub fn execute_file(&mut self, file_path: &PathBuf) -> Result<()> {
+        let source = fs::read_to_string(file_path)?;
+        self.execute_source(&source)
+    }
+
+    /// Run .slut source with the tree-walking interpreter
+    pub fn execute_source(&mut self, source: &str) -> Result<()> {
+        self.begin_run();
+
         // CRITICAL: Reload cache before each execution to pick up previous run's learning
         self.reload_cache()?;
+        self.apply_presets()?;
+
+        let source = lexer::strip_comments(source)?;
+        match parser::Parser::new()?.parse_program(&source) {
+            Ok(program) => self.report_checks(&program),
+            // The interpreter cannot resolve a jump to a missing loop either, so stop here
+            Err(e) if e.is::<parser::UnknownLabel>() => return Err(e),
+            Err(_) => {}
+        }
+        let run = self.parse_and_execute(&source);
+        self.finish_run(run)?;
+        self.save_cache()?;
+        Ok(())
+    }
 
+    /// Execute a .slut file on the bytecode VM
+    pub fn execute_file_vm(&mut self, file_path: &PathBuf) -> Result<()> {
         let source = fs::read_to_string(file_path)?;
-        self.parse_and_execute(&source)?;
+        self.execute_source_vm(&source)
+    }
+
+    /// Run .slut source on the bytecode VM
+    ///
+    /// The main class is compiled once and run with variables in slots;
+    /// statements the VM has no opcode for are handed back to the interpreter.
+    /// Programs that do not compile (e.g. function calls inside conditions)
+    /// run on the interpreter instead.
+    pub fn execute_source_vm(&mut self, source: &str) -> Result<()> {
+        self.begin_run();
+        self.reload_cache()?;
+        self.apply_presets()?;
+
+        let source = &lexer::strip_comments(source)?;
+        let parsed = parser::Parser::new()?.parse_program(source);
+        match &parsed {
+            Ok(program) => self.report_checks(program),
+            Err(e) if e.is::<parser::UnknownLabel>() => return Err(parsed.unwrap_err()),
+            Err(_) => {}
+        }
+        let compiled = parsed.and_then(|program| {
+            bytecode::Compiler::new()
+                .preset(self.presets.keys().cloned())
+                .compile(&program)
+        });
+
+        match compiled {
+            Ok(chunk) => {
+                self.extract_all_classes(source)?;
+                self.current_class_name = chunk.class_name.clone();
+                let run = bytecode::Vm::new(&chunk).run(self);
+                self.finish_run(run)?;
+            }
+            Err(e) => {
+                self.output.info(&format!(">> Falling back to the interpreter: {}", e));
+                let run = self.parse_and_execute(source);
+                self.finish_run(run)?;
+            }
+        }
+
         self.save_cache()?;
         Ok(())
     }
 
+    /// Store the preset variables, replacing what the cache restored
+    fn apply_presets(&mut self) -> Result<()> {
+        for (name, value) in &self.presets {
+            self.variable_manager.store_variable(name, value.clone(), Some("preset".to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// End a run, treating `exit(code)` as a normal finish with that status
+    fn finish_run(&mut self, run: Result<()>) -> Result<()> {
+        match run {
+            Err(e) => match e.downcast_ref::<ProgramExit>() {
+                Some(exit) => {
+                    self.output.info(&format!("** {}", exit));
+                    self.exit_code = Some(exit.code);
+                    Ok(())
+                }
+                None => Err(e),
+            },
+            Ok(()) => Ok(()),
+        }
+    }
+
+    /// Print the checker's warnings for a program about to run
+    fn report_checks(&self, program: &parser::Program) {
+        for warning in checker::check(program) {
+            self.output.warn(&warning);
+        }
+    }
+
+    /// Compile a .slut file into a standalone Rust crate in `out_dir`
+    ///
+    /// `result([t]) <> randomChoice(...)` lines are replaced by the equations
+    /// already in the cache, so the program must have been run at least once.
+    pub fn transpile_file_to_rust(&mut self, file_path: &PathBuf, out_dir: &std::path::Path) -> Result<()> {
+        let source = fs::read_to_string(file_path)?;
+        let project = self.generate_rust_project(&source)?;
+        project.write_to(out_dir)?;
+
+        self.output.info(&format!("** Rust crate for {} written to {}", project.program_name, out_dir.display()));
+        Ok(())
+    }
+
+    /// Compile .slut source into a standalone Rust crate, without writing it
+    pub fn generate_rust_project(&mut self, source: &str) -> Result<codegen::GeneratedProject> {
+        self.reload_cache()?;
+
+        let program = parser::Parser::new()?.parse_program(source)?;
+        let solved = codegen::SolvedEquations::new(self.math_engine.get_solutions());
+        codegen::RustBackend::new(solved)
+            .max_while_iterations(self.budget.limits().max_while_iterations)
+            .generate(&program)
+    }
+
+    /// Compile a .slut file into `program.js` and a self-contained `index.html` in `out_dir`
+    pub fn transpile_file_to_js(&mut self, file_path: &PathBuf, out_dir: &std::path::Path) -> Result<()> {
+        let source = fs::read_to_string(file_path)?;
+        let project = self.generate_js_project(&source)?;
+        project.write_to(out_dir)?;
+
+        self.output.info(&format!("** JavaScript for {} written to {}", project.program_name, out_dir.display()));
+        Ok(())
+    }
+
+    /// Compile .slut source into `program.js` and `index.html`, without writing them
+    pub fn generate_js_project(&mut self, source: &str) -> Result<codegen::GeneratedProject> {
+        self.reload_cache()?;
+
+        let program = parser::Parser::new()?.parse_program(source)?;
+        let solved = codegen::SolvedEquations::new(self.math_engine.get_solutions());
+        codegen::JavaScriptBackend::new(solved)
+            .max_while_iterations(self.budget.limits().max_while_iterations)
+            .generate(&program)
+    }
+
+    /// Compile .slut source into a script defining `run(output)`, for in-page previews
+    pub fn transpile_source_to_js(&mut self, source: &str) -> Result<String> {
+        self.reload_cache()?;
+
+        let program = parser::Parser::new()?.parse_program(source)?;
+        let solved = codegen::SolvedEquations::new(self.math_engine.get_solutions());
+        codegen::JavaScriptBackend::new(solved)
+            .max_while_iterations(self.budget.limits().max_while_iterations)
+            .generate_script(&program)
+    }
+
+    /// Current value of every variable
+    pub fn variables(&self) -> HashMap<String, VariableValue> {
+        self.variable_manager.get_all_variables()
+            .into_iter()
+            .map(|(name, stored)| (name, stored.value))
+            .collect()
+    }
+
+    /// Every equation solved so far, keyed by `class-variable-target-inputs`
+    pub fn solutions(&self) -> HashMap<String, MathSolution> {
+        self.math_engine.get_solutions()
+    }
+
+    /// Reset per-run state: the resource budget and any loop state left by an aborted run
+    fn begin_run(&mut self) {
+        self.budget.start();
+        self.math_engine.set_solve_limits(self.budget.solve_limits());
+        self.result = None;
+        self.exit_code = None;
+        self.loop_executor = LoopExecutor::new();
+        self.loop_executor.set_output_sink(self.output.clone());
+        self.loop_executor.set_cancellation_token(self.cancel.clone());
+    }
+
     /// Reload cache from disk before execution to ensure continuity
     fn reload_cache(&mut self) -> Result<()> {
-        if let Ok(cache) = Self::load_cache(&self.cache_directory) {
-            info!("** Reloading cache from previous runs...");
+        if let Ok(Some(cache)) = self.store.load() {
+            self.output.info("** Reloading cache from previous runs...");
 
             // Update the in-memory cache
             self.cache = cache;
@@ -297,54 +673,64 @@ impl QuantumTranspiler {
                 self.cache.math_solutions.clone(),
                 self.cache.variable_attempts.clone()
             );
-            self.variable_manager = VariableManager::new(self.cache.variables.clone());
-
-            // Re-propagate console callback to new engine instances
-            if let Some(callback) = &self.console_callback {
-                self.math_engine.set_console_callback(callback.clone());
-                self.variable_manager.set_console_callback(callback.clone());
-            }
+            self.variable_manager = VariableManager::with_output_sink(
+                self.cache.variables.clone(),
+                self.output.clone()
+            );
 
-            info!("** Cache reloaded: {} variables, {} solutions",
-                  self.cache.variables.len(),
-                  self.cache.math_solutions.len());
+            // Re-propagate output sink to new engine instances
+            self.propagate_output_sink();
+            self.math_engine.set_cancellation_token(self.cancel.clone());
+            self.math_engine.set_solve_limits(self.budget.solve_limits());
+            self.math_engine.set_search_config(self.search);
+            self.math_engine.set_operators(self.operators);
+            self.math_engine.set_cost_model(self.cost_model.clone());
+
+            self.output.info(&format!("** Cache reloaded: {} variables, {} solutions",
+                self.cache.variables.len(),
+                self.cache.math_solutions.len()));
         }
         Ok(())
     }
     
     fn parse_and_execute(&mut self, source: &str) -> Result<()> {
-        info!(">> Building program from your intentions...");
+        self.output.info(">> Building program from your intentions...");
 
         self.extract_all_classes(source)?;
 
-        let main_regex = Regex::new(r"\*\s*<main>\s*(\w+)\s*\{[^}]*\^\s*observe_execution\s*\{([\s\S]*?)\}\s*\}")?;
+        let main_regex = Regex::new(r"\*\s*<main>\s*(\w+)\s*\{[^}]*\^\s*observe_execution\s*\{")?;
 
         if let Some(captures) = main_regex.captures(source) {
             let class_name = &captures[1];
-            let body = &captures[2];
+            let body = parser::block_contents(source, captures.get(0).unwrap().end() - 1)?;
 
-            info!(">> Quantum consciousness activated for: {}", class_name);
+            self.output.info(&format!(">> Quantum consciousness activated for: {}", class_name));
             self.current_class_name = class_name.to_string();
             self.execute_main_body(body, class_name)?;
-            info!("** Program built and executed successfully!");
+            self.output.info("** Program built and executed successfully!");
         } else {
-            warn!("!! No main class found in source");
+            self.output.warn("!! No main class found in source");
         }
 
         Ok(())
     }
     
     fn extract_all_classes(&mut self, source: &str) -> Result<()> {
-        let class_regex = Regex::new(r"\*\s*(?:<main>\s*)?(\w+)\s*(?:\(\[([^\]]*)\]\))?\s*\{[\s\S]*?\^\s*observe_execution\s*\{([\s\S]*?)\}\s*\}")?;
+        let class_regex = Regex::new(r"\*\s*(?:<main>\s*)?(\w+)\s*(?:\(\[([^\]]*)\]\))?\s*\{[\s\S]*?\^\s*observe_execution\s*\{")?;
         
         for captures in class_regex.captures_iter(source) {
             let class_name = &captures[1];
             let _parameters = captures.get(2).map(|m| m.as_str()).unwrap_or("");
-            let body = &captures[3];
+            let body = parser::block_contents(source, captures.get(0).unwrap().end() - 1)?;
             
             if !captures[0].contains("<main>") {
+                if self.natives.contains(class_name) {
+                    return Err(anyhow::anyhow!(
+                        "Function class '{}' clashes with a registered native function", class_name
+                    ));
+                }
                 
-                println!(">> Discovered function class: {}", class_name);
+                self.output.info(&format!(">> Discovered function class: {}", class_name));
                 self.cache.function_results.insert(
                     class_name.to_string(),
                     FunctionResult {
@@ -373,12 +759,15 @@ impl QuantumTranspiler {
                 continue;
             }
 
-            // Check if this is the start of a loop statement
-            if line.starts_with("loop") && line.contains("<>") {
+            // Check if this is the start of a loop statement, parallel block or match
+            if (line.starts_with("loop") && line.contains("<>"))
+                || parser::is_parallel_header(line)
+                || parser::is_match_header(line) {
                 // Collect the entire loop statement across multiple lines
                 let mut full_statement = String::new();
                 let mut brace_count = 0;
-                let mut in_loop = false;
+                // A match may open and close on its header line
+                let mut in_loop = parser::is_match_header(line);
 
                 while i < lines.len() {
                     let current_line = lines[i].trim();
@@ -389,8 +778,7 @@ impl QuantumTranspiler {
                     }
 
                     // Track braces
-                    brace_count += current_line.chars().filter(|&c| c == '{').count() as i32;
-                    brace_count -= current_line.chars().filter(|&c| c == '}').count() as i32;
+                    brace_count += lexer::brace_delta(current_line);
 
                     // Add line to statement
                     if full_statement.is_empty() {
@@ -420,59 +808,7 @@ impl QuantumTranspiler {
             // Check if this is the start of a selection statement
             if line.starts_with("if") && line.contains("<>") {
                 // Collect the entire selection statement across multiple lines
-                let mut full_statement = String::new();
-                let mut brace_count = 0;
-                let mut in_selection = false;
-                let mut first_line = true;
-
-                while i < lines.len() {
-                    let current_line = lines[i].trim();
-
-                    if current_line.is_empty() {
-                        i += 1;
-                        continue;
-                    }
-
-                    // Track braces BEFORE adding to statement
-                    let has_open_brace = current_line.contains('{');
-                    brace_count += current_line.chars().filter(|&c| c == '{').count() as i32;
-                    brace_count -= current_line.chars().filter(|&c| c == '}').count() as i32;
-
-                    // Add line to statement, preserving structure
-                    if first_line {
-                        // First line: "if <> (...) ... {"
-                        full_statement.push_str(current_line);
-                        first_line = false;
-                    } else if has_open_brace {
-                        // Line with opening brace
-                        full_statement.push(' ');
-                        full_statement.push_str(current_line);
-                    } else if current_line == "<>" {
-                        // Delimiter line - preserve with newline
-                        full_statement.push('\n');
-                        full_statement.push_str(current_line);
-                        full_statement.push('\n');
-                    } else if current_line.starts_with("<elif>") || current_line.starts_with("<else>") {
-                        // Condition lines
-                        full_statement.push(' ');
-                        full_statement.push_str(current_line);
-                    } else {
-                        // Regular statement line
-                        full_statement.push('\n');
-                        full_statement.push_str(current_line);
-                    }
-
-                    if brace_count > 0 {
-                        in_selection = true;
-                    }
-
-                    i += 1;
-
-                    // Break when we've closed all braces
-                    if in_selection && brace_count == 0 {
-                        break;
-                    }
-                }
+                let full_statement = collect_selection(&lines, &mut i);
 
                 // Execute the complete selection statement
                 self.execute_statement(&full_statement, class_name)?;
@@ -486,31 +822,63 @@ impl QuantumTranspiler {
     }
     
     fn execute_statement(&mut self, statement: &str, class_name: &str) -> Result<()> {
-        // Check for break statement - must be checked FIRST
-        if statement.trim() == "break" {
-            self.loop_executor.signal_break();
+        self.checkpoint()?;
+        self.dispatch_statement(statement, class_name)?;
+        self.budget.check_variables(self.variable_manager.len())?;
+        Ok(())
+    }
+
+    fn dispatch_statement(&mut self, statement: &str, class_name: &str) -> Result<()> {
+        // Check for break / continue, optionally labelled - must be checked FIRST
+        let jump_regex = Regex::new(r"^\s*(break|continue)(?:\s+(\w+))?\s*$")?;
+        if let Some(captures) = jump_regex.captures(statement) {
+            let label = captures.get(2).map(|m| m.as_str());
+            if &captures[1] == "break" {
+                self.loop_executor.signal_break(label);
+            } else {
+                self.loop_executor.signal_continue(label);
+            }
             return Ok(());
         }
 
-        // Check for continue statement
-        if statement.trim() == "continue" {
-            self.loop_executor.signal_continue();
-            return Ok(());
+        // Parallel blocks come first: their children may be any other statement
+        if parser::is_parallel_header(statement.trim()) {
+            let statement = statement.trim();
+            let body = parser::block_contents(statement, statement.find('{').unwrap_or_default())?;
+            return self.execute_parallel_block(body, class_name);
+        }
+
+        let parallel_range_regex = Regex::new(
+            r"^\s*loop(?:\s+(\w+))?\s*<>\s*parallel\s+range\s*\(\s*([^,]+)\s*,\s*([^)]+)\s*\)\s*as\s+(\w+)\s*\{([\s\S]*?)\}"
+        )?;
+
+        if let Some(captures) = parallel_range_regex.captures(statement) {
+            let label = captures.get(1).map(|m| m.as_str());
+            let start_expr = &captures[2];
+            let end_expr = &captures[3];
+            let loop_var = &captures[4];
+            let body = block_body(statement, &captures, 5)?;
+
+            return self.execute_parallel_range_loop(label, start_expr, end_expr, loop_var, body, class_name);
+        }
+
+        if parser::is_match_header(statement.trim()) {
+            return self.execute_match(statement, class_name);
         }
 
         // Check for selection statement (if/elif/else)
         let selection_regex = Regex::new(
-            r"if\s*<>\s*\(([^)]+)\)((?:\s*<elif>\s*\([^)]+\))*)\s*<else>\s*\(([^)]+)\)\s*\{([\s\S]*?)\}"
+            r"^\s*if\s*<>\s*\(((?:[^()]|\([^()]*\))+)\)((?:\s*<elif>\s*\((?:[^()]|\([^()]*\))+\))*)\s*<else>\s*\(((?:[^()]|\([^()]*\))+)\)\s*\{([\s\S]*?)\}"
         )?;
 
         if let Some(captures) = selection_regex.captures(statement) {
             let if_condition = &captures[1];
             let elif_part = &captures[2];
             let else_condition = &captures[3];
-            let full_body = &captures[4];
+            let full_body = block_body(statement, &captures, 4)?;
 
             // Parse elif conditions
-            let elif_regex = Regex::new(r"<elif>\s*\(([^)]+)\)")?;
+            let elif_regex = Regex::new(r"<elif>\s*\(((?:[^()]|\([^()]*\))+)\)")?;
             let elif_conditions: Vec<String> = elif_regex
                 .captures_iter(elif_part)
                 .map(|c| c[1].to_string())
@@ -556,68 +924,101 @@ impl QuantumTranspiler {
 
             // Verify we have matching conditions and bodies
             if conditions.len() != bodies.len() {
-                println!("!! Selection error: {} conditions but {} body blocks",
-                        conditions.len(), bodies.len());
+                self.output.warn(&format!("!! Selection error: {} conditions but {} body blocks",
+                        conditions.len(), bodies.len()));
                 return Ok(());
             }
 
             return self.execute_selection_statement(conditions, bodies, class_name);
         }
 
+        // Loops may be labelled (`loop outer <> ...`) for `break outer` / `continue outer`.
+        // The patterns are anchored so a loop is not mistaken for one nested in its body.
+
         // Check for count loop - PHASE 1
         let count_loop_regex = Regex::new(
-            r"loop\s*<>\s*count\s*\(\s*([^)]+)\s*\)\s*\{([\s\S]*?)\}"
+            r"^\s*loop(?:\s+(\w+))?\s*<>\s*count\s*\(\s*([^)]+)\s*\)\s*\{([\s\S]*?)\}"
         )?;
 
         if let Some(captures) = count_loop_regex.captures(statement) {
-            let count_expr = &captures[1];
-            let body = &captures[2];
+            let label = captures.get(1).map(|m| m.as_str());
+            let count_expr = &captures[2];
+            let body = block_body(statement, &captures, 3)?;
 
-            return self.execute_count_loop(count_expr, body, class_name);
+            return self.execute_count_loop(label, count_expr, body, class_name);
         }
 
         // Check for range loop - PHASE 2
         let range_loop_regex = Regex::new(
-            r"loop\s*<>\s*range\s*\(\s*([^,]+)\s*,\s*([^)]+)\s*\)\s*as\s+(\w+)\s*\{([\s\S]*?)\}"
+            r"^\s*loop(?:\s+(\w+))?\s*<>\s*range\s*\(\s*([^,]+)\s*,\s*([^)]+)\s*\)\s*as\s+(\w+)\s*\{([\s\S]*?)\}"
         )?;
 
         if let Some(captures) = range_loop_regex.captures(statement) {
-            let start_expr = &captures[1];
-            let end_expr = &captures[2];
-            let loop_var = &captures[3];
-            let body = &captures[4];
+            let label = captures.get(1).map(|m| m.as_str());
+            let start_expr = &captures[2];
+            let end_expr = &captures[3];
+            let loop_var = &captures[4];
+            let body = block_body(statement, &captures, 5)?;
 
-            return self.execute_range_loop(start_expr, end_expr, loop_var, body, class_name);
+            return self.execute_range_loop(label, start_expr, end_expr, loop_var, body, class_name);
         }
 
         // Check for while loop - PHASE 3
         let while_loop_regex = Regex::new(
-            r"loop\s*<>\s*while\s*\(\s*([^)]+)\s*\)\s*\{([\s\S]*?)\}"
+            r"^\s*loop(?:\s+(\w+))?\s*<>\s*while\s*\(\s*((?:[^()]|\([^()]*\))+)\s*\)\s*\{([\s\S]*?)\}"
         )?;
 
         if let Some(captures) = while_loop_regex.captures(statement) {
-            let condition = &captures[1];
-            let body = &captures[2];
+            let label = captures.get(1).map(|m| m.as_str());
+            let condition = &captures[2];
+            let body = block_body(statement, &captures, 3)?;
 
-            return self.execute_while_loop(condition, body, class_name);
+            return self.execute_while_loop(label, condition, body, class_name);
         }
 
-        let speak_interpolation_regex = Regex::new(r#"speak\s*\(\s*"([^"]*)"\s*\)"#)?;
+        let speak_interpolation_regex = Regex::new(&format!(r"speak\s*\(\s*{}\s*\)", lexer::STRING_LITERAL))?;
         if let Some(captures) = speak_interpolation_regex.captures(statement) {
-            let message = &captures[1];
-            let interpolated = self.interpolate_string(message)?;
-            println!("{}", interpolated);
+            let message = lexer::unescape(&captures[1])?;
+            let interpolated = self.interpolate_string(&message)?;
+            self.output.program(&interpolated);
             return Ok(());
         }
         
         
-        let user_input_regex = Regex::new(r#"(\w+)\s*<>\s*userIn\s*\(\s*"([^"]*)"\s*\)"#)?;
+        let file_read_regex = Regex::new(r"^\s*(\w+)\s*<>\s*(readFile|readLines|readCsv)\s*\(\s*(.+?)\s*\)\s*$")?;
+        if let Some(captures) = file_read_regex.captures(statement) {
+            return self.execute_file_read(&captures[1], &captures[2], &captures[3]);
+        }
+
+        let file_write_regex = Regex::new(r"^\s*writeFile\s*\(\s*([^,]+?)\s*,\s*(.+?)\s*\)\s*$")?;
+        if let Some(captures) = file_write_regex.captures(statement) {
+            let path = self.resolve_text_argument(&captures[1])?;
+            let contents = self.resolve_text_argument(&captures[2])?;
+            self.fs.write_file(&path, &contents)?;
+            self.output.debug(&format!("++ Wrote {} bytes to {}", contents.len(), path));
+            return Ok(());
+        }
+
+        let exit_regex = Regex::new(r"^\s*exit\s*\(\s*(.+?)\s*\)\s*$")?;
+        if let Some(captures) = exit_regex.captures(statement) {
+            let code = self.resolve_exit_code(&captures[1])?;
+            return Err(ProgramExit { code }.into());
+        }
+
+        let user_input_regex = Regex::new(&format!(r"(\w+)\s*<>\s*userIn\s*\(\s*{}\s*\)", lexer::STRING_LITERAL))?;
         if let Some(captures) = user_input_regex.captures(statement) {
             let var_name = &captures[1];
-            let prompt = &captures[2];
-            return self.execute_user_input_assignment(var_name, prompt);
+            let prompt = lexer::unescape(&captures[2])?;
+            return self.execute_user_input_assignment(var_name, &prompt);
         }
         
+        let native_call_regex = Regex::new(r"^\s*(\w+)\s*<>\s*(.+)$")?;
+        if let Some(captures) = native_call_regex.captures(statement) {
+            if self.calls_native(&captures[2])? {
+                return self.execute_native_assignment(&captures[1], captures[2].trim());
+            }
+        }
+
         let var_function_regex = Regex::new(r"(\w+)\s*<>\s*(\w+)\s*\(\s*\)")?;
         if let Some(captures) = var_function_regex.captures(statement) {
             let var_name = &captures[1];
@@ -632,12 +1033,16 @@ impl QuantumTranspiler {
             return self.execute_variable_assignment(var_name, expression, class_name);
         }
         
-        let target_math_regex = Regex::new(r"(\w+)\s*\(\s*\[\s*([^\]]+)\s*\]\s*\)\s*<>\s*randomChoice\s*\(\s*\[\s*([^\]]*)\s*\]\s*\)")?;
+        let target_math_regex = Regex::new(r"(\w+)\s*\(\s*\[\s*([^\]]+)\s*\]\s*\)\s*<>\s*(randomChoice|allChoices)\s*\(\s*\[\s*([^\]]*)\s*\]\s*((?:,\s*\w+\s*:\s*(?:\[[^\]]*\]|[\w+]+)\s*)*)\)")?;
         if let Some(captures) = target_math_regex.captures(statement) {
             let var_name = &captures[1];
             let target_str = &captures[2];
-            let inputs_str = &captures[3];
-            return self.solve_target_math(var_name, target_str, inputs_str, class_name);
+            let inputs_str = &captures[4];
+            let options = captures[5].parse()?;
+            if &captures[3] == "allChoices" {
+                return self.list_target_choices(var_name, target_str, inputs_str, options);
+            }
+            return self.solve_target_math(var_name, target_str, inputs_str, options, class_name);
         }
         
         let poly_synthesis_regex = Regex::new(r"(\w+)\s*\(\s*([^)]*)\s*\)\s*<>\s*function\s*\(\s*(\w+)\s*\)")?;
@@ -660,19 +1065,15 @@ impl QuantumTranspiler {
         let woof_regex = Regex::new(r"woof\s+(\w+)")?;
         if let Some(captures) = woof_regex.captures(statement) {
             let var_name = &captures[1];
-            return self.output_variable(var_name);
+            return self.output_variable(var_name, class_name);
         }
         
         Ok(())
     }
     
     fn execute_user_input_assignment(&mut self, var_name: &str, prompt: &str) -> Result<()> {
-        print!("{}: ", prompt);
-        io::stdout().flush()?;
-
-        let mut input = String::new();
-        io::stdin().read_line(&mut input)?;
-        let input = input.trim();
+        let input = self.input.read_line(prompt)?;
+        let input = input.as_str();
 
 
         let value = if let Ok(num) = input.parse::<f64>() {
@@ -690,29 +1091,88 @@ impl QuantumTranspiler {
         Ok(())
     }
     
+    fn execute_file_read(&mut self, var_name: &str, builtin: &str, argument: &str) -> Result<()> {
+        let path = self.resolve_text_argument(argument)?;
+        let value = match builtin {
+            "readFile" => self.fs.read_file(&path)?,
+            "readLines" => self.fs.read_lines(&path)?,
+            _ => self.fs.read_csv(&path)?,
+        };
+        if let VariableValue::List(items) = &value {
+            self.budget.check_list(items.len())?;
+        }
+
+        self.variable_manager.store_variable(
+            var_name,
+            value,
+            Some(format!("{}(\"{}\")", builtin, path)),
+        )?;
+
+        Ok(())
+    }
+
+    /// A builtin's string argument: a quoted literal (interpolated) or a variable
+    fn resolve_text_argument(&self, argument: &str) -> Result<String> {
+        let argument = argument.trim();
+        if let Some(literal) = argument.strip_prefix('"').and_then(|rest| rest.strip_suffix('"')) {
+            return self.interpolate_string(&lexer::unescape(literal)?);
+        }
+
+        match self.variable_manager.get_variable(argument) {
+            Some(variable) => Ok(display_value(&variable.value)),
+            None => Err(anyhow::anyhow!("Expected a quoted string or a variable, found '{}'", argument)),
+        }
+    }
+
     fn interpolate_string(&self, message: &str) -> Result<String> {
-        let var_regex = Regex::new(r"~(\w+)~")?;
-        let mut result = message.to_string();
-        
-        for captures in var_regex.captures_iter(message) {
-            let var_name = &captures[1];
-            let placeholder = &captures[0];
-            
-            if let Some(variable) = self.variable_manager.get_variable(var_name) {
-                let value_str = match &variable.value {
-                    VariableValue::Number(n) => n.to_string(),
-                    VariableValue::String(s) => s.clone(),
-                    VariableValue::Boolean(b) => b.to_string(),
-                    VariableValue::FunctionResult(f) => format!("[Function: {}]", f),
-                };
-                result = result.replace(placeholder, &value_str);
-            } else {
-                result = result.replace(placeholder, &format!("[undefined: {}]", var_name));
+        let mut result = String::new();
+
+        for part in codegen::split_template(message) {
+            match part {
+                TemplatePart::Text(text) => result.push_str(&text),
+                // In both cases the warning is the diagnostic; the text gets nothing
+                TemplatePart::Variable(name) => match self.variable_manager.get_variable(&name) {
+                    Some(variable) => result.push_str(&display_value(&variable.value)),
+                    None => self.output.warn(&format!("!! Undefined variable '{}' in speak", name)),
+                },
+                TemplatePart::Expression { expression, spec } => {
+                    match self.format_placeholder(&expression, spec.as_deref()) {
+                        Ok(text) => result.push_str(&text),
+                        Err(e) => self.output.warn(&format!("!! Cannot interpolate '{}': {}", expression, e)),
+                    }
+                }
             }
         }
-        
+
         Ok(result)
     }
+
+    /// Value of a `~expression:spec~` placeholder, evaluated like a condition
+    fn format_placeholder(&self, expression: &str, spec: Option<&str>) -> Result<String> {
+        let spec = spec.map(FormatSpec::parse).transpose()?.unwrap_or_default();
+        let value = match self.variable_manager.get_variable(expression) {
+            Some(variable) => variable.value.clone(),
+            None => self.condition_evaluator.evaluate_value(expression, &self.variable_manager.get_all_variables())?,
+        };
+        spec.apply(&value)
+    }
+
+    /// Whether `expression` calls a registered native function
+    fn calls_native(&self, expression: &str) -> Result<bool> {
+        if self.natives.is_empty() {
+            return Ok(false);
+        }
+        let call_regex = Regex::new(r"(\w+)\s*\(")?;
+        let calls_native = call_regex.captures_iter(expression).any(|c| self.natives.contains(&c[1]));
+        Ok(calls_native)
+    }
+
+    fn execute_native_assignment(&mut self, var_name: &str, expression: &str) -> Result<()> {
+        let value = self.condition_evaluator
+            .evaluate_value(expression, &self.variable_manager.get_all_variables())?;
+
+        self.variable_manager.store_variable(var_name, value, Some(expression.to_string()))
+    }
     
     fn execute_function_call_assignment(&mut self, var_name: &str, function_name: &str, _class_name: &str) -> Result<()> {
         if let Some(_function_result) = self.cache.function_results.get(function_name) {
@@ -725,7 +1185,7 @@ impl QuantumTranspiler {
             )?;
 
         } else {
-            println!("!! Function {} not found", function_name);
+            self.output.warn(&format!("!! Function {} not found", function_name));
         }
 
         Ok(())
@@ -751,10 +1211,10 @@ impl QuantumTranspiler {
                     
                     if let Some(variable) = self.variable_manager.get_variable(return_var) {
                         function_return_value = variable.value.clone();
-                        println!("-- Function {} returning: {:?}", function_name, function_return_value);
+                        self.output.debug(&format!("-- Function {} returning: {:?}", function_name, function_return_value));
                         break;
                     } else {
-                        println!("!! Return variable '{}' not found in function {}", return_var, function_name);
+                        self.output.warn(&format!("!! Return variable '{}' not found in function {}", return_var, function_name));
                     }
                 } else {
                     
@@ -788,11 +1248,11 @@ impl QuantumTranspiler {
                         if let VariableValue::Number(n) = variable.value {
                             resolved_params.push(n);
                         } else {
-                            println!("!! Variable '{}' is not numeric", param);
+                            self.output.warn(&format!("!! Variable '{}' is not numeric", param));
                             return Ok(());
                         }
                     } else {
-                        println!("!! Could not resolve parameter: {}", param);
+                        self.output.warn(&format!("!! Could not resolve parameter: {}", param));
                         return Ok(());
                     }
                 }
@@ -812,7 +1272,7 @@ impl QuantumTranspiler {
                 )?;
 
             } else {
-                println!("!! calc() requires at least 2 parameters");
+                self.output.warn("!! calc() requires at least 2 parameters");
             }
         } else if expression.starts_with("randomChoice(") {
 
@@ -820,6 +1280,7 @@ impl QuantumTranspiler {
             if let Some(captures) = choice_regex.captures(expression) {
                 let choices_str = &captures[1];
                 let choice_parts: Vec<&str> = choices_str.split(',').map(|s| s.trim()).collect();
+                self.budget.check_list(choice_parts.len())?;
 
                 let mut resolved_choices = Vec::new();
 
@@ -832,14 +1293,12 @@ impl QuantumTranspiler {
                         resolved_choices.push(variable.value.clone());
                     } else {
 
-                        resolved_choices.push(VariableValue::String(choice.trim_matches('"').to_string()));
+                        resolved_choices.push(VariableValue::String(lexer::text_literal(choice)));
                     }
                 }
 
                 if !resolved_choices.is_empty() {
-                    use rand::Rng;
-                    let mut rng = rand::thread_rng();
-                    let chosen = &resolved_choices[rng.gen_range(0..resolved_choices.len())];
+                    let chosen = &resolved_choices[self.rng.gen_range(0..resolved_choices.len())];
 
                     self.variable_manager.store_variable(
                         var_name,
@@ -848,6 +1307,8 @@ impl QuantumTranspiler {
                     )?;
                 }
             }
+        } else if let Some(preset) = self.presets.get(var_name) {
+            self.output.debug(&format!("-- Keeping preset {} = {}", var_name, display_value(preset)));
         } else {
 
             let value = if let Ok(num) = expression.parse::<f64>() {
@@ -855,7 +1316,7 @@ impl QuantumTranspiler {
             } else if expression == "true" || expression == "false" {
                 VariableValue::Boolean(expression == "true")
             } else {
-                VariableValue::String(expression.trim_matches('"').to_string())
+                VariableValue::String(lexer::text_literal(expression))
             };
 
             self.variable_manager.store_variable(var_name, value, None)?;
@@ -864,78 +1325,179 @@ impl QuantumTranspiler {
         Ok(())
     }
     
-    fn output_variable(&self, var_name: &str) -> Result<()> {
+    fn output_variable(&mut self, var_name: &str, class_name: &str) -> Result<()> {
         if let Some(variable) = self.variable_manager.get_variable(var_name) {
             match &variable.value {
-                VariableValue::Number(n) => self.emit(format!("Final result: {}", n), "success"),
-                VariableValue::String(s) => self.emit(format!("Final result: {}", s), "success"),
-                VariableValue::Boolean(b) => self.emit(format!("Final result: {}", b), "success"),
-                VariableValue::FunctionResult(f) => self.emit(format!("Final result: [Function: {}]", f), "success"),
+                VariableValue::Number(n) => self.output.program(&format!("Final result: {}", n)),
+                VariableValue::String(s) => self.output.program(&format!("Final result: {}", s)),
+                VariableValue::Boolean(b) => self.output.program(&format!("Final result: {}", b)),
+                VariableValue::FunctionResult(f) => self.output.program(&format!("Final result: [Function: {}]", f)),
+                value => self.output.program(&format!("Final result: {}", display_value(value))),
             }
 
             if let Some(eq) = &variable.source_equation {
-                self.emit(format!("   Source: {}", eq), "info");
+                self.output.info(&format!("   Source: {}", eq));
+            }
+
+            if class_name == self.current_class_name {
+                let accuracy = variable.source_equation.as_ref().and_then(|equation| {
+                    self.math_engine.get_solutions().into_values()
+                        .find(|solution| &solution.equation == equation
+                            && variable.value == solution.value())
+                        .map(|solution| solution.accuracy)
+                });
+                self.result = Some(ProgramResult {
+                    variable: var_name.to_string(),
+                    value: variable.value.clone(),
+                    source_equation: variable.source_equation.clone(),
+                    accuracy,
+                });
             }
         } else {
-            self.emit(format!("!! Variable '{}' not found", var_name), "error");
+            self.output.warn(&format!("!! Variable '{}' not found", var_name));
         }
         Ok(())
     }
     
-    fn solve_target_math(&mut self, var_name: &str, target_str: &str, inputs_str: &str, class_name: &str) -> Result<()> {
-        
-        let target: f64 = if let Ok(num) = target_str.parse::<f64>() {
-            num
-        } else if let Some(variable) = self.variable_manager.get_variable(target_str) {
-            match &variable.value {
-                VariableValue::Number(n) => {
-                    self.emit(format!("-- Resolved target variable '{}' = {}", target_str, n), "info");
-                    *n
-                },
-                _ => {
-                    self.emit(format!("!! Target variable '{}' is not numeric", target_str), "error");
-                    return Ok(());
-                }
-            }
-        } else {
-            self.emit(format!("!! Could not resolve target: {}", target_str), "error");
+    fn solve_target_math(&mut self, var_name: &str, target_str: &str, inputs_str: &str, options: SolveOptions, class_name: &str) -> Result<()> {
+        let Some(target) = self.resolve_solve_target(target_str) else {
             return Ok(());
         };
         
-        let inputs = self.variable_manager.resolve_expression_inputs_with_target(inputs_str, Some(target));
-        
-        self.emit(format!(">> Target-seeking quantum mathematics for variable '{}': target={}, inputs={:?}",
-                var_name, target, inputs), "info");
+        let target_hint = match &target {
+            SolveTarget::Float(t) => *t,
+            SolveTarget::Integer(t) => t.to_f64(),
+        };
+        let resolved = self.variable_manager.resolve_solver_inputs(inputs_str, Some(target_hint));
+        self.budget.check_list(resolved.len())?;
+        self.budget.check_solver_candidates(EquationSolver::candidate_upper_bound(resolved.len()))?;
+        let inputs = resolved.floats();
         
-        let solution = self.math_engine.solve_target(target, &inputs, var_name, class_name)?;
+        let solution = match &target {
+            SolveTarget::Float(target) => {
+                self.output.info(&format!(">> Target-seeking quantum mathematics for variable '{}': target={}, inputs={:?}",
+                        var_name, target, inputs));
+                self.math_engine.solve_target_with(*target, &inputs, var_name, class_name, options)?
+            }
+            // Beyond 2^53 f64 rounds the target, so only an exact integer search can hit it
+            SolveTarget::Integer(target) => match resolved.integers() {
+                Some(integers) => {
+                    self.output.info(&format!(">> Target-seeking integer mathematics for variable '{}': target={}, inputs=[{}]",
+                            var_name, target, integers.iter().map(BigInt::to_string).collect::<Vec<_>>().join(", ")));
+                    self.math_engine.solve_integer_target(target, &integers, var_name, class_name, options)?
+                }
+                None => {
+                    self.output.warn(&format!("!! Target {} is too large for f64 and the inputs are not all whole numbers; solving approximately",
+                            target));
+                    self.math_engine.solve_target_with(target.to_f64(), &inputs, var_name, class_name, options)?
+                }
+            },
+        };
         
         self.variable_manager.store_variable(
             var_name,
-            VariableValue::Number(solution.result),
+            solution.value(),
             Some(solution.equation.clone()),
         )?;
         
-        self.emit(format!("== Solution found: {} = {} (accuracy: {}%)",
-                solution.equation, solution.result, solution.accuracy), "success");
-        self.emit(format!("-- Variable '{}' stored with value: {}", var_name, solution.result), "info");
+        self.output.info(&format!("== Solution found: {} = {} (accuracy: {}%)",
+                solution.equation, solution.display_result(), solution.accuracy));
+        self.output.debug(&format!("-- Variable '{}' stored with value: {}", var_name, solution.display_result()));
         
         Ok(())
     }
+
+    /// `x([t]) <> allChoices([...])`: every exact equation for `t` as a list of
+    /// `{equation, result, accuracy, cost}` records, cheapest first, or the
+    /// closest few when none is exact
+    ///
+    /// Nothing is cached; `ops: ...` applies, `rules: ...` does not.
+    fn list_target_choices(&mut self, var_name: &str, target_str: &str, inputs_str: &str, options: SolveOptions) -> Result<()> {
+        if options.rules != SolveRules::Free {
+            return Err(anyhow::anyhow!("allChoices takes `ops: ...` but not `rules: {}`", options.rules));
+        }
+        let target = match self.resolve_solve_target(target_str) {
+            Some(SolveTarget::Float(target)) => target,
+            Some(SolveTarget::Integer(target)) => {
+                self.output.warn(&format!("!! Target {} is too large for f64; listing approximately", target));
+                target.to_f64()
+            }
+            None => return Ok(()),
+        };
+
+        let inputs = self.variable_manager.resolve_expression_inputs_with_target(inputs_str, Some(target));
+        self.budget.check_list(inputs.len())?;
+        self.budget.check_solver_candidates(EquationSolver::candidate_upper_bound(inputs.len()))?;
+
+        let operators = options.operators.unwrap_or(self.operators);
+        self.output.info(&format!(">> Listing equations for variable '{}': target={}, inputs={:?}", var_name, target, inputs));
+        let mut solutions = self.math_engine.enumerate_exact_with(target, &inputs, operators)?;
+        if solutions.is_empty() {
+            self.output.warn(&format!("!! No exact equation for target {}; listing the {} closest", target, ALL_CHOICES_FALLBACK));
+            solutions = self.math_engine.solve_target_topk_with(target, &inputs, ALL_CHOICES_FALLBACK, operators)?;
+        }
+        self.budget.check_list(solutions.len())?;
+
+        let choices = solutions.iter()
+            .map(|solution| VariableValue::Record(vec![
+                ("equation".to_string(), VariableValue::String(solution.equation.clone())),
+                ("result".to_string(), VariableValue::Number(solution.result)),
+                ("accuracy".to_string(), VariableValue::Number(solution.accuracy)),
+                ("cost".to_string(), VariableValue::Number(solution.cost.unwrap_or(u32::MAX) as f64)),
+            ]))
+            .collect();
+        self.variable_manager.store_variable(
+            var_name,
+            VariableValue::List(choices),
+            Some(format!("allChoices([{}])", inputs_str)),
+        )?;
+
+        self.output.info(&format!("== Listed {} equations for '{}'", solutions.len(), var_name));
+        Ok(())
+    }
+
+    /// A solver target: a literal, or a numeric variable; warns and gives `None` otherwise
+    fn resolve_solve_target(&self, target_str: &str) -> Option<SolveTarget> {
+        let target = if let Ok(big) = target_str.parse::<BigInt>() {
+            if big.fits_f64() { SolveTarget::Float(big.to_f64()) } else { SolveTarget::Integer(big) }
+        } else if let Ok(num) = target_str.parse::<f64>() {
+            SolveTarget::Float(num)
+        } else if let Some(variable) = self.variable_manager.get_variable(target_str) {
+            match &variable.value {
+                VariableValue::Number(n) => {
+                    self.output.debug(&format!("-- Resolved target variable '{}' = {}", target_str, n));
+                    SolveTarget::Float(*n)
+                },
+                VariableValue::Integer(n) => {
+                    self.output.debug(&format!("-- Resolved target variable '{}' = {}", target_str, n));
+                    SolveTarget::Integer(n.clone())
+                },
+                _ => {
+                    self.output.warn(&format!("!! Target variable '{}' is not numeric", target_str));
+                    return None;
+                }
+            }
+        } else {
+            self.output.warn(&format!("!! Could not resolve target: {}", target_str));
+            return None;
+        };
+        Some(target)
+    }
     
     fn synthesize_polymorphic_function(&mut self, name: &str, params: &str, func_type: &str) -> Result<()> {
         let param_count = if params.trim().is_empty() { 0 } else { params.split(',').count() };
         let cache_key = format!("{}_{}_{}", name, func_type, param_count);
         
-        println!(">> Synthesizing {} function: {} (supports {} parameters)", func_type, name, param_count);
+        self.output.info(&format!(">> Synthesizing {} function: {} (supports {} parameters)", func_type, name, param_count));
         
         if let Some(template) = self.cache.templates.get(&cache_key) {
             if template.is_built {
-                println!("== Using previously built function: {}", name);
+                self.output.info(&format!("== Using previously built function: {}", name));
                 return Ok(());
             }
         }
         
-        println!(">> Generating Rust code for function: {}", name);
+        self.output.info(&format!(">> Generating Rust code for function: {}", name));
         let built_function = self.function_builder.build_function(name, func_type, param_count)?;
         
         let template = CachedTemplate {
@@ -950,20 +1512,20 @@ impl QuantumTranspiler {
         self.cache.templates.insert(cache_key, template);
         self.cache.built_functions.insert(name.to_string(), built_function);
         
-        println!("** Function successfully built and cached: {}", name);
+        self.output.info(&format!("** Function successfully built and cached: {}", name));
         Ok(())
     }
     
     fn execute_polymorphic_function(&mut self, func_name: &str, params: &str, body: &str) -> Result<()> {
         let param_list: Vec<&str> = params.split(',').map(|p| p.trim()).collect();
 
-        println!(">> Executing built function: {}({}) with {} parameters",
-                func_name, params, param_list.len());
+        self.output.info(&format!(">> Executing built function: {}({}) with {} parameters",
+                func_name, params, param_list.len()));
 
         if let Some(built_function) = self.cache.built_functions.get(func_name) {
             self.function_executor.execute_function(built_function, &param_list, body)?;
         } else {
-            println!("!! Function {} not found in built functions - needs synthesis first", func_name);
+            self.output.warn(&format!("!! Function {} not found in built functions - needs synthesis first", func_name));
         }
 
         Ok(())
@@ -978,7 +1540,7 @@ impl QuantumTranspiler {
         bodies: Vec<String>,
         class_name: &str
     ) -> Result<()> {
-        println!(">> Evaluating selection statement with {} branches", conditions.len());
+        self.output.info(&format!(">> Evaluating selection statement with {} branches", conditions.len()));
 
         // Get current variables for condition evaluation
         let variables = self.variable_manager.get_all_variables();
@@ -988,8 +1550,8 @@ impl QuantumTranspiler {
             let result = self.condition_evaluator.evaluate(condition, &variables)?;
 
             if result {
-                println!("-- Condition {} evaluated to true: {}", i, condition);
-                println!("-- Executing branch {}", i);
+                self.output.debug(&format!("-- Condition {} evaluated to true: {}", i, condition));
+                self.output.debug(&format!("-- Executing branch {}", i));
 
                 // Execute the corresponding body block
                 let body = &bodies[i];
@@ -997,12 +1559,59 @@ impl QuantumTranspiler {
 
                 return Ok(()); // Exit after first true condition
             } else {
-                println!("-- Condition {} evaluated to false: {}", i, condition);
+                self.output.debug(&format!("-- Condition {} evaluated to false: {}", i, condition));
             }
         }
 
         // If we get here, something went wrong (else should always be true)
-        println!("!! Warning: No condition matched (else should be true)");
+        self.output.warn("!! Warning: No condition matched (else should be true)");
+        Ok(())
+    }
+
+    /// Execute a `match` statement
+    ///
+    /// Arms are tried in order; the first whose pattern fits the value and
+    /// whose guard holds runs. A binding is stored before its guard is evaluated.
+    fn execute_match(&mut self, statement: &str, class_name: &str) -> Result<()> {
+        let (scrutinee, arms) = parser::split_match(statement)?;
+        let value = match self.variable_manager.get_variable(&scrutinee) {
+            Some(var) => var.value.clone(),
+            None => {
+                let variables = self.variable_manager.get_all_variables();
+                self.condition_evaluator.evaluate_value(&scrutinee, &variables)?
+            }
+        };
+        self.output.info(&format!(">> Matching {} = {} against {} arms", scrutinee, display_value(&value), arms.len()));
+
+        for (i, arm) in arms.iter().enumerate() {
+            if !arm.pattern.matches(&value) {
+                continue;
+            }
+            if let Some(guard) = &arm.guard {
+                // The guard sees the binding, but it is only stored once the arm is taken
+                let mut variables = self.variable_manager.get_all_variables();
+                if let parser::Pattern::Binding(name) = &arm.pattern {
+                    variables.insert(name.clone(), StoredVariable {
+                        name: name.clone(),
+                        value: value.clone(),
+                        timestamp: 0,
+                        source_equation: Some("match binding".to_string()),
+                    });
+                }
+                if !self.condition_evaluator.evaluate(guard, &variables)? {
+                    self.output.debug(&format!("-- Guard of arm {} evaluated to false: {}", i, guard));
+                    continue;
+                }
+            }
+            if let parser::Pattern::Binding(name) = &arm.pattern {
+                self.variable_manager.store_variable(name, value.clone(), Some("match binding".to_string()))?;
+            }
+
+            self.output.debug(&format!("-- Executing match arm {}", i));
+            return self.execute_body_block(&arm.body, class_name);
+        }
+
+        self.output.warn(&format!("!! No match arm matched {} = {}", scrutinee, display_value(&value)));
         Ok(())
     }
 
@@ -1030,11 +1639,11 @@ impl QuantumTranspiler {
                 continue;
             }
 
-            // Check if this is the start of a loop statement
-            if line.starts_with("loop") && line.contains("<>") {
+            // Check if this is the start of a loop statement or match
+            if (line.starts_with("loop") && line.contains("<>")) || parser::is_match_header(line) {
                 let mut full_statement = String::new();
                 let mut brace_count = 0;
-                let mut in_loop = false;
+                let mut in_loop = parser::is_match_header(line);
 
                 while i < lines.len() {
                     let current_line = lines[i].trim();
@@ -1044,8 +1653,7 @@ impl QuantumTranspiler {
                         continue;
                     }
 
-                    brace_count += current_line.chars().filter(|&c| c == '{').count() as i32;
-                    brace_count -= current_line.chars().filter(|&c| c == '}').count() as i32;
+                    brace_count += lexer::brace_delta(current_line);
 
                     if full_statement.is_empty() {
                         full_statement.push_str(current_line);
@@ -1069,7 +1677,18 @@ impl QuantumTranspiler {
 
                 // Check if we should exit early (continue or break)
                 if self.loop_executor.should_skip_iteration() {
-                    println!("   >> Skipping rest of iteration (continue)");
+                    self.output.debug("   >> Skipping rest of iteration (continue)");
+                    return Ok(());
+                }
+                continue;
+            }
+
+            if line.starts_with("if") && line.contains("<>") {
+                let full_statement = collect_selection(&lines, &mut i);
+                self.execute_statement(&full_statement, class_name)?;
+
+                if self.loop_executor.should_skip_iteration() {
+                    self.output.debug("   >> Skipping rest of iteration (continue)");
                     return Ok(());
                 }
                 continue;
@@ -1080,7 +1699,7 @@ impl QuantumTranspiler {
 
             // Check if we should exit early (continue or break)
             if self.loop_executor.should_skip_iteration() {
-                println!("   >> Skipping rest of iteration (continue)");
+                self.output.debug("   >> Skipping rest of iteration (continue)");
                 return Ok(());
             }
 
@@ -1135,6 +1754,7 @@ impl QuantumTranspiler {
     /// Execute a count-based loop
     fn execute_count_loop(
         &mut self,
+        label: Option<&str>,
         count_expr: &str,
         body: &str,
         class_name: &str
@@ -1149,15 +1769,15 @@ impl QuantumTranspiler {
             match &var.value {
                 VariableValue::Number(n) => {
                     if *n >= 0.0 && n.fract() == 0.0 {
-                        println!("-- Resolved count variable '{}' = {}", count_expr, n);
+                        self.output.debug(&format!("-- Resolved count variable '{}' = {}", count_expr, n));
                         *n as u32
                     } else {
-                        println!("!! Count must be a non-negative integer, got {}", n);
+                        self.output.warn(&format!("!! Count must be a non-negative integer, got {}", n));
                         return Ok(());
                     }
                 }
                 _ => {
-                    println!("!! Count variable '{}' is not numeric", count_expr);
+                    self.output.warn(&format!("!! Count variable '{}' is not numeric", count_expr));
                     return Ok(());
                 }
             }
@@ -1171,82 +1791,58 @@ impl QuantumTranspiler {
 
             match self.math_engine.solve_expression(count_expr, &var_map) {
                 Ok(result) if result >= 0.0 && result.fract() == 0.0 => {
-                    println!("-- Evaluated count expression '{}' = {}", count_expr, result);
+                    self.output.debug(&format!("-- Evaluated count expression '{}' = {}", count_expr, result));
                     result as u32
                 }
                 Ok(result) => {
-                    println!("!! Count expression result must be non-negative integer, got {}", result);
+                    self.output.warn(&format!("!! Count expression result must be non-negative integer, got {}", result));
                     return Ok(());
                 }
                 Err(e) => {
-                    println!("!! Could not resolve count expression '{}': {}", count_expr, e);
+                    self.output.warn(&format!("!! Could not resolve count expression '{}': {}", count_expr, e));
                     return Ok(());
                 }
             }
         };
 
         // Execute the loop manually to avoid borrow checker issues
-        self.loop_executor.loop_depth += 1;
+        self.loop_executor.enter_loop(label);
 
-        for i in 0..count {
-            self.loop_executor.should_continue = false;
+        for _ in 0..count {
+            self.checkpoint()?;
 
             // Execute body
             self.execute_body_block(body, class_name)?;
 
-            // Check for break
-            if self.loop_executor.should_break {
-                self.loop_executor.should_break = false;
+            // Check for break, here or aimed at an outer loop
+            if self.loop_executor.finish_iteration() == LoopControl::Exit {
                 break;
             }
         }
 
-        self.loop_executor.loop_depth -= 1;
+        self.loop_executor.exit_loop();
         Ok(())
     }
 
     /// Execute a range-based loop with iterator variable
     fn execute_range_loop(
         &mut self,
+        label: Option<&str>,
         start_expr: &str,
         end_expr: &str,
         loop_var_name: &str,
         body: &str,
         class_name: &str
     ) -> Result<()> {
-
-        // Helper to resolve expression to integer
-        let resolve_to_int = |transpiler: &mut Self, expr: &str| -> Result<i32> {
-            if let Ok(num) = expr.trim().parse::<i32>() {
-                Ok(num)
-            } else if let Some(var) = transpiler.variable_manager.get_variable(expr.trim()) {
-                match &var.value {
-                    VariableValue::Number(n) => Ok(*n as i32),
-                    _ => Err(anyhow::anyhow!("Variable '{}' is not numeric", expr))
-                }
-            } else {
-                let variables = transpiler.variable_manager.get_all_variables();
-                let mut var_map = HashMap::new();
-                for (name, stored_var) in variables {
-                    var_map.insert(name, stored_var.value);
-                }
-
-                match transpiler.math_engine.solve_expression(expr, &var_map) {
-                    Ok(result) => Ok(result as i32),
-                    Err(e) => Err(anyhow::anyhow!("Could not evaluate '{}': {}", expr, e))
-                }
-            }
-        };
-
         // Resolve start and end
-        let start = resolve_to_int(self, start_expr)?;
-        let end = resolve_to_int(self, end_expr)?;
+        let start = self.resolve_loop_bound(start_expr)?;
+        let end = self.resolve_loop_bound(end_expr)?;
 
         // Execute the loop manually to avoid borrow checker issues
-        self.loop_executor.loop_depth += 1;
+        self.loop_executor.enter_loop(label);
 
         for i in start..end {
-            self.loop_executor.should_continue = false;
+            self.checkpoint()?;
 
             // Store loop variable before executing body
             self.variable_manager.store_variable(
@@ -1258,67 +1854,236 @@ impl QuantumTranspiler {
             // Execute body
             self.execute_body_block(body, class_name)?;
 
-            // Check for break
-            if self.loop_executor.should_break {
-                self.loop_executor.should_break = false;
+            // Check for break, here or aimed at an outer loop
+            if self.loop_executor.finish_iteration() == LoopControl::Exit {
                 break;
             }
         }
 
-        self.loop_executor.loop_depth -= 1;
+        self.loop_executor.exit_loop();
         Ok(())
     }
 
+    /// Resolve a range bound: a literal, a numeric variable or an expression
+    fn resolve_loop_bound(&mut self, expr: &str) -> Result<i32> {
+        if let Ok(num) = expr.trim().parse::<i32>() {
+            Ok(num)
+        } else {
+            self.resolve_number(expr).map(|n| n as i32)
+        }
+    }
+
+    /// `exit(code)`'s status: a whole number in `i32` range, never truncated or clamped
+    fn resolve_exit_code(&mut self, expr: &str) -> Result<i32> {
+        let code = match self.variable_manager.get_variable(expr.trim()).map(|var| &var.value) {
+            Some(VariableValue::Integer(n)) => n.to_f64(),
+            _ => self.resolve_number(expr)?,
+        };
+        if code.fract() != 0.0 || code < i32::MIN as f64 || code > i32::MAX as f64 {
+            return Err(anyhow::anyhow!("exit({}) needs a whole number in i32 range, got {}", expr, code));
+        }
+        Ok(code as i32)
+    }
+
+    /// A literal, numeric variable or expression as f64
+    fn resolve_number(&mut self, expr: &str) -> Result<f64> {
+        if let Ok(num) = expr.trim().parse::<f64>() {
+            Ok(num)
+        } else if let Some(var) = self.variable_manager.get_variable(expr.trim()) {
+            match &var.value {
+                VariableValue::Number(n) => Ok(*n),
+                _ => Err(anyhow::anyhow!("Variable '{}' is not numeric", expr))
+            }
+        } else {
+            let variables = self.variable_manager.get_all_variables();
+            let mut var_map = HashMap::new();
+            for (name, stored_var) in variables {
+                var_map.insert(name, stored_var.value);
+            }
+
+            match self.math_engine.solve_expression(expr, &var_map) {
+                Ok(result) => Ok(result),
+                Err(e) => Err(anyhow::anyhow!("Could not evaluate '{}': {}", expr, e))
+            }
+        }
+    }
+
     /// Execute a while loop with condition
+    ///
+    /// Each pass is a statement, so `Limits::max_statements`, the timeout and
+    /// Stop end a runaway loop; `Limits::max_while_iterations` caps it on its own.
     fn execute_while_loop(
         &mut self,
+        label: Option<&str>,
         condition: &str,
         body: &str,
         class_name: &str
     ) -> Result<()> {
-        // Safety limit to prevent infinite loops
-        const MAX_ITERATIONS: u32 = 10000;
-
         // Clone strings for closures
         let condition_str = condition.to_string();
         let body_str = body.to_string();
         let class_name_str = class_name.to_string();
 
-        // We need to handle the borrow checker carefully here
-        // Store whether we should continue in a variable outside the closures
-        let mut should_continue = true;
-        let mut iteration_count = 0;
+        self.loop_executor.enter_loop(label);
+
+        let mut iterations: u64 = 0;
+        loop {
+            self.checkpoint()?;
 
-        while should_continue && iteration_count < MAX_ITERATIONS {
             // Check condition
             let variables = self.variable_manager.get_all_variables();
             let condition_result = self.condition_evaluator.evaluate(&condition_str, &variables)?;
 
             if !condition_result {
-                should_continue = false;
                 break;
             }
 
+            iterations += 1;
+            self.budget.check_while_iterations(iterations)?;
+
             // Execute body
-            if !self.loop_executor.should_skip_iteration() {
-                self.execute_body_block(&body_str, &class_name_str)?;
-            }
+            self.execute_body_block(&body_str, &class_name_str)?;
 
-            // Check for break
-            if self.loop_executor.should_break {
-                self.loop_executor.should_break = false;
+            // Check for break, here or aimed at an outer loop
+            if self.loop_executor.finish_iteration() == LoopControl::Exit {
                 break;
             }
+        }
+        self.loop_executor.exit_loop();
+
+        Ok(())
+    }
+}
+
+/// The whole body of a block whose pattern captured group `group` from just after its `{`
+///
+/// The patterns stop at the first `}`; this follows the braces to the matching one.
+fn block_body<'a>(statement: &'a str, captures: &regex::Captures, group: usize) -> Result<&'a str> {
+    let open = captures.get(group).map_or(0, |m| m.start().saturating_sub(1));
+    parser::block_contents(statement, open)
+}
 
-            // Reset continue flag
-            self.loop_executor.should_continue = false;
-            iteration_count += 1;
+/// Join a selection starting at `lines[*i]` into the one statement `execute_selection` reads,
+/// leaving `*i` on the line after its closing brace
+fn collect_selection(lines: &[&str], i: &mut usize) -> String {
+    let mut full_statement = String::new();
+    let mut brace_count = 0;
+    let mut in_selection = false;
+    let mut first_line = true;
+
+    while *i < lines.len() {
+        let current_line = lines[*i].trim();
+
+        if current_line.is_empty() {
+            *i += 1;
+            continue;
+        }
+
+        // Track braces BEFORE adding to statement
+        let has_open_brace = current_line.contains('{');
+        brace_count += lexer::brace_delta(current_line);
+
+        // Add line to statement, preserving structure
+        if first_line {
+            // First line: "if <> (...) ... {"
+            full_statement.push_str(current_line);
+            first_line = false;
+        } else if has_open_brace {
+            // Line with opening brace
+            full_statement.push(' ');
+            full_statement.push_str(current_line);
+        } else if current_line == "<>" {
+            // Delimiter line - preserve with newline
+            full_statement.push('\n');
+            full_statement.push_str(current_line);
+            full_statement.push('\n');
+        } else if current_line.starts_with("<elif>") || current_line.starts_with("<else>") {
+            // Condition lines
+            full_statement.push(' ');
+            full_statement.push_str(current_line);
+        } else {
+            // Regular statement line
+            full_statement.push('\n');
+            full_statement.push_str(current_line);
+        }
+
+        if brace_count > 0 {
+            in_selection = true;
+        }
+
+        *i += 1;
+
+        // Break when we've closed all braces
+        if in_selection && brace_count == 0 {
+            break;
         }
+    }
+
+    full_statement
+}
+
+/// How many approximations `allChoices` lists when no equation is exact
+const ALL_CHOICES_FALLBACK: usize = 5;
+
+/// A `solve` target: f64 when it holds the value exactly, a big integer past 2^53
+enum SolveTarget {
+    Float(f64),
+    Integer(BigInt),
+}
 
-        if iteration_count >= MAX_ITERATIONS {
-            println!("!! While loop hit max iterations ({})", MAX_ITERATIONS);
+/// How a value is shown in `speak` interpolation
+fn display_value(value: &VariableValue) -> String {
+    match value {
+        VariableValue::Number(n) => n.to_string(),
+        VariableValue::Integer(n) => n.to_string(),
+        VariableValue::String(s) => s.clone(),
+        VariableValue::Boolean(b) => b.to_string(),
+        VariableValue::FunctionResult(f) => format!("[Function: {}]", f),
+        VariableValue::List(items) => {
+            let items: Vec<String> = items.iter().map(display_value).collect();
+            format!("[{}]", items.join(", "))
+        }
+        VariableValue::Record(fields) => {
+            let fields: Vec<String> = fields.iter()
+                .map(|(name, value)| format!("{}: {}", name, display_value(value)))
+                .collect();
+            format!("{{{}}}", fields.join(", "))
         }
+    }
+}
+
+impl bytecode::VmHost for QuantumTranspiler {
+    fn output(&self) -> SharedSink {
+        self.output.clone()
+    }
+
+    fn load_variable(&self, name: &str) -> Option<VariableValue> {
+        self.variable_manager.get_variable_value(name).cloned()
+    }
 
+    fn store_variable(&mut self, name: &str, value: VariableValue) -> Result<()> {
+        self.variable_manager.store_variable(name, value, None)?;
+        self.budget.check_variables(self.variable_manager.len())?;
         Ok(())
     }
-}
\ No newline at end of file
+
+    fn execute_statement(&mut self, statement: &str, class_name: &str) -> Result<()> {
+        QuantumTranspiler::execute_statement(self, statement, class_name)
+    }
+
+    fn step(&mut self) -> Result<()> {
+        self.checkpoint()
+    }
+
+    fn check_list_len(&self, len: usize) -> Result<()> {
+        Ok(self.budget.check_list(len)?)
+    }
+
+    fn check_while_iterations(&self, iterations: u64) -> Result<()> {
+        Ok(self.budget.check_while_iterations(iterations)?)
+    }
+
+    fn random_index(&mut self, len: usize) -> usize {
+        self.rng.gen_range(0..len)
+    }
+}
@@ -0,0 +1,377 @@
+// Resource limits for a single run
+// Guards against runaway loops, slow solves and unbounded growth in untrusted programs
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Passes one `loop <> while` may make unless a caller raises it
+pub const DEFAULT_MAX_WHILE_ITERATIONS: u64 = 10_000;
+
+/// Per-run resource limits; `None` means unlimited
+///
+/// Everything is unlimited by default except `max_while_iterations`, so a
+/// `while(true)` ends with an error instead of spinning forever.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Limits {
+    /// Statements (and loop iterations) executed in one run
+    pub max_statements: Option<u64>,
+    /// Passes through the body of any one `loop <> while`
+    pub max_while_iterations: Option<u64>,
+    /// Wall-clock time for one run, in milliseconds
+    pub timeout_ms: Option<u64>,
+    /// Variables alive at once
+    pub max_variables: Option<usize>,
+    /// Entries in a `randomChoice([...])` list or solver input list
+    pub max_list_len: Option<usize>,
+    /// Candidate equations the solver may generate for one target: the fixed
+    /// shapes up front, then each tree the search evaluates
+    pub max_solver_candidates: Option<usize>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_statements: None,
+            max_while_iterations: Some(DEFAULT_MAX_WHILE_ITERATIONS),
+            timeout_ms: None,
+            max_variables: None,
+            max_list_len: None,
+            max_solver_candidates: None,
+        }
+    }
+}
+
+impl Limits {
+    /// No limits at all, not even on `while` loops
+    pub fn unlimited() -> Self {
+        Self { max_while_iterations: None, ..Self::default() }
+    }
+}
+
+/// The error a run stops with when it hits one of its `Limits`
+///
+/// Returned inside `anyhow::Error`; use `error.downcast_ref::<LimitExceeded>()`
+/// to tell limits apart from ordinary failures.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LimitExceeded {
+    Statements { limit: u64 },
+    WhileIterations { limit: u64 },
+    Timeout { limit: Duration },
+    Variables { limit: usize, count: usize },
+    ListSize { limit: usize, len: usize },
+    SolverCandidates { limit: usize, candidates: usize },
+}
+
+impl LimitExceeded {
+    /// Short machine-readable name of the limit
+    pub fn kind(&self) -> &'static str {
+        match self {
+            LimitExceeded::Statements { .. } => "statements",
+            LimitExceeded::WhileIterations { .. } => "while_iterations",
+            LimitExceeded::Timeout { .. } => "timeout",
+            LimitExceeded::Variables { .. } => "variables",
+            LimitExceeded::ListSize { .. } => "list_size",
+            LimitExceeded::SolverCandidates { .. } => "solver_candidates",
+        }
+    }
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LimitExceeded::Statements { limit } =>
+                write!(f, "Statement budget of {} exceeded", limit),
+            LimitExceeded::WhileIterations { limit } =>
+                write!(f, "While loop ran past its limit of {} iterations", limit),
+            LimitExceeded::Timeout { limit } =>
+                write!(f, "Run exceeded its {} ms deadline", limit.as_millis()),
+            LimitExceeded::Variables { limit, count } =>
+                write!(f, "{} variables exceed the limit of {}", count, limit),
+            LimitExceeded::ListSize { limit, len } =>
+                write!(f, "List of {} entries exceeds the limit of {}", len, limit),
+            LimitExceeded::SolverCandidates { limit, candidates } =>
+                write!(f, "Solver would try {} candidates, limit is {}", candidates, limit),
+        }
+    }
+}
+
+impl std::error::Error for LimitExceeded {}
+
+/// The run's deadline and candidate limit, checked from inside one solve
+///
+/// `Budget::step` only runs between statements, so a long search polls this
+/// as it evaluates candidates.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SolveLimits {
+    deadline: Option<(Instant, Duration)>,
+    max_candidates: Option<usize>,
+}
+
+impl SolveLimits {
+    /// Fail once the deadline has passed or `candidates` is over the limit
+    pub fn check(&self, candidates: usize) -> Result<(), LimitExceeded> {
+        self.check_candidates(candidates)?;
+        self.check_deadline()
+    }
+
+    pub fn check_candidates(&self, candidates: usize) -> Result<(), LimitExceeded> {
+        match self.max_candidates {
+            Some(limit) if candidates > limit => Err(LimitExceeded::SolverCandidates { limit, candidates }),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn check_deadline(&self) -> Result<(), LimitExceeded> {
+        match self.deadline {
+            Some((deadline, limit)) if Instant::now() > deadline => Err(LimitExceeded::Timeout { limit }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Usage so far in the current run, checked against the limits
+///
+/// Parallel frames run on a clone and hand their usage back with `absorb`.
+#[derive(Clone)]
+pub(crate) struct Budget {
+    limits: Limits,
+    statements: u64,
+    deadline: Option<Instant>,
+}
+
+impl Budget {
+    pub fn new(limits: Limits) -> Self {
+        Self { limits, statements: 0, deadline: None }
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    /// Reset usage at the start of a run
+    pub fn start(&mut self) {
+        self.statements = 0;
+        self.deadline = self.limits.timeout_ms.map(|ms| Instant::now() + Duration::from_millis(ms));
+    }
+
+    /// Account for one statement or loop iteration
+    pub fn step(&mut self) -> Result<(), LimitExceeded> {
+        self.statements += 1;
+        if let Some(limit) = self.limits.max_statements {
+            if self.statements > limit {
+                return Err(LimitExceeded::Statements { limit });
+            }
+        }
+        if let Some(deadline) = self.deadline {
+            if Instant::now() > deadline {
+                let limit = Duration::from_millis(self.limits.timeout_ms.unwrap_or_default());
+                return Err(LimitExceeded::Timeout { limit });
+            }
+        }
+        Ok(())
+    }
+
+    /// Fail early when `count` more steps would pass the statement limit
+    pub fn check_steps_ahead(&self, count: u64) -> Result<(), LimitExceeded> {
+        match self.limits.max_statements {
+            Some(limit) if self.statements.saturating_add(count) > limit => Err(LimitExceeded::Statements { limit }),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn statements(&self) -> u64 {
+        self.statements
+    }
+
+    /// Add the statements a clone taken at `fork_point` ran, then check the total
+    pub fn absorb(&mut self, fork_point: u64, fork: &Budget) -> Result<(), LimitExceeded> {
+        self.statements += fork.statements.saturating_sub(fork_point);
+        match self.limits.max_statements {
+            Some(limit) if self.statements > limit => Err(LimitExceeded::Statements { limit }),
+            _ => Ok(()),
+        }
+    }
+
+    /// What a solve started now must stay within
+    pub fn solve_limits(&self) -> SolveLimits {
+        SolveLimits {
+            deadline: self.deadline.map(|deadline| (deadline, Duration::from_millis(self.limits.timeout_ms.unwrap_or_default()))),
+            max_candidates: self.limits.max_solver_candidates,
+        }
+    }
+
+    /// Fail once one `while` loop is about to start pass number `iterations`
+    pub fn check_while_iterations(&self, iterations: u64) -> Result<(), LimitExceeded> {
+        match self.limits.max_while_iterations {
+            Some(limit) if iterations > limit => Err(LimitExceeded::WhileIterations { limit }),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn check_variables(&self, count: usize) -> Result<(), LimitExceeded> {
+        match self.limits.max_variables {
+            Some(limit) if count > limit => Err(LimitExceeded::Variables { limit, count }),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn check_list(&self, len: usize) -> Result<(), LimitExceeded> {
+        match self.limits.max_list_len {
+            Some(limit) if len > limit => Err(LimitExceeded::ListSize { limit, len }),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn check_solver_candidates(&self, candidates: usize) -> Result<(), LimitExceeded> {
+        match self.limits.max_solver_candidates {
+            Some(limit) if candidates > limit => Err(LimitExceeded::SolverCandidates { limit, candidates }),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{both_backends, Engine};
+
+    #[test]
+    fn test_statement_budget() {
+        let mut budget = Budget::new(Limits { max_statements: Some(2), ..Default::default() });
+        budget.start();
+        assert!(budget.step().is_ok());
+        assert!(budget.step().is_ok());
+        assert_eq!(budget.step(), Err(LimitExceeded::Statements { limit: 2 }));
+
+        budget.start();
+        assert!(budget.step().is_ok());
+        assert!(budget.check_steps_ahead(1).is_ok());
+        assert_eq!(budget.check_steps_ahead(2), Err(LimitExceeded::Statements { limit: 2 }));
+        assert!(Budget::new(Limits::default()).check_steps_ahead(u64::MAX).is_ok());
+    }
+
+    #[test]
+    fn test_solve_limits_carry_the_deadline_and_candidate_cap() {
+        let mut budget = Budget::new(Limits { timeout_ms: Some(0), max_solver_candidates: Some(10), ..Default::default() });
+        assert!(budget.solve_limits().check(usize::MAX).is_err());
+
+        budget.start();
+        std::thread::sleep(Duration::from_millis(2));
+        let limits = budget.solve_limits();
+        assert_eq!(limits.check(11).unwrap_err().kind(), "solver_candidates");
+        assert_eq!(limits.check(10).unwrap_err().kind(), "timeout");
+        assert!(SolveLimits::default().check(usize::MAX).is_ok());
+    }
+
+    #[test]
+    fn test_size_limits_have_distinct_kinds() {
+        let budget = Budget::new(Limits {
+            max_variables: Some(1),
+            max_list_len: Some(3),
+            max_solver_candidates: Some(10),
+            ..Default::default()
+        });
+
+        assert_eq!(budget.check_variables(2).unwrap_err().kind(), "variables");
+        assert_eq!(budget.check_list(4).unwrap_err().kind(), "list_size");
+        assert_eq!(budget.check_solver_candidates(11).unwrap_err().kind(), "solver_candidates");
+        assert!(budget.check_list(3).is_ok());
+    }
+
+    #[test]
+    fn test_limits_read_camel_case_and_default_to_a_while_cap() {
+        let limits: Limits = serde_json::from_str(r#"{"maxStatements": 100, "timeoutMs": 250}"#).unwrap();
+        assert_eq!(limits, Limits { max_statements: Some(100), timeout_ms: Some(250), ..Default::default() });
+        assert_eq!(serde_json::from_str::<Limits>("{}").unwrap(), Limits::default());
+        assert_eq!(serde_json::from_str::<Limits>(r#"{"maxWhileIterations": null}"#).unwrap(), Limits::unlimited());
+
+        let mut budget = Budget::new(Limits::default());
+        budget.start();
+        assert!((0..10_000).all(|_| budget.step().is_ok()));
+        assert!(budget.check_variables(usize::MAX).is_ok() && budget.check_list(usize::MAX).is_ok());
+        assert!(budget.check_while_iterations(DEFAULT_MAX_WHILE_ITERATIONS).is_ok());
+        assert_eq!(
+            budget.check_while_iterations(DEFAULT_MAX_WHILE_ITERATIONS + 1),
+            Err(LimitExceeded::WhileIterations { limit: DEFAULT_MAX_WHILE_ITERATIONS })
+        );
+        assert!(Budget::new(Limits::unlimited()).check_while_iterations(u64::MAX).is_ok());
+        assert_eq!(LimitExceeded::Statements { limit: 5 }.to_string(), "Statement budget of 5 exceeded");
+    }
+
+    #[test]
+    fn test_limits_stop_runs_with_distinct_errors() {
+        let looping = r#"* <main> Spin {
+    ^ observe_execution {
+        loop <> count(100000) {
+            speak("tick")
+        }
+        speak("done")
+    }
+}"#;
+        let limits = Limits { max_statements: Some(50), ..Default::default() };
+        for mut engine in both_backends(|builder| builder.limits(limits)) {
+            let error = engine.run_source(looping).unwrap_err();
+            assert_eq!(error.downcast_ref::<LimitExceeded>(), Some(&LimitExceeded::Statements { limit: 50 }));
+
+            // The statement budget ends a `while` before its own cap does, with an error, not a warning
+            let spinning = looping.replace("count(100000)", "while(true)");
+            let error = engine.run_source(&spinning).unwrap_err();
+            assert_eq!(error.downcast_ref::<LimitExceeded>(), Some(&LimitExceeded::Statements { limit: 50 }));
+        }
+
+        let solving = r#"* <main> Solve {
+    ^ observe_execution {
+        result([12]) <> randomChoice([3, 4, 5])
+    }
+}"#;
+        let limits = Limits { max_solver_candidates: Some(10), ..Default::default() };
+        let mut engine = Engine::builder().limits(limits).build().unwrap();
+        let error = engine.run_source(solving).unwrap_err();
+        assert_eq!(error.downcast_ref::<LimitExceeded>().map(|e| e.kind()), Some("solver_candidates"));
+
+        let limits = Limits { timeout_ms: Some(0), ..Default::default() };
+        let mut engine = Engine::builder().limits(limits).build().unwrap();
+        let error = engine.run_source(looping).unwrap_err();
+        assert_eq!(error.downcast_ref::<LimitExceeded>().map(|e| e.kind()), Some("timeout"));
+    }
+
+    #[test]
+    fn test_bare_while_ends_under_default_limits() {
+        let spinning = r#"* <main> Spin {
+    ^ observe_execution {
+        loop <> while(true) {
+        }
+        speak("never")
+    }
+}"#;
+        for mut engine in both_backends(|builder| builder) {
+            let error = engine.run_source(spinning).unwrap_err();
+            assert_eq!(
+                error.downcast_ref::<LimitExceeded>(),
+                Some(&LimitExceeded::WhileIterations { limit: DEFAULT_MAX_WHILE_ITERATIONS })
+            );
+        }
+
+        // Callers can raise the cap
+        let counting = r#"* <main> Count {
+    ^ observe_execution {
+        n <> 0
+        loop <> while(n < 30) {
+            n <> calc(n, 1)
+        }
+        speak("n = ~n~")
+    }
+}"#;
+        let limits = Limits { max_while_iterations: Some(30), ..Default::default() };
+        for mut engine in both_backends(|builder| builder.limits(limits)) {
+            assert_eq!(engine.run_source(counting).unwrap().program_lines(), ["n = 30"]);
+        }
+        let limits = Limits { max_while_iterations: Some(29), ..Default::default() };
+        for mut engine in both_backends(|builder| builder.limits(limits)) {
+            let error = engine.run_source(counting).unwrap_err();
+            assert_eq!(error.downcast_ref::<LimitExceeded>().map(|e| e.kind()), Some("while_iterations"));
+        }
+    }
+}
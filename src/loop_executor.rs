@@ -1,23 +1,50 @@
 use anyhow::Result;
 
+use crate::cancellation::CancellationToken;
+use crate::output_sink::{SharedSink, StdoutSink};
+
+/// What a loop does once an iteration's body has run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoopControl {
+    /// Go on to the next iteration
+    Next,
+    /// Leave the loop; a break or continue aimed at an outer loop stays pending
+    Exit,
+}
+
 pub struct LoopExecutor {
-    // Track loop depth for nested loops
-    pub loop_depth: usize,
+    // Label of each enclosing loop, innermost last; None for unlabelled loops
+    labels: Vec<Option<String>>,
     // Track if we should break out of loop
     pub should_break: bool,
     // Track if we should continue to next iteration
     pub should_continue: bool,
+    // Index in `labels` of the loop a pending break or continue is aimed at
+    target: usize,
+    output: SharedSink,
+    cancel: CancellationToken,
 }
 
 impl LoopExecutor {
     pub fn new() -> Self {
         Self {
-            loop_depth: 0,
+            labels: Vec::new(),
             should_break: false,
             should_continue: false,
+            target: 0,
+            output: StdoutSink::shared(),
+            cancel: CancellationToken::new(),
         }
     }
 
+    pub fn set_output_sink(&mut self, sink: SharedSink) {
+        self.output = sink;
+    }
+
+    pub fn set_cancellation_token(&mut self, token: CancellationToken) {
+        self.cancel = token;
+    }
+
     /// Execute a count-based loop
     pub fn execute_count_loop<F>(
         &mut self,
@@ -27,28 +54,23 @@ impl LoopExecutor {
     where
         F: FnMut(Option<u32>) -> Result<()>
     {
-        println!("-- Executing count loop: {} iterations", count);
-        self.loop_depth += 1;
+        self.output.debug(&format!("-- Executing count loop: {} iterations", count));
+        self.enter_loop(None);
 
         for i in 0..count {
-            // Reset continue flag for each iteration
-            self.should_continue = false;
+            self.cancel.check()?;
 
             // Execute body (no loop variable in Phase 1)
             body_executor(None)?;
 
-            // Check for break
-            if self.should_break {
-                println!("   Loop broken at iteration {}", i);
-                self.should_break = false;
+            if self.finish_iteration() == LoopControl::Exit {
+                self.output.debug(&format!("   Loop broken at iteration {}", i));
                 break;
             }
-
-            // Continue already handled by flag reset
         }
 
-        self.loop_depth -= 1;
-        println!("-- Count loop complete");
+        self.exit_loop();
+        self.output.debug("-- Count loop complete");
         Ok(())
     }
 
@@ -62,24 +84,23 @@ impl LoopExecutor {
     where
         F: FnMut(Option<i32>) -> Result<()>
     {
-        println!("-- Executing range loop: {} to {}", start, end);
-        self.loop_depth += 1;
+        self.output.debug(&format!("-- Executing range loop: {} to {}", start, end));
+        self.enter_loop(None);
 
         for i in start..end {
-            self.should_continue = false;
+            self.cancel.check()?;
 
             // Execute body with loop variable
             body_executor(Some(i))?;
 
-            if self.should_break {
-                println!("   Loop broken at value {}", i);
-                self.should_break = false;
+            if self.finish_iteration() == LoopControl::Exit {
+                self.output.debug(&format!("   Loop broken at value {}", i));
                 break;
             }
         }
 
-        self.loop_depth -= 1;
-        println!("-- Range loop complete");
+        self.exit_loop();
+        self.output.debug("-- Range loop complete");
         Ok(())
     }
 
@@ -94,60 +115,173 @@ impl LoopExecutor {
         F: FnMut() -> Result<()>,
         C: FnMut() -> Result<bool>
     {
-        println!("-- Executing while loop (max iterations: {})", max_iterations);
-        self.loop_depth += 1;
+        self.output.debug(&format!("-- Executing while loop (max iterations: {})", max_iterations));
+        self.enter_loop(None);
 
         let mut iteration = 0;
         while condition_checker()? {
-            self.should_continue = false;
+            self.cancel.check()?;
 
             body_executor()?;
 
-            if self.should_break {
-                println!("   While loop broken at iteration {}", iteration);
-                self.should_break = false;
+            if self.finish_iteration() == LoopControl::Exit {
+                self.output.debug(&format!("   While loop broken at iteration {}", iteration));
                 break;
             }
 
             iteration += 1;
             if iteration >= max_iterations {
-                println!("!! While loop hit max iterations ({})", max_iterations);
+                self.output.warn(&format!("!! While loop hit max iterations ({})", max_iterations));
                 break;
             }
         }
 
-        self.loop_depth -= 1;
-        println!("-- While loop complete after {} iterations", iteration);
+        self.exit_loop();
+        self.output.debug(&format!("-- While loop complete after {} iterations", iteration));
         Ok(())
     }
 
-    /// Signal that we should break out of the current loop
-    pub fn signal_break(&mut self) {
-        if self.loop_depth > 0 {
-            println!("   >> Break signaled");
+    /// Push a loop, optionally labelled, onto the label stack
+    pub fn enter_loop(&mut self, label: Option<&str>) {
+        self.labels.push(label.map(str::to_string));
+    }
+
+    pub fn exit_loop(&mut self) {
+        self.labels.pop();
+    }
+
+    /// Settle a pending break or continue at the end of an iteration
+    ///
+    /// One aimed at this loop is cleared here; one aimed further out makes
+    /// this loop exit and is left for the outer loop to settle.
+    pub fn finish_iteration(&mut self) -> LoopControl {
+        if !self.should_break && !self.should_continue {
+            return LoopControl::Next;
+        }
+        if self.target + 1 < self.labels.len() {
+            return LoopControl::Exit;
+        }
+
+        let control = if self.should_break { LoopControl::Exit } else { LoopControl::Next };
+        self.should_break = false;
+        self.should_continue = false;
+        control
+    }
+
+    /// Signal that we should break out of the current loop, or the one labelled `label`
+    pub fn signal_break(&mut self, label: Option<&str>) {
+        if let Some(target) = self.resolve_target("Break", label) {
+            self.output.debug("   >> Break signaled");
+            self.target = target;
             self.should_break = true;
-        } else {
-            println!("!! Break called outside of loop");
         }
     }
 
-    /// Signal that we should continue to next iteration
-    pub fn signal_continue(&mut self) {
-        if self.loop_depth > 0 {
-            println!("   >> Continue signaled");
+    /// Signal that we should continue to the next iteration of the current loop, or the one labelled `label`
+    pub fn signal_continue(&mut self, label: Option<&str>) {
+        if let Some(target) = self.resolve_target("Continue", label) {
+            self.output.debug("   >> Continue signaled");
+            self.target = target;
             self.should_continue = true;
-        } else {
-            println!("!! Continue called outside of loop");
+        }
+    }
+
+    /// Index of the loop a break or continue is aimed at; warns when there is none
+    fn resolve_target(&self, keyword: &str, label: Option<&str>) -> Option<usize> {
+        if self.labels.is_empty() {
+            self.output.warn(&format!("!! {} called outside of loop", keyword));
+            return None;
+        }
+        match label {
+            None => Some(self.labels.len() - 1),
+            Some(label) => {
+                let target = self.labels.iter().rposition(|l| l.as_deref() == Some(label));
+                if target.is_none() {
+                    self.output.warn(&format!("!! {} to unknown loop label '{}'", keyword, label));
+                }
+                target
+            }
         }
     }
 
     /// Check if we should skip the rest of this iteration
     pub fn should_skip_iteration(&self) -> bool {
-        self.should_continue
+        self.should_continue || self.should_break
     }
 
     /// Check if we're currently inside a loop
     pub fn is_in_loop(&self) -> bool {
-        self.loop_depth > 0
+        !self.labels.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output_sink::BufferedSink;
+    use std::sync::Arc;
+    use crate::engine::both_backends;
+
+    #[test]
+    fn test_labelled_break_and_continue_unwind_to_their_loop() {
+        let mut loops = LoopExecutor::new();
+        loops.set_output_sink(Arc::new(BufferedSink::new()));
+        loops.enter_loop(Some("outer"));
+        loops.enter_loop(None);
+        loops.enter_loop(Some("inner"));
+
+        loops.signal_break(Some("outer"));
+        assert!(loops.should_skip_iteration());
+        assert_eq!(loops.finish_iteration(), LoopControl::Exit);
+        loops.exit_loop();
+        assert_eq!(loops.finish_iteration(), LoopControl::Exit);
+        loops.exit_loop();
+        assert_eq!(loops.finish_iteration(), LoopControl::Exit);
+        assert!(!loops.should_skip_iteration());
+
+        loops.enter_loop(None);
+        loops.signal_continue(Some("outer"));
+        assert_eq!(loops.finish_iteration(), LoopControl::Exit);
+        loops.exit_loop();
+        assert_eq!(loops.finish_iteration(), LoopControl::Next);
+        assert!(!loops.should_skip_iteration());
+
+        loops.signal_break(Some("missing"));
+        assert!(!loops.should_break);
+    }
+
+    #[test]
+    fn test_labelled_loops_in_programs() {
+        let program = r#"* <main> Labelled {
+    ^ observe_execution {
+        loop outer <> range(0, 4) as i {
+            loop <> range(0, 4) as j {
+                if <> (j > i)
+                <elif> (i == 3)
+                <else> (true) {
+                    continue outer
+                    <>
+                    break outer
+                    <>
+                    speak("~i~ ~j~")
+                }
+            }
+            speak("never")
+        }
+        loop rows <> count(2) {
+            loop <> range(0, 3) as k {
+                speak("row ~k~")
+                continue rows
+            }
+        }
+    }
+}"#;
+        for mut engine in both_backends(|builder| builder) {
+            let outcome = engine.run_source(program).unwrap();
+            assert_eq!(outcome.program_lines(), ["0 0", "1 0", "1 1", "2 0", "2 1", "2 2", "row 0", "row 0"]);
+
+            let error = engine.run_source("* <main> Bad {\n    ^ observe_execution {\n        loop <> count(2) {\n            break nowhere\n        }\n    }\n}").unwrap_err();
+            assert_eq!(error.to_string(), "Unknown loop label 'nowhere' in `break nowhere`");
+        }
     }
 }
@@ -1,8 +1,17 @@
 use anyhow::Result;
 use std::collections::HashMap;
 use std::time::{SystemTime, UNIX_EPOCH, Instant};
-use crate::{MathSolution, VariableAttempt, VariableValue, ConsoleCallback};
+use crate::{MathSolution, VariableAttempt, VariableValue};
+use crate::bigint::BigInt;
+use crate::cancellation::CancellationToken;
+use crate::cost_model::{DefaultCostModel, SharedCostModel};
+use crate::output_sink::{SharedSink, StdoutSink};
 use crate::equation_solver::{EquationSolver, Operation};
+use crate::equation_tree::EquationNode;
+use crate::expression_search::{SearchConfig, SearchGuard, SolveOptions, SolveRules};
+use crate::limits::SolveLimits;
+use crate::operator_set::OperatorSet;
+use crate::rational::Rational;
 use rayon::prelude::*;
 use evalexpr::*;
 
@@ -12,7 +21,24 @@ pub struct MathEngine {
     equation_solver: EquationSolver,
     observation_count: u32,
     function_call_results: HashMap<String, f64>,
-    console_callback: Option<ConsoleCallback>,
+    output: SharedSink,
+    /// Ranks exact matches; the cheapest wins
+    cost_model: SharedCostModel,
+    /// Polled by every search; a cancelled solve leaves the cache untouched
+    cancel: CancellationToken,
+    /// The run's deadline and candidate limit, polled like `cancel`
+    solve_limits: SolveLimits,
+    /// Caps for the tree search tried when the fixed shapes have no exact match
+    search: SearchConfig,
+    /// Operators a solve may use unless its statement names its own
+    operators: OperatorSet,
+    /// Attempt counts and observations when this engine was forked for a parallel frame
+    fork_base: Option<ForkBase>,
+}
+
+struct ForkBase {
+    attempts: HashMap<String, usize>,
+    observations: u32,
 }
 
 impl MathEngine {
@@ -26,55 +52,327 @@ impl MathEngine {
             equation_solver: EquationSolver::new(),
             observation_count: 0,
             function_call_results: HashMap::new(),
-            console_callback: None,
+            output: StdoutSink::shared(),
+            cost_model: DefaultCostModel::shared(),
+            cancel: CancellationToken::new(),
+            solve_limits: SolveLimits::default(),
+            search: SearchConfig::default(),
+            operators: OperatorSet::ALL,
+            fork_base: None,
         }
     }
 
-    pub fn set_console_callback(&mut self, callback: ConsoleCallback) {
-        self.console_callback = Some(callback);
+    /// Copy for a parallel frame; what it learns is merged back with `absorb`
+    pub fn fork(&self) -> Self {
+        Self {
+            solutions: self.solutions.clone(),
+            variable_attempts: self.variable_attempts.clone(),
+            equation_solver: EquationSolver::new(),
+            observation_count: self.observation_count,
+            function_call_results: self.function_call_results.clone(),
+            output: self.output.clone(),
+            cost_model: self.cost_model.clone(),
+            cancel: self.cancel.clone(),
+            solve_limits: self.solve_limits,
+            search: self.search,
+            operators: self.operators,
+            fork_base: Some(ForkBase {
+                attempts: self.variable_attempts.iter()
+                    .map(|(name, attempts)| (name.clone(), attempts.len()))
+                    .collect(),
+                observations: self.observation_count,
+            }),
+        }
     }
 
-    fn emit(&self, message: String, level: &str) {
-        println!("{}", message);
-        if let Some(callback) = &self.console_callback {
-            callback(message, level);
+    /// Take over the solutions, attempts and function results a forked engine learned
+    pub fn absorb(&mut self, fork: MathEngine) {
+        let base = fork.fork_base.unwrap_or(ForkBase { attempts: HashMap::new(), observations: 0 });
+
+        for (key, solution) in fork.solutions {
+            match self.solutions.get(&key) {
+                Some(existing) if !solution.improves_on(existing) => {}
+                _ => {
+                    self.solutions.insert(key, solution);
+                }
+            }
         }
+
+        for (name, attempts) in fork.variable_attempts {
+            let known = base.attempts.get(&name).copied().unwrap_or(0);
+            self.variable_attempts
+                .entry(name)
+                .or_default()
+                .extend(attempts.into_iter().skip(known));
+        }
+
+        self.function_call_results.extend(fork.function_call_results);
+        self.observation_count += fork.observation_count.saturating_sub(base.observations);
+    }
+
+    pub fn set_output_sink(&mut self, sink: SharedSink) {
+        self.output = sink;
+    }
+
+    pub fn set_cost_model(&mut self, cost_model: SharedCostModel) {
+        self.cost_model = cost_model;
+    }
+
+    pub fn set_cancellation_token(&mut self, token: CancellationToken) {
+        self.cancel = token;
+    }
+
+    /// Deadline and candidate limit for the solves that follow, from the run's `Budget`
+    pub fn set_solve_limits(&mut self, limits: SolveLimits) {
+        self.solve_limits = limits;
+    }
+
+    /// What every tree search polls to stop early
+    fn guard(&self) -> SearchGuard {
+        SearchGuard { cancel: self.cancel.clone(), limits: self.solve_limits }
+    }
+
+    pub fn set_search_config(&mut self, search: SearchConfig) {
+        self.search = search;
+    }
+
+    pub fn set_operators(&mut self, operators: OperatorSet) {
+        self.operators = operators;
+    }
+
+    /// Cheapest exact match in `ops` and its cost, the earliest on a tie
+    fn find_exact<'a>(&self, ops: &'a [Operation], target: f64) -> Result<Option<(&'a Operation, Option<u32>)>> {
+        // A cancelled token matches nothing so the rest of the scan is cheap
+        let cancel = &self.cancel;
+        let target_exact = Rational::from_f64(target);
+        let found = ops.par_iter()
+            .enumerate()
+            .filter(|(_, op)| !cancel.is_cancelled() && hits_target(op, target, target_exact))
+            .map(|(index, op)| (self.cost_of(&op.equation, Some(&op.formula)), index, op))
+            .min_by_key(|&(cost, index, _)| (cost.unwrap_or(u32::MAX), index));
+        self.cancel.check()?;
+        self.solve_limits.check_deadline()?;
+        Ok(found.map(|(cost, _, op)| (op, cost)))
+    }
+
+    /// Cheapest of the tree search's exact hits and its cost, the earliest on a tie
+    fn cheapest_hit(&self, hits: Vec<Operation>) -> Option<(Operation, Option<u32>)> {
+        hits.into_iter()
+            .enumerate()
+            .map(|(index, op)| (self.cost_of(&op.equation, Some(&op.formula)), index, op))
+            .min_by_key(|&(cost, index, _)| (cost.unwrap_or(u32::MAX), index))
+            .map(|(cost, _, op)| (op, cost))
+    }
+
+    /// The cheapest fixed-shape equation that hits `target`, to weigh against a cached one
+    fn cheapest_fixed_shape(&self, target: f64, inputs: &[f64], operators: OperatorSet) -> Result<Option<MathSolution>> {
+        let ops = EquationSolver::restrict(self.equation_solver.generate_all_operations(inputs), operators);
+        Ok(self.find_exact(&ops, target)?.map(|(op, cost)| MathSolution {
+            result: target,
+            equation: op.equation.clone(),
+            accuracy: 100.0,
+            timestamp: 0,
+            attempts: 1,
+            formula: Some(op.formula.clone()),
+            exact: None,
+            cost,
+        }))
+    }
+
+    /// The cost model's price for `equation`; `None` when it does not parse
+    fn cost_of(&self, equation: &str, formula: Option<&str>) -> Option<u32> {
+        let node = EquationNode::parse(equation).ok()?;
+        Some(self.cost_model.cost(&node, formula))
+    }
+
+    /// Most accurate operation in `ops`, stopping early when cancelled
+    fn find_most_accurate(&self, ops: &[Operation], target: f64) -> Result<Option<MathSolution>> {
+        let best = ops.par_iter()
+            .map(|op| {
+                if self.cancel.is_cancelled() {
+                    return None;
+                }
+                let accuracy = self.calculate_accuracy(op.result, target);
+                Some(MathSolution {
+                    result: op.result,
+                    equation: op.equation.clone(),
+                    accuracy,
+                    timestamp: 0,
+                    attempts: 1,
+                    formula: Some(op.formula.clone()),
+                    exact: None,
+                    cost: None,
+                })
+            })
+            .while_some()
+            .max_by(|a, b| a.accuracy.partial_cmp(&b.accuracy).unwrap_or(std::cmp::Ordering::Equal));
+        self.cancel.check()?;
+        self.solve_limits.check_deadline()?;
+        Ok(best)
     }
     
     pub fn solve_target(&mut self, target: f64, inputs: &[f64], var_name: &str, class_name: &str) -> Result<MathSolution> {
-        let start_time = Instant::now();
+        self.solve_target_with(target, inputs, var_name, class_name, SolveOptions::default())
+    }
+
+    /// Like `solve_target`, restricted to equations that use the inputs as
+    /// `options.rules` allows and only the operators in `options.operators`
+    pub fn solve_target_with(
+        &mut self,
+        target: f64,
+        inputs: &[f64],
+        var_name: &str,
+        class_name: &str,
+        options: SolveOptions
+    ) -> Result<MathSolution> {
+        self.observation_count += 1;
+        self.output.info(&format!(">> Observation #{} - Target: {} for variable '{}'",
+                self.observation_count, target, var_name));
+
+        let (rules, operators) = (options.rules, options.operators.unwrap_or(self.operators));
+        let cache_key = format!("{}{}{}", self.create_cache_key(&key_number(target), inputs, class_name, var_name),
+                rules.cache_suffix(), operators.cache_suffix());
+        self.solve_cached(
+            cache_key,
+            var_name,
+            |engine| match rules {
+                SolveRules::Free => engine.solve_free(target, inputs, var_name, operators),
+                _ => engine.solve_by_rules(target, inputs, rules, operators),
+            },
+            |engine| match rules {
+                SolveRules::Free => engine.cheapest_fixed_shape(target, inputs, operators),
+                _ => Ok(None),
+            }
+        )
+    }
+
+    /// `solve_target_with` for an integer target too large for f64, such as `30!`
+    ///
+    /// Searches with big integers, so factorials, powers and products stay
+    /// exact at any size. Inputs must be whole numbers that f64 holds exactly.
+    pub fn solve_integer_target(
+        &mut self,
+        target: &BigInt,
+        inputs: &[BigInt],
+        var_name: &str,
+        class_name: &str,
+        options: SolveOptions
+    ) -> Result<MathSolution> {
         self.observation_count += 1;
+        self.output.info(&format!(">> Observation #{} - Integer target: {} for variable '{}'",
+                self.observation_count, target, var_name));
 
-        self.emit(format!(">> Observation #{} - Target: {} for variable '{}'",
-                self.observation_count, target, var_name), "info");
+        let (rules, operators) = (options.rules, options.operators.unwrap_or(self.operators));
+        let inputs_key = inputs.iter().map(BigInt::to_string).collect::<Vec<_>>().join(",");
+        let cache_key = format!("{}-{}-{}-{}{}{}", class_name, var_name, target, inputs_key,
+                rules.cache_suffix(), operators.cache_suffix());
+        self.solve_cached(cache_key, var_name, |engine| engine.solve_integers(target, inputs, rules, operators), |_| Ok(None))
+    }
 
-        let cache_key = self.create_cache_key(target, inputs, class_name, var_name);
+    /// The `k` best equations for `target`: exact ones cheapest first, then the closest approximations
+    pub fn solve_target_topk(&self, target: f64, inputs: &[f64], k: usize) -> Result<Vec<MathSolution>> {
+        self.solve_target_topk_with(target, inputs, k, self.operators)
+    }
 
-        if let Some(cached) = self.solutions.get(&cache_key) {
-            if cached.accuracy == 100.0 {
-                let cache_time = start_time.elapsed();
-                self.emit(format!("== Using perfect cached solution: {} = {} (100% accuracy)",
-                        cached.equation, cached.result), "success");
-                self.emit(format!("   Cache retrieval time: {:?}", cache_time), "info");
-                return Ok(cached.clone());
+    /// `solve_target_topk` limited to `operators`
+    pub fn solve_target_topk_with(&self, target: f64, inputs: &[f64], k: usize, operators: OperatorSet) -> Result<Vec<MathSolution>> {
+        let mut ranked = self.rank_solutions(target, inputs, operators)?;
+        ranked.truncate(k);
+        Ok(ranked)
+    }
+
+    /// Every distinct exact equation for `target` the fixed shapes and tree enumeration
+    /// reach, cheapest first; `rank_solutions` says where enumeration stops
+    pub fn enumerate_exact(&self, target: f64, inputs: &[f64]) -> Result<Vec<MathSolution>> {
+        self.enumerate_exact_with(target, inputs, self.operators)
+    }
+
+    /// `enumerate_exact` limited to `operators`
+    pub fn enumerate_exact_with(&self, target: f64, inputs: &[f64], operators: OperatorSet) -> Result<Vec<MathSolution>> {
+        let mut ranked = self.rank_solutions(target, inputs, operators)?;
+        ranked.retain(|solution| solution.accuracy >= 100.0);
+        Ok(ranked)
+    }
+
+    /// The fixed shapes, every exact tree the search enumerates and its closest
+    /// miss, one per canonical form, most accurate first, then cheapest, then in
+    /// generation order
+    ///
+    /// Exact trees stop at the search config's limits and `max_candidates`, and
+    /// a subtree whose value a simpler one over the same inputs already reached
+    /// is not built on; see `expression_search::enumerate`. Neither the cache
+    /// nor past attempts are read or written.
+    fn rank_solutions(&self, target: f64, inputs: &[f64], operators: OperatorSet) -> Result<Vec<MathSolution>> {
+        let mut ops = EquationSolver::restrict(self.equation_solver.generate_all_operations(inputs), operators);
+        let search = self.equation_solver.enumerate_trees(inputs, target, &self.search, operators, &self.guard())?;
+        self.output.debug(&format!("-- Enumeration evaluated {} trees, {} exact", search.candidates, search.hits.len()));
+        ops.extend(search.hits);
+        ops.extend(search.best);
+        let ops = EquationSolver::dedup_canonical(ops);
+
+        let target_exact = Rational::from_f64(target);
+        let mut ranked: Vec<MathSolution> = ops.into_par_iter()
+            .map(|op| {
+                let exact = hits_target(&op, target, target_exact);
+                MathSolution {
+                    result: if exact { target } else { op.result },
+                    accuracy: if exact { 100.0 } else { self.calculate_accuracy(op.result, target).min(100f64.next_down()) },
+                    cost: self.cost_of(&op.equation, Some(&op.formula)),
+                    equation: op.equation,
+                    timestamp: 0,
+                    attempts: 1,
+                    formula: Some(op.formula),
+                    exact: None,
+                }
+            })
+            .collect();
+        self.cancel.check()?;
+
+        ranked.sort_by(|a, b| b.accuracy.total_cmp(&a.accuracy)
+            .then_with(|| a.cost.unwrap_or(u32::MAX).cmp(&b.cost.unwrap_or(u32::MAX))));
+        Ok(ranked)
+    }
+
+    /// Reuse a perfect cached solution, or run `solve` and cache its result if it beats the old one
+    ///
+    /// A perfect entry is weighed against `upgrade`, a quick exact scan, and
+    /// replaced when that finds a cheaper equation.
+    fn solve_cached(
+        &mut self,
+        cache_key: String,
+        var_name: &str,
+        solve: impl FnOnce(&Self) -> Result<MathSolution>,
+        upgrade: impl FnOnce(&Self) -> Result<Option<MathSolution>>
+    ) -> Result<MathSolution> {
+        let start_time = Instant::now();
+
+        if let Some(cached) = self.solutions.get_mut(&cache_key).filter(|cached| cached.accuracy == 100.0) {
+            // Priced by the current model, which may not be the one it was cached under
+            cached.cost = EquationNode::parse(&cached.equation).ok()
+                .map(|node| self.cost_model.cost(&node, cached.formula.as_deref()))
+                .or(cached.cost);
+            let mut cached = cached.clone();
+            self.output.info(&format!("== Using perfect cached solution: {} = {} (100% accuracy)",
+                    cached.equation, cached.display_result()));
+
+            if let Some(mut cheaper) = upgrade(self)?.filter(|found| found.improves_on(&cached)) {
+                cheaper.timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+                self.output.info(&format!("** Simpler solution cached: {} = {} (cost: {})",
+                        cheaper.equation, cheaper.display_result(), cheaper.cost.unwrap_or_default()));
+                self.remember_variable_attempt(var_name, &cheaper);
+                self.solutions.insert(cache_key, cheaper.clone());
+                cached = cheaper;
             }
-        }
 
-        // Build formula map from previous attempts
-        let formula_map = self.build_formula_map(var_name, inputs);
-        if !formula_map.is_empty() {
-            self.emit(format!("-- Built formula map with {} entries", formula_map.len()), "info");
+            let cache_time = start_time.elapsed();
+            self.output.debug(&format!("   Cache retrieval time: {:?}", cache_time));
+            return Ok(cached);
         }
 
-        let untried_ops = self.get_untried_operations_with_formulas(var_name, inputs, &formula_map);
-        self.emit(format!("-- {} untried operations available for '{}'", untried_ops.len(), var_name), "info");
-        
         let solution_start = Instant::now();
-        let mut solution = self.find_exact_solution(target, inputs, &untried_ops, var_name)?;
-        
-        if solution.accuracy < 100.0 {
-            self.emit(format!("!! No exact match found, finding best approximation for target {}", target), "warning");
-            solution = self.find_best_approximation(target, inputs, &untried_ops, var_name)?;
+        let mut solution = solve(self)?;
+        if solution.cost.is_none() {
+            solution.cost = self.cost_of(&solution.equation, solution.formula.as_deref());
         }
 
         let solution_time = solution_start.elapsed();
@@ -84,24 +382,188 @@ impl MathEngine {
 
         if let Some(cached) = self.solutions.get(&cache_key) {
             if solution.accuracy > cached.accuracy {
+                self.output.info(&format!("** New best solution cached: {} = {} (accuracy: {}%)",
+                        solution.equation, solution.display_result(), solution.accuracy));
+                self.solutions.insert(cache_key, solution.clone());
+            } else if solution.improves_on(cached) {
+                self.output.info(&format!("** Simpler solution cached: {} = {} (cost: {})",
+                        solution.equation, solution.display_result(), solution.cost.unwrap_or_default()));
                 self.solutions.insert(cache_key, solution.clone());
-                self.emit(format!("** New best solution cached: {} = {} (accuracy: {}%)",
-                        solution.equation, solution.result, solution.accuracy), "success");
             }
         } else {
             self.solutions.insert(cache_key, solution.clone());
-            self.emit(format!("** Solution cached: {} = {} (accuracy: {}%)",
-                    solution.equation, solution.result, solution.accuracy), "success");
+            self.output.info(&format!("** Solution cached: {} = {} (accuracy: {}%)",
+                    solution.equation, solution.display_result(), solution.accuracy));
         }
 
         let total_time = start_time.elapsed();
-        self.emit(format!("   Solution time: {:?}, Total time: {:?}", solution_time, total_time), "info");
+        self.output.debug(&format!("   Solution time: {:?}, Total time: {:?}", solution_time, total_time));
         
         Ok(solution)
     }
     
+    /// Fixed shapes with formula substitution first, then the tree search
+    fn solve_free(&self, target: f64, inputs: &[f64], var_name: &str, operators: OperatorSet) -> Result<MathSolution> {
+        // Build formula map from previous attempts
+        let formula_map = self.build_formula_map(var_name, inputs);
+        if !formula_map.is_empty() {
+            self.output.debug(&format!("-- Built formula map with {} entries", formula_map.len()));
+        }
+
+        let untried_ops = self.get_untried_operations_with_formulas(var_name, inputs, &formula_map, operators);
+        self.output.debug(&format!("-- {} untried operations available for '{}'", untried_ops.len(), var_name));
+        
+        let solution = self.find_exact_solution(target, inputs, &untried_ops, operators)?;
+        if solution.accuracy >= 100.0 {
+            return Ok(solution);
+        }
+
+        let search = self.equation_solver.search_trees(inputs, target, &self.search, SolveRules::Free, operators, &self.guard())?;
+        self.output.debug(&format!("-- Tree search evaluated {} candidates", search.candidates));
+
+        if let Some((op, cost)) = self.cheapest_hit(search.hits) {
+            self.output.info(&format!("== Exact match found by tree search: {} = {}", op.equation, target));
+            return Ok(MathSolution {
+                result: target,
+                equation: op.equation,
+                accuracy: 100.0,
+                timestamp: 0,
+                attempts: 1,
+                formula: Some(op.formula),
+                exact: None,
+                cost,
+            });
+        }
+
+        self.output.warn(&format!("!! No exact match found, finding best approximation for target {}", target));
+        self.find_best_approximation(target, inputs, &untried_ops, search.best, operators)
+    }
+
+    /// Tree search alone over every subset of the inputs, so none is reused
+    /// and no cached intermediate stands in for one
+    ///
+    /// The operator cap is lifted to cover all inputs. A search that runs out
+    /// only rules out binary trees within its limits: `%` and functions such as
+    /// `sqrt` are never tried and powers are capped, so other equations may still exist.
+    fn solve_by_rules(&self, target: f64, inputs: &[f64], rules: SolveRules, operators: OperatorSet) -> Result<MathSolution> {
+        let config = SearchConfig { max_operators: inputs.len().saturating_sub(1), ..self.search };
+        let search = self.equation_solver.search_trees(inputs, target, &config, rules, operators, &self.guard())?;
+        self.output.debug(&format!("-- Tree search evaluated {} candidates", search.candidates));
+
+        if let Some((op, cost)) = self.cheapest_hit(search.hits) {
+            self.output.info(&format!("== Exact match found {}: {} = {}", rules.describe(), op.equation, target));
+            return Ok(MathSolution {
+                result: target,
+                equation: op.equation,
+                accuracy: 100.0,
+                timestamp: 0,
+                attempts: 1,
+                formula: Some(op.formula),
+                exact: None,
+                cost,
+            });
+        }
+
+        if search.exhausted && config.max_depth.is_none() {
+            self.output.info(&format!("== No solution found for target {} {} within the searched operators/limits: all {} candidates tried",
+                    target, rules.describe(), search.candidates));
+        } else {
+            self.output.warn(&format!("!! No exact match found {} within the search limits ({} candidates)",
+                    rules.describe(), search.candidates));
+        }
+
+        let best = match search.best {
+            Some(op) => MathSolution {
+                accuracy: self.calculate_accuracy(op.result, target),
+                result: op.result,
+                equation: op.equation,
+                timestamp: 0,
+                attempts: 1,
+                formula: Some(op.formula),
+                exact: None,
+                cost: None,
+            },
+            None => MathSolution {
+                result: target,
+                equation: target.to_string(),
+                accuracy: 0.0,
+                timestamp: 0,
+                attempts: 1,
+                formula: Some(target.to_string()),
+                exact: None,
+                cost: None,
+            },
+        };
+        self.output.info(&format!("== Best approximation: {} = {} (accuracy: {}%)",
+                best.equation, best.result, best.accuracy));
+        Ok(best)
+    }
+
+    /// Tree search over big integers; the rules decide which inputs a tree must use
+    fn solve_integers(&self, target: &BigInt, inputs: &[BigInt], rules: SolveRules, operators: OperatorSet) -> Result<MathSolution> {
+        let config = match rules {
+            SolveRules::Free => self.search,
+            _ => SearchConfig { max_operators: inputs.len().saturating_sub(1), ..self.search },
+        };
+        let search = self.equation_solver.search_integer_trees(inputs, target, &config, rules, operators, &self.guard())?;
+        self.output.debug(&format!("-- Integer tree search evaluated {} candidates", search.candidates));
+
+        if let Some((op, cost)) = self.cheapest_hit(search.hits) {
+            self.output.info(&format!("== Exact match found {}: {} = {}", rules.describe(), op.equation, target));
+            return Ok(MathSolution {
+                result: op.result,
+                equation: op.equation,
+                accuracy: 100.0,
+                timestamp: 0,
+                attempts: 1,
+                formula: Some(op.formula),
+                exact: Some(target.clone()).filter(|n| !n.fits_f64()),
+                cost,
+            });
+        }
+
+        if search.exhausted && config.max_depth.is_none() {
+            self.output.info(&format!("== No solution found for target {} {} within the searched operators/limits: all {} candidates tried",
+                    target, rules.describe(), search.candidates));
+        } else {
+            self.output.warn(&format!("!! No exact match found {} within the search limits ({} candidates)",
+                    rules.describe(), search.candidates));
+        }
+
+        let best = match search.best {
+            Some(op) => {
+                let value = EquationNode::parse(&op.equation).ok().and_then(|node| node.evaluate_integer());
+                // f64 can round a near miss onto the target, which must not read as exact
+                let accuracy = self.calculate_accuracy(op.result, target.to_f64()).min(100f64.next_down());
+                MathSolution {
+                    result: op.result,
+                    equation: op.equation,
+                    accuracy,
+                    timestamp: 0,
+                    attempts: 1,
+                    formula: Some(op.formula),
+                    exact: value.filter(|n| !n.fits_f64()),
+                    cost: None,
+                }
+            }
+            None => MathSolution {
+                result: target.to_f64(),
+                equation: target.to_string(),
+                accuracy: 0.0,
+                timestamp: 0,
+                attempts: 1,
+                formula: Some(target.to_string()),
+                exact: Some(target.clone()).filter(|n| !n.fits_f64()),
+                cost: None,
+            },
+        };
+        self.output.info(&format!("== Best approximation: {} = {} (accuracy: {}%)",
+                best.equation, best.display_result(), best.accuracy));
+        Ok(best)
+    }
+
     pub fn solve_expression(&mut self, expression: &str, variables: &HashMap<String, VariableValue>) -> Result<f64> {
-        println!(">> Evaluating expression: {}", expression);
+        self.output.info(&format!(">> Evaluating expression: {}", expression));
         
         if expression.starts_with("calc(") && expression.ends_with(")") {
             let inner = &expression[5..expression.len()-1];
@@ -109,11 +571,11 @@ impl MathEngine {
             
             if params.len() == 2 {
                 let result = self.execute_two_number_calc(params[0], params[1]);
-                println!("-- calc({}, {}) = {}", params[0], params[1], result);
+                self.output.debug(&format!("-- calc({}, {}) = {}", params[0], params[1], result));
                 return Ok(result);
             } else if params.len() == 3 {
                 let result = self.execute_three_number_calc(params[0], params[1], params[2]);
-                println!("-- calc({}, {}, {}) = {}", params[0], params[1], params[2], result);
+                self.output.debug(&format!("-- calc({}, {}, {}) = {}", params[0], params[1], params[2], result));
                 return Ok(result);
             }
         }
@@ -124,7 +586,7 @@ impl MathEngine {
         
         if let Some(var_value) = variables.get(expression) {
             if let VariableValue::Number(n) = var_value {
-                println!("-- Resolved variable '{}' = {}", expression, n);
+                self.output.debug(&format!("-- Resolved variable '{}' = {}", expression, n));
                 return Ok(*n);
             }
         }
@@ -145,7 +607,7 @@ impl MathEngine {
             else if let Some(var_value) = variables.get(param) {
                 if let VariableValue::Number(n) = var_value {
                     params.push(*n);
-                    println!("-- Resolved parameter '{}' = {}", param, n);
+                    self.output.debug(&format!("-- Resolved parameter '{}' = {}", param, n));
                 } else {
                     return Err(anyhow::anyhow!("Variable '{}' is not numeric", param));
                 }
@@ -173,7 +635,7 @@ impl MathEngine {
         ];
 
         let chosen = &operations[0];
-        println!("   Using operation: {}", chosen.equation);
+        self.output.debug(&format!("   Using operation: {}", chosen.equation));
         chosen.result
     }
     
@@ -187,7 +649,7 @@ impl MathEngine {
         ];
 
         let chosen = &operations[0];
-        println!("   Using operation: {}", chosen.equation);
+        self.output.debug(&format!("   Using operation: {}", chosen.equation));
         chosen.result
     }
     
@@ -215,7 +677,7 @@ impl MathEngine {
             }
             Err(e) => {
                 // Fallback to simple operand resolution if evalexpr fails
-                println!("-- evalexpr failed ({}), trying simple resolution", e);
+                self.output.debug(&format!("-- evalexpr failed ({}), trying simple resolution", e));
                 self.resolve_operand(expression, variables)
             }
         }
@@ -236,9 +698,9 @@ impl MathEngine {
         Err(anyhow::anyhow!("Could not resolve operand: {}", operand))
     }
     
-    fn create_cache_key(&self, target: f64, inputs: &[f64], class_name: &str, var_name: &str) -> String {
+    fn create_cache_key(&self, target: &str, inputs: &[f64], class_name: &str, var_name: &str) -> String {
         let inputs_str = inputs.iter()
-            .map(|i| i.to_string())
+            .map(|&i| key_number(i))
             .collect::<Vec<_>>()
             .join(",");
         format!("{}-{}-{}-{}", class_name, var_name, target, inputs_str)
@@ -271,28 +733,38 @@ impl MathEngine {
 
     fn get_untried_operations(&self, var_name: &str, inputs: &[f64]) -> Vec<Operation> {
         let formula_map = HashMap::new();
-        self.get_untried_operations_with_formulas(var_name, inputs, &formula_map)
+        self.get_untried_operations_with_formulas(var_name, inputs, &formula_map, OperatorSet::ALL)
     }
 
-    fn get_untried_operations_with_formulas(&self, var_name: &str, inputs: &[f64], formula_map: &HashMap<String, String>) -> Vec<Operation> {
+    fn get_untried_operations_with_formulas(
+        &self,
+        var_name: &str,
+        inputs: &[f64],
+        formula_map: &HashMap<String, String>,
+        operators: OperatorSet
+    ) -> Vec<Operation> {
         let previous_attempts = self.variable_attempts.get(var_name).cloned().unwrap_or_default();
         let attempted_equations: std::collections::HashSet<String> =
             previous_attempts.iter().map(|a| a.equation.clone()).collect();
 
-        println!("-- Variable '{}' has {} previous attempts", var_name, attempted_equations.len());
+        self.output.debug(&format!("-- Variable '{}' has {} previous attempts", var_name, attempted_equations.len()));
 
-        let all_operations = self.equation_solver.generate_all_operations_with_formulas(inputs, formula_map);
+        let generation_start = Instant::now();
+        let generated = self.equation_solver.generate_all_operations_with_formulas(inputs, formula_map);
+        self.output.debug(&format!("-- {} distinct fixed-shape candidates of up to {} in {:?}",
+                generated.len(), EquationSolver::candidate_upper_bound(inputs.len()), generation_start.elapsed()));
+        let all_operations = EquationSolver::restrict(generated, operators);
 
         all_operations.into_iter()
             .filter(|op| !attempted_equations.contains(&op.equation))
             .collect()
     }
     
-    fn find_exact_solution(&self, target: f64, inputs: &[f64], untried_ops: &[Operation], _var_name: &str) -> Result<MathSolution> {
+    fn find_exact_solution(&self, target: f64, inputs: &[f64], untried_ops: &[Operation], operators: OperatorSet) -> Result<MathSolution> {
         // Search untried operations in parallel
-        if let Some(op) = untried_ops.par_iter().find_any(|op| (op.result - target).abs() < f64::EPSILON) {
-            println!("== Exact match found from untried operations: {} = {}", op.equation, target);
-            println!("   Formula: {}", op.formula);
+        if let Some((op, cost)) = self.find_exact(untried_ops, target)? {
+            self.output.info(&format!("== Exact match found from untried operations: {} = {}", op.equation, target));
+            self.output.debug(&format!("   Formula: {}", op.formula));
             return Ok(MathSolution {
                 result: target,
                 equation: op.equation.clone(),
@@ -300,14 +772,16 @@ impl MathEngine {
                 timestamp: 0,
                 attempts: 1,
                 formula: Some(op.formula.clone()),
+                exact: None,
+                cost,
             });
         }
 
         // Search all operations in parallel
-        let all_ops = self.equation_solver.generate_all_operations(inputs);
-        if let Some(op) = all_ops.par_iter().find_any(|op| (op.result - target).abs() < f64::EPSILON) {
-            println!("== Exact match found: {} = {}", op.equation, target);
-            println!("   Formula: {}", op.formula);
+        let all_ops = EquationSolver::restrict(self.equation_solver.generate_all_operations(inputs), operators);
+        if let Some((op, cost)) = self.find_exact(&all_ops, target)? {
+            self.output.info(&format!("== Exact match found: {} = {}", op.equation, target));
+            self.output.debug(&format!("   Formula: {}", op.formula));
             return Ok(MathSolution {
                 result: target,
                 equation: op.equation.clone(),
@@ -315,6 +789,8 @@ impl MathEngine {
                 timestamp: 0,
                 attempts: 1,
                 formula: Some(op.formula.clone()),
+                exact: None,
+                cost,
             });
         }
 
@@ -325,10 +801,20 @@ impl MathEngine {
             timestamp: 0,
             attempts: 1,
             formula: if !inputs.is_empty() { Some(inputs[0].to_string()) } else { Some(target.to_string()) },
+            exact: None,
+            cost: None,
         })
     }
     
-    fn find_best_approximation(&self, target: f64, inputs: &[f64], untried_ops: &[Operation], _var_name: &str) -> Result<MathSolution> {
+    /// Closest of the fixed-shape operations and `tree_best`, the tree search's closest
+    fn find_best_approximation(
+        &self,
+        target: f64,
+        inputs: &[f64],
+        untried_ops: &[Operation],
+        tree_best: Option<Operation>,
+        operators: OperatorSet
+    ) -> Result<MathSolution> {
         if inputs.is_empty() {
             return Ok(MathSolution {
                 result: target,
@@ -337,6 +823,8 @@ impl MathEngine {
                 timestamp: 0,
                 attempts: 1,
                 formula: Some(target.to_string()),
+                exact: None,
+                cost: None,
             });
         }
 
@@ -347,51 +835,43 @@ impl MathEngine {
             timestamp: 0,
             attempts: 1,
             formula: Some(inputs[0].to_string()),
+            exact: None,
+            cost: None,
         };
 
         // Search untried operations in parallel for best match
-        if let Some(best_untried) = untried_ops.par_iter()
-            .map(|op| {
-                let accuracy = self.calculate_accuracy(op.result, target);
-                MathSolution {
-                    result: op.result,
-                    equation: op.equation.clone(),
-                    accuracy,
-                    timestamp: 0,
-                    attempts: 1,
-                    formula: Some(op.formula.clone()),
-                }
-            })
-            .max_by(|a, b| a.accuracy.partial_cmp(&b.accuracy).unwrap_or(std::cmp::Ordering::Equal))
-        {
+        if let Some(best_untried) = self.find_most_accurate(untried_ops, target)? {
             if best_untried.accuracy > best.accuracy {
                 best = best_untried;
             }
         }
 
         // Search all operations in parallel for best match
-        let all_ops = self.equation_solver.generate_all_operations(inputs);
-        if let Some(best_all) = all_ops.par_iter()
-            .map(|op| {
-                let accuracy = self.calculate_accuracy(op.result, target);
-                MathSolution {
+        let all_ops = EquationSolver::restrict(self.equation_solver.generate_all_operations(inputs), operators);
+        if let Some(best_all) = self.find_most_accurate(&all_ops, target)? {
+            if best_all.accuracy > best.accuracy {
+                best = best_all;
+            }
+        }
+
+        if let Some(op) = tree_best {
+            let accuracy = self.calculate_accuracy(op.result, target);
+            if accuracy > best.accuracy {
+                best = MathSolution {
                     result: op.result,
-                    equation: op.equation.clone(),
+                    equation: op.equation,
                     accuracy,
                     timestamp: 0,
                     attempts: 1,
-                    formula: Some(op.formula.clone()),
-                }
-            })
-            .max_by(|a, b| a.accuracy.partial_cmp(&b.accuracy).unwrap_or(std::cmp::Ordering::Equal))
-        {
-            if best_all.accuracy > best.accuracy {
-                best = best_all;
+                    formula: Some(op.formula),
+                    exact: None,
+                    cost: None,
+                };
             }
         }
 
-        println!("== Best approximation: {} = {} (accuracy: {}%)",
-                best.equation, best.result, best.accuracy);
+        self.output.info(&format!("== Best approximation: {} = {} (accuracy: {}%)",
+                best.equation, best.result, best.accuracy));
 
         Ok(best)
     }
@@ -436,10 +916,264 @@ impl MathEngine {
     
     pub fn store_function_result(&mut self, function_name: &str, result: f64) {
         self.function_call_results.insert(function_name.to_string(), result);
-        println!("++ Cached function result: {}() = {}", function_name, result);
+        self.output.debug(&format!("++ Cached function result: {}() = {}", function_name, result));
     }
     
     pub fn get_function_result(&self, function_name: &str) -> Option<f64> {
         self.function_call_results.get(function_name).copied()
     }
-}
\ No newline at end of file
+}
+
+/// Whether `op` reaches `target`: exactly when both sides are rational, within `f64::EPSILON` otherwise
+///
+/// Only results already close in f64 are re-evaluated exactly, so the check stays cheap.
+fn hits_target(op: &Operation, target: f64, target_exact: Option<Rational>) -> bool {
+    let difference = (op.result - target).abs();
+    if difference > target.abs().max(1.0) * 1e-9 {
+        return false;
+    }
+    let exact = target_exact.and_then(|_| EquationNode::parse(&op.equation).ok()?.evaluate_exact());
+    match (exact, target_exact) {
+        (Some(a), Some(b)) => a == b,
+        _ => difference < f64::EPSILON,
+    }
+}
+
+/// A number as it appears in a cache key, rounding noise removed so `0.1 + 0.2` and `0.3` share a key
+///
+/// Only snaps to fractions that print as exact decimals; other values keep their f64 text.
+fn key_number(x: f64) -> String {
+    match Rational::approximate(x) {
+        Some(r) if Rational::from_f64(r.to_f64()) == Some(r) => r.to_f64().to_string(),
+        _ => x.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{both_backends, Engine};
+    use crate::output_sink::{BufferedSink, DiagnosticLevel};
+    use crate::Cancelled;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn quiet_engine() -> MathEngine {
+        let mut engine = MathEngine::new(HashMap::new(), HashMap::new());
+        engine.set_output_sink(Arc::new(BufferedSink::new()));
+        engine
+    }
+
+    #[test]
+    fn test_cancelling_mid_search_leaves_the_cache_untouched() {
+        let mut engine = MathEngine::new(HashMap::new(), HashMap::new());
+        engine.set_output_sink(Arc::new(BufferedSink::new()));
+        // Far more trees than can be evaluated before the stop arrives
+        engine.set_search_config(SearchConfig { max_operators: 7, max_depth: None, max_candidates: usize::MAX });
+        let token = CancellationToken::new();
+        engine.set_cancellation_token(token.clone());
+
+        let delay = Duration::from_millis(100);
+        let canceller = std::thread::spawn(move || {
+            std::thread::sleep(delay);
+            token.cancel();
+        });
+        let started = Instant::now();
+        let inputs = [2.0, 3.0, 5.0, 7.0, 11.0, 13.0, 17.0, 19.0];
+        let error = engine.solve_target(1e9 + 0.123, &inputs, "x", "Main").unwrap_err();
+        canceller.join().unwrap();
+
+        assert_eq!(error.downcast_ref::<Cancelled>(), Some(&Cancelled));
+        // Still searching when the stop came, and stopped soon after
+        assert!(started.elapsed() >= delay && started.elapsed() < Duration::from_secs(5), "{:?}", started.elapsed());
+        assert!(engine.get_solutions().is_empty());
+        assert!(engine.get_variable_attempts().is_empty());
+    }
+
+    #[test]
+    fn test_integer_misses_say_whether_the_search_ran_out() {
+        let target = "1152921504606846979".parse::<BigInt>().unwrap();
+        let inputs = [BigInt::from_f64(2.0).unwrap(), BigInt::from_f64(3.0).unwrap()];
+        let notes = |search: SearchConfig| {
+            let mut engine = MathEngine::new(HashMap::new(), HashMap::new());
+            let sink = Arc::new(BufferedSink::new());
+            engine.set_output_sink(sink.clone());
+            engine.set_search_config(search);
+            let solution = engine.solve_integer_target(&target, &inputs, "x", "Main", SolveOptions::default()).unwrap();
+            assert!(solution.accuracy < 100.0);
+            sink.lines().into_iter()
+                .filter(|line| line.message.contains("No solution found") || line.message.contains("No exact match"))
+                .map(|line| (line.level, line.message))
+                .collect::<Vec<_>>()
+        };
+
+        let exhausted = notes(SearchConfig { max_operators: 1, max_depth: None, max_candidates: usize::MAX });
+        assert_eq!(exhausted.len(), 1);
+        assert_eq!(exhausted[0].0, Some(DiagnosticLevel::Info));
+        assert!(exhausted[0].1.contains("within the searched operators/limits: all"), "{}", exhausted[0].1);
+
+        let truncated = notes(SearchConfig { max_operators: 1, max_depth: None, max_candidates: 2 });
+        assert_eq!(truncated.len(), 1);
+        assert_eq!(truncated[0].0, Some(DiagnosticLevel::Warn));
+        assert!(truncated[0].1.contains("within the search limits (2 candidates)"), "{}", truncated[0].1);
+    }
+
+    #[test]
+    fn test_tree_search_reaches_targets_the_fixed_shapes_miss() {
+        let program = r#"* <main> Deep {
+    ^ observe_execution {
+        total([60]) <> randomChoice([2, 3, 5, 7])
+    }
+}"#;
+        let mut engine = Engine::builder().seed(7).build().unwrap();
+        let outcome = engine.run_source(program).unwrap();
+        let solution = outcome.solutions.values().next().unwrap();
+        assert_eq!((solution.result, solution.accuracy), (60.0, 100.0));
+        assert!(outcome.output.iter().any(|line| line.message.contains("Exact match found by tree search")));
+
+        let shallow = SearchConfig { max_operators: 2, ..Default::default() };
+        let mut engine = Engine::builder().seed(7).search(shallow).build().unwrap();
+        let outcome = engine.run_source(program).unwrap();
+        assert!(outcome.solutions.values().next().unwrap().accuracy < 100.0);
+    }
+
+    #[test]
+    fn test_solving_rules_use_each_input_once() {
+        let program = r#"* <main> Puzzle {
+    ^ observe_execution {
+        total([60]) <> randomChoice([2, 3, 5, 7], rules: all)
+        stuck([100]) <> randomChoice([1, 1, 1], rules: once)
+        speak("total ~total~")
+    }
+}"#;
+        for mut engine in both_backends(|builder| builder.seed(7)) {
+            let outcome = engine.run_source(program).unwrap();
+            assert_eq!(outcome.program_lines(), ["total 60"]);
+
+            let (_, total) = outcome.solutions.iter().find(|(key, _)| key.ends_with("-2,3,5,7-all")).unwrap();
+            assert!(["2", "3", "5", "7"].iter().all(|n| total.equation.contains(n)), "{}", total.equation);
+            assert!(outcome.output.iter().any(|line| line.message
+                .contains("No solution found for target 100 using each input at most once within the searched operators/limits")));
+        }
+
+        let mut engine = Engine::builder().build().unwrap();
+        let unknown = program.replace("rules: all", "rules: twice");
+        assert!(engine.run_source(&unknown).unwrap_err().to_string().contains("Unknown solving rules 'twice'"));
+    }
+
+    #[test]
+    fn test_rational_inputs_match_exactly() {
+        let mut engine = quiet_engine();
+
+        // 1.1 * 3 is 3.3000000000000003 in f64, which used to miss by more than EPSILON
+        let product = engine.solve_target(3.3, &[1.1, 3.0], "product", "Exact").unwrap();
+        assert_eq!((product.equation.as_str(), product.accuracy), ("1.1 * 3", 100.0));
+        assert_eq!(engine.solve_target(0.3, &[0.1, 3.0], "ratio", "Exact").unwrap().accuracy, 100.0);
+        assert!(engine.get_solutions().contains_key("Exact-product-3.3-1.1,3"));
+    }
+
+    #[test]
+    fn test_integer_targets_past_f64_are_exact() {
+        let program = r#"* <main> Big {
+    ^ observe_execution {
+        big([15511210043330985984000001]) <> randomChoice([25, 1, 2])
+        speak("big is ~big~")
+        woof big
+    }
+}"#;
+        let mut engine = Engine::builder().build().unwrap();
+        let outcome = engine.run_source(program).unwrap();
+
+        let exact = "15511210043330985984000001".parse::<BigInt>().unwrap();
+        assert_eq!(outcome.program_lines()[0], "big is 15511210043330985984000001");
+        assert_eq!(outcome.result.unwrap().value, VariableValue::Integer(exact.clone()));
+
+        // The cache round-trips through JSON without rounding
+        let solution = &outcome.solutions["Big-big-15511210043330985984000001-25,1,2"];
+        assert_eq!((solution.accuracy, solution.exact.as_ref()), (100.0, Some(&exact)));
+        let json = serde_json::to_string(solution).unwrap();
+        assert_eq!(serde_json::from_str::<MathSolution>(&json).unwrap().exact, Some(exact));
+    }
+
+    #[test]
+    fn test_integer_inputs_past_f64_are_not_rounded() {
+        let program = r#"* <main> Big {
+    ^ observe_execution {
+        big([1152921504606846977]) <> randomChoice([1152921504606846976, 1])
+        next([1152921504606846979]) <> randomChoice([big, 2])
+        woof next
+    }
+}"#;
+        let mut engine = Engine::builder().build().unwrap();
+        let outcome = engine.run_source(program).unwrap();
+
+        // 2^60 + 1 rounds to 2^60 as f64, so `big + 2` only hits from the exact input
+        let exact = "1152921504606846979".parse::<BigInt>().unwrap();
+        assert_eq!(outcome.result.unwrap().value, VariableValue::Integer(exact.clone()));
+        let solution = &outcome.solutions["Big-next-1152921504606846979-1152921504606846977,2"];
+        assert_eq!((solution.accuracy, solution.exact.as_ref()), (100.0, Some(&exact)));
+    }
+
+    #[test]
+    fn test_ranked_alternatives() {
+        let math = quiet_engine();
+
+        let exact = math.enumerate_exact(5.0, &[3.0, 4.0, 1.0]).unwrap();
+        let equations: Vec<&str> = exact.iter().map(|s| s.equation.as_str()).collect();
+        assert_eq!(equations[0], "4 + 1");
+        assert!(equations.contains(&"hypot(3, 4)"), "{:?}", equations);
+        assert!(exact.iter().all(|s| s.accuracy == 100.0 && s.cost.is_some()));
+        assert!(exact.windows(2).all(|pair| pair[0].cost <= pair[1].cost));
+        let keys: std::collections::HashSet<String> = exact.iter()
+            .map(|s| EquationNode::parse(&s.equation).unwrap().canonical_key())
+            .collect();
+        assert_eq!(keys.len(), exact.len());
+
+        // Four inputs take three operators, past what the fixed shapes build
+        let deep = math.enumerate_exact(5.0, &[2.0, 3.0, 10.0, 7.0]).unwrap();
+        assert!(deep.iter().any(|s| EquationNode::parse(&s.equation).unwrap().operator_uses().len() == 3), "{:?}", deep);
+
+        // Past the exact ones come the closest misses
+        let top = math.solve_target_topk(5.0, &[3.0, 4.0, 1.0], exact.len() + 2).unwrap();
+        assert_eq!(top.len(), exact.len() + 2);
+        assert!(top[exact.len()].accuracy < 100.0);
+        assert!(top.windows(2).all(|pair| pair[0].accuracy >= pair[1].accuracy));
+        assert!(math.enumerate_exact(1000.0, &[2.0, 3.0]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_all_choices_lists_equations() {
+        let program = r#"* <main> Alts {
+    ^ observe_execution {
+        fives([5]) <> allChoices([3, 4, 1], ops: basic)
+        far([1000]) <> allChoices([2, 3])
+    }
+}"#;
+        for mut engine in both_backends(|builder| builder) {
+            let outcome = engine.run_source(program).unwrap();
+
+            let Some(VariableValue::List(fives)) = outcome.variable("fives") else {
+                panic!("fives is not a list: {:?}", outcome.variable("fives"));
+            };
+            assert_eq!(fives[0], VariableValue::Record(vec![
+                ("equation".to_string(), VariableValue::String("4 + 1".to_string())),
+                ("result".to_string(), VariableValue::Number(5.0)),
+                ("accuracy".to_string(), VariableValue::Number(100.0)),
+                ("cost".to_string(), VariableValue::Number(1.0)),
+            ]));
+            // `ops: basic` leaves hypot out
+            assert!(!format!("{:?}", fives).contains("hypot"));
+
+            // No exact equation: the closest few instead
+            let Some(VariableValue::List(far)) = outcome.variable("far") else { panic!() };
+            assert_eq!(far.len(), 5);
+
+            // Listing caches nothing
+            assert!(outcome.solutions.is_empty());
+        }
+
+        let mut engine = Engine::builder().build().unwrap();
+        let strict = program.replace("ops: basic", "rules: once");
+        assert!(engine.run_source(&strict).unwrap_err().to_string().contains("allChoices takes `ops: ...`"));
+    }
+}
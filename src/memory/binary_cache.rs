@@ -5,6 +5,7 @@ use bincode;
 use anyhow::{Result, Context};
 
 use super::compact_solution::{CompactSolution, OperandPool};
+use crate::output_sink::SharedSink;
 use crate::MathSolution;
 
 pub struct BinaryCache {
@@ -29,18 +30,20 @@ impl BinaryCache {
     }
 
     pub fn from_hashmap(
-        math_solutions: std::collections::HashMap<String, MathSolution>
+        math_solutions: std::collections::HashMap<String, MathSolution>,
+        output: &SharedSink
     ) -> Result<Self> {
-        Self::from_hashmap_with_path(math_solutions, "quantum_cache.bin")
+        Self::from_hashmap_with_path(math_solutions, "quantum_cache.bin", output)
     }
 
     pub fn from_hashmap_with_path(
         math_solutions: std::collections::HashMap<String, MathSolution>,
-        file_path: &str
+        file_path: &str,
+        output: &SharedSink
     ) -> Result<Self> {
         let mut binary_cache = Self::new(file_path)?;
 
-        println!(">> Converting {} solutions to binary format...", math_solutions.len());
+        output.debug(&format!(">> Converting {} solutions to binary format...", math_solutions.len()));
         let start = std::time::Instant::now();
 
         for (_key, solution) in &math_solutions {
@@ -52,14 +55,13 @@ impl BinaryCache {
             binary_cache.solutions.push(compact);
         }
 
-        let duration = start.elapsed();
-        println!("   Converted {} solutions in {:?}",
-                 binary_cache.solutions.len(), duration);
+        output.debug(&format!("   Converted {} solutions in {:?}", binary_cache.solutions.len(), start.elapsed()));
 
         Ok(binary_cache)
     }
 
-    pub fn save_to_disk(&self) -> Result<()> {
+    /// Write the cache to disk, returning the number of bytes written
+    pub fn save_to_disk(&self, output: &SharedSink) -> Result<usize> {
         let start = std::time::Instant::now();
 
         let encoded = bincode::serialize(&(
@@ -74,15 +76,12 @@ impl BinaryCache {
         file.write_all(&encoded)
             .context("Failed to write binary cache")?;
 
-        let duration = start.elapsed();
-        let size_kb = encoded.len() / 1024;
-
-        println!(">> Saved binary cache: {} KB in {:?}", size_kb, duration);
+        output.debug(&format!(">> Saved binary cache: {} KB in {:?}", encoded.len() / 1024, start.elapsed()));
 
-        Ok(())
+        Ok(encoded.len())
     }
 
-    pub fn load_from_disk(file_path: &str) -> Result<Self> {
+    pub fn load_from_disk(file_path: &str, output: &SharedSink) -> Result<Self> {
         if !Path::new(file_path).exists() {
             return Err(anyhow::anyhow!("Binary cache file not found: {}", file_path));
         }
@@ -99,9 +98,7 @@ impl BinaryCache {
             bincode::deserialize(&encoded)
                 .context("Failed to deserialize binary cache")?;
 
-        let duration = start.elapsed();
-        println!(">> Loaded {} solutions from binary cache in {:?}",
-                 solutions.len(), duration);
+        output.debug(&format!(">> Loaded {} solutions from binary cache in {:?}", solutions.len(), start.elapsed()));
 
         Ok(Self {
             solutions,
@@ -155,6 +152,9 @@ mod tests {
             accuracy: 100.0,
             timestamp: cache.start_time,
             attempts: 1,
+            formula: None,
+            exact: None,
+            cost: None,
         };
 
         cache.insert_solution(solution);
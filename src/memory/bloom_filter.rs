@@ -1,6 +1,8 @@
 use bitvec::prelude::*;
 use serde::{Serialize, Deserialize};
 
+use crate::output_sink::SharedSink;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BloomFilter {
     bits: BitVec,
@@ -9,7 +11,14 @@ pub struct BloomFilter {
 }
 
 impl BloomFilter {
-    pub fn new(expected_items: usize, false_positive_rate: f64) -> Self {
+    pub fn new(expected_items: usize, false_positive_rate: f64, output: &SharedSink) -> Self {
+        let bloom = Self::sized(expected_items, false_positive_rate);
+        output.debug(&format!(">> Creating bloom filter: {} bits, {} hashes",
+                bloom.bits.len(), bloom.hash_count));
+        bloom
+    }
+
+    fn sized(expected_items: usize, false_positive_rate: f64) -> Self {
         if expected_items == 0 {
             return Self {
                 bits: bitvec![0; 64],
@@ -27,9 +36,6 @@ impl BloomFilter {
                          std::f64::consts::LN_2).ceil() as usize;
         let hash_count = hash_count.max(1).min(10); // Reasonable bounds
 
-        println!(">> Creating bloom filter: {} bits, {} hashes",
-                 bit_count, hash_count);
-
         Self {
             bits: bitvec![0; bit_count],
             hash_count,
@@ -94,17 +100,23 @@ impl BloomFilter {
 
 impl Default for BloomFilter {
     fn default() -> Self {
-        Self::new(1000, 0.01)
+        Self::sized(1000, 0.01)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::output_sink::BufferedSink;
+    use std::sync::Arc;
+
+    fn sink() -> SharedSink {
+        Arc::new(BufferedSink::new())
+    }
 
     #[test]
     fn test_bloom_filter_basic() {
-        let mut bloom = BloomFilter::new(100, 0.01);
+        let mut bloom = BloomFilter::new(100, 0.01, &sink());
 
         bloom.insert(42.0);
         bloom.insert(3.14);
@@ -116,7 +128,7 @@ mod tests {
 
     #[test]
     fn test_bloom_filter_false_positive_rate() {
-        let mut bloom = BloomFilter::new(1000, 0.01);
+        let mut bloom = BloomFilter::new(1000, 0.01, &sink());
 
         for i in 0..1000 {
             bloom.insert(i as f32);
@@ -128,7 +140,7 @@ mod tests {
 
     #[test]
     fn test_bloom_filter_negative_lookups() {
-        let mut bloom = BloomFilter::new(100, 0.01);
+        let mut bloom = BloomFilter::new(100, 0.01, &sink());
 
         for i in 0..100 {
             bloom.insert(i as f32);
@@ -67,6 +67,8 @@ impl CompactSolution {
             timestamp: start_time + (self.timestamp_delta as u64 * 1000),
             attempts: 1,
             formula: Some(equation),
+            exact: None,
+            cost: None,
         }
     }
 }
@@ -3,6 +3,7 @@ use rayon::prelude::*;
 use serde::{Serialize, Deserialize};
 
 use super::compact_solution::CompactSolution;
+use crate::output_sink::SharedSink;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PartitionedIndex {
@@ -17,7 +18,7 @@ pub struct PartitionedIndex {
 }
 
 impl PartitionedIndex {
-    pub fn build_from_solutions(solutions: &[CompactSolution]) -> Self {
+    pub fn build_from_solutions(solutions: &[CompactSolution], output: &SharedSink) -> Self {
         if solutions.is_empty() {
             return Self::default();
         }
@@ -53,10 +54,10 @@ impl PartitionedIndex {
             }
         }
 
-        println!(">> Built partitioned index:");
-        println!("   Head: {} entries (0.0 - {})", head.len(), head_max);
-        println!("   Middle: {} entries ({} - {})", middle.len(), head_max, middle_max);
-        println!("   Tail: {} entries ({} - max)", tail.len(), middle_max);
+        output.debug(">> Built partitioned index:");
+        output.debug(&format!("   Head: {} entries (0.0 - {})", head.len(), head_max));
+        output.debug(&format!("   Middle: {} entries ({} - {})", middle.len(), head_max, middle_max));
+        output.debug(&format!("   Tail: {} entries ({} - max)", tail.len(), middle_max));
 
         Self {
             head,
@@ -122,7 +123,9 @@ impl Default for PartitionedIndex {
 mod tests {
     use super::*;
     use crate::memory::compact_solution::{CompactSolution, OperandPool};
+    use crate::output_sink::BufferedSink;
     use crate::MathSolution;
+    use std::sync::Arc;
 
     #[test]
     fn test_partitioned_index_basic() {
@@ -137,11 +140,16 @@ mod tests {
                 accuracy: 100.0,
                 timestamp: start_time,
                 attempts: 1,
+                formula: None,
+                exact: None,
+                cost: None,
             };
             solutions.push(CompactSolution::from_math_solution(&sol, &mut pool, start_time));
         }
 
-        let index = PartitionedIndex::build_from_solutions(&solutions);
+        let sink = Arc::new(BufferedSink::new());
+        let index = PartitionedIndex::build_from_solutions(&solutions, &(sink.clone() as SharedSink));
+        assert_eq!(sink.lines()[0].message, ">> Built partitioned index:");
 
         // Test smart search
         assert!(index.smart_search(5.0).is_some());
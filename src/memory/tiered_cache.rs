@@ -7,6 +7,7 @@ use super::binary_cache::BinaryCache;
 use super::bloom_filter::BloomFilter;
 use super::partitioned_index::PartitionedIndex;
 use super::compact_solution::CompactSolution;
+use crate::output_sink::SharedSink;
 use crate::MathSolution;
 
 pub struct TieredMemory {
@@ -31,27 +32,31 @@ pub struct TieredMemory {
     warm_hits: u64,
     cold_hits: u64,
     misses: u64,
+
+    output: SharedSink,
 }
 
 impl TieredMemory {
-    pub fn new(cold_storage: BinaryCache) -> Self {
+    pub fn new(cold_storage: BinaryCache, output: SharedSink) -> Self {
         // Build index on initialization
         let cold_index = PartitionedIndex::build_from_solutions(
-            &cold_storage.solutions
+            &cold_storage.solutions,
+            &output
         );
 
         // Build bloom filter from cold storage
         let mut bloom = BloomFilter::new(
             cold_storage.solutions.len().max(1),
-            0.01  // 1% false positive rate
+            0.01,  // 1% false positive rate
+            &output
         );
 
         for solution in &cold_storage.solutions {
             bloom.insert(solution.result);
         }
 
-        println!(">> Bloom filter FP rate: {:.2}%",
-                 bloom.expected_false_positive_rate() * 100.0);
+        output.debug(&format!(">> Bloom filter FP rate: {:.2}%",
+                bloom.expected_false_positive_rate() * 100.0));
 
         Self {
             hot_cache: LruCache::new(NonZeroUsize::new(100).unwrap()),
@@ -64,6 +69,7 @@ impl TieredMemory {
             warm_hits: 0,
             cold_hits: 0,
             misses: 0,
+            output,
         }
     }
 
@@ -167,32 +173,30 @@ impl TieredMemory {
 
     fn rebuild_index(&mut self) {
         self.cold_index = PartitionedIndex::build_from_solutions(
-            &self.cold_storage.solutions
+            &self.cold_storage.solutions,
+            &self.output
         );
     }
 
     pub fn save(&self) -> Result<()> {
-        self.cold_storage.save_to_disk()
+        self.cold_storage.save_to_disk(&self.output)?;
+        Ok(())
     }
 
     pub fn print_stats(&self) {
         let total = self.hot_hits + self.warm_hits + self.cold_hits + self.misses;
+        self.output.info("=== Cache Performance ===");
         if total == 0 {
-            println!("\n=== Cache Performance ===");
-            println!("No queries yet");
+            self.output.info("No queries yet");
             return;
         }
 
-        println!("\n=== Cache Performance ===");
-        println!("Hot hits:   {} ({:.1}%)", self.hot_hits,
-                 (self.hot_hits as f64 / total as f64) * 100.0);
-        println!("Warm hits:  {} ({:.1}%)", self.warm_hits,
-                 (self.warm_hits as f64 / total as f64) * 100.0);
-        println!("Cold hits:  {} ({:.1}%)", self.cold_hits,
-                 (self.cold_hits as f64 / total as f64) * 100.0);
-        println!("Misses:     {} ({:.1}%)", self.misses,
-                 (self.misses as f64 / total as f64) * 100.0);
-        println!("Total solutions: {}", self.cold_storage.len());
+        let share = |hits: u64| (hits as f64 / total as f64) * 100.0;
+        self.output.info(&format!("Hot hits:   {} ({:.1}%)", self.hot_hits, share(self.hot_hits)));
+        self.output.info(&format!("Warm hits:  {} ({:.1}%)", self.warm_hits, share(self.warm_hits)));
+        self.output.info(&format!("Cold hits:  {} ({:.1}%)", self.cold_hits, share(self.cold_hits)));
+        self.output.info(&format!("Misses:     {} ({:.1}%)", self.misses, share(self.misses)));
+        self.output.info(&format!("Total solutions: {}", self.cold_storage.len()));
     }
 
     pub fn total_queries(&self) -> u64 {
@@ -211,11 +215,13 @@ impl TieredMemory {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::output_sink::BufferedSink;
+    use std::sync::Arc;
 
     #[test]
     fn test_tiered_memory_basic() {
         let cache = BinaryCache::new("test.bin").unwrap();
-        let mut tiered = TieredMemory::new(cache);
+        let mut tiered = TieredMemory::new(cache, Arc::new(BufferedSink::new()));
 
         let solution = MathSolution {
             result: 42.0,
@@ -223,6 +229,9 @@ mod tests {
             accuracy: 100.0,
             timestamp: 0,
             attempts: 1,
+            formula: None,
+            exact: None,
+            cost: None,
         };
 
         tiered.insert_solution(solution);
@@ -237,7 +246,8 @@ mod tests {
     #[test]
     fn test_cache_promotion() {
         let cache = BinaryCache::new("test.bin").unwrap();
-        let mut tiered = TieredMemory::new(cache);
+        let sink = Arc::new(BufferedSink::new());
+        let mut tiered = TieredMemory::new(cache, sink.clone());
 
         // Insert many solutions to fill hot cache
         for i in 0..150 {
@@ -247,6 +257,9 @@ mod tests {
                 accuracy: 100.0,
                 timestamp: 0,
                 attempts: 1,
+                formula: None,
+                exact: None,
+                cost: None,
             };
             tiered.insert_solution(solution);
         }
@@ -256,5 +269,7 @@ mod tests {
         assert!(retrieved.is_some());
 
         tiered.print_stats();
+        let stats: Vec<String> = sink.lines().into_iter().map(|line| line.message).collect();
+        assert!(stats.contains(&"=== Cache Performance ===".to_string()), "{:?}", stats);
     }
 }
@@ -0,0 +1,190 @@
+// Functions provided by the embedding application
+// Callable from .slut assignments, conditions and interpolation like builtins
+
+use anyhow::Result;
+use evalexpr::{EvalexprError, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::VariableValue;
+
+pub type NativeFn = Arc<dyn Fn(&[VariableValue]) -> Result<VariableValue> + Send + Sync>;
+
+/// Names the language already uses for its own calls
+const RESERVED_NAMES: &[&str] = &[
+    "calc", "randomChoice", "allChoices", "userIn", "speak", "woof", "function", "loop", "if", "match",
+    "readFile", "readLines", "readCsv", "writeFile",
+];
+
+#[derive(Clone)]
+pub struct NativeFunction {
+    pub name: String,
+    pub arity: usize,
+    function: NativeFn,
+}
+
+impl NativeFunction {
+    pub fn call(&self, args: &[VariableValue]) -> Result<VariableValue> {
+        if args.len() != self.arity {
+            return Err(anyhow::anyhow!(
+                "{}() takes {} argument(s) but {} were given", self.name, self.arity, args.len()
+            ));
+        }
+        (self.function)(args)
+    }
+
+    /// Wrap for an evalexpr context, which passes several arguments as a tuple
+    pub fn to_evalexpr(&self) -> evalexpr::Function {
+        let native = self.clone();
+        evalexpr::Function::new(move |argument: &Value| {
+            let args: Vec<VariableValue> = match argument {
+                Value::Empty => Vec::new(),
+                Value::Tuple(values) if native.arity != 1 => values.iter().map(from_evalexpr).collect(),
+                single => vec![from_evalexpr(single)],
+            };
+            native.call(&args)
+                .map(|value| to_evalexpr(&value))
+                .map_err(|e| EvalexprError::CustomMessage(e.to_string()))
+        })
+    }
+}
+
+/// Registry of host functions, shared by the interpreter and the condition evaluator
+#[derive(Clone, Default)]
+pub struct NativeFunctions {
+    functions: HashMap<String, NativeFunction>,
+}
+
+impl NativeFunctions {
+    pub fn register(&mut self, name: &str, arity: usize, function: NativeFn) -> Result<()> {
+        if RESERVED_NAMES.contains(&name) {
+            return Err(anyhow::anyhow!("Cannot register '{}': it is a built-in", name));
+        }
+        if self.functions.contains_key(name) {
+            return Err(anyhow::anyhow!("Native function '{}' is already registered", name));
+        }
+
+        self.functions.insert(name.to_string(), NativeFunction {
+            name: name.to_string(),
+            arity,
+            function,
+        });
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&NativeFunction> {
+        self.functions.get(name)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.functions.contains_key(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &NativeFunction> {
+        self.functions.values()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.functions.is_empty()
+    }
+}
+
+pub fn to_evalexpr(value: &VariableValue) -> Value {
+    match value {
+        VariableValue::Number(n) => Value::from(*n),
+        // Rounded: evalexpr has no big integers, and comparisons only need the magnitude
+        VariableValue::Integer(n) => Value::from(n.to_f64()),
+        VariableValue::Boolean(b) => Value::from(*b),
+        VariableValue::String(s) => Value::from(s.as_str()),
+        VariableValue::FunctionResult(f) => Value::from(f.as_str()),
+        VariableValue::List(items) => Value::Tuple(items.iter().map(to_evalexpr).collect()),
+        VariableValue::Record(_) => Value::from(crate::display_value(value)),
+    }
+}
+
+pub fn from_evalexpr(value: &Value) -> VariableValue {
+    match value {
+        Value::Float(n) => VariableValue::Number(*n),
+        Value::Int(n) => VariableValue::Number(*n as f64),
+        Value::Boolean(b) => VariableValue::Boolean(*b),
+        Value::String(s) => VariableValue::String(s.clone()),
+        Value::Tuple(values) => VariableValue::List(values.iter().map(from_evalexpr).collect()),
+        other => VariableValue::String(other.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::Engine;
+
+    fn double() -> NativeFn {
+        Arc::new(|args: &[VariableValue]| match &args[0] {
+            VariableValue::Number(n) => Ok(VariableValue::Number(n * 2.0)),
+            _ => Err(anyhow::anyhow!("double() needs a number")),
+        })
+    }
+
+    #[test]
+    fn test_register_rejects_duplicates_and_builtins() {
+        let mut natives = NativeFunctions::default();
+        natives.register("double", 1, double()).unwrap();
+        assert!(natives.register("double", 1, double()).is_err());
+        assert!(natives.register("calc", 2, double()).is_err());
+    }
+
+    #[test]
+    fn test_call_checks_arity() {
+        let mut natives = NativeFunctions::default();
+        natives.register("double", 1, double()).unwrap();
+        let double = natives.get("double").unwrap();
+
+        assert!(matches!(double.call(&[VariableValue::Number(4.0)]).unwrap(), VariableValue::Number(n) if n == 8.0));
+        assert!(double.call(&[]).is_err());
+    }
+
+    #[test]
+    fn test_registered_functions() {
+        let mut engine = Engine::builder().build().unwrap();
+        engine.register_fn("celsius", 1, |args: &[VariableValue]| match &args[0] {
+            VariableValue::Number(f) => Ok(VariableValue::Number((f - 32.0) * 5.0 / 9.0)),
+            _ => Err(anyhow::anyhow!("celsius() needs a number")),
+        }).unwrap();
+        assert!(engine.register_fn("celsius", 1, |_: &[VariableValue]| Ok(VariableValue::Boolean(true))).is_err());
+
+        let outcome = engine.run_source(r#"* <main> Weather {
+    ^ observe_execution {
+        f <> 212
+        c <> celsius(f)
+        speak("boils at ~celsius(f)~ C")
+        if <> (celsius(f) > 50) <else> (true) {
+            speak("hot")
+            <>
+            speak("cold")
+        }
+    }
+}"#).unwrap();
+        assert!(matches!(outcome.variable("c"), Some(VariableValue::Number(n)) if *n == 100.0));
+        assert_eq!(outcome.program_lines()[..2], ["boils at 100 C", "hot"]);
+    }
+
+    #[test]
+    fn test_registered_function_clashes_with_function_class() {
+        let mut engine = Engine::builder().build().unwrap();
+        engine.register_fn("Helper", 0, |_: &[VariableValue]| Ok(VariableValue::Number(1.0))).unwrap();
+
+        let result = engine.run_source(r#"* Helper {
+    ^ observe_execution {
+        x <> 1
+        woof x
+    }
+}
+
+* <main> Uses {
+    ^ observe_execution {
+        y <> Helper()
+    }
+}"#);
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,354 @@
+// Which operators the solver may put in an equation
+// Set per engine (CLI, quantum.toml) or per solve (`randomChoice([...], ops: basic)`)
+
+use anyhow::Result;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::equation_tree::BinaryOp;
+
+/// One operator or function the solver can emit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operator {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Pow,
+    Factorial,
+    Sqrt,
+    Abs,
+    Ceil,
+    Floor,
+    Max,
+    Min,
+    Hypot,
+    Atan2,
+    Avg,
+    Geomean,
+}
+
+impl Operator {
+    pub const ALL: [Operator; 17] = [
+        Operator::Add,
+        Operator::Sub,
+        Operator::Mul,
+        Operator::Div,
+        Operator::Rem,
+        Operator::Pow,
+        Operator::Factorial,
+        Operator::Sqrt,
+        Operator::Abs,
+        Operator::Ceil,
+        Operator::Floor,
+        Operator::Max,
+        Operator::Min,
+        Operator::Hypot,
+        Operator::Atan2,
+        Operator::Avg,
+        Operator::Geomean,
+    ];
+
+    /// How the operator is written in equations and operator lists
+    pub fn name(&self) -> &'static str {
+        match self {
+            Operator::Add => "+",
+            Operator::Sub => "-",
+            Operator::Mul => "*",
+            Operator::Div => "/",
+            Operator::Rem => "%",
+            Operator::Pow => "^",
+            Operator::Factorial => "!",
+            Operator::Sqrt => "sqrt",
+            Operator::Abs => "abs",
+            Operator::Ceil => "ceil",
+            Operator::Floor => "floor",
+            Operator::Max => "max",
+            Operator::Min => "min",
+            Operator::Hypot => "hypot",
+            Operator::Atan2 => "atan2",
+            Operator::Avg => "avg",
+            Operator::Geomean => "geomean",
+        }
+    }
+
+    /// By symbol, function name or spelled-out alias (`mul`, `×`, `factorial`)
+    pub fn from_name(name: &str) -> Option<Self> {
+        let alias = match name {
+            "add" | "plus" => "+",
+            "sub" | "minus" | "\u{2212}" => "-",
+            "mul" | "times" | "\u{d7}" => "*",
+            "div" | "\u{f7}" => "/",
+            "rem" | "mod" => "%",
+            "pow" => "^",
+            "factorial" => "!",
+            other => other,
+        };
+        Self::ALL.into_iter().find(|op| op.name() == alias)
+    }
+
+    fn bit(&self) -> u32 {
+        1 << *self as u32
+    }
+}
+
+impl From<BinaryOp> for Operator {
+    fn from(op: BinaryOp) -> Self {
+        match op {
+            BinaryOp::Add => Operator::Add,
+            BinaryOp::Sub => Operator::Sub,
+            BinaryOp::Mul => Operator::Mul,
+            BinaryOp::Div => Operator::Div,
+            BinaryOp::Rem => Operator::Rem,
+            BinaryOp::Pow => Operator::Pow,
+        }
+    }
+}
+
+/// The operators a solve may use; everything by default
+///
+/// Written as comma-separated terms: a preset (`basic`, `arith+pow`, `all`),
+/// an operator to allow, or `!name` to deny one. Allowed operators start
+/// from nothing, so `+, -, *` is exactly those three; a spec of only denies
+/// starts from `all`, so `!atan2, !hypot` removes two. Denies win.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct OperatorSet {
+    bits: u32,
+}
+
+impl OperatorSet {
+    pub const EMPTY: OperatorSet = OperatorSet { bits: 0 };
+    pub const ALL: OperatorSet = OperatorSet { bits: (1 << Operator::ALL.len()) - 1 };
+
+    /// `+ - * /`
+    pub fn basic() -> Self {
+        Self::of(&[Operator::Add, Operator::Sub, Operator::Mul, Operator::Div])
+    }
+
+    /// `basic` and `^`
+    pub fn arith_pow() -> Self {
+        Self::basic().with(Operator::Pow)
+    }
+
+    /// Named presets, in the order they are tried when naming a set
+    pub fn presets() -> [(&'static str, OperatorSet); 3] {
+        [("all", Self::ALL), ("basic", Self::basic()), ("arith+pow", Self::arith_pow())]
+    }
+
+    pub fn of(operators: &[Operator]) -> Self {
+        operators.iter().fold(Self::EMPTY, |set, &op| set.with(op))
+    }
+
+    pub fn with(self, op: Operator) -> Self {
+        Self { bits: self.bits | op.bit() }
+    }
+
+    pub fn union(self, other: OperatorSet) -> Self {
+        Self { bits: self.bits | other.bits }
+    }
+
+    pub fn without(self, op: Operator) -> Self {
+        Self { bits: self.bits & !op.bit() }
+    }
+
+    pub fn contains(&self, op: Operator) -> bool {
+        self.bits & op.bit() != 0
+    }
+
+    /// Every operator in `other` is also in this set
+    pub fn covers(&self, other: OperatorSet) -> bool {
+        other.bits & !self.bits == 0
+    }
+
+    pub fn is_all(&self) -> bool {
+        *self == Self::ALL
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Operator> + '_ {
+        Operator::ALL.into_iter().filter(|op| self.contains(*op))
+    }
+
+    /// Appended to solution cache keys; empty for `all`, so existing keys stay valid
+    pub fn cache_suffix(&self) -> String {
+        if self.is_all() {
+            String::new()
+        } else {
+            format!("-ops:{}", self.to_string().replace(' ', ""))
+        }
+    }
+}
+
+impl Default for OperatorSet {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+impl FromStr for OperatorSet {
+    type Err = anyhow::Error;
+
+    fn from_str(spec: &str) -> Result<Self> {
+        let spec = spec.trim();
+        let spec = spec.strip_prefix('[').and_then(|s| s.strip_suffix(']')).unwrap_or(spec);
+
+        let mut allowed = None;
+        let mut denied = Self::EMPTY;
+        for term in spec.split(',').map(str::trim).filter(|term| !term.is_empty()) {
+            // A lone `!` is factorial; `!!` denies it
+            let (deny, name) = match term.strip_prefix('!') {
+                Some(name) if !name.trim().is_empty() => (true, name.trim()),
+                _ => (false, term),
+            };
+            let set = match Self::presets().into_iter().find(|(preset, _)| *preset == name) {
+                Some((_, set)) => set,
+                None => Self::of(&[Operator::from_name(name).ok_or_else(|| unknown_operator(name))?]),
+            };
+            if deny {
+                denied = denied.union(set);
+            } else {
+                allowed = Some(allowed.unwrap_or(Self::EMPTY).union(set));
+            }
+        }
+
+        Ok(Self { bits: allowed.unwrap_or(Self::ALL).bits & !denied.bits })
+    }
+}
+
+fn unknown_operator(name: &str) -> anyhow::Error {
+    let names: Vec<&str> = Operator::ALL.iter().map(Operator::name).collect();
+    anyhow::anyhow!("Unknown operator '{}'; expected basic, arith+pow, all or one of {}", name, names.join(" "))
+}
+
+/// A preset name when the set is one, otherwise the operators as `[+, -, sqrt]`
+impl fmt::Display for OperatorSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some((name, _)) = Self::presets().into_iter().find(|(_, set)| set == self) {
+            return write!(f, "{}", name);
+        }
+        let names: Vec<&str> = self.iter().map(|op| op.name()).collect();
+        write!(f, "[{}]", names.join(", "))
+    }
+}
+
+impl Serialize for OperatorSet {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// A spec string, or a table as in `quantum.toml`: `{ preset = "all", deny = ["atan2"] }`
+impl<'de> Deserialize<'de> for OperatorSet {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct Lists {
+            preset: Option<String>,
+            #[serde(default)]
+            allow: Vec<String>,
+            #[serde(default)]
+            deny: Vec<String>,
+        }
+
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Spec {
+            Text(String),
+            Lists(Lists),
+        }
+
+        let text = match Spec::deserialize(deserializer)? {
+            Spec::Text(text) => text,
+            Spec::Lists(Lists { preset, allow, deny }) => {
+                // A table with only `deny` keeps its meaning: start from everything
+                let base = preset.or_else(|| allow.is_empty().then(|| "all".to_string()));
+                base.into_iter()
+                    .chain(allow)
+                    .chain(deny.into_iter().map(|name| format!("!{}", name)))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            }
+        };
+        text.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{both_backends, Engine};
+    use crate::equation_tree::EquationNode;
+
+    #[test]
+    fn test_specs_and_presets() {
+        let parse = |spec: &str| spec.parse::<OperatorSet>().unwrap();
+        assert_eq!(parse("basic"), OperatorSet::of(&[Operator::Add, Operator::Sub, Operator::Mul, Operator::Div]));
+        assert_eq!(parse("arith+pow").to_string(), "arith+pow");
+        assert_eq!(parse("[basic, pow]"), OperatorSet::arith_pow());
+        assert_eq!(parse("+, \u{d7}, sqrt").to_string(), "[+, *, sqrt]");
+        assert_eq!(parse("!atan2, !hypot"), OperatorSet::ALL.without(Operator::Atan2).without(Operator::Hypot));
+        assert_eq!(parse("arith+pow, !^"), OperatorSet::basic());
+        assert_eq!((parse("basic, !"), parse("!!")), (OperatorSet::basic().with(Operator::Factorial), OperatorSet::ALL.without(Operator::Factorial)));
+        assert_eq!(parse(""), OperatorSet::ALL);
+        assert!("basic, cos".parse::<OperatorSet>().unwrap_err().to_string().contains("Unknown operator 'cos'"));
+
+        assert_eq!(OperatorSet::ALL.cache_suffix(), "");
+        assert_eq!(parse("+, -, sqrt").cache_suffix(), "-ops:[+,-,sqrt]");
+
+        let table: OperatorSet = serde_json::from_str(r#"{"deny": ["%", "atan2"]}"#).unwrap();
+        assert_eq!(table, parse("all, !%, !atan2"));
+        let table: OperatorSet = serde_json::from_str(r#"{"preset": "basic", "allow": ["!"]}"#).unwrap();
+        assert_eq!(table, OperatorSet::basic().with(Operator::Factorial));
+        assert_eq!(serde_json::to_string(&OperatorSet::basic()).unwrap(), "\"basic\"");
+    }
+
+    #[test]
+    fn test_display_round_trips_and_bad_specs() {
+        for op in Operator::ALL {
+            let single = OperatorSet::of(&[op]);
+            assert_eq!(single.to_string().parse::<OperatorSet>().unwrap(), single, "{}", op.name());
+            assert_eq!(format!("!{}", op.name()).parse::<OperatorSet>().unwrap(), OperatorSet::ALL.without(op));
+        }
+        for (name, set) in OperatorSet::presets() {
+            assert_eq!(set.to_string(), name);
+        }
+        assert_eq!("[ ]".parse::<OperatorSet>().unwrap(), OperatorSet::ALL);
+        assert!(OperatorSet::arith_pow().covers(OperatorSet::basic()) && !OperatorSet::basic().covers(OperatorSet::arith_pow()));
+
+        assert!("+, sine".parse::<OperatorSet>().unwrap_err().to_string().contains("Unknown operator 'sine'"));
+        assert!("!cos".parse::<OperatorSet>().is_err());
+        assert!(serde_json::from_str::<OperatorSet>(r#"{"deny": ["cos"]}"#).is_err());
+        assert!(serde_json::from_str::<OperatorSet>(r#"{"preset": "basic", "only": ["+"]}"#).is_err());
+    }
+
+    #[test]
+    fn test_operator_sets_limit_equations() {
+        let program = r#"* <main> Ops {
+    ^ observe_execution {
+        side([5]) <> randomChoice([3, 4], ops: basic)
+        hyp([5]) <> randomChoice([3, 4])
+    }
+}"#;
+        for mut engine in both_backends(|builder| builder.seed(7)) {
+            let outcome = engine.run_source(program).unwrap();
+
+            // No + - * / equation over 3 and 4 makes 5; hypot does
+            let side = &outcome.solutions["Ops-side-5-3,4-ops:basic"];
+            assert!(side.accuracy < 100.0, "{}", side.equation);
+            assert!(OperatorSet::basic().covers(EquationNode::parse(&side.equation).unwrap().operators()), "{}", side.equation);
+            assert_eq!(outcome.solutions["Ops-hyp-5-3,4"].equation, "hypot(3, 4)");
+        }
+
+        // The engine-wide set applies where a statement names none, and keys its own cache entries
+        let strict = "all, !hypot, !geomean, !sqrt, !atan2".parse().unwrap();
+        let mut engine = Engine::builder().seed(7).operators(strict).build().unwrap();
+        let outcome = engine.run_source(program).unwrap();
+        let hyp = &outcome.solutions[&format!("Ops-hyp-5-3,4{}", strict.cache_suffix())];
+        assert!(!hyp.equation.contains("hypot"), "{}", hyp.equation);
+        assert!(outcome.solutions.contains_key("Ops-side-5-3,4-ops:basic"));
+
+        let unknown = program.replace("ops: basic", "ops: [+, cos]");
+        assert!(engine.run_source(&unknown).unwrap_err().to_string().contains("Unknown operator 'cos'"));
+    }
+}
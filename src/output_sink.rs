@@ -0,0 +1,311 @@
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+use crate::ConsoleCallback;
+
+/// Severity of an engine diagnostic
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiagnosticLevel {
+    Debug,
+    Info,
+    Warn,
+}
+
+/// Which stream a line of output belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputStream {
+    /// Output the program asked for (`speak`, `woof`)
+    Program,
+    /// Messages from the engine itself
+    Diagnostic,
+}
+
+/// A single captured line of output
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OutputLine {
+    pub stream: OutputStream,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub level: Option<DiagnosticLevel>,
+    pub message: String,
+}
+
+impl OutputLine {
+    /// Send this line to another sink, e.g. when replaying buffered output
+    pub fn emit(&self, sink: &dyn OutputSink) {
+        match (self.stream, self.level) {
+            (OutputStream::Program, _) => sink.program(&self.message),
+            (OutputStream::Diagnostic, level) => sink.diagnostic(level.unwrap_or(DiagnosticLevel::Info), &self.message),
+        }
+    }
+}
+
+/// Destination for everything the engine prints
+///
+/// Program output (`speak`, `woof`) is kept separate from engine diagnostics so
+/// embedders can show one without the other.
+pub trait OutputSink: Send + Sync {
+    /// Emit a line of program output
+    fn program(&self, line: &str);
+
+    /// Emit an engine diagnostic
+    fn diagnostic(&self, level: DiagnosticLevel, message: &str);
+
+    fn debug(&self, message: &str) {
+        self.diagnostic(DiagnosticLevel::Debug, message);
+    }
+
+    fn info(&self, message: &str) {
+        self.diagnostic(DiagnosticLevel::Info, message);
+    }
+
+    fn warn(&self, message: &str) {
+        self.diagnostic(DiagnosticLevel::Warn, message);
+    }
+}
+
+/// Shared handle passed down to every engine component
+pub type SharedSink = Arc<dyn OutputSink>;
+
+/// Default sink for the CLI: prints to stdout
+pub struct StdoutSink {
+    min_level: DiagnosticLevel,
+}
+
+impl StdoutSink {
+    /// Print program output and every diagnostic
+    pub fn new() -> Self {
+        Self::with_level(DiagnosticLevel::Debug)
+    }
+
+    /// Print program output and diagnostics at or above `min_level`
+    pub fn with_level(min_level: DiagnosticLevel) -> Self {
+        Self { min_level }
+    }
+
+    pub fn shared() -> SharedSink {
+        Arc::new(Self::new())
+    }
+}
+
+impl Default for StdoutSink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OutputSink for StdoutSink {
+    fn program(&self, line: &str) {
+        println!("{}", line);
+    }
+
+    fn diagnostic(&self, level: DiagnosticLevel, message: &str) {
+        if level >= self.min_level {
+            println!("{}", message);
+        }
+    }
+}
+
+/// Captures everything in memory (tests, embedding, Tauri result payloads)
+#[derive(Default)]
+pub struct BufferedSink {
+    lines: Mutex<Vec<OutputLine>>,
+}
+
+impl BufferedSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// All captured lines in emission order
+    pub fn lines(&self) -> Vec<OutputLine> {
+        self.lines.lock().unwrap().clone()
+    }
+
+    /// Only the program output (`speak` / `woof`)
+    pub fn program_lines(&self) -> Vec<String> {
+        self.lines.lock().unwrap()
+            .iter()
+            .filter(|line| line.stream == OutputStream::Program)
+            .map(|line| line.message.clone())
+            .collect()
+    }
+
+    pub fn clear(&self) {
+        self.lines.lock().unwrap().clear();
+    }
+
+    fn push(&self, line: OutputLine) {
+        self.lines.lock().unwrap().push(line);
+    }
+}
+
+impl OutputSink for BufferedSink {
+    fn program(&self, line: &str) {
+        self.push(OutputLine {
+            stream: OutputStream::Program,
+            level: None,
+            message: line.to_string(),
+        });
+    }
+
+    fn diagnostic(&self, level: DiagnosticLevel, message: &str) {
+        self.push(OutputLine {
+            stream: OutputStream::Diagnostic,
+            level: Some(level),
+            message: message.to_string(),
+        });
+    }
+}
+
+/// Forwards output to a `ConsoleCallback` (used by the Tauri IDE)
+///
+/// The callback receives the UI level names: program output is `"success"`,
+/// warnings are `"error"`, everything else is `"info"`.
+pub struct CallbackSink {
+    callback: ConsoleCallback,
+    min_level: DiagnosticLevel,
+}
+
+impl CallbackSink {
+    pub fn new(callback: ConsoleCallback) -> Self {
+        Self { callback, min_level: DiagnosticLevel::Info }
+    }
+
+    pub fn with_level(callback: ConsoleCallback, min_level: DiagnosticLevel) -> Self {
+        Self { callback, min_level }
+    }
+}
+
+impl OutputSink for CallbackSink {
+    fn program(&self, line: &str) {
+        (self.callback)(line.to_string(), "success");
+    }
+
+    fn diagnostic(&self, level: DiagnosticLevel, message: &str) {
+        if level < self.min_level {
+            return;
+        }
+        let ui_level = match level {
+            DiagnosticLevel::Warn => "error",
+            DiagnosticLevel::Debug | DiagnosticLevel::Info => "info",
+        };
+        (self.callback)(message.to_string(), ui_level);
+    }
+}
+
+/// Writes one JSON object per line to any writer
+pub struct JsonLinesSink<W: Write + Send> {
+    writer: Mutex<W>,
+}
+
+impl<W: Write + Send> JsonLinesSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer: Mutex::new(writer) }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer.into_inner().unwrap()
+    }
+
+    fn write_line(&self, line: &OutputLine) {
+        if let Ok(json) = serde_json::to_string(line) {
+            let mut writer = self.writer.lock().unwrap();
+            let _ = writeln!(writer, "{}", json);
+            let _ = writer.flush();
+        }
+    }
+}
+
+impl<W: Write + Send> OutputSink for JsonLinesSink<W> {
+    fn program(&self, line: &str) {
+        self.write_line(&OutputLine {
+            stream: OutputStream::Program,
+            level: None,
+            message: line.to_string(),
+        });
+    }
+
+    fn diagnostic(&self, level: DiagnosticLevel, message: &str) {
+        self.write_line(&OutputLine {
+            stream: OutputStream::Diagnostic,
+            level: Some(level),
+            message: message.to_string(),
+        });
+    }
+}
+
+/// Sends every line to several sinks (e.g. capture a run while still printing it)
+pub struct TeeSink {
+    sinks: Vec<SharedSink>,
+}
+
+impl TeeSink {
+    pub fn new(sinks: Vec<SharedSink>) -> Self {
+        Self { sinks }
+    }
+}
+
+impl OutputSink for TeeSink {
+    fn program(&self, line: &str) {
+        for sink in &self.sinks {
+            sink.program(line);
+        }
+    }
+
+    fn diagnostic(&self, level: DiagnosticLevel, message: &str) {
+        for sink in &self.sinks {
+            sink.diagnostic(level, message);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buffered_sink_separates_streams() {
+        let sink = BufferedSink::new();
+        sink.info(">> starting");
+        sink.program("hello");
+        sink.warn("!! careful");
+        sink.program("world");
+
+        assert_eq!(sink.program_lines(), vec!["hello", "world"]);
+        assert_eq!(sink.lines().len(), 4);
+        assert_eq!(sink.lines()[2].level, Some(DiagnosticLevel::Warn));
+    }
+
+    #[test]
+    fn test_json_lines_sink() {
+        let sink = JsonLinesSink::new(Vec::new());
+        sink.program("RESULT: 250");
+        sink.debug("-- detail");
+
+        let output = String::from_utf8(sink.into_inner()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], r#"{"stream":"program","message":"RESULT: 250"}"#);
+        assert_eq!(lines[1], r#"{"stream":"diagnostic","level":"debug","message":"-- detail"}"#);
+    }
+
+    #[test]
+    fn test_callback_sink_filters_debug() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let received_clone = received.clone();
+        let sink = CallbackSink::new(Arc::new(move |message, level| {
+            received_clone.lock().unwrap().push((message, level.to_string()));
+        }));
+
+        sink.debug("-- hidden");
+        sink.info(">> shown");
+        sink.program("out");
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 2);
+        assert_eq!(received[1], ("out".to_string(), "success".to_string()));
+    }
+}
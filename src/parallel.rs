@@ -0,0 +1,392 @@
+// Parallel blocks and parallel range loops
+// Each child runs in an isolated frame on the rayon pool; results merge back in source order
+
+use anyhow::Result;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::cache_store::InMemoryCache;
+use crate::input_source::InputSource;
+use crate::output_sink::{BufferedSink, SharedSink};
+use crate::{
+    display_value, parser, ConditionEvaluator, FunctionBuilder, FunctionExecutor, LoopExecutor,
+    QuantumTranspiler, StoredVariable, VariableValue,
+};
+
+/// Two parallel children wrote different values to the same variable
+#[derive(Debug, Clone, PartialEq)]
+pub struct WriteConflict {
+    pub variable: String,
+    /// Position of the earlier writer in source (or iteration) order
+    pub first: usize,
+    pub first_value: VariableValue,
+    /// Position of the later writer, whose value is kept
+    pub second: usize,
+    pub second_value: VariableValue,
+}
+
+impl fmt::Display for WriteConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Conflicting parallel writes to '{}': child {} wrote {}, child {} wrote {} (keeping child {})",
+            self.variable,
+            self.first + 1, display_value(&self.first_value),
+            self.second + 1, display_value(&self.second_value),
+            self.second + 1)
+    }
+}
+
+/// Parallel range iterations forked and run at a time
+///
+/// Each frame is a full copy of the interpreter, so a wide range is worked
+/// through in chunks rather than forking every iteration up front.
+const FRAMES_PER_CHUNK: i32 = 64;
+
+/// Folds each child's writes into one value per variable, later children winning
+#[derive(Default)]
+pub(crate) struct WriteMerge {
+    merged: BTreeMap<String, (usize, StoredVariable)>,
+    conflicts: Vec<WriteConflict>,
+}
+
+impl WriteMerge {
+    /// Add the writes of the child at `index`; children must be added in order
+    pub(crate) fn add(&mut self, index: usize, writes: Vec<StoredVariable>) {
+        for stored in writes {
+            if let Some((first, existing)) = self.merged.get(&stored.name) {
+                if existing.value != stored.value {
+                    self.conflicts.push(WriteConflict {
+                        variable: stored.name.clone(),
+                        first: *first,
+                        first_value: existing.value.clone(),
+                        second: index,
+                        second_value: stored.value.clone(),
+                    });
+                }
+            }
+            self.merged.insert(stored.name.clone(), (index, stored));
+        }
+    }
+
+    /// Results are in variable name order, so the merge does not depend on
+    /// which child finished first.
+    pub(crate) fn finish(self) -> (Vec<StoredVariable>, Vec<WriteConflict>) {
+        (self.merged.into_values().map(|(_, stored)| stored).collect(), self.conflicts)
+    }
+}
+
+/// `userIn` has no terminal to read from inside a parallel child
+struct NoInput;
+
+impl InputSource for NoInput {
+    fn read_line(&mut self, prompt: &str) -> Result<String> {
+        Err(anyhow::anyhow!("userIn(\"{}\") is not available inside parallel blocks", prompt))
+    }
+}
+
+/// An isolated copy of the interpreter plus the output it buffered
+struct Frame {
+    transpiler: QuantumTranspiler,
+    output: Arc<BufferedSink>,
+    /// Statements the parent had run when this frame was forked
+    fork_point: u64,
+}
+
+impl QuantumTranspiler {
+    /// Run each statement of a `parallel { ... }` body in its own frame
+    pub(crate) fn execute_parallel_block(&mut self, body: &str, class_name: &str) -> Result<()> {
+        let statements = parser::collect_statements(body);
+        self.output.debug(&format!("-- Executing parallel block: {} children", statements.len()));
+
+        let frames = statements.iter().map(|_| self.fork_frame()).collect::<Result<Vec<_>>>()?;
+        let mut merge = WriteMerge::default();
+        self.run_frames(frames, 0, &mut merge, |index, child| child.execute_statement(&statements[index], class_name))?;
+        self.apply_merge(merge);
+        Ok(())
+    }
+
+    /// Run each iteration of `loop <> parallel range(a, b) as i { ... }` in its own frame
+    ///
+    /// The loop variable is local to its iteration and is not merged back. Every
+    /// iteration starts from the state before the loop, even though the range
+    /// runs a chunk of frames at a time.
+    pub(crate) fn execute_parallel_range_loop(
+        &mut self,
+        label: Option<&str>,
+        start_expr: &str,
+        end_expr: &str,
+        loop_var_name: &str,
+        body: &str,
+        class_name: &str
+    ) -> Result<()> {
+        let start = self.resolve_loop_bound(start_expr)?;
+        let end = self.resolve_loop_bound(end_expr)?;
+        self.output.debug(&format!("-- Executing parallel range loop: {} to {}", start, end));
+
+        // Every iteration costs at least one statement; fail before forking a frame per iteration
+        self.budget.check_steps_ahead(end.saturating_sub(start).max(0) as u64)?;
+
+        // Frames fork from this snapshot, so earlier chunks' writes stay invisible to later ones
+        let mut snapshot = self.fork_frame()?;
+        let mut merge = WriteMerge::default();
+
+        for chunk_start in (start..end).step_by(FRAMES_PER_CHUNK as usize) {
+            let chunk_end = chunk_start.saturating_add(FRAMES_PER_CHUNK).min(end);
+            let mut frames = Vec::new();
+            for i in chunk_start..chunk_end {
+                self.checkpoint()?;
+                let mut frame = snapshot.transpiler.fork_frame()?;
+                frame.transpiler.variable_manager.store_variable(
+                    loop_var_name,
+                    VariableValue::Number(i as f64),
+                    Some("loop iterator".to_string()),
+                )?;
+                frame.transpiler.loop_executor.enter_loop(label);
+                frames.push(frame);
+            }
+
+            self.run_frames(frames, (chunk_start - start) as usize, &mut merge, |_, child| {
+                child.execute_body_block(body, class_name)?;
+                if child.loop_executor.should_break {
+                    child.output.warn("!! break has no effect in a parallel loop");
+                }
+                Ok(())
+            })?;
+        }
+
+        self.apply_merge(merge);
+        Ok(())
+    }
+
+    /// Copy of the interpreter for one parallel child
+    ///
+    /// Shares variables, solutions, limits, file permissions and the cancellation token; gets its
+    /// own output buffer, an rng derived from this one, and no cache store or stdin.
+    fn fork_frame(&mut self) -> Result<Frame> {
+        let output = Arc::new(BufferedSink::new());
+        let sink: SharedSink = output.clone();
+
+        let mut condition_evaluator = ConditionEvaluator::new();
+        condition_evaluator.set_native_functions(self.natives.clone());
+
+        let mut transpiler = QuantumTranspiler {
+            cache: self.cache.clone(),
+            execution_count: self.execution_count,
+            function_builder: FunctionBuilder::detached(sink.clone()),
+            function_executor: FunctionExecutor::new()?,
+            math_engine: self.math_engine.fork(),
+            variable_manager: self.variable_manager.fork(),
+            condition_evaluator,
+            loop_executor: LoopExecutor::new(),
+            current_class_name: self.current_class_name.clone(),
+            store: Box::new(InMemoryCache::new()),
+            output: sink,
+            input: Box::new(NoInput),
+            rng: StdRng::seed_from_u64(self.rng.gen()),
+            seed: self.seed,
+            natives: self.natives.clone(),
+            budget: self.budget.clone(),
+            cancel: self.cancel.clone(),
+            fs: self.fs.clone(),
+            result: None,
+            exit_code: None,
+            presets: self.presets.clone(),
+            search: self.search,
+            operators: self.operators,
+            cost_model: self.cost_model.clone(),
+        };
+        transpiler.propagate_output_sink();
+        transpiler.propagate_cancellation();
+
+        Ok(Frame { transpiler, output, fork_point: self.budget.statements() })
+    }
+
+    /// Run `child` on every frame concurrently, then fold the frames back in order
+    ///
+    /// `first_index` is the position of the first frame among all children, for
+    /// conflict reports. Output is replayed frame by frame. If a child fails, the
+    /// output up to and including that child is replayed and its error returned;
+    /// no variable writes are applied.
+    fn run_frames<F>(&mut self, mut frames: Vec<Frame>, first_index: usize, merge: &mut WriteMerge, child: F) -> Result<()>
+    where
+        F: Fn(usize, &mut QuantumTranspiler) -> Result<()> + Sync,
+    {
+        for frame in &mut frames {
+            frame.transpiler.variable_manager.track_writes();
+        }
+
+        let results: Vec<Result<()>> = frames.par_iter_mut()
+            .enumerate()
+            .map(|(index, frame)| child(index, &mut frame.transpiler))
+            .collect();
+
+        for (offset, (frame, result)) in frames.into_iter().zip(results).enumerate() {
+            for line in frame.output.lines() {
+                line.emit(self.output.as_ref());
+            }
+            result?;
+
+            self.budget.absorb(frame.fork_point, &frame.transpiler.budget)?;
+            merge.add(first_index + offset, frame.transpiler.variable_manager.written());
+            self.math_engine.absorb(frame.transpiler.math_engine);
+            self.cache.function_results.extend(frame.transpiler.cache.function_results);
+            if frame.transpiler.result.is_some() {
+                self.result = frame.transpiler.result;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Apply the merged writes of every child and warn about conflicting ones
+    fn apply_merge(&mut self, merge: WriteMerge) {
+        let (merged, conflicts) = merge.finish();
+        for conflict in &conflicts {
+            self.output.warn(&format!("!! {}", conflict));
+        }
+        for stored in merged {
+            self.variable_manager.restore_variable(stored);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::both_backends;
+
+    fn stored(name: &str, value: f64) -> StoredVariable {
+        StoredVariable {
+            name: name.to_string(),
+            value: VariableValue::Number(value),
+            timestamp: 0,
+            source_equation: None,
+        }
+    }
+
+    #[test]
+    fn test_merge_keeps_later_writes_and_reports_conflicts() {
+        let mut merge = WriteMerge::default();
+        merge.add(0, vec![stored("a", 1.0), stored("x", 3.0)]);
+        merge.add(1, vec![stored("b", 2.0), stored("x", 3.0)]);
+        merge.add(2, vec![stored("x", 4.0)]);
+        let (merged, conflicts) = merge.finish();
+
+        let values: Vec<(String, VariableValue)> = merged.into_iter().map(|s| (s.name, s.value)).collect();
+        assert_eq!(values, vec![
+            ("a".to_string(), VariableValue::Number(1.0)),
+            ("b".to_string(), VariableValue::Number(2.0)),
+            ("x".to_string(), VariableValue::Number(4.0)),
+        ]);
+
+        // Equal writes agree; only the differing one conflicts
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!((conflicts[0].first, conflicts[0].second), (1, 2));
+    }
+
+    #[test]
+    fn test_huge_parallel_ranges_stop_before_forking_every_iteration() {
+        use crate::{Cancelled, Engine, LimitExceeded, Limits};
+        use std::time::{Duration, Instant};
+
+        let program = r#"* <main> Wide {
+    ^ observe_execution {
+        loop <> parallel range(0, 2000000000) as i {
+            x <> i
+        }
+    }
+}"#;
+        let limits = Limits { max_statements: Some(1000), ..Default::default() };
+        let mut engine = Engine::builder().limits(limits).build().unwrap();
+        let error = engine.run_source(program).unwrap_err();
+        assert_eq!(error.downcast_ref::<LimitExceeded>(), Some(&LimitExceeded::Statements { limit: 1000 }));
+
+        let limits = Limits { timeout_ms: Some(50), ..Default::default() };
+        let mut engine = Engine::builder().limits(limits).build().unwrap();
+        let error = engine.run_source(program).unwrap_err();
+        assert_eq!(error.downcast_ref::<LimitExceeded>().map(|e| e.kind()), Some("timeout"));
+
+        let mut engine = Engine::builder().build().unwrap();
+        let token = engine.cancellation_token();
+        let canceller = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            token.cancel();
+        });
+        let started = Instant::now();
+        let error = engine.run_source(program).unwrap_err();
+        canceller.join().unwrap();
+        assert_eq!(error.downcast_ref::<Cancelled>(), Some(&Cancelled));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_wide_parallel_ranges_run_a_chunk_of_frames_at_a_time() {
+        use crate::{Cancelled, Engine};
+        use std::time::{Duration, Instant};
+
+        // Forking a frame per iteration up front would exhaust memory before `iteration 0` ran
+        let program = r#"* <main> Wide {
+    ^ observe_execution {
+        loop <> parallel range(0, 2000000000) as i {
+            speak("iteration ~i~")
+        }
+    }
+}"#;
+        let sink = Arc::new(BufferedSink::new());
+        let mut engine = Engine::builder().output(sink.clone()).record_output(false).build().unwrap();
+        let token = engine.cancellation_token();
+        let watcher = {
+            let sink = sink.clone();
+            std::thread::spawn(move || {
+                let deadline = Instant::now() + Duration::from_secs(60);
+                while sink.program_lines().is_empty() && Instant::now() < deadline {
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+                token.cancel();
+            })
+        };
+        let error = engine.run_source(program).unwrap_err();
+        watcher.join().unwrap();
+
+        assert_eq!(error.downcast_ref::<Cancelled>(), Some(&Cancelled));
+        let lines = sink.program_lines();
+        assert!(lines.len() >= FRAMES_PER_CHUNK as usize, "{} lines", lines.len());
+        assert_eq!(lines[..2], ["iteration 0", "iteration 1"]);
+    }
+
+    #[test]
+    fn test_parallel_blocks_merge_in_source_order() {
+        let program = r#"* <main> Fanout {
+    ^ observe_execution {
+        base <> 3
+        parallel {
+            a([12]) <> randomChoice([base, 4, ?])
+            b([20]) <> randomChoice([4, 5, ?])
+            speak("first child")
+            speak("second child")
+        }
+        loop <> parallel range(0, 3) as i {
+            last <> calc(i, 10)
+            speak("iteration ~i~")
+        }
+        speak("a is ~a~, b is ~b~, last is ~last~")
+    }
+}"#;
+        for mut engine in both_backends(|builder| builder.seed(7)) {
+            let outcome = engine.run_source(program).unwrap();
+
+            assert_eq!(outcome.program_lines(), [
+                "first child", "second child", "iteration 0", "iteration 1", "iteration 2",
+                "a is 12, b is 20, last is 12",
+            ]);
+            assert!(outcome.solutions.keys().any(|key| key.starts_with("Fanout-b-20-")));
+            let conflicts = outcome.output.iter()
+                .filter(|line| line.message.contains("Conflicting parallel writes to 'last'"))
+                .count();
+            assert_eq!(conflicts, 2);
+        }
+    }
+}
@@ -0,0 +1,960 @@
+use anyhow::Result;
+use regex::Regex;
+use std::fmt;
+
+use crate::bigint::BigInt;
+use crate::expression_search::SolveOptions;
+use crate::{lexer, VariableValue};
+
+/// A parsed .slut program
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub main_class: String,
+    pub body: Vec<Statement>,
+    pub function_classes: Vec<FunctionClass>,
+}
+
+/// A non-main class callable as `x <> Name()`
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionClass {
+    pub name: String,
+    pub parameters: Vec<String>,
+    pub body: Vec<Statement>,
+}
+
+/// One `if` / `<elif>` / `<else>` arm
+#[derive(Debug, Clone, PartialEq)]
+pub struct Branch {
+    pub condition: String,
+    pub body: Vec<Statement>,
+}
+
+/// One `pattern [if guard] => body` arm of a `match`
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub guard: Option<String>,
+    pub body: Vec<Statement>,
+}
+
+/// Left-hand side of a match arm
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    /// `3`, `-1.5`
+    Number(f64),
+    /// `2..5` leaves out the end, `2..=5` includes it
+    Range { start: f64, end: f64, inclusive: bool },
+    /// `"text"`
+    Text(String),
+    Boolean(bool),
+    /// `n`: matches any value and stores it in `n` before the guard runs
+    Binding(String),
+    /// `_`
+    Wildcard,
+}
+
+impl Pattern {
+    pub fn parse(text: &str) -> Result<Self> {
+        let text = text.trim();
+        if text == "_" {
+            return Ok(Pattern::Wildcard);
+        }
+        if text == "true" || text == "false" {
+            return Ok(Pattern::Boolean(text == "true"));
+        }
+        if text.len() >= 2 && text.starts_with('"') && text.ends_with('"') {
+            return Ok(Pattern::Text(lexer::unescape(&text[1..text.len() - 1])?));
+        }
+        if let Ok(n) = text.parse::<f64>() {
+            return Ok(Pattern::Number(n));
+        }
+
+        let range = text.split_once("..=").map(|(start, end)| (start, end, true))
+            .or_else(|| text.split_once("..").map(|(start, end)| (start, end, false)));
+        if let Some((start, end, inclusive)) = range {
+            let bound = |bound: &str| bound.trim().parse::<f64>()
+                .map_err(|_| anyhow::anyhow!("Range pattern '{}' needs number bounds", text));
+            return Ok(Pattern::Range { start: bound(start)?, end: bound(end)?, inclusive });
+        }
+
+        let mut chars = text.chars();
+        if matches!(chars.next(), Some(c) if c.is_alphabetic() || c == '_')
+            && chars.all(|c| c.is_alphanumeric() || c == '_') {
+            return Ok(Pattern::Binding(text.to_string()));
+        }
+
+        Err(anyhow::anyhow!("Invalid match pattern '{}'", text))
+    }
+
+    /// Whether `value` fits the pattern; values of another type never do
+    pub fn matches(&self, value: &VariableValue) -> bool {
+        match (self, value) {
+            (Pattern::Wildcard | Pattern::Binding(_), _) => true,
+            (Pattern::Number(n), VariableValue::Number(v)) => n == v,
+            (Pattern::Range { start, end, inclusive }, VariableValue::Number(v)) => {
+                start <= v && (if *inclusive { v <= end } else { v < end })
+            }
+            (Pattern::Text(text), VariableValue::String(s)) => text == s,
+            (Pattern::Boolean(b), VariableValue::Boolean(v)) => b == v,
+            _ => false,
+        }
+    }
+
+    /// `_` and bindings, which match every value
+    pub fn is_catch_all(&self) -> bool {
+        matches!(self, Pattern::Wildcard | Pattern::Binding(_))
+    }
+}
+
+/// A match arm before its body is parsed
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchArmSource {
+    pub pattern: Pattern,
+    pub guard: Option<String>,
+    pub body: String,
+}
+
+/// Literal value on the right-hand side of `name <> value`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    Number(f64),
+    /// A whole number too large for `Number` to hold exactly
+    Integer(BigInt),
+    Boolean(bool),
+    Text(String),
+}
+
+impl From<Literal> for VariableValue {
+    fn from(literal: Literal) -> Self {
+        match literal {
+            Literal::Number(n) => VariableValue::Number(n),
+            Literal::Integer(n) => VariableValue::Integer(n),
+            Literal::Boolean(b) => VariableValue::Boolean(b),
+            Literal::Text(text) => VariableValue::String(text),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    /// `x <> 5`, `x <> true`, `x <> "text"`
+    Assign { name: String, value: Literal },
+    /// `x <> calc(a, b, ...)`
+    Calc { name: String, params: Vec<String> },
+    /// `x <> randomChoice([a, b, ...])`
+    RandomChoice { name: String, choices: Vec<String> },
+    /// `x <> userIn("prompt")`
+    UserInput { name: String, prompt: String },
+    /// `x <> FunctionClass()`
+    FunctionCall { name: String, function: String },
+    /// `x([target]) <> randomChoice([inputs])`, optionally `randomChoice([inputs], rules: once)`
+    SolveTarget { name: String, target: String, inputs: String, options: SolveOptions },
+    /// `speak("... ~var~ ...")`
+    Speak { template: String },
+    /// `woof x`
+    Woof { name: String },
+    /// `exit(code)`: ends the program with that status
+    Exit { code: String },
+    /// `if <> (...) <elif> (...) <else> (...) { a <> b <> c }`
+    Selection { branches: Vec<Branch> },
+    /// `match value { 1 => ..., 2..5 => ..., "x" => ..., n if n > 10 => ..., _ => ... }`
+    Match { scrutinee: String, arms: Vec<MatchArm>, source: String },
+    /// `loop <> count(n) { ... }`; any loop may be labelled, e.g. `loop outer <> count(n) { ... }`
+    CountLoop { label: Option<String>, count: String, body: Vec<Statement> },
+    /// `loop <> range(a, b) as i { ... }`
+    RangeLoop { label: Option<String>, start: String, end: String, variable: String, body: Vec<Statement> },
+    /// `loop <> while(cond) { ... }`
+    WhileLoop { label: Option<String>, condition: String, body: Vec<Statement> },
+    /// `parallel { ... }`: each child statement runs in its own frame
+    Parallel { body: Vec<Statement>, source: String },
+    /// `loop <> parallel range(a, b) as i { ... }`: each iteration runs in its own frame
+    ParallelRangeLoop {
+        label: Option<String>,
+        start: String,
+        end: String,
+        variable: String,
+        body: Vec<Statement>,
+        source: String,
+    },
+    /// `break` or `break outer`
+    Break { label: Option<String> },
+    /// `continue` or `continue outer`
+    Continue { label: Option<String> },
+    /// `x([t]) <> allChoices([...])`: every equation for `t`, listed by the interpreter only
+    AllChoices { name: String, source: String },
+    /// Polymorphic function synthesis / execution and `writeFile`, kept verbatim
+    Unsupported { source: String },
+}
+
+/// `break name` or `continue name` outside any loop labelled `name`
+///
+/// Returned inside `anyhow::Error` by `Parser::parse_program`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownLabel {
+    pub keyword: &'static str,
+    pub label: String,
+}
+
+impl fmt::Display for UnknownLabel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown loop label '{}' in `{} {}`", self.label, self.keyword, self.label)
+    }
+}
+
+impl std::error::Error for UnknownLabel {}
+
+pub struct Parser {
+    main_regex: Regex,
+    class_regex: Regex,
+    selection_regex: Regex,
+    elif_regex: Regex,
+    count_loop_regex: Regex,
+    range_loop_regex: Regex,
+    while_loop_regex: Regex,
+    parallel_range_regex: Regex,
+    jump_regex: Regex,
+    speak_regex: Regex,
+    user_input_regex: Regex,
+    var_function_regex: Regex,
+    var_expression_regex: Regex,
+    choice_regex: Regex,
+    target_math_regex: Regex,
+    poly_synthesis_regex: Regex,
+    poly_exec_regex: Regex,
+    woof_regex: Regex,
+    file_write_regex: Regex,
+    exit_regex: Regex,
+}
+
+impl Parser {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            main_regex: Regex::new(r"\*\s*<main>\s*(\w+)\s*\{[^}]*?\^\s*observe_execution\s*\{")?,
+            class_regex: Regex::new(r"\*\s*(?:<main>\s*)?(\w+)\s*(?:\(\[([^\]]*)\]\))?\s*\{[^{}]*?\^\s*observe_execution\s*\{")?,
+            selection_regex: Regex::new(r"^if\s*<>\s*\(((?:[^()]|\([^()]*\))+)\)((?:\s*<elif>\s*\((?:[^()]|\([^()]*\))+\))*)\s*<else>\s*\(((?:[^()]|\([^()]*\))+)\)\s*\{")?,
+            elif_regex: Regex::new(r"<elif>\s*\(((?:[^()]|\([^()]*\))+)\)")?,
+            count_loop_regex: Regex::new(r"^loop(?:\s+(\w+))?\s*<>\s*count\s*\(\s*([^)]+?)\s*\)\s*\{")?,
+            range_loop_regex: Regex::new(r"^loop(?:\s+(\w+))?\s*<>\s*range\s*\(\s*([^,]+?)\s*,\s*([^)]+?)\s*\)\s*as\s+(\w+)\s*\{")?,
+            while_loop_regex: Regex::new(r"^loop(?:\s+(\w+))?\s*<>\s*while\s*\(\s*((?:[^()]|\([^()]*\))+?)\s*\)\s*\{")?,
+            parallel_range_regex: Regex::new(r"^loop(?:\s+(\w+))?\s*<>\s*parallel\s+range\s*\(\s*([^,]+?)\s*,\s*([^)]+?)\s*\)\s*as\s+(\w+)\s*\{")?,
+            jump_regex: Regex::new(r"^(break|continue)(?:\s+(\w+))?$")?,
+            speak_regex: Regex::new(&format!(r"speak\s*\(\s*{}\s*\)", lexer::STRING_LITERAL))?,
+            user_input_regex: Regex::new(&format!(r"(\w+)\s*<>\s*userIn\s*\(\s*{}\s*\)", lexer::STRING_LITERAL))?,
+            var_function_regex: Regex::new(r"(\w+)\s*<>\s*(\w+)\s*\(\s*\)")?,
+            var_expression_regex: Regex::new(r"(\w+)\s*<>\s*(.+)")?,
+            choice_regex: Regex::new(r"randomChoice\s*\(\s*\[\s*([^\]]*)\s*\]\s*\)")?,
+            target_math_regex: Regex::new(r"(\w+)\s*\(\s*\[\s*([^\]]+)\s*\]\s*\)\s*<>\s*(randomChoice|allChoices)\s*\(\s*\[\s*([^\]]*)\s*\]\s*((?:,\s*\w+\s*:\s*(?:\[[^\]]*\]|[\w+]+)\s*)*)\)")?,
+            poly_synthesis_regex: Regex::new(r"(\w+)\s*\(\s*([^)]*)\s*\)\s*<>\s*function\s*\(\s*(\w+)\s*\)")?,
+            poly_exec_regex: Regex::new(r#"(\w+)\s*\(\s*([^)]+)\s*\)\s*\(\s*"((?:[^"\\]|\\.)*)"\s*\)"#)?,
+            woof_regex: Regex::new(r"woof\s+(\w+)")?,
+            file_write_regex: Regex::new(r"^writeFile\s*\(")?,
+            exit_regex: Regex::new(r"^exit\s*\(\s*(.+?)\s*\)$")?,
+        })
+    }
+
+    /// Parse a complete .slut source file
+    ///
+    /// Class bodies are brace-matched rather than cut at the first `}`, so
+    /// nested loops and selections survive intact. A `break` or `continue`
+    /// naming a label no enclosing loop has is an `UnknownLabel` error.
+    pub fn parse_program(&self, source: &str) -> Result<Program> {
+        let source = &lexer::strip_comments(source)?;
+        let captures = self.main_regex.captures(source)
+            .ok_or_else(|| anyhow::anyhow!("No main class found in source"))?;
+        let main_class = captures[1].to_string();
+        let open = captures.get(0).unwrap().end() - 1;
+        let body = self.parse_block(block_contents(source, open)?)?;
+
+        let mut function_classes = Vec::new();
+        for captures in self.class_regex.captures_iter(source) {
+            if captures[0].contains("<main>") {
+                continue;
+            }
+            let parameters = captures.get(2)
+                .map(|m| m.as_str())
+                .unwrap_or("")
+                .split(',')
+                .map(|p| p.trim().to_string())
+                .filter(|p| !p.is_empty())
+                .collect();
+            let open = captures.get(0).unwrap().end() - 1;
+            function_classes.push(FunctionClass {
+                name: captures[1].to_string(),
+                parameters,
+                body: self.parse_block(block_contents(source, open)?)?,
+            });
+        }
+
+        check_labels(&body, &mut Vec::new())?;
+        for function in &function_classes {
+            check_labels(&function.body, &mut Vec::new())?;
+        }
+
+        Ok(Program { main_class, body, function_classes })
+    }
+
+    /// Parse the statements of a block body
+    pub fn parse_block(&self, body: &str) -> Result<Vec<Statement>> {
+        let mut statements = Vec::new();
+        for statement in collect_statements(body) {
+            if let Some(parsed) = self.parse_statement(&statement)? {
+                statements.push(parsed);
+            }
+        }
+        Ok(statements)
+    }
+
+    /// Parse a single complete statement, as produced by `collect_statements`
+    ///
+    /// Patterns are tried in the same order as the interpreter so both agree on
+    /// what a line means. Returns `None` for lines the interpreter ignores.
+    pub fn parse_statement(&self, statement: &str) -> Result<Option<Statement>> {
+        let trimmed = statement.trim();
+
+        if let Some(captures) = self.jump_regex.captures(trimmed) {
+            let label = captures.get(2).map(|m| m.as_str().to_string());
+            return Ok(Some(if &captures[1] == "break" {
+                Statement::Break { label }
+            } else {
+                Statement::Continue { label }
+            }));
+        }
+
+        if is_parallel_header(trimmed) {
+            let open = trimmed.find('{').unwrap_or_default();
+            return Ok(Some(Statement::Parallel {
+                body: self.parse_block(block_contents(trimmed, open)?)?,
+                source: trimmed.to_string(),
+            }));
+        }
+
+        if let Some(captures) = self.parallel_range_regex.captures(trimmed) {
+            let open = captures.get(0).unwrap().end() - 1;
+            return Ok(Some(Statement::ParallelRangeLoop {
+                label: captures.get(1).map(|m| m.as_str().to_string()),
+                start: captures[2].trim().to_string(),
+                end: captures[3].trim().to_string(),
+                variable: captures[4].to_string(),
+                body: self.parse_block(block_contents(trimmed, open)?)?,
+                source: trimmed.to_string(),
+            }));
+        }
+
+        if is_match_header(trimmed) {
+            let (scrutinee, arms) = split_match(trimmed)?;
+            let arms = arms.into_iter()
+                .map(|arm| Ok(MatchArm { pattern: arm.pattern, guard: arm.guard, body: self.parse_block(&arm.body)? }))
+                .collect::<Result<_>>()?;
+            return Ok(Some(Statement::Match { scrutinee, arms, source: trimmed.to_string() }));
+        }
+
+        if let Some(captures) = self.selection_regex.captures(trimmed) {
+            let mut conditions = vec![captures[1].to_string()];
+            conditions.extend(
+                self.elif_regex.captures_iter(&captures[2]).map(|c| c[1].to_string())
+            );
+            conditions.push(captures[3].to_string());
+
+            let open = captures.get(0).unwrap().end() - 1;
+            let full_body = block_contents(trimmed, open)?;
+            let bodies = split_selection_bodies(full_body);
+
+            if conditions.len() != bodies.len() {
+                return Err(anyhow::anyhow!(
+                    "Selection has {} conditions but {} body blocks", conditions.len(), bodies.len()
+                ));
+            }
+
+            let mut branches = Vec::new();
+            for (condition, body) in conditions.into_iter().zip(bodies) {
+                branches.push(Branch { condition, body: self.parse_block(&body)? });
+            }
+            return Ok(Some(Statement::Selection { branches }));
+        }
+
+        if let Some(captures) = self.count_loop_regex.captures(trimmed) {
+            let open = captures.get(0).unwrap().end() - 1;
+            return Ok(Some(Statement::CountLoop {
+                label: captures.get(1).map(|m| m.as_str().to_string()),
+                count: captures[2].trim().to_string(),
+                body: self.parse_block(block_contents(trimmed, open)?)?,
+            }));
+        }
+
+        if let Some(captures) = self.range_loop_regex.captures(trimmed) {
+            let open = captures.get(0).unwrap().end() - 1;
+            return Ok(Some(Statement::RangeLoop {
+                label: captures.get(1).map(|m| m.as_str().to_string()),
+                start: captures[2].trim().to_string(),
+                end: captures[3].trim().to_string(),
+                variable: captures[4].to_string(),
+                body: self.parse_block(block_contents(trimmed, open)?)?,
+            }));
+        }
+
+        if let Some(captures) = self.while_loop_regex.captures(trimmed) {
+            let open = captures.get(0).unwrap().end() - 1;
+            return Ok(Some(Statement::WhileLoop {
+                label: captures.get(1).map(|m| m.as_str().to_string()),
+                condition: captures[2].trim().to_string(),
+                body: self.parse_block(block_contents(trimmed, open)?)?,
+            }));
+        }
+
+        if let Some(captures) = self.speak_regex.captures(trimmed) {
+            return Ok(Some(Statement::Speak { template: lexer::unescape(&captures[1])? }));
+        }
+
+        if let Some(captures) = self.user_input_regex.captures(trimmed) {
+            return Ok(Some(Statement::UserInput {
+                name: captures[1].to_string(),
+                prompt: lexer::unescape(&captures[2])?,
+            }));
+        }
+
+        if let Some(captures) = self.var_function_regex.captures(trimmed) {
+            return Ok(Some(Statement::FunctionCall {
+                name: captures[1].to_string(),
+                function: captures[2].to_string(),
+            }));
+        }
+
+        if self.file_write_regex.is_match(trimmed) {
+            return Ok(Some(Statement::Unsupported { source: trimmed.to_string() }));
+        }
+
+        if let Some(captures) = self.exit_regex.captures(trimmed) {
+            return Ok(Some(Statement::Exit { code: captures[1].to_string() }));
+        }
+
+        if let Some(captures) = self.var_expression_regex.captures(trimmed) {
+            return Ok(Some(self.parse_assignment(&captures[1], &captures[2])));
+        }
+
+        if let Some(captures) = self.target_math_regex.captures(trimmed) {
+            let options = captures[5].parse()?;
+            if &captures[3] == "allChoices" {
+                return Ok(Some(Statement::AllChoices { name: captures[1].to_string(), source: trimmed.to_string() }));
+            }
+            return Ok(Some(Statement::SolveTarget {
+                name: captures[1].to_string(),
+                target: captures[2].trim().to_string(),
+                inputs: captures[4].trim().to_string(),
+                options,
+            }));
+        }
+
+        if self.poly_synthesis_regex.is_match(trimmed) || self.poly_exec_regex.is_match(trimmed) {
+            return Ok(Some(Statement::Unsupported { source: trimmed.to_string() }));
+        }
+
+        if let Some(captures) = self.woof_regex.captures(trimmed) {
+            return Ok(Some(Statement::Woof { name: captures[1].to_string() }));
+        }
+
+        Ok(None)
+    }
+
+    fn parse_assignment(&self, name: &str, expression: &str) -> Statement {
+        let name = name.to_string();
+
+        if expression.starts_with("calc(") && expression.ends_with(')') {
+            let inner = &expression[5..expression.len() - 1];
+            let params = inner.split(',').map(|s| s.trim().to_string()).collect();
+            return Statement::Calc { name, params };
+        }
+
+        if expression.starts_with("randomChoice(") {
+            let choices = self.choice_regex.captures(expression)
+                .map(|c| c[1].split(',').map(|s| s.trim().to_string()).collect())
+                .unwrap_or_default();
+            return Statement::RandomChoice { name, choices };
+        }
+
+        Statement::Assign { name, value: parse_literal(expression) }
+    }
+}
+
+/// Fail on a `break` / `continue` whose label is not in `labels`, the enclosing loops' labels
+fn check_labels(statements: &[Statement], labels: &mut Vec<String>) -> Result<()> {
+    for statement in statements {
+        match statement {
+            Statement::Break { label: Some(label) } | Statement::Continue { label: Some(label) }
+                if !labels.contains(label) =>
+            {
+                let keyword = if matches!(statement, Statement::Break { .. }) { "break" } else { "continue" };
+                return Err(UnknownLabel { keyword, label: label.clone() }.into());
+            }
+            Statement::CountLoop { label, body, .. }
+            | Statement::RangeLoop { label, body, .. }
+            | Statement::WhileLoop { label, body, .. }
+            | Statement::ParallelRangeLoop { label, body, .. } => {
+                labels.extend(label.clone());
+                let checked = check_labels(body, labels);
+                if label.is_some() {
+                    labels.pop();
+                }
+                checked?;
+            }
+            Statement::Parallel { body, .. } => check_labels(body, labels)?,
+            Statement::Selection { branches } => {
+                for branch in branches {
+                    check_labels(&branch.body, labels)?;
+                }
+            }
+            Statement::Match { arms, .. } => {
+                for arm in arms {
+                    check_labels(&arm.body, labels)?;
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Interpret the right-hand side of a plain assignment the way the interpreter does
+pub fn parse_literal(expression: &str) -> Literal {
+    if let Some(big) = expression.parse::<BigInt>().ok().filter(|big| !big.fits_f64()) {
+        Literal::Integer(big)
+    } else if let Ok(num) = expression.parse::<f64>() {
+        Literal::Number(num)
+    } else if expression == "true" || expression == "false" {
+        Literal::Boolean(expression == "true")
+    } else {
+        Literal::Text(lexer::text_literal(expression))
+    }
+}
+
+/// Whether a line opens a `parallel { ... }` block
+pub fn is_parallel_header(line: &str) -> bool {
+    line.strip_prefix("parallel")
+        .is_some_and(|rest| rest.trim_start().starts_with('{'))
+}
+
+/// Whether a line opens a `match value {` statement
+pub fn is_match_header(line: &str) -> bool {
+    line.strip_prefix("match")
+        .is_some_and(|rest| rest.starts_with(char::is_whitespace) && rest.contains('{'))
+}
+
+/// Split a complete `match` statement into its scrutinee and arms
+///
+/// Arms are separated by commas or line breaks. A body is either a single
+/// statement or a `{ ... }` block, which may span lines.
+pub fn split_match(statement: &str) -> Result<(String, Vec<MatchArmSource>)> {
+    let statement = statement.trim();
+    let open = top_level_positions(statement, "{").first().copied()
+        .ok_or_else(|| anyhow::anyhow!("Expected '{{' after match"))?;
+    let scrutinee = statement["match".len()..open].trim().to_string();
+    if scrutinee.is_empty() {
+        return Err(anyhow::anyhow!("match needs a value to match on"));
+    }
+    let contents = block_contents(statement, open)?;
+
+    let mut separators = top_level_positions(contents, ",");
+    separators.extend(top_level_positions(contents, "\n"));
+    separators.sort_unstable();
+
+    let mut arms = Vec::new();
+    let mut start = 0;
+    for end in separators.into_iter().chain(std::iter::once(contents.len())) {
+        let arm = contents[start..end].trim();
+        start = end + 1;
+        if arm.is_empty() {
+            continue;
+        }
+
+        let arrow = top_level_positions(arm, "=>").first().copied()
+            .ok_or_else(|| anyhow::anyhow!("Match arm '{}' needs the form `pattern => body`", arm))?;
+        let (left, body) = (arm[..arrow].trim(), arm[arrow + 2..].trim());
+        let (pattern, guard) = match top_level_positions(left, " if ").first() {
+            Some(&at) => (&left[..at], Some(left[at + 4..].trim().to_string())),
+            None => (left, None),
+        };
+
+        let body = if body.starts_with('{') {
+            let inner = block_contents(body, 0)?;
+            if !body[inner.len() + 2..].trim().is_empty() {
+                return Err(anyhow::anyhow!("Unexpected text after the body of match arm '{}'", left));
+            }
+            inner.to_string()
+        } else if body.is_empty() {
+            return Err(anyhow::anyhow!("Match arm '{}' has no body", left));
+        } else {
+            body.to_string()
+        };
+
+        arms.push(MatchArmSource { pattern: Pattern::parse(pattern)?, guard, body });
+    }
+
+    Ok((scrutinee, arms))
+}
+
+/// Byte offsets of `needle` in `text` outside string literals and brackets
+fn top_level_positions(text: &str, needle: &str) -> Vec<usize> {
+    let mut positions = Vec::new();
+    let mut depth = 0i32;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (index, c) in text.char_indices() {
+        if !in_string && depth == 0 && text[index..].starts_with(needle) {
+            positions.push(index);
+        }
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            _ if in_string => {}
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            _ => {}
+        }
+    }
+    positions
+}
+
+/// Split a block body into complete statements
+///
+/// Multi-line `loop`, `parallel`, `match` and `if` statements are gathered until their braces
+/// balance; selection statements keep their `<>` delimiter lines so the
+/// branch bodies can be split again afterwards.
+pub fn collect_statements(body: &str) -> Vec<String> {
+    let lines: Vec<&str> = body.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect();
+
+    let mut statements = Vec::new();
+    let mut i = 0;
+
+    while i < lines.len() {
+        let line = lines[i];
+        let is_loop = line.starts_with("loop") && line.contains("<>");
+        let is_selection = line.starts_with("if") && line.contains("<>");
+
+        if !is_loop && !is_selection && !is_parallel_header(line) && !is_match_header(line) {
+            statements.push(line.to_string());
+            i += 1;
+            continue;
+        }
+
+        let mut full_statement = String::new();
+        let mut brace_count = 0i32;
+        // A match may open and close on its header line
+        let mut opened = is_match_header(line);
+        let mut first_line = true;
+
+        while i < lines.len() {
+            let current_line = lines[i];
+            let has_open_brace = current_line.contains('{');
+            brace_count += lexer::brace_delta(current_line);
+
+            if first_line {
+                full_statement.push_str(current_line);
+                first_line = false;
+            } else if is_selection && current_line == "<>" {
+                full_statement.push('\n');
+                full_statement.push_str(current_line);
+                full_statement.push('\n');
+            } else if is_selection && !opened && (has_open_brace
+                || current_line.starts_with("<elif>")
+                || current_line.starts_with("<else>")) {
+                // Header continuation lines stay on the condition line
+                full_statement.push(' ');
+                full_statement.push_str(current_line);
+            } else {
+                full_statement.push('\n');
+                full_statement.push_str(current_line);
+            }
+
+            if brace_count > 0 {
+                opened = true;
+            }
+
+            i += 1;
+
+            if opened && brace_count <= 0 {
+                break;
+            }
+        }
+
+        statements.push(full_statement);
+    }
+
+    statements
+}
+
+/// Return the text between the brace at `open` and its matching close brace
+///
+/// Braces inside string literals do not count.
+pub fn block_contents(statement: &str, open: usize) -> Result<&str> {
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (index, c) in statement[open..].char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            _ if in_string => {}
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(&statement[open + 1..open + index]);
+                }
+            }
+            _ => {}
+        }
+    }
+    Err(anyhow::anyhow!("Unbalanced braces in statement: {}", statement.lines().next().unwrap_or("")))
+}
+
+/// Split a selection body on standalone `<>` lines at brace depth zero
+fn split_selection_bodies(full_body: &str) -> Vec<String> {
+    let mut body_blocks = Vec::new();
+    let mut current_block = String::new();
+    let mut depth = 0i32;
+
+    for line in full_body.lines() {
+        let trimmed = line.trim();
+
+        if trimmed == "<>" && depth == 0 {
+            if !current_block.trim().is_empty() {
+                body_blocks.push(current_block.trim().to_string());
+            }
+            current_block.clear();
+        } else if !trimmed.is_empty() {
+            depth += lexer::brace_delta(trimmed);
+            if !current_block.is_empty() {
+                current_block.push('\n');
+            }
+            current_block.push_str(trimmed);
+        }
+    }
+
+    if !current_block.trim().is_empty() {
+        body_blocks.push(current_block.trim().to_string());
+    }
+
+    body_blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::both_backends;
+    use crate::output_sink::DiagnosticLevel;
+
+    const PROGRAM: &str = r#"
+* <main> LearnToSolve {
+    ^ observe_execution {
+        targetNum <> 250
+        firstInput <> 3
+        speak("Searching for: ~targetNum~")
+        result([targetNum]) <> randomChoice([firstInput, 7, ?])
+        loop <> range(0, 3) as i {
+            speak("i = ~i~")
+        }
+        woof result
+    }
+}
+"#;
+
+    #[test]
+    fn test_parse_program() {
+        let parser = Parser::new().unwrap();
+        let program = parser.parse_program(PROGRAM).unwrap();
+
+        assert_eq!(program.main_class, "LearnToSolve");
+        assert_eq!(program.body.len(), 6);
+        assert_eq!(program.body[0], Statement::Assign {
+            name: "targetNum".to_string(),
+            value: Literal::Number(250.0),
+        });
+        assert_eq!(program.body[3], Statement::SolveTarget {
+            name: "result".to_string(),
+            target: "targetNum".to_string(),
+            inputs: "firstInput, 7, ?".to_string(),
+            options: SolveOptions::default(),
+        });
+        match &program.body[4] {
+            Statement::RangeLoop { variable, body, .. } => {
+                assert_eq!(variable, "i");
+                assert_eq!(body.len(), 1);
+            }
+            other => panic!("expected range loop, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_selection() {
+        let parser = Parser::new().unwrap();
+        let body = "if <> (x > 10)\n<elif> (x > 5)\n<else> (true) {\nspeak(\"big\")\n<>\nspeak(\"medium\")\n<>\nspeak(\"small\")\n}";
+        let statements = parser.parse_block(body).unwrap();
+
+        match &statements[0] {
+            Statement::Selection { branches } => {
+                assert_eq!(branches.len(), 3);
+                assert_eq!(branches[1].condition, "x > 5");
+                assert_eq!(branches[2].body, vec![Statement::Speak { template: "small".to_string() }]);
+            }
+            other => panic!("expected selection, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_nested_loops_keep_inner_body() {
+        let parser = Parser::new().unwrap();
+        let body = "loop <> count(2) {\nloop <> count(3) {\nspeak(\"inner\")\n}\nspeak(\"outer\")\n}";
+        let statements = parser.parse_block(body).unwrap();
+
+        match &statements[0] {
+            Statement::CountLoop { body, .. } => assert_eq!(body.len(), 2),
+            other => panic!("expected count loop, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_labelled_loops() {
+        let parser = Parser::new().unwrap();
+        let body = "loop outer <> range(0, 3) as i {\nloop <> count(2) {\nbreak outer\ncontinue\n}\n}";
+        let statements = parser.parse_block(body).unwrap();
+
+        match &statements[0] {
+            Statement::RangeLoop { label, variable, body, .. } => {
+                assert_eq!(label.as_deref(), Some("outer"));
+                assert_eq!(variable, "i");
+                match &body[0] {
+                    Statement::CountLoop { label: None, body, .. } => assert_eq!(body, &[
+                        Statement::Break { label: Some("outer".to_string()) },
+                        Statement::Continue { label: None },
+                    ]),
+                    other => panic!("expected count loop, got {:?}", other),
+                }
+            }
+            other => panic!("expected range loop, got {:?}", other),
+        }
+
+        let source = "* <main> Jumps {\n    ^ observe_execution {\n        loop inner <> count(2) {\n            continue outer\n        }\n    }\n}";
+        let error = parser.parse_program(source).unwrap_err();
+        assert_eq!(error.to_string(), "Unknown loop label 'outer' in `continue outer`");
+        assert!(error.is::<UnknownLabel>());
+    }
+
+    #[test]
+    fn test_parse_match() {
+        let parser = Parser::new().unwrap();
+        let body = "match x % 7 { 1 => speak(\"one\"), 2..=5 => {\nspeak(\"a, b\")\ny <> calc(x, 1)\n}\n\"x\" => speak(\"=> text\")\nn if n > 10 => woof n\n_ => {}\n}";
+        let statements = parser.parse_block(body).unwrap();
+
+        match &statements[0] {
+            Statement::Match { scrutinee, arms, .. } => {
+                assert_eq!(scrutinee, "x % 7");
+                let patterns: Vec<&Pattern> = arms.iter().map(|arm| &arm.pattern).collect();
+                assert_eq!(patterns, [
+                    &Pattern::Number(1.0),
+                    &Pattern::Range { start: 2.0, end: 5.0, inclusive: true },
+                    &Pattern::Text("x".to_string()),
+                    &Pattern::Binding("n".to_string()),
+                    &Pattern::Wildcard,
+                ]);
+                assert_eq!(arms[1].body.len(), 2);
+                assert_eq!(arms[2].body, vec![Statement::Speak { template: "=> text".to_string() }]);
+                assert_eq!(arms[3].guard.as_deref(), Some("n > 10"));
+                assert!(arms[4].body.is_empty());
+            }
+            other => panic!("expected match, got {:?}", other),
+        }
+
+        assert!(Pattern::Range { start: 2.0, end: 5.0, inclusive: false }.matches(&VariableValue::Number(4.5)));
+        assert!(!Pattern::Range { start: 2.0, end: 5.0, inclusive: false }.matches(&VariableValue::Number(5.0)));
+        assert!(!Pattern::Number(1.0).matches(&VariableValue::String("1".to_string())));
+        assert!(parser.parse_block("match x { 1 speak(\"no arrow\") }").is_err());
+        assert!(parser.parse_block("match x { a..b => speak(\"no\") }").is_err());
+    }
+
+    #[test]
+    fn test_parse_parallel_blocks() {
+        let parser = Parser::new().unwrap();
+        let body = "parallel {\na([12]) <> randomChoice([3, 4, ?])\nb([20]) <> randomChoice([4, 5, ?])\n}\nloop <> parallel range(0, 4) as i {\nspeak(\"i = ~i~\")\n}";
+        let statements = parser.parse_block(body).unwrap();
+
+        match &statements[0] {
+            Statement::Parallel { body, .. } => assert_eq!(body.len(), 2),
+            other => panic!("expected parallel block, got {:?}", other),
+        }
+        match &statements[1] {
+            Statement::ParallelRangeLoop { variable, end, .. } => {
+                assert_eq!(variable, "i");
+                assert_eq!(end, "4");
+            }
+            other => panic!("expected parallel range loop, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_match_statement_runs() {
+        let program = r#"* <main> Matching {
+    ^ observe_execution {
+        loop <> range(0, 14) as i {
+            match i % 13 {
+                0 => speak("zero"), "0" => speak("never")
+                1..3 => { speak("small ~i~") }
+                n if n > 10 => {
+                    speak("big ~n~")
+                    match n { 12 => speak("twelve"), _ => speak("eleven") }
+                }
+                3..=4 => continue
+                n => speak("other ~n~")
+            }
+        }
+        match "x" { "x" => speak("literal"), _ => {} }
+        done <> true
+        match done {
+            true => speak("done")
+        }
+    }
+}"#;
+        for mut engine in both_backends(|builder| builder) {
+            let outcome = engine.run_source(program).unwrap();
+
+            assert_eq!(outcome.program_lines(), [
+                "zero", "small 1", "small 2", "other 5", "other 6", "other 7", "other 8", "other 9",
+                "other 10", "big 11", "eleven", "big 12", "twelve", "zero", "literal", "done",
+            ]);
+            assert!(matches!(outcome.variable("n"), Some(VariableValue::Number(n)) if *n == 12.0));
+
+            let warnings: Vec<&str> = outcome.output.iter()
+                .filter(|line| line.level == Some(DiagnosticLevel::Warn))
+                .map(|line| line.message.as_str())
+                .collect();
+            assert!(warnings.contains(&"!! match done is not exhaustive; add a `_ => ...` arm"));
+        }
+    }
+
+    #[test]
+    fn test_failed_guard_leaves_an_existing_variable_alone() {
+        let program = r#"* <main> Guarded {
+    ^ observe_execution {
+        n <> 5
+        x <> 3
+        match x {
+            n if n > 10 => speak("big ~n~")
+            _ => speak("n is ~n~")
+        }
+        match x {
+            n if n < 10 => speak("bound ~n~")
+        }
+    }
+}"#;
+        for mut engine in both_backends(|builder| builder) {
+            let outcome = engine.run_source(program).unwrap();
+
+            assert_eq!(outcome.program_lines(), ["n is 5", "bound 3"]);
+            assert!(matches!(outcome.variable("n"), Some(VariableValue::Number(n)) if *n == 3.0));
+        }
+    }
+}
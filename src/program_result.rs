@@ -0,0 +1,35 @@
+// What a finished program hands back to its caller
+// The main class's `woof` is the result; `exit(code)` sets the status and ends the run early
+
+use serde::Serialize;
+use std::fmt;
+
+use crate::VariableValue;
+
+/// The value of the main class's last `woof`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProgramResult {
+    pub variable: String,
+    pub value: VariableValue,
+    /// How the value was produced, e.g. the solved equation
+    pub source_equation: Option<String>,
+    /// Accuracy of the solved equation behind the value, when there is one
+    pub accuracy: Option<f64>,
+}
+
+/// The error `exit(code)` unwinds the run with
+///
+/// `execute_source` catches it and finishes the run normally, keeping `code`
+/// as the exit status; it only escapes when `exit` is called outside a run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProgramExit {
+    pub code: i32,
+}
+
+impl fmt::Display for ProgramExit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Program exited with status {}", self.code)
+    }
+}
+
+impl std::error::Error for ProgramExit {}
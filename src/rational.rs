@@ -0,0 +1,248 @@
+// Exact rational numbers for the solver
+// Integer and decimal inputs are combined without rounding; results that overflow or are irrational stay f64
+
+use std::fmt;
+
+/// A fraction in lowest terms with a positive denominator
+///
+/// Arithmetic is checked: anything that would overflow `i128` returns `None`
+/// and the caller falls back to `f64`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rational {
+    num: i128,
+    den: i128,
+}
+
+impl Rational {
+    pub const ZERO: Rational = Rational { num: 0, den: 1 };
+    pub const ONE: Rational = Rational { num: 1, den: 1 };
+
+    pub fn new(num: i128, den: i128) -> Option<Self> {
+        if den == 0 {
+            return None;
+        }
+        let (num, den) = if den < 0 { (num.checked_neg()?, den.checked_neg()?) } else { (num, den) };
+        let divisor = gcd(num, den)?;
+        Some(Self { num: num / divisor, den: den / divisor })
+    }
+
+    pub fn integer(n: i128) -> Self {
+        Self { num: n, den: 1 }
+    }
+
+    pub fn numer(&self) -> i128 {
+        self.num
+    }
+
+    pub fn denom(&self) -> i128 {
+        self.den
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.den == 1
+    }
+
+    /// The value `x` is written as, read back exactly: `0.1` is 1/10, not the nearest binary fraction
+    pub fn from_f64(x: f64) -> Option<Self> {
+        if !x.is_finite() {
+            return None;
+        }
+        let text = x.to_string();
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, text.as_str()),
+        };
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+
+        let mut num: i128 = 0;
+        for c in whole.chars().chain(fraction.chars()) {
+            num = num.checked_mul(10)?.checked_add(c.to_digit(10)? as i128)?;
+        }
+        let den = 10i128.checked_pow(fraction.len() as u32)?;
+        Self::new(if negative { -num } else { num }, den)
+    }
+
+    /// Simplest fraction within rounding error of `x`, e.g. 3/10 for `0.1 + 0.2`
+    pub fn approximate(x: f64) -> Option<Self> {
+        if !x.is_finite() || x.abs() >= 1e18 {
+            return None;
+        }
+        let tolerance = x.abs().max(1.0) * 1e-14;
+
+        // Continued-fraction convergents h/k of x
+        let (mut h, mut h_prev) = (x.floor() as i128, 1i128);
+        let (mut k, mut k_prev) = (1i128, 0i128);
+        let mut rest = x - x.floor();
+        loop {
+            if (h as f64 / k as f64 - x).abs() <= tolerance {
+                return Self::new(h, k);
+            }
+            if rest.abs() < f64::EPSILON || k > 1_000_000_000 {
+                return None;
+            }
+            rest = 1.0 / rest;
+            let term = rest.floor() as i128;
+            rest -= rest.floor();
+            (h, h_prev) = (term.checked_mul(h)?.checked_add(h_prev)?, h);
+            (k, k_prev) = (term.checked_mul(k)?.checked_add(k_prev)?, k);
+        }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.num as f64 / self.den as f64
+    }
+
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        let divisor = gcd(self.den, other.den)?;
+        let num = self.num.checked_mul(other.den / divisor)?
+            .checked_add(other.num.checked_mul(self.den / divisor)?)?;
+        Self::new(num, (self.den / divisor).checked_mul(other.den)?)
+    }
+
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        self.checked_add(other.checked_neg()?)
+    }
+
+    pub fn checked_mul(self, other: Self) -> Option<Self> {
+        // Cross-reduce first so the products stay small
+        let a = gcd(self.num, other.den)?;
+        let b = gcd(other.num, self.den)?;
+        Self::new(
+            (self.num / a).checked_mul(other.num / b)?,
+            (self.den / b).checked_mul(other.den / a)?,
+        )
+    }
+
+    pub fn checked_div(self, other: Self) -> Option<Self> {
+        self.checked_mul(other.recip()?)
+    }
+
+    /// Remainder with the sign of `self`, like `f64 %`
+    pub fn checked_rem(self, other: Self) -> Option<Self> {
+        let quotient = self.checked_div(other)?;
+        let truncated = Self::integer(quotient.num / quotient.den);
+        self.checked_sub(other.checked_mul(truncated)?)
+    }
+
+    /// `self ^ exponent` for whole exponents; `None` for fractional ones, whose result may be irrational
+    pub fn checked_pow(self, exponent: Self) -> Option<Self> {
+        if !exponent.is_integer() {
+            return None;
+        }
+        let power = u32::try_from(exponent.num.unsigned_abs()).ok()?;
+        let raised = Self {
+            num: self.num.checked_pow(power)?,
+            den: self.den.checked_pow(power)?,
+        };
+        if exponent.num < 0 { raised.recip() } else { Some(raised) }
+    }
+
+    pub fn checked_neg(self) -> Option<Self> {
+        Some(Self { num: self.num.checked_neg()?, den: self.den })
+    }
+
+    pub fn recip(self) -> Option<Self> {
+        Self::new(self.den, self.num)
+    }
+
+    pub fn abs(self) -> Option<Self> {
+        if self.num < 0 { self.checked_neg() } else { Some(self) }
+    }
+
+    pub fn floor(self) -> Self {
+        Self::integer(self.num.div_euclid(self.den))
+    }
+
+    pub fn ceil(self) -> Self {
+        Self::integer(-(-self.num).div_euclid(self.den))
+    }
+
+    /// Square root when it is rational, i.e. both terms are perfect squares
+    pub fn sqrt(self) -> Option<Self> {
+        if self.num < 0 {
+            return None;
+        }
+        Some(Self { num: exact_sqrt(self.num)?, den: exact_sqrt(self.den)? })
+    }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Rational {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        match (self.num.checked_mul(other.den), other.num.checked_mul(self.den)) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            _ => self.to_f64().total_cmp(&other.to_f64()),
+        }
+    }
+}
+
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.den == 1 {
+            write!(f, "{}", self.num)
+        } else {
+            write!(f, "{}/{}", self.num, self.den)
+        }
+    }
+}
+
+/// Greatest common divisor, at least 1; `None` only for `i128::MIN`
+fn gcd(a: i128, b: i128) -> Option<i128> {
+    let (mut a, mut b) = (a.checked_abs()?, b.checked_abs()?);
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    Some(a.max(1))
+}
+
+fn exact_sqrt(n: i128) -> Option<i128> {
+    let mut root = (n as f64).sqrt() as i128;
+    // The f64 estimate can be off by one either way for large n
+    while root > 0 && root.checked_mul(root).is_none_or(|square| square > n) {
+        root -= 1;
+    }
+    while (root + 1).checked_mul(root + 1).is_some_and(|square| square <= n) {
+        root += 1;
+    }
+    (root * root == n).then_some(root)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn q(num: i128, den: i128) -> Rational {
+        Rational::new(num, den).unwrap()
+    }
+
+    #[test]
+    fn test_decimals_are_exact() {
+        let tenth = Rational::from_f64(0.1).unwrap();
+        assert_eq!(tenth, q(1, 10));
+        assert_eq!(tenth.checked_add(q(2, 10)).unwrap(), Rational::from_f64(0.3).unwrap());
+        assert_eq!(Rational::from_f64(1.1).unwrap().checked_mul(Rational::integer(3)).unwrap(), q(33, 10));
+        assert_eq!(Rational::from_f64(-2.5).unwrap(), q(-5, 2));
+        assert_eq!(Rational::from_f64(f64::NAN), None);
+
+        assert_eq!(q(7, 2).checked_rem(Rational::integer(-2)).unwrap(), q(3, 2));
+        assert_eq!(q(2, 3).checked_pow(Rational::integer(-2)).unwrap(), q(9, 4));
+        assert_eq!(q(9, 4).sqrt(), Some(q(3, 2)));
+        assert_eq!(Rational::integer(2).sqrt(), None);
+        assert_eq!((q(-7, 2).floor(), q(-7, 2).ceil()), (Rational::integer(-4), Rational::integer(-3)));
+        assert_eq!(Rational::integer(i128::MAX).checked_add(Rational::ONE), None);
+        assert_eq!(Rational::ONE.checked_div(Rational::ZERO), None);
+    }
+
+    #[test]
+    fn test_approximate_snaps_rounding_noise() {
+        assert_eq!(Rational::approximate(0.1 + 0.2), Some(q(3, 10)));
+        assert_eq!(Rational::approximate(1.0 / 3.0), Some(q(1, 3)));
+        assert_eq!(Rational::approximate(250.0), Some(Rational::integer(250)));
+        assert_eq!(Rational::approximate(f64::INFINITY), None);
+    }
+}
@@ -3,7 +3,15 @@ use std::sync::Mutex;
 use std::path::PathBuf;
 use anyhow::Result;
 
-use crate::QuantumTranspiler;
+use std::collections::HashMap;
+
+use crate::{
+    Cancelled, DefaultCostModel, LimitExceeded, Limits, MathSolution, OperatorSet,
+    QuantumTranspiler, RunTokens, SearchConfig, SharedCostModel,
+};
+use crate::equation_solver::EquationSolver;
+use crate::limits::Budget;
+use crate::math_engine::MathEngine;
 
 /// Shared state that the UI can access
 pub struct AppState {
@@ -12,6 +20,31 @@ pub struct AppState {
     pub current_file: Mutex<Option<String>>,
     pub observation_count: Mutex<u32>,
     pub last_accuracy: Mutex<f64>,
+    pub limits: Mutex<Limits>,
+    /// A token per run, running or queued for the transpiler, all tripped by `stop_execution`
+    pub cancel: RunTokens,
+    /// How runs and `list_solutions` solve targets, readable while a program holds the transpiler
+    pub solver: Mutex<SolverSettings>,
+    /// A token per `list_solutions` call in flight, also tripped by `stop_execution`
+    pub listing_cancel: RunTokens,
+}
+
+/// Operators, search limits and cost model applied to every run and listing
+#[derive(Clone)]
+pub struct SolverSettings {
+    pub search: SearchConfig,
+    pub operators: OperatorSet,
+    pub cost_model: SharedCostModel,
+}
+
+impl Default for SolverSettings {
+    fn default() -> Self {
+        Self {
+            search: SearchConfig::default(),
+            operators: OperatorSet::ALL,
+            cost_model: DefaultCostModel::shared(),
+        }
+    }
 }
 
 impl AppState {
@@ -22,13 +55,17 @@ impl AppState {
             current_file: Mutex::new(None),
             observation_count: Mutex::new(0),
             last_accuracy: Mutex::new(0.0),
+            limits: Mutex::new(Limits::default()),
+            cancel: RunTokens::new(),
+            solver: Mutex::new(SolverSettings::default()),
+            listing_cancel: RunTokens::new(),
         }
     }
 }
 
 /// Get the cache directory for the application
 /// Uses ./cache/ folder in project directory (ignored by .taurignore)
-fn get_cache_directory(_app: &AppHandle) -> Result<PathBuf, String> {
+fn get_cache_directory(app: &AppHandle) -> Result<PathBuf, String> {
     // Use current working directory + cache subfolder
     let current_dir = std::env::current_dir()
         .map_err(|e| format!("Failed to get current directory: {}", e))?;
@@ -39,7 +76,7 @@ fn get_cache_directory(_app: &AppHandle) -> Result<PathBuf, String> {
     if !cache_dir.exists() {
         std::fs::create_dir_all(&cache_dir)
             .map_err(|e| format!("Failed to create cache directory: {}", e))?;
-        println!(">> Created cache directory: {}", cache_dir.display());
+        emit_console(app, format!(">> Created cache directory: {}", cache_dir.display()), "info");
     }
 
     Ok(cache_dir)
@@ -120,15 +157,17 @@ pub async fn run_file(
     // Capture stdout by redirecting prints
     // Note: This is a simplified approach; in production you'd use a proper stdout capture mechanism
 
+    // Registered before waiting for the transpiler, so a Stop pressed while queued still applies;
+    // a queued run never replaces the token of the run it waits behind
+    let run = state.cancel.register();
+
     let mut transpiler_guard = state.transpiler.lock().unwrap();
 
     // Initialize transpiler if needed
     if transpiler_guard.is_none() {
         // Get cache directory from Tauri app data
         let cache_dir = get_cache_directory(&app)?;
-        let cache_msg = format!("Using cache directory: {}", cache_dir.display());
-        println!("{}", cache_msg);
-        emit_console(&app, cache_msg, "info");
+        emit_console(&app, format!("Using cache directory: {}", cache_dir.display()), "info");
 
         match QuantumTranspiler::new_with_cache_dir(cache_dir) {
             Ok(mut trans) => {
@@ -150,6 +189,12 @@ pub async fn run_file(
     }
 
     let transpiler = transpiler_guard.as_mut().unwrap();
+    transpiler.set_limits(*state.limits.lock().unwrap());
+    let solver = state.solver.lock().unwrap().clone();
+    transpiler.set_search_config(solver.search);
+    transpiler.set_operators(solver.operators);
+    transpiler.set_cost_model(solver.cost_model);
+    transpiler.set_cancellation_token(run.token());
 
     // Execute the file
     emit_console(&app, format!("Executing {}...", file_name), "info");
@@ -172,14 +217,35 @@ pub async fn run_file(
             // Return the success message
             Ok(success_msg)
         }
+        Err(e) if e.downcast_ref::<Cancelled>().is_some() => {
+            let err_msg = format!("Execution of {} cancelled", file_name);
+            emit_console(&app, err_msg.clone(), "info");
+            Err(err_msg)
+        }
         Err(e) => {
-            let err_msg = format!("Execution error: {}", e);
+            let err_msg = match e.downcast_ref::<LimitExceeded>() {
+                Some(limit) => format!("Execution stopped ({} limit): {}", limit.kind(), limit),
+                None => format!("Execution error: {}", e),
+            };
             emit_console(&app, err_msg.clone(), "error");
             Err(err_msg)
         }
     }
 }
 
+/// Command to set the resource limits applied to every following run
+#[tauri::command]
+pub fn set_limits(limits: Limits, state: State<'_, AppState>) -> Result<(), String> {
+    *state.limits.lock().unwrap() = limits;
+    Ok(())
+}
+
+/// Command to get the current resource limits
+#[tauri::command]
+pub fn get_limits(state: State<'_, AppState>) -> Result<Limits, String> {
+    Ok(*state.limits.lock().unwrap())
+}
+
 /// Command to get current cache stats - ALWAYS reads from cache file
 #[tauri::command]
 pub fn get_cache_stats(app: AppHandle, state: State<'_, AppState>) -> Result<CacheStats, String> {
@@ -267,10 +333,15 @@ pub async fn run_until_solved(
         emit_console(&app, format!("Attempt {}/{}", attempts + 1, max_attempts), "info");
 
         match run_file(file_path.clone(), state.clone(), app.clone()).await {
-            Ok(msg) => {
-                println!("{}", msg);
-            }
+            // run_file has already reported the attempt to the console
+            Ok(_) => {}
             Err(e) => {
+                // A stop request cancels the attempt in flight
+                if !*state.is_running.lock().unwrap() {
+                    let stop_msg = format!("Stopped during attempt {}", attempts + 1);
+                    emit_console(&app, stop_msg.clone(), "info");
+                    return Ok(stop_msg);
+                }
                 *state.is_running.lock().unwrap() = false;
                 emit_console(&app, format!("Error: {}", e), "error");
                 return Err(e);
@@ -307,10 +378,72 @@ pub async fn run_until_solved(
     Ok(final_msg)
 }
 
-/// Command to stop running execution
+/// Command to compile a .slut file to JavaScript for an instant preview in the webview
+///
+/// Returns a script defining `run(output)`; solver lines use the solutions
+/// already in the cache.
+#[tauri::command]
+pub fn transpile_to_js(file_path: String, app: AppHandle) -> Result<String, String> {
+    let cache_dir = get_cache_directory(&app)?;
+    let source = std::fs::read_to_string(&file_path)
+        .map_err(|e| format!("Failed to read {}: {}", file_path, e))?;
+
+    let mut transpiler = QuantumTranspiler::new_with_cache_dir(cache_dir)
+        .map_err(|e| format!("Failed to initialize transpiler: {}", e))?;
+    transpiler.set_output_sink(std::sync::Arc::new(crate::BufferedSink::new()));
+
+    transpiler.transpile_source_to_js(&source)
+        .map_err(|e| format!("JavaScript generation failed: {}", e))
+}
+
+/// Command to list equations for a target: every exact one cheapest first,
+/// or the `k` best, approximations included, when `k` is given
+///
+/// Runs off the main thread and never waits for a running program.
+#[tauri::command]
+pub async fn list_solutions(
+    target: f64,
+    inputs: Vec<f64>,
+    k: Option<usize>,
+    state: State<'_, AppState>,
+) -> Result<Vec<MathSolution>, String> {
+    let mut budget = Budget::new(*state.limits.lock().unwrap());
+    budget.start();
+    budget.check_list(inputs.len()).map_err(|e| e.to_string())?;
+    budget.check_solver_candidates(EquationSolver::candidate_upper_bound(inputs.len()))
+        .map_err(|e| e.to_string())?;
+
+    // Same operators, search limits and cost model a run would solve with
+    let solver = state.solver.lock().unwrap().clone();
+    let listing = state.listing_cancel.register();
+
+    let mut engine = MathEngine::new(HashMap::new(), HashMap::new());
+    engine.set_output_sink(std::sync::Arc::new(crate::BufferedSink::new()));
+    engine.set_operators(solver.operators);
+    engine.set_search_config(solver.search);
+    engine.set_cost_model(solver.cost_model);
+    engine.set_cancellation_token(listing.token());
+    engine.set_solve_limits(budget.solve_limits());
+
+    let ranked = tauri::async_runtime::spawn_blocking(move || match k {
+        Some(k) => engine.solve_target_topk(target, &inputs, k),
+        None => engine.enumerate_exact(target, &inputs),
+    })
+    .await
+    .map_err(|e| format!("Listing solutions for {} failed: {}", target, e))?;
+
+    ranked.map_err(|e| match e.downcast_ref::<Cancelled>() {
+        Some(_) => format!("Listing solutions for {} cancelled", target),
+        None => format!("Listing solutions for {} failed: {}", target, e),
+    })
+}
+
+/// Command to stop running execution: the program currently running and any listing in flight
 #[tauri::command]
 pub fn stop_execution(state: State<'_, AppState>) -> Result<(), String> {
     *state.is_running.lock().unwrap() = false;
+    state.cancel.cancel_all();
+    state.listing_cancel.cancel_all();
     Ok(())
 }
 
@@ -324,7 +457,7 @@ pub fn reset_transpiler(state: State<'_, AppState>, app: AppHandle) -> Result<()
         let cache_file = cache_dir.join("quantum_consciousness_cache.json");
         if cache_file.exists() {
             let _ = std::fs::remove_file(&cache_file);
-            println!(">> Cleared cache file: {}", cache_file.display());
+            emit_console(&app, format!(">> Cleared cache file: {}", cache_file.display()), "info");
         }
     }
 
@@ -407,8 +540,8 @@ pub fn get_cache_history(app: AppHandle) -> Result<CacheHistory, String> {
 
 /// Command to clear in-memory state (call on window close or manual reset)
 #[tauri::command]
-pub fn clear_memory_state(state: State<'_, AppState>) -> Result<(), String> {
-    println!(">> Clearing in-memory state");
+pub fn clear_memory_state(state: State<'_, AppState>, app: AppHandle) -> Result<(), String> {
+    emit_console(&app, ">> Clearing in-memory state".to_string(), "info");
 
     *state.transpiler.lock().unwrap() = None;
     *state.observation_count.lock().unwrap() = 0;
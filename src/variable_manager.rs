@@ -1,40 +1,117 @@
 use anyhow::Result;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::time::{SystemTime, UNIX_EPOCH};
-use crate::{StoredVariable, VariableValue, ConsoleCallback};
+use crate::{StoredVariable, VariableValue};
+use crate::bigint::BigInt;
+use crate::output_sink::{SharedSink, StdoutSink};
+
+/// One solver input: an f64, or an integer f64 cannot hold exactly
+#[derive(Debug, Clone, PartialEq)]
+pub enum SolverInput {
+    Number(f64),
+    Integer(BigInt),
+}
+
+impl SolverInput {
+    fn parse(text: &str) -> Option<Self> {
+        match text.parse::<BigInt>() {
+            Ok(n) if !n.fits_f64() => Some(SolverInput::Integer(n)),
+            _ => text.parse::<f64>().ok().map(SolverInput::Number),
+        }
+    }
+}
+
+/// Resolved solver inputs, read as f64 or, for integer-only problems, as big integers
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SolverInputs(Vec<SolverInput>);
+
+impl SolverInputs {
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The inputs as f64; integers past what f64 holds are left out rather than rounded
+    pub fn floats(&self) -> Vec<f64> {
+        self.0.iter()
+            .filter_map(|input| match input {
+                SolverInput::Number(n) => Some(*n),
+                SolverInput::Integer(n) => n.fits_f64().then(|| n.to_f64()),
+            })
+            .collect()
+    }
+
+    /// Every input as an exact integer, or `None` when one is not a whole number
+    pub fn integers(&self) -> Option<Vec<BigInt>> {
+        self.0.iter()
+            .map(|input| match input {
+                SolverInput::Number(n) => BigInt::from_f64(*n),
+                SolverInput::Integer(n) => Some(n.clone()),
+            })
+            .collect()
+    }
+}
 
 pub struct VariableManager {
     variables: HashMap<String, StoredVariable>,
-    console_callback: Option<ConsoleCallback>,
+    output: SharedSink,
+    /// Names stored since `track_writes`, for merging parallel frames
+    written: Option<BTreeSet<String>>,
 }
 
 impl VariableManager {
     pub fn new(cached_variables: HashMap<String, StoredVariable>) -> Self {
-        let msg = format!(">> Initializing variable manager with {} cached variables", cached_variables.len());
-        println!("{}", msg);
+        Self::with_output_sink(cached_variables, StdoutSink::shared())
+    }
+
+    pub fn with_output_sink(cached_variables: HashMap<String, StoredVariable>, output: SharedSink) -> Self {
+        output.info(&format!(">> Initializing variable manager with {} cached variables", cached_variables.len()));
 
         if !cached_variables.is_empty() {
             for (name, var) in &cached_variables {
-                let var_msg = format!("   - Restored variable '{}': {:?}", name, var.value);
-                println!("{}", var_msg);
+                output.debug(&format!("   - Restored variable '{}': {:?}", name, var.value));
             }
         }
 
         Self {
             variables: cached_variables,
-            console_callback: None,
+            output,
+            written: None,
         }
     }
 
-    pub fn set_console_callback(&mut self, callback: ConsoleCallback) {
-        self.console_callback = Some(callback);
+    /// Silent copy of the current variables for a parallel frame
+    pub fn fork(&self) -> Self {
+        Self {
+            variables: self.variables.clone(),
+            output: self.output.clone(),
+            written: None,
+        }
     }
 
-    fn emit(&self, message: String, level: &str) {
-        println!("{}", message);
-        if let Some(callback) = &self.console_callback {
-            callback(message, level);
-        }
+    /// Start recording which variables are written
+    pub fn track_writes(&mut self) {
+        self.written = Some(BTreeSet::new());
+    }
+
+    /// Variables written since `track_writes`, in name order
+    pub fn written(&self) -> Vec<StoredVariable> {
+        self.written.iter()
+            .flatten()
+            .filter_map(|name| self.variables.get(name).cloned())
+            .collect()
+    }
+
+    /// Put back a variable exactly as another manager stored it
+    pub fn restore_variable(&mut self, stored: StoredVariable) {
+        self.variables.insert(stored.name.clone(), stored);
+    }
+
+    pub fn set_output_sink(&mut self, sink: SharedSink) {
+        self.output = sink;
     }
     
     pub fn store_variable(
@@ -51,15 +128,19 @@ impl VariableManager {
         };
         
         self.variables.insert(name.to_string(), stored_var);
+        if let Some(written) = &mut self.written {
+            written.insert(name.to_string());
+        }
         
         let value_str = match &value {
             VariableValue::Number(n) => n.to_string(),
             VariableValue::String(s) => format!("\"{}\"", s),
             VariableValue::Boolean(b) => b.to_string(),
             VariableValue::FunctionResult(f) => format!("[Function: {}]", f),
+            other => crate::display_value(other),
         };
 
-        self.emit(format!("++ Variable stored: '{}' = {}", name, value_str), "info");
+        self.output.debug(&format!("++ Variable stored: '{}' = {}", name, value_str));
 
         Ok(())
     }
@@ -72,32 +153,37 @@ impl VariableManager {
         self.variables.get(name).map(|var| &var.value)
     }
     
+    pub fn len(&self) -> usize {
+        self.variables.len()
+    }
+
     pub fn get_all_variables(&self) -> HashMap<String, StoredVariable> {
         self.variables.clone()
     }
     
     pub fn list_variables(&self) {
         if self.variables.is_empty() {
-            println!("== No variables stored");
+            self.output.info("== No variables stored");
             return;
         }
         
-        println!("== Stored variables:");
+        self.output.info("== Stored variables:");
         for (name, var) in &self.variables {
             let value_str = match &var.value {
                 VariableValue::Number(n) => n.to_string(),
                 VariableValue::String(s) => format!("\"{}\"", s),
                 VariableValue::Boolean(b) => b.to_string(),
                 VariableValue::FunctionResult(f) => format!("[Function: {}]", f),
+                other => crate::display_value(other),
             };
             
-            print!("   {} = {}", name, value_str);
+            let mut line = format!("   {} = {}", name, value_str);
             
             if let Some(eq) = &var.source_equation {
-                print!(" (from: {})", eq);
+                line.push_str(&format!(" (from: {})", eq));
             }
             
-            println!();
+            self.output.info(&line);
         }
     }
     
@@ -123,6 +209,7 @@ impl VariableManager {
                 VariableValue::Number(n) => Some(n.to_string()),
                 VariableValue::Boolean(b) => Some(b.to_string()),
                 VariableValue::FunctionResult(f) => Some(format!("[Function: {}]", f)),
+                other => Some(crate::display_value(other)),
             }
         } else {
             None
@@ -134,6 +221,12 @@ impl VariableManager {
     }
 
     pub fn resolve_expression_inputs_with_target(&self, inputs_str: &str, target: Option<f64>) -> Vec<f64> {
+        self.resolve_solver_inputs(inputs_str, target).floats()
+    }
+
+    /// Literals, variables, strings and lists of numbers in `inputs_str`, with `?`
+    /// blanks filled from cached solutions; integers too large for f64 stay exact
+    pub fn resolve_solver_inputs(&self, inputs_str: &str, target: Option<f64>) -> SolverInputs {
         let mut resolved = Vec::new();
         let mut blanks_count = 0;
         
@@ -142,39 +235,48 @@ impl VariableManager {
             
             if input == "?" {
                 blanks_count += 1;
-            } else if let Ok(num) = input.parse::<f64>() {
+            } else if let Some(num) = SolverInput::parse(input) {
                 resolved.push(num);
             } else if let Some(variable) = self.get_variable(input) {
                 match &variable.value {
                     VariableValue::Number(num) => {
-                        println!("-- Resolved variable '{}' = {}", input, num);
-                        resolved.push(*num);
+                        self.output.debug(&format!("-- Resolved variable '{}' = {}", input, num));
+                        resolved.push(SolverInput::Number(*num));
+                    }
+                    VariableValue::Integer(num) => {
+                        self.output.debug(&format!("-- Resolved variable '{}' = {}", input, num));
+                        resolved.push(SolverInput::Integer(num.clone()));
                     }
                     VariableValue::String(s) => {
-                        println!("-- Parsing string variable '{}' = '{}'", input, s);
-                        for part in s.split(',') {
+                        self.output.debug(&format!("-- Parsing string variable '{}' = '{}'", input, s));
+                        // Commas or newlines, so a file read with readFile works too
+                        for part in s.split([',', '\n']) {
                             let part = part.trim();
                             if part == "?" {
                                 blanks_count += 1;
-                            } else if let Ok(num) = part.parse::<f64>() {
+                            } else if let Some(num) = SolverInput::parse(part) {
+                                self.output.debug(&format!("   - Parsed number: {}", part));
                                 resolved.push(num);
-                                println!("   - Parsed number: {}", num);
                             } else {
-                                println!("   - Skipping non-numeric: {}", part);
+                                self.output.debug(&format!("   - Skipping non-numeric: {}", part));
                             }
                         }
                     }
+                    value @ (VariableValue::List(_) | VariableValue::Record(_)) => {
+                        self.output.debug(&format!("-- Collecting numbers from data variable '{}'", input));
+                        collect_numbers(value, &mut resolved);
+                    }
                     _ => {
-                        println!("-- Variable '{}' is not numeric or string, skipping", input);
+                        self.output.debug(&format!("-- Variable '{}' is not numeric or string, skipping", input));
                     }
                 }
             }
         }
         
         if blanks_count > 0 {
-            self.emit(format!("-- Found {} blank placeholders (?), searching for cached solutions...", blanks_count), "info");
+            self.output.debug(&format!("-- Found {} blank placeholders (?), searching for cached solutions...", blanks_count));
             if let Some(t) = target {
-                self.emit(format!("   >> Target-aware selection enabled for target: {}", t), "info");
+                self.output.debug(&format!("   >> Target-aware selection enabled for target: {}", t));
             }
 
             let available_solutions = self.get_available_cached_solutions();
@@ -184,19 +286,19 @@ impl VariableManager {
 
             let filled_count = selected_solutions.len();
             for solution in selected_solutions {
-                resolved.push(solution);
-                self.emit(format!("   + Filled ? with cached solution: {}", solution), "info");
+                resolved.push(SolverInput::Number(solution));
+                self.output.debug(&format!("   + Filled ? with cached solution: {}", solution));
             }
 
             // Warn if we couldn't fill all blanks
             if filled_count < blanks_count {
-                println!("   >> Warning: Need {} blanks but only have {} cached values",
-                         blanks_count, filled_count);
-                println!("   >> Some placeholders remain unfilled. Provide more concrete inputs or build up cache.");
+                self.output.warn(&format!("   >> Warning: Need {} blanks but only have {} cached values",
+                         blanks_count, filled_count));
+                self.output.warn("   >> Some placeholders remain unfilled. Provide more concrete inputs or build up cache.");
             }
         }
         
-        resolved
+        SolverInputs(resolved)
     }
     
     fn get_available_cached_solutions(&self) -> Vec<f64> {
@@ -214,12 +316,12 @@ impl VariableManager {
 
                     if var.source_equation.is_some() {
                         solutions.push(*num);
-                        self.emit(format!("   - Found computed solution: {} = {} (from: {})",
-                                name, num, var.source_equation.as_ref().unwrap()), "info");
+                        self.output.debug(&format!("   - Found computed solution: {} = {} (from: {})",
+                                name, num, var.source_equation.as_ref().unwrap()));
                     } else if solutions.len() < 3 {
 
                         solutions.push(*num);
-                        self.emit(format!("   - Found stored value: {} = {}", name, num), "info");
+                        self.output.debug(&format!("   - Found stored value: {} = {}", name, num));
                     }
                 }
             }
@@ -230,9 +332,9 @@ impl VariableManager {
         solutions.dedup_by(|a, b| (*a - *b).abs() < f64::EPSILON);
 
         if solutions.is_empty() {
-            self.emit("   - No suitable cached solutions found".to_string(), "info");
+            self.output.debug("   - No suitable cached solutions found");
         } else {
-            self.emit(format!("   - Available cached solutions (deduplicated): {:?}", solutions), "info");
+            self.output.debug(&format!("   - Available cached solutions (deduplicated): {:?}", solutions));
         }
 
         solutions
@@ -254,7 +356,7 @@ impl VariableManager {
         let mut selected = Vec::new();
         let len = available.len();
 
-        self.emit(format!("   >> Selecting {} diverse values from {} available solutions", count, len), "info");
+        self.output.debug(&format!("   >> Selecting {} diverse values from {} available solutions", count, len));
 
         // Target-aware selection: Filter and prioritize based on target size
         if let Some(t) = target {
@@ -262,11 +364,11 @@ impl VariableManager {
 
             if !filtered.is_empty() && filtered.len() >= count {
                 // Use filtered set for better target alignment
-                self.emit(format!("      >> Using target-aware filtered set of {} values", filtered.len()), "info");
+                self.output.debug(&format!("      >> Using target-aware filtered set of {} values", filtered.len()));
                 return self.distribute_values(&filtered, count);
             } else if !filtered.is_empty() {
                 // Use what we have from filtered set, then add from full set
-                self.emit(format!("      >> Target filtering gave {} values, need {}", filtered.len(), count), "info");
+                self.output.debug(&format!("      >> Target filtering gave {} values, need {}", filtered.len(), count));
                 selected.extend_from_slice(&filtered);
                 let remaining = count - selected.len();
 
@@ -298,7 +400,7 @@ impl VariableManager {
     fn filter_by_target_range(&self, available: &[f64], target: f64) -> Vec<f64> {
         let mut filtered: Vec<f64> = if target < 100.0 {
             // Small target: prefer small values (< target * 2)
-            self.emit(format!("      >> Small target ({}) - preferring small cached values", target), "info");
+            self.output.debug(&format!("      >> Small target ({}) - preferring small cached values", target));
             available.iter()
                 .filter(|&&v| v < target * 2.0)
                 .copied()
@@ -306,14 +408,14 @@ impl VariableManager {
         } else if target < 1000.0 {
             // Medium target: mix of small and large
             // Include values from 1 to target * 2
-            self.emit(format!("      >> Medium target ({}) - selecting balanced range", target), "info");
+            self.output.debug(&format!("      >> Medium target ({}) - selecting balanced range", target));
             available.iter()
                 .filter(|&&v| v >= 2.0 && v <= target * 2.0)
                 .copied()
                 .collect()
         } else {
             // Large target: prefer large values and small multipliers
-            self.emit(format!("      >> Large target ({}) - preferring large values and small multipliers", target), "info");
+            self.output.debug(&format!("      >> Large target ({}) - preferring large values and small multipliers", target));
             let small_multipliers: Vec<f64> = available.iter()
                 .filter(|&&v| v >= 2.0 && v <= 20.0)
                 .copied()
@@ -356,19 +458,19 @@ impl VariableManager {
                 // This helps build bigger numbers through multiplication/exponentiation
                 let idx = len - 1; // Pick the largest available value
                 selected.push(available[idx]);
-                self.emit(format!("      + Selected largest value to maximize potential: {}", available[idx]), "info");
+                self.output.debug(&format!("      + Selected largest value to maximize potential: {}", available[idx]));
             }
             2 => {
                 // Take smallest and largest for maximum diversity
                 selected.push(available[0]);
                 selected.push(available[len - 1]);
-                println!("      + Selected smallest: {}", available[0]);
-                println!("      + Selected largest: {}", available[len - 1]);
+                self.output.debug(&format!("      + Selected smallest: {}", available[0]));
+                self.output.debug(&format!("      + Selected largest: {}", available[len - 1]));
             }
             _ => {
                 // Take smallest, evenly distributed middle values, and largest
                 selected.push(available[0]);
-                println!("      + Selected smallest: {}", available[0]);
+                self.output.debug(&format!("      + Selected smallest: {}", available[0]));
 
                 // Calculate how many middle values we need
                 let middle_count = count - 2;
@@ -378,11 +480,11 @@ impl VariableManager {
                     let idx = (i as f64 * step).round() as usize;
                     let idx = idx.min(len - 2).max(1); // Ensure valid range
                     selected.push(available[idx]);
-                    println!("      + Selected middle value: {}", available[idx]);
+                    self.output.debug(&format!("      + Selected middle value: {}", available[idx]));
                 }
 
                 selected.push(available[len - 1]);
-                println!("      + Selected largest: {}", available[len - 1]);
+                self.output.debug(&format!("      + Selected largest: {}", available[len - 1]));
             }
         }
 
@@ -401,11 +503,11 @@ impl VariableManager {
             
             else if let Some(variable) = self.get_variable(input) {
                 resolved.push(variable.value.clone());
-                println!("-- Resolved variable '{}' = {:?}", input, variable.value);
+                self.output.debug(&format!("-- Resolved variable '{}' = {:?}", input, variable.value));
             }
             
             else {
-                resolved.push(VariableValue::String(input.trim_matches('"').to_string()));
+                resolved.push(VariableValue::String(crate::lexer::text_literal(input)));
             }
         }
         
@@ -416,7 +518,10 @@ impl VariableManager {
         if let Some(var) = self.variables.get_mut(name) {
             var.value = new_value;
             var.timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
-            println!("++ Variable '{}' updated", name);
+            if let Some(written) = &mut self.written {
+                written.insert(name.to_string());
+            }
+            self.output.debug(&format!("++ Variable '{}' updated", name));
             Ok(())
         } else {
             self.store_variable(name, new_value, None)
@@ -441,7 +546,7 @@ impl VariableManager {
     
     pub fn clear_variables(&mut self) {
         self.variables.clear();
-        println!("++ All variables cleared");
+        self.output.debug("++ All variables cleared");
     }
     
     pub fn export_variables_to_string(&self) -> String {
@@ -453,6 +558,7 @@ impl VariableManager {
                 VariableValue::String(s) => format!("\"{}\"", s),
                 VariableValue::Boolean(b) => b.to_string(),
                 VariableValue::FunctionResult(f) => format!("[Function: {}]", f),
+                other => crate::display_value(other),
             };
             
             output.push_str(&format!("{} = {}\n", name, value_str));
@@ -460,4 +566,16 @@ impl VariableManager {
         
         output
     }
-}
\ No newline at end of file
+}
+
+/// Every number inside a list or record, in order; numeric strings count
+fn collect_numbers(value: &VariableValue, numbers: &mut Vec<SolverInput>) {
+    match value {
+        VariableValue::Number(n) => numbers.push(SolverInput::Number(*n)),
+        VariableValue::Integer(n) => numbers.push(SolverInput::Integer(n.clone())),
+        VariableValue::String(s) => numbers.extend(SolverInput::parse(s.trim())),
+        VariableValue::List(items) => items.iter().for_each(|item| collect_numbers(item, numbers)),
+        VariableValue::Record(fields) => fields.iter().for_each(|(_, field)| collect_numbers(field, numbers)),
+        VariableValue::Boolean(_) | VariableValue::FunctionResult(_) => {}
+    }
+}
@@ -11,7 +11,7 @@ let invoke, open, listen;
 
 // UI Elements
 let dropZone, fileInfo, statusIndicator;
-let runOnceBtn, runLoopBtn, stopBtn;
+let runOnceBtn, runLoopBtn, stopBtn, previewBtn;
 let varCount, solCount, accuracy, accuracyBar, obsCount;
 let stepsOutput, consoleOutput, codeEditor;
 
@@ -79,6 +79,7 @@ function initializeUI() {
     runOnceBtn = document.getElementById('runOnceBtn');
     runLoopBtn = document.getElementById('runLoopBtn');
     stopBtn = document.getElementById('stopBtn');
+    previewBtn = document.getElementById('previewBtn');
     varCount = document.getElementById('varCount');
     solCount = document.getElementById('solCount');
     accuracy = document.getElementById('accuracy');
@@ -184,6 +185,7 @@ async function loadFile(filePath) {
         // Enable execute buttons
         runOnceBtn.disabled = false;
         runLoopBtn.disabled = false;
+        previewBtn.disabled = false;
 
     } catch (error) {
         console.error('Error loading file:', error);
@@ -465,6 +467,35 @@ async function runUntilSolved() {
     }
 }
 
+// Run the file as generated JavaScript, without the Rust engine
+async function previewInBrowser() {
+    if (!currentFile) {
+        addStep('No file loaded', 'error');
+        return;
+    }
+
+    clearSteps();
+    addStep(`Previewing: ${currentFile}`, 'info');
+
+    try {
+        const script = await invoke('transpile_to_js', { filePath: currentFile });
+        const run = new Function(script + '\nreturn run;')();
+
+        run({
+            program: (line) => addConsoleOutput(line),
+            diagnostic: (level, message) => {
+                if (level === 'warn') addStep(message, 'error');
+            },
+        });
+
+        addStep('Preview complete (solver results come from the cache)', 'success');
+        simulateExecution();
+    } catch (error) {
+        addStep('Preview error: ' + error, 'error');
+        console.error('Preview error:', error);
+    }
+}
+
 // Stop execution
 async function stopExecution() {
     try {
@@ -591,11 +622,13 @@ function setRunningState(running) {
     if (runOnceBtn) runOnceBtn.disabled = running || !currentFile;
     if (runLoopBtn) runLoopBtn.disabled = running || !currentFile;
     if (stopBtn) stopBtn.disabled = !running;
+    if (previewBtn) previewBtn.disabled = running || !currentFile;
 }
 
 // Global functions for button onclick handlers
 window.runOnce = runOnce;
 window.runUntilSolved = runUntilSolved;
+window.previewInBrowser = previewInBrowser;
 window.stopExecution = stopExecution;
 window.resetGlobeView = () => window.globeEngine && window.globeEngine.reset();
 window.toggleRotation = () => window.globeEngine && window.globeEngine.toggleRotation();